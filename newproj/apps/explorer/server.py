@@ -11,6 +11,7 @@ import uvicorn
 
 from core.config import get_config
 from core.utils import ensure_dirs
+from core.crypto import is_valid_address
 from core.db import get_db, BlockHeader, UTXO, Reward, Transaction, MempoolTx, KV, FairnessEpoch, FairnessCredit
 from sqlalchemy import func
 import json
@@ -97,7 +98,7 @@ button{background:var(--primary);color:#000;border:none;padding:10px 16px;border
           <input type="text" name="q" placeholder="Block height / block hash / tx id / Address" value="{{q or ''}}"/>
           <button type="submit">Search</button>
         </form>
-        <div class="notice">Examples: 1003, a1b2c3..., txid..., SMELLY_...</div>
+        <div class="notice">Examples: 1003, a1b2c3..., txid..., smc...</div>
         {% if search_error %}<div class="notice">Error: {{search_error}}</div>{% endif %}
         {% if search_result %}
           <div style="margin-top:12px">
@@ -465,8 +466,8 @@ def search_route(q: str | None = Query(default=None, alias="q")):
                 from fastapi.responses import RedirectResponse
                 return RedirectResponse(url=f"/block/{h.hash_hex}", status_code=302)
 
-    # 2) Address exact (SMELLY_ or stored)
-    if q_raw.startswith("SMELLY_"):
+    # 2) Address exact (valid for this network, legacy SMELLY_, or stored)
+    if is_valid_address(q_raw):
         from fastapi.responses import RedirectResponse
         return RedirectResponse(url=f"/address/{q_raw}", status_code=302)
     else:
@@ -580,8 +581,8 @@ def index(q: str | None = Query(default=None, alias="q")):
                             "href": f"/block/{h.hash_hex}",
                         })
 
-            # 2) Exact address if SMELLY_ or subaddress-like
-            if q_raw.startswith("SMELLY_"):
+            # 2) Exact address if valid for this network or legacy SMELLY_
+            if is_valid_address(q_raw):
                 suggestions.append({
                     "kind": "address",
                     "title": "Address",
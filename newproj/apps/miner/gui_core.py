@@ -3,8 +3,9 @@ import threading
 import time
 from typing import Callable, Dict, List
 
-from .solo_miner import get_work, submit_work, build_merkle_root_for_job, header_serialize, pow_hash
+from .solo_miner import HashrateMeter, WorkFeed, submit_work, build_merkle_root_for_job, header_serialize, pow_hash
 from .config import get_config
+from core.target import hash_meets_target, to_int
 
 
 class SoloMinerCore:
@@ -14,6 +15,7 @@ class SoloMinerCore:
     def __init__(self):
         self._stop_evt = threading.Event()
         self._threads = []
+        self._feed = None
         self._cfg = get_config()
         self._miner_address = self._cfg.get("miner.default_address", "SMELLY_SOLO")
         self._thread_count = int(self._cfg.get("miner.threads", 4))
@@ -21,8 +23,7 @@ class SoloMinerCore:
         self._slice_ms = 250
 
         self._accepted_blocks = 0
-        self._last_hashes = [0 for _ in range(self._thread_count)]
-        self._hash_lock = threading.Lock()
+        self._meter = HashrateMeter(self._thread_count, float(self._cfg.get("miner.hashrate_ema_sec", 30)))
 
         # GUI Callbacks
         self.on_log: Callable[[str, str], None] = lambda lvl, msg: None
@@ -37,7 +38,7 @@ class SoloMinerCore:
             self._miner_address = addr
         if threads:
             self._thread_count = int(threads)
-            self._last_hashes = [0 for _ in range(self._thread_count)]
+            self._meter = HashrateMeter(self._thread_count, float(self._cfg.get("miner.hashrate_ema_sec", 30)))
 
     def start(self):
         if self._threads:
@@ -46,6 +47,8 @@ class SoloMinerCore:
         self.on_log("info", f"Starting miner with {self._thread_count} threads")
         self.on_status("mining")
         self._stop_evt.clear()
+        self._feed = WorkFeed(self._miner_address)
+        self._feed.start()
 
         for tid in range(self._thread_count):
             th = threading.Thread(target=self._worker, args=(tid,), daemon=True)
@@ -57,6 +60,8 @@ class SoloMinerCore:
 
     def stop(self, join=False):
         self._stop_evt.set()
+        if self._feed is not None:
+            self._feed.stop()
         if join:
             for t in self._threads:
                 t.join(timeout=1.0)
@@ -68,19 +73,23 @@ class SoloMinerCore:
         self.start()
 
     def _worker(self, tid: int):
+        feed = self._feed
         while not self._stop_evt.is_set():
-            work = get_work(self._miner_address)
+            gen = feed.generation
+            work = feed.get()
             if not work:
                 time.sleep(self._poll_ms / 1000.0)
                 continue
 
             merkle_root, txids_used = build_merkle_root_for_job(work.height, work.txids)
-            target_int = int(work.target_hex, 16)
+            target_int = to_int(work.target_hex)
             start = time.time()
             hashes = 0
             nonce = tid
 
             while (time.time() - start) * 1000.0 < self._slice_ms and not self._stop_evt.is_set():
+                if hashes % 256 == 0 and feed.stale(gen):
+                    break  # new tip or txs: abandon this job
                 hdr_bytes = header_serialize(
                     version=work.version,
                     prev_hash_hex=work.prev_hash,
@@ -92,7 +101,7 @@ class SoloMinerCore:
                     tx_count=len(txids_used),
                 )
                 digest = pow_hash(hdr_bytes, nonce)
-                if int(digest.hex(), 16) <= target_int:
+                if hash_meets_target(digest, target_int):
                     ok, res = submit_work(
                         job_id=work.job_id,
                         miner_address=self._miner_address,
@@ -103,8 +112,10 @@ class SoloMinerCore:
                     )
                     if ok:
                         self._accepted_blocks += 1
+                        self._meter.solution()
                         self.on_log("info", f"[T{tid}] Accepted block {res}")
                         self.on_accepts(self._accepted_blocks, 0)
+                        feed.refresh()
                         break
                     else:
                         self.on_log("warn", f"[T{tid}] Submit rejected: {res}")
@@ -113,16 +124,16 @@ class SoloMinerCore:
                 hashes += 1
                 nonce += self._thread_count
 
-            with self._hash_lock:
-                self._last_hashes[tid] = hashes
+            self._meter.add(tid, hashes, work.job_id)
 
             time.sleep(self._poll_ms / 1000.0)
 
     def _reporter_loop(self):
         while not self._stop_evt.is_set():
             time.sleep(2.0)
-            with self._hash_lock:
-                total = sum(self._last_hashes)
-                per = {str(i): float(h) / 2.0 for i, h in enumerate(self._last_hashes)}
-                self._last_hashes = [0 for _ in self._last_hashes]
-            self.on_rates(total / 2.0, per)
+            self._meter.sample()
+            st = self._meter.snapshot()
+            self.on_rates(st["hashrate"], {str(i): r for i, r in enumerate(st["per_thread_hashrate"])})
+
+    def stats(self) -> Dict:
+        return self._meter.snapshot()
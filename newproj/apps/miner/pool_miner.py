@@ -10,7 +10,8 @@ import curses
 from typing import Optional, List, Tuple
 
 from core.pow.pow_backend import pow_hash
-import hashlib
+from core.target import hash_meets_target
+from core.merkle import merkle_root
 
 # Lazy import guard for GUI
 try:
@@ -31,6 +32,9 @@ class PoolMinerClient:
         self.alive = False
 
         self.current_job = None  # dict job template
+        # nonce = extranonce1 || extranonce2 (pool-assigned prefix, miner-rolled suffix)
+        self.extranonce1 = 0
+        self.extranonce2_size = 8
         self.lock = threading.Lock()
 
         # metrics for TUI
@@ -48,6 +52,7 @@ class PoolMinerClient:
 
         # subscribe/authorize
         self._send({"id": 1, "method": "mining.subscribe", "params": []})
+        self._send({"id": 4, "method": "mining.extranonce.subscribe", "params": []})
         self._send({"id": 2, "method": "mining.authorize", "params": [self.address]})
         # request job
         self._send({"id": 3, "method": "mining.get_job", "params": []})
@@ -93,6 +98,12 @@ class PoolMinerClient:
                 }
             print("New job:", self.current_job["job_id"], "prev:", (self.current_job["prev_hash"] or "")[:16], "txids:", len(self.current_job["txids"] or []))
             return
+        if msg.get("method") == "mining.set_extranonce":
+            self._set_extranonce(msg.get("params") or [])
+            return
+        if msg.get("method") == "mining.ping":
+            self._send({"id": msg.get("id"), "result": "pong", "error": None})
+            return
 
         # responses
         if msg.get("id") in (1, 2, 3):
@@ -102,6 +113,8 @@ class PoolMinerClient:
             if err:
                 print("RPC error:", err)
             else:
+                if msg["id"] == 1 and isinstance(res, list) and len(res) >= 3:
+                    self._set_extranonce(res[1:3])
                 if msg["id"] == 3 and isinstance(res, dict):
                     tmpl = res.get("template") or {}
                     with self.lock:
@@ -128,6 +141,15 @@ class PoolMinerClient:
                 print("Share accepted")
             return
 
+    def _set_extranonce(self, params: list):
+        try:
+            e1 = int(str(params[0]), 16)
+            size = max(1, min(8, int(params[1])))
+        except (IndexError, TypeError, ValueError):
+            e1, size = 0, 8
+        with self.lock:
+            self.extranonce1, self.extranonce2_size = e1, size
+
     def _header_bytes(self, version: int, prev_hash: str, merkle_root_hex: str, ts: int, target_hex: str, nonce: int, miner_addr: str, tx_count: int) -> bytes:
         # Must match consensus serialization
         fields = [
@@ -151,21 +173,7 @@ class PoolMinerClient:
         - IMPORTANT: inputs and the resulting hex should be lowercase to match node normalization
         """
         txids = snapshot_txids or []
-        tx_count = len(txids)
-        def h(b: bytes) -> bytes:
-            return hashlib.sha3_256(b).digest()
-        if tx_count == 0:
-            # Fallback; shouldn’t happen because server includes coinbase
-            return hashlib.sha3_256(b"").hexdigest(), 0
-        layer = [bytes.fromhex(t.lower()) for t in txids]
-        while len(layer) > 1:
-            if len(layer) % 2 == 1:
-                layer.append(layer[-1])
-            nxt = []
-            for i in range(0, len(layer), 2):
-                nxt.append(h(layer[i] + layer[i + 1]))
-            layer = nxt
-        return layer[0].hex(), tx_count
+        return merkle_root(txids), len(txids)
 
     def _worker_loop(self):
         # Use thread id as nonce stride
@@ -175,7 +183,7 @@ class PoolMinerClient:
         except Exception:
             stride = 0
         nonce = stride
-        miner_addr = self.address
+        miner_addr = self.address.split(".", 1)[0]  # header commits to the payout address, not the worker
         report_t = time.time()
         hashes = 0
 
@@ -183,6 +191,8 @@ class PoolMinerClient:
             tname = threading.current_thread().name
             with self.lock:
                 job = dict(self.current_job) if self.current_job else None
+                e2_bits = 8 * self.extranonce2_size
+                prefix = (self.extranonce1 << e2_bits) if e2_bits < 64 else 0
             if not job or not job.get("job_id"):
                 time.sleep(0.2)
                 continue
@@ -200,12 +210,13 @@ class PoolMinerClient:
 
             try:
                 for _ in range(10000):
-                    hdr = self._header_bytes(version, prev, mr, ts, target_hex, nonce, miner_addr, tx_count)
-                    digest = pow_hash(hdr, nonce, prev)
+                    nonce_val = prefix | (nonce & ((1 << e2_bits) - 1))
+                    hdr = self._header_bytes(version, prev, mr, ts, target_hex, nonce_val, miner_addr, tx_count)
+                    digest = pow_hash(hdr, nonce_val, prev)
                     hashes += 1
-                    if int(digest.hex(), 16) <= int(job["pool_target_hex"], 16):
+                    if hash_meets_target(digest, job["pool_target_hex"]):
                         # Submit share using template fields; include prev to avoid stale job_id races
-                        self._submit_share(job["job_id"], nonce, ts, mr, version, prev)
+                        self._submit_share(job["job_id"], nonce_val, ts, mr, version, prev)
                     nonce = (nonce + 1) & 0xFFFFFFFF
                 # update per-thread hashes every second for TUI
                 nowt = time.time()
@@ -347,6 +358,10 @@ def main():
             return 1
         return launch_gui(default_host=args.host, default_port=args.port, default_address=args.address, default_intensity=args.intensity)
 
+    from core.crypto import check_address, parse_worker
+    addr_err = check_address(parse_worker(args.address)[0])
+    if addr_err:
+        parser.error(f"--address: {addr_err}")
     client = PoolMinerClient(args.host, args.port, args.address, args.intensity)
     print(f"Connecting to pool {args.host}:{args.port} as {args.address} with {args.intensity} workers...")
     client.connect()
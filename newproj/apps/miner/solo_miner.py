@@ -12,7 +12,10 @@ from dataclasses import dataclass
 from typing import Optional, List, Tuple
 
 from core.config import get_config
+from core import metrics
 from core.pow.randomx_stub import pow_hash
+from core.target import hash_meets_target, to_int
+from core.merkle import merkle_root
 
 
 def rpc_url() -> str:
@@ -43,6 +46,20 @@ class Work:
     timestamp: int
     txids: List[str]
     miner_hint: str
+    share_target_hex: str = ""  # pool jobs: submit anything meeting this (easier) share target
+
+
+def _work_from_json(w: dict) -> Work:
+    return Work(
+        job_id=w["job_id"],
+        height=w["height"],
+        prev_hash=w["prev_hash"],
+        target_hex=w["target"],
+        version=w["version"],
+        timestamp=w["timestamp"],
+        txids=w.get("txids", []),
+        miner_hint=w.get("miner_hint", ""),
+    )
 
 
 def get_work(miner_address: Optional[str]) -> Optional[Work]:
@@ -53,22 +70,84 @@ def get_work(miner_address: Optional[str]) -> Optional[Work]:
             timeout=10,
         )
         r.raise_for_status()
-        w = r.json()
-        return Work(
-            job_id=w["job_id"],
-            height=w["height"],
-            prev_hash=w["prev_hash"],
-            target_hex=w["target"],
-            version=w["version"],
-            timestamp=w["timestamp"],
-            txids=w.get("txids", []),
-            miner_hint=w.get("miner_hint", ""),
-        )
+        return _work_from_json(r.json())
     except Exception as e:
         print("get_work error:", e)
         return None
 
 
+class WorkFeed:
+    """
+    Solo work that follows the node's shared block template (core.blocktemplate) by long-polling
+    get_block_template. generation bumps whenever the template changes (new tip, new txs), so
+    workers can abandon a stale job mid-slice instead of finishing it; after a found block the
+    miner calls refresh() to pick up the next template without waiting for the long-poll.
+    """
+
+    def __init__(self, miner_address: str, longpoll_sec: float = 30.0):
+        self.miner_address = miner_address
+        self.longpoll_sec = longpoll_sec
+        self.lock = threading.Lock()
+        self.work: Optional[Work] = None
+        self.generation = 0
+        self._have_work = threading.Event()
+        self._stop = threading.Event()
+
+    def _fetch(self, longpollid: Optional[str]) -> dict:
+        r = requests.post(
+            f"{rpc_url()}/rpc/get_block_template",
+            json={"miner_address": self.miner_address, "longpollid": longpollid,
+                  "timeout_sec": self.longpoll_sec if longpollid else None},
+            timeout=(self.longpoll_sec if longpollid else 0.0) + 10.0,
+        )
+        r.raise_for_status()
+        return r.json()
+
+    def _set(self, js: dict):
+        w = _work_from_json(js)
+        with self.lock:
+            old = self.work
+            if old is not None and old.job_id == w.job_id:
+                return
+            self.work = w
+            self.generation += 1
+        self._have_work.set()
+        if old is not None and old.prev_hash != w.prev_hash:
+            print(f"New tip: height={w.height} prev={w.prev_hash[:16]}.. (abandoning old work)")
+
+    def _loop(self):
+        longpollid: Optional[str] = None
+        while not self._stop.is_set():
+            try:
+                js = self._fetch(longpollid)
+                self._set(js)
+                longpollid = js.get("longpollid")
+            except Exception as e:
+                print("get_block_template error:", e)
+                longpollid = None
+                self._stop.wait(2.0)
+
+    def start(self):
+        threading.Thread(target=self._loop, name="work-feed", daemon=True).start()
+
+    def stop(self):
+        self._stop.set()
+
+    def refresh(self):
+        try:
+            self._set(self._fetch(None))
+        except Exception as e:
+            print("get_block_template error:", e)
+
+    def get(self, _miner_address: Optional[str] = None) -> Optional[Work]:
+        self._have_work.wait(timeout=1.0)
+        with self.lock:
+            return self.work
+
+    def stale(self, generation: int) -> bool:
+        return self.generation != generation
+
+
 def submit_work(job_id: str, miner_address: str, nonce: int, version: int, timestamp: int, merkle_root_hex: str) -> Tuple[bool, Optional[str]]:
     try:
         r = requests.post(
@@ -119,21 +198,7 @@ def header_serialize(version: int, prev_hash_hex: str, merkle_root_hex: str, tim
 
 
 def merkle_root_from_txids(txids: List[str]) -> str:
-    # Must match consensus.merkle_root_hex()
-    import hashlib
-    def sha3_256_hex(b: bytes) -> str:
-        return hashlib.sha3_256(b).hexdigest()
-    if not txids:
-        return sha3_256_hex(b"")
-    layer = [bytes.fromhex(t) for t in txids]
-    while len(layer) > 1:
-        nxt = []
-        if len(layer) % 2 == 1:
-            layer.append(layer[-1])
-        for i in range(0, len(layer), 2):
-            nxt.append(bytes.fromhex(sha3_256_hex(layer[i] + layer[i + 1])))
-        layer = nxt
-    return layer[0].hex()
+    return merkle_root(txids)
 
 
 def coinbase_txid_for_height(height: int) -> str:
@@ -147,30 +212,345 @@ def build_merkle_root_for_job(height: int, snapshot_txids: List[str]) -> Tuple[s
     return merkle_root_from_txids(txids), txids
 
 
-def mine_client_side(miner_address: str, threads: int, slice_ms: int, poll_ms: int):
-    print(f"Client miner starting: threads={threads}, slice_ms={slice_ms}, poll_ms={poll_ms}")
+class MinerControl:
+    """
+    Runtime-adjustable mining knobs shared by all worker threads.
+    - threads: active workers (0..max_threads); idle workers park without hashing
+    - intensity: duty cycle in percent; each hashing slice is followed by a proportional rest
+    - throttle: percent of intensity actually used, lowered by the adaptive throttle (--adaptive)
+      while other processes need the CPU; the effective duty cycle is intensity * throttle / 100
+    Changed via the local control endpoint (--control-port) or SIGUSR1/SIGUSR2 (+1/-1 thread, POSIX).
+    """
+
+    def __init__(self, threads: int, max_threads: int, intensity: int):
+        self.lock = threading.Lock()
+        self.max_threads = max(1, max_threads)
+        self.threads = 0
+        self.intensity = 100
+        self.throttle = 100
+        self.set(threads=threads, intensity=intensity)
+
+    def set(self, threads: Optional[int] = None, intensity: Optional[int] = None) -> dict:
+        with self.lock:
+            if threads is not None:
+                self.threads = max(0, min(self.max_threads, int(threads)))
+            if intensity is not None:
+                self.intensity = max(1, min(100, int(intensity)))
+            return {"threads": self.threads, "max_threads": self.max_threads, "intensity": self.intensity,
+                    "throttle": self.throttle}
+
+    def snapshot(self) -> dict:
+        return self.set()
+
+    def set_throttle(self, pct: int):
+        with self.lock:
+            self.throttle = max(5, min(100, int(pct)))
+
+    def rest_seconds(self, busy_sec: float) -> float:
+        with self.lock:
+            pct = max(1.0, self.intensity * self.throttle / 100.0)
+        return busy_sec * (100 - pct) / pct
+
+
+def default_threads(reserve: int) -> int:
+    """Worker count when --threads is not given: every allowed CPU but `reserve` (at least one)."""
+    cpus = len(os.sched_getaffinity(0)) if hasattr(os, "sched_getaffinity") else (os.cpu_count() or 1)
+    return max(1, cpus - max(0, reserve))
+
+
+def set_priority(nice: int) -> bool:
+    """Lower the miner's scheduling priority (POSIX nice increment; on Windows a below-normal class)."""
+    if nice <= 0:
+        return False
+    if hasattr(os, "nice"):
+        try:
+            os.nice(nice)
+            return True
+        except OSError:
+            return False
+    try:
+        import psutil  # type: ignore
+
+        cls = psutil.IDLE_PRIORITY_CLASS if nice >= 15 else psutil.BELOW_NORMAL_PRIORITY_CLASS
+        psutil.Process().nice(cls)
+        return True
+    except Exception:
+        return False
+
+
+def _adaptive_throttle(ctl: MinerControl, stop_evt: threading.Event, target_idle_pct: float, interval_sec: float = 5.0):
+    """
+    Keep CPU headroom for the node and the desktop: every interval, measure total CPU use and the
+    share used by other processes (total minus this process). When the machine is busier than
+    100 - target_idle_pct and other processes account for a real part of it, lower the throttle by a
+    quarter; when there is headroom again, raise it by 10 points.
+    Uses psutil when available, else the 1-minute load average (POSIX) as a coarser signal.
+    """
+    try:
+        import psutil  # type: ignore
+
+        proc = psutil.Process()
+        proc.cpu_percent(None)
+        psutil.cpu_percent(None)
+    except Exception:
+        psutil = None  # type: ignore
+        proc = None
+    ncpu = os.cpu_count() or 1
+    while not stop_evt.wait(interval_sec):
+        if psutil is not None:
+            total = psutil.cpu_percent(None)  # all CPUs, 0..100
+            ours = proc.cpu_percent(None) / ncpu
+        elif hasattr(os, "getloadavg"):
+            snap = ctl.snapshot()
+            total = min(100.0, os.getloadavg()[0] * 100.0 / ncpu)
+            ours = snap["threads"] * snap["intensity"] * snap["throttle"] / 100.0 / ncpu
+        else:
+            return
+        others = max(0.0, total - ours)
+        throttle = ctl.snapshot()["throttle"]
+        if total >= 100.0 - target_idle_pct and others >= target_idle_pct / 2:
+            new = int(throttle * 0.75)
+        elif total < 100.0 - target_idle_pct:
+            new = throttle + 10
+        else:
+            new = throttle
+        if new != throttle:
+            ctl.set_throttle(new)
+
+
+_HASHRATE = metrics.gauge("smelly_miner_hashrate", "Smoothed (EMA) miner hashrate, H/s")
+_THREAD_HASHRATE = metrics.gauge("smelly_miner_thread_hashrate", "Smoothed (EMA) hashrate per worker thread, H/s", ("thread",))
+_HASHES = metrics.counter("smelly_miner_hashes_total", "Hashes computed")
+_SOLUTIONS = metrics.counter("smelly_miner_solutions_total", "Accepted blocks (solo) or shares (pool)")
+_LAST_SOLUTION = metrics.gauge("smelly_miner_last_solution_timestamp_ms", "When the last accepted solution was found")
+_NONCES_JOB = metrics.gauge("smelly_miner_job_nonces", "Nonces tried on the current job")
+
+NONCE_SPACE = 1 << 64  # 8-byte nonce (pools: 2-byte extranonce1 || 6-byte extranonce2)
+
+
+class HashrateMeter:
+    """
+    Per-thread hash counters turned into hashrates by a sampler: each sample() takes the hashes
+    counted since the previous one and folds the rate into an exponential moving average with
+    time constant miner.hashrate_ema_sec, so short slices and rest periods don't make the number
+    jump. Also tracks nonces tried on the current job (coverage of the nonce space) and the time
+    of the last accepted solution.
+    """
+
+    def __init__(self, threads: int, tau_sec: float = 30.0):
+        self.lock = threading.Lock()
+        self.tau = max(1.0, float(tau_sec))
+        self.pending = [0] * threads  # hashes since the last sample
+        self.totals = [0] * threads
+        self.ema = [0.0] * threads
+        self.instant = [0.0] * threads
+        self.last_sample = time.monotonic()
+        self.started_ms = int(time.time() * 1000)
+        self.job_id: Optional[str] = None
+        self.job_nonces = 0
+        self.solutions = 0
+        self.last_solution_ms: Optional[int] = None
+
+    def add(self, tid: int, hashes: int, job_id: str):
+        with self.lock:
+            self.pending[tid] += hashes
+            self.totals[tid] += hashes
+            if job_id != self.job_id:
+                self.job_id, self.job_nonces = job_id, 0
+            self.job_nonces += hashes
+        _HASHES.inc(hashes)
+
+    def solution(self):
+        with self.lock:
+            self.solutions += 1
+            self.last_solution_ms = int(time.time() * 1000)
+        _SOLUTIONS.inc()
+        _LAST_SOLUTION.set(self.last_solution_ms)
+
+    def sample(self):
+        nowt = time.monotonic()
+        with self.lock:
+            dt = max(1e-6, nowt - self.last_sample)
+            self.last_sample = nowt
+            alpha = 1.0 - math.exp(-dt / self.tau)
+            for i, n in enumerate(self.pending):
+                rate = n / dt
+                self.instant[i] = rate
+                # seed with the first real rate so the average does not crawl up from zero
+                self.ema[i] = rate if self.ema[i] == 0.0 else self.ema[i] + alpha * (rate - self.ema[i])
+                self.pending[i] = 0
+            ema = list(self.ema)
+            job_nonces = self.job_nonces
+        _HASHRATE.set(sum(ema))
+        for i, r in enumerate(ema):
+            _THREAD_HASHRATE.set(r, (str(i),))
+        _NONCES_JOB.set(job_nonces)
+
+    def snapshot(self, active_threads: Optional[int] = None) -> dict:
+        with self.lock:
+            n = len(self.ema) if active_threads is None else active_threads
+            return {
+                "hashrate": sum(self.ema),
+                "hashrate_instant": sum(self.instant),
+                "per_thread_hashrate": [round(r, 1) for r in self.ema[:n]],
+                "hashes_total": sum(self.totals),
+                "per_thread_hashes": list(self.totals[:n]),
+                "nonce_coverage": {"job_id": self.job_id, "nonces": self.job_nonces,
+                                   "fraction": self.job_nonces / NONCE_SPACE},
+                "solutions": self.solutions,
+                "last_solution_ms": self.last_solution_ms,
+                "uptime_ms": int(time.time() * 1000) - self.started_ms,
+            }
+
+
+def _pin_current_thread(cpu: int) -> bool:
+    # Linux: sched_setaffinity on the native thread id pins just this thread
+    if not hasattr(os, "sched_setaffinity"):
+        return False
+    try:
+        os.sched_setaffinity(threading.get_native_id(), {cpu})
+        return True
+    except OSError:
+        return False
+
+
+def _available_cpus(spec: str) -> List[int]:
+    if spec:
+        return [int(c) for c in spec.split(",") if c.strip()]
+    if hasattr(os, "sched_getaffinity"):
+        return sorted(os.sched_getaffinity(0))
+    return list(range(os.cpu_count() or 1))
+
+
+def _start_control_server(ctl: MinerControl, port: int, stats_fn):
+    """Tiny localhost JSON endpoint: GET /status, POST /control {"threads": n, "intensity": pct}."""
+    from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+    class Handler(BaseHTTPRequestHandler):
+        def _reply(self, code: int, obj: dict):
+            body = json.dumps(obj).encode("utf-8")
+            self.send_response(code)
+            self.send_header("Content-Type", "application/json")
+            self.send_header("Content-Length", str(len(body)))
+            self.end_headers()
+            self.wfile.write(body)
+
+        def do_GET(self):
+            if self.path.rstrip("/") == "/status":
+                self._reply(200, {**ctl.snapshot(), **stats_fn()})
+            else:
+                self._reply(404, {"error": "not found"})
+
+        def do_POST(self):
+            if self.path.rstrip("/") != "/control":
+                self._reply(404, {"error": "not found"})
+                return
+            try:
+                n = int(self.headers.get("Content-Length") or 0)
+                req = json.loads(self.rfile.read(n) or b"{}")
+                res = ctl.set(threads=req.get("threads"), intensity=req.get("intensity"))
+            except Exception as e:
+                self._reply(400, {"error": str(e)})
+                return
+            print(f"[control] threads={res['threads']} intensity={res['intensity']}%")
+            self._reply(200, res)
+
+        def log_message(self, *args):
+            pass
+
+    srv = ThreadingHTTPServer(("127.0.0.1", port), Handler)
+    threading.Thread(target=srv.serve_forever, daemon=True).start()
+    print(f"Miner control API on http://127.0.0.1:{port} (GET /status, POST /control)")
+
+
+def _install_signal_controls(ctl: MinerControl):
+    import signal
+    if not hasattr(signal, "SIGUSR1"):
+        return
+
+    def _inc(*_):
+        print(f"[control] threads={ctl.set(threads=ctl.snapshot()['threads'] + 1)['threads']}")
+
+    def _dec(*_):
+        print(f"[control] threads={ctl.set(threads=ctl.snapshot()['threads'] - 1)['threads']}")
+
+    signal.signal(signal.SIGUSR1, _inc)
+    signal.signal(signal.SIGUSR2, _dec)
+
+
+def mine_client_side(miner_address: str, threads: int, slice_ms: int, poll_ms: int,
+                     max_threads: Optional[int] = None, intensity: int = 100, pin: bool = False,
+                     cpus: str = "", control_port: int = 0, pool: str = "", longpoll_sec: float = 30.0,
+                     metrics_port: int = 0, nice: int = 0, adaptive: bool = False):
+    # Workers are spawned up to max_threads once; the active count is changed at runtime via MinerControl.
+    # Nonce stride is max_threads so slots never overlap whatever the active count.
+    max_threads = max(1, max_threads or max(threads, os.cpu_count() or 1))
+    ctl = MinerControl(threads, max_threads, intensity)
+    # Priority first: worker threads inherit it
+    reniced = set_priority(nice)
+    print(f"Client miner starting: threads={ctl.threads}/{max_threads}, intensity={ctl.intensity}%, "
+          f"slice_ms={slice_ms}, poll_ms={poll_ms}, pin={pin}, nice={nice if reniced else 0}, adaptive={adaptive}")
+
+    # Job source: local node RPC (solo) or a remote Stratum pool (--pool host:port)
+    client = None
+    feed: Optional[WorkFeed] = None
+    fetch_work, submit, hash_fn = get_work, submit_work, (lambda b, n, _prev: pow_hash(b, n))
+    if not pool:
+        # Solo: follow the node's template (tip changes abandon current work)
+        feed = WorkFeed(miner_address, longpoll_sec)
+        feed.start()
+        fetch_work = feed.get
+    else:
+        from apps.miner.stratum_client import StratumClient
+        from core.pow.pow_backend import pow_hash as backend_pow_hash
+
+        host, _, port = pool.rpartition(":")
+        client = StratumClient(host or "127.0.0.1", int(port), miner_address)
+        client.start()
+        # Pools verify with the node's PoW backend and submit the prev hash with each share
+        fetch_work, submit, hash_fn = client.get_work, client.submit, backend_pow_hash
+        print(f"Mining via Stratum pool {host}:{port}")
     stop_evt = threading.Event()
-    stats_lock = threading.Lock()
-    last_hashes = [0 for _ in range(threads)]
-    accepted_total = 0
+    meter = HashrateMeter(max_threads, float(get_config().get("miner.hashrate_ema_sec", 30)))
+    cpu_list = _available_cpus(cpus) if pin else []
 
     def worker(tid: int):
-        nonlocal accepted_total
-        rng = 0
+        if cpu_list:
+            cpu = cpu_list[tid % len(cpu_list)]
+            if not _pin_current_thread(cpu):
+                print(f"[T{tid}] CPU pinning unsupported on this platform")
+        last_job_id, next_nonce = None, tid
         while not stop_evt.is_set():
+            if tid >= ctl.snapshot()["threads"]:
+                # parked: thread count lowered at runtime
+                time.sleep(0.2)
+                continue
             # Get or refresh work
-            w = get_work(miner_address)
+            gen = feed.generation if feed is not None else 0
+            w = fetch_work(miner_address)
             if not w:
                 time.sleep(max(0.1, poll_ms / 1000.0))
                 continue
 
-            mr, txids_used = build_merkle_root_for_job(w.height, w.txids)
-            target_int = int(w.target_hex, 16)
+            if client is not None:
+                # Pool txids already start with the coinbase
+                txids_used = list(w.txids)
+                mr = merkle_root_from_txids(txids_used)
+            else:
+                mr, txids_used = build_merkle_root_for_job(w.height, w.txids)
+            target_int = to_int(w.share_target_hex or w.target_hex)
             start = time.time()
             hashes = 0
-            # thread-specific stride: try nonces tid, tid+threads, tid+2*threads...
-            nonce = tid
+            # thread-specific stride: try nonces tid, tid+max_threads, tid+2*max_threads...
+            # (offset by the pool-assigned extranonce; resumed while the job is unchanged)
+            job_key = (w.job_id, client.extranonce_epoch if client is not None else 0)
+            if job_key != last_job_id:
+                last_job_id = job_key
+                next_nonce = (client.nonce_base() if client is not None else 0) + tid
+            nonce = next_nonce
             while (time.time() - start) * 1000.0 < slice_ms and not stop_evt.is_set():
+                if feed is not None and hashes % 256 == 0 and feed.stale(gen):
+                    break  # template changed (new tip / txs): drop this job
                 # Construct header JSON matching server format
                 hdr_bytes = header_serialize(
                     version=w.version,
@@ -182,58 +562,87 @@ def mine_client_side(miner_address: str, threads: int, slice_ms: int, poll_ms: i
                     miner_address=miner_address,
                     tx_count=len(txids_used),
                 )
-                h = pow_hash(hdr_bytes, nonce)
-                if int(h.hex(), 16) <= target_int:
-                    ok, res = submit_work(
+                h = hash_fn(hdr_bytes, nonce, w.prev_hash)
+                if hash_meets_target(h, target_int):
+                    kwargs = {"prev_hash_hex": w.prev_hash} if client is not None else {}
+                    ok, res = submit(
                         job_id=w.job_id,
                         miner_address=miner_address,
                         nonce=nonce,
                         version=w.version,
                         timestamp=w.timestamp,
                         merkle_root_hex=mr,
+                        **kwargs,
                     )
+                    if ok and client is not None:
+                        # share sent; keep hashing the same job
+                        hashes += 1
+                        nonce += max_threads
+                        continue
                     if ok:
-                        with stats_lock:
-                            accepted_total += 1
+                        meter.solution()
                         print(f"[T{tid}] ACCEPTED block {res} at nonce={nonce}")
                         # after acceptance, fetch new work
+                        if feed is not None:
+                            feed.refresh()
                         break
                     else:
                         # If stale, break slice and refresh work immediately
                         if res and ("stale" in res or "stale-prev" in res or "expired" in res):
                             print(f"[T{tid}] submit stale: {res}")
+                            if feed is not None:
+                                feed.refresh()
                             break
                         else:
                             print(f"[T{tid}] submit rejected: {res}")
                 hashes += 1
-                nonce += threads
-
-            with stats_lock:
-                last_hashes[tid] = hashes
-            # short pause before next slice
-            time.sleep(max(0.0, poll_ms / 1000.0))
+                nonce += max_threads
+            next_nonce = nonce
+
+            meter.add(tid, hashes, w.job_id)
+            # duty-cycle rest (intensity < 100%) plus short pause before next slice
+            time.sleep(ctl.rest_seconds(time.time() - start) + max(0.0, poll_ms / 1000.0))
+
+    def _stats() -> dict:
+        out = meter.snapshot(ctl.snapshot()["threads"])
+        out["accepted"] = out["solutions"]
+        if client is not None:
+            out.update(pool_connected=client.connected, shares_accepted=client.accepted,
+                       shares_rejected=client.rejected, reconnects=client.reconnects)
+        return out
+
+    if control_port:
+        _start_control_server(ctl, control_port, _stats)
+    if metrics_port:
+        metrics.start_http_server("127.0.0.1", metrics_port)
+        print(f"Miner metrics on http://127.0.0.1:{metrics_port}/metrics")
+    _install_signal_controls(ctl)
+    if adaptive:
+        idle = float(get_config().get("miner.adaptive_idle_pct", 20))
+        threading.Thread(target=_adaptive_throttle, args=(ctl, stop_evt, idle), name="throttle", daemon=True).start()
 
     # Launch workers
-    threads = max(1, threads)
     ths: List[threading.Thread] = []
-    for i in range(threads):
+    for i in range(max_threads):
         t = threading.Thread(target=worker, args=(i,), daemon=True)
         t.start()
         ths.append(t)
 
     try:
-        last_t = time.time()
         while True:
             time.sleep(2.0)
-            with stats_lock:
-                total_h = sum(last_hashes)
-                print(f"Hashrate ~ {total_h/2.0:.0f} H/s | accepted={accepted_total} | per-thread={last_hashes}")
-                # reset counters for next interval measurement
-                for i in range(len(last_hashes)):
-                    last_hashes[i] = 0
+            meter.sample()
+            st = meter.snapshot(ctl.threads)
+            shares = f"shares={client.accepted}/{client.accepted + client.rejected} | " if client is not None else ""
+            print(f"Hashrate ~ {st['hashrate']:.0f} H/s (now {st['hashrate_instant']:.0f}) | accepted={st['solutions']} | {shares}"
+                  f"threads={ctl.threads}/{max_threads} intensity={ctl.intensity}% | per-thread={st['per_thread_hashrate']}")
     except KeyboardInterrupt:
         print("Stopping miner...")
         stop_evt.set()
+        if client is not None:
+            client.stop()
+        if feed is not None:
+            feed.stop()
         for t in ths:
             t.join(timeout=1.0)
 
@@ -243,12 +652,32 @@ def main():
     parser.add_argument("--miner-address", type=str, default="SMELLY_SOLO")
     parser.add_argument("--mode", type=str, choices=["client", "legacy"], default="client",
                         help="client: mines locally using get_work/submit_work; legacy: calls /rpc/mine_one")
-    parser.add_argument("--threads", type=int, default=os.cpu_count() or 4)
+    parser.add_argument("--threads", type=int, default=None,
+                        help="worker threads (default: all CPUs minus --reserve-cores)")
+    parser.add_argument("--reserve-cores", type=int, default=int(get_config().get("miner.reserve_cores", 1)),
+                        help="CPUs left free for the node and desktop when --threads is not given")
+    parser.add_argument("--nice", type=int, default=int(get_config().get("miner.nice", 10)),
+                        help="lower scheduling priority by this much (POSIX nice; Windows: below normal); 0 = unchanged")
+    parser.add_argument("--adaptive", action="store_true", default=bool(get_config().get("miner.adaptive_throttle", False)),
+                        help="back off the duty cycle while other processes need the CPU")
+    parser.add_argument("--max-threads", type=int, default=None, help="upper bound for runtime thread changes (default: max(threads, cpu count))")
+    parser.add_argument("--intensity", type=int, default=int(get_config().get("miner.intensity", 100)),
+                        help="duty cycle percent (1-100); lower keeps the machine responsive")
+    parser.add_argument("--pin", action="store_true", help="pin each worker thread to one CPU (Linux)")
+    parser.add_argument("--cpus", type=str, default="", help="comma-separated CPU ids for --pin (default: all allowed)")
+    parser.add_argument("--control-port", type=int, default=0, help="serve a localhost control API on this port (0 = off)")
+    parser.add_argument("--metrics-port", type=int, default=0, help="serve Prometheus /metrics on localhost (0 = off)")
     parser.add_argument("--slice-ms", type=int, default=250, help="time slice per work attempt per thread")
     parser.add_argument("--poll-ms", type=int, default=200, help="sleep between work polls")
+    parser.add_argument("--longpoll-sec", type=float, default=30.0, help="client solo: get_block_template long-poll timeout")
     parser.add_argument("--loop", action="store_true", help="legacy only: continuously call mine_one()")
+    parser.add_argument("--pool", type=str, default="", help="client only: mine shares for a Stratum pool at host:port instead of the local node")
     args = parser.parse_args()
 
+    from core.crypto import check_address
+    addr_err = check_address(args.miner_address)
+    if addr_err:
+        parser.error(f"--miner-address: {addr_err}")
     print("Solo miner using RPC:", rpc_url())
     if args.mode == "legacy":
         if args.loop:
@@ -273,7 +702,11 @@ def main():
             except Exception as e:
                 print("Mine error:", e)
     else:
-        mine_client_side(args.miner_address, threads=args.threads, slice_ms=args.slice_ms, poll_ms=args.poll_ms)
+        threads = args.threads if args.threads is not None else default_threads(args.reserve_cores)
+        mine_client_side(args.miner_address, threads=threads, slice_ms=args.slice_ms, poll_ms=args.poll_ms,
+                         max_threads=args.max_threads, intensity=args.intensity, pin=args.pin, cpus=args.cpus,
+                         control_port=args.control_port, pool=args.pool, longpoll_sec=args.longpoll_sec,
+                         metrics_port=args.metrics_port, nice=args.nice, adaptive=args.adaptive)
 
 
 if __name__ == "__main__":
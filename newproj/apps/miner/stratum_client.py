@@ -0,0 +1,201 @@
+from __future__ import annotations
+
+import json
+import random
+import socket
+import threading
+import time
+from typing import Optional, Tuple
+
+from .solo_miner import Work
+
+
+class StratumClient:
+    """
+    Job source for the built-in CPU miner backed by a remote SMELLY Stratum pool (JSON lines).
+
+    - subscribe / authorize / get_job on connect; mining.notify replaces the current job
+    - extranonce: the subscribe result may carry [session, extranonce1_hex, extranonce2_size];
+      nonces are then extranonce1 || extranonce2 (extranonce2_size bytes) so sessions sharing a
+      payout address do not hash the same nonce range
+    - reconnects with exponential backoff (plus jitter) and drops the job while disconnected
+    """
+
+    SUBMIT_ID_BASE = 1_000_000
+
+    def __init__(self, host: str, port: int, address: str, backoff_max_sec: float = 30.0, password: str = ""):
+        self.host = host
+        self.port = port
+        self.address = address  # "address" or "address.worker"
+        self.password = password
+        self.backoff_max_sec = backoff_max_sec
+        self.lock = threading.Lock()
+        self._send_lock = threading.Lock()
+        self._sock: Optional[socket.socket] = None
+        self._file = None
+        self._stop = threading.Event()
+        self._job: Optional[Work] = None
+        self._submit_id = self.SUBMIT_ID_BASE
+        self.connected = False
+        self.extranonce1 = 0
+        self.extranonce2_size = 8
+        self.extranonce_epoch = 0  # bumped on mining.set_extranonce so miners restart their nonce range
+        self.accepted = 0
+        self.rejected = 0
+        self.reconnects = 0
+
+    # ----- lifecycle -----
+
+    def start(self):
+        threading.Thread(target=self._run, name="stratum-client", daemon=True).start()
+
+    def stop(self):
+        self._stop.set()
+        self._close()
+
+    def _run(self):
+        backoff = 1.0
+        while not self._stop.is_set():
+            try:
+                self._connect()
+                backoff = 1.0
+                self._read_loop()
+            except OSError as e:
+                print(f"[stratum] connection to {self.host}:{self.port} failed: {e}")
+            finally:
+                self._close()
+            if self._stop.is_set():
+                break
+            delay = backoff + random.uniform(0, backoff / 2)
+            print(f"[stratum] disconnected; reconnecting in {delay:.1f}s")
+            self._stop.wait(delay)
+            backoff = min(self.backoff_max_sec, backoff * 2)
+            self.reconnects += 1
+
+    def _connect(self):
+        sock = socket.create_connection((self.host, self.port), timeout=10)
+        sock.settimeout(None)
+        self._sock = sock
+        self._file = sock.makefile(mode="rwb")
+        self.connected = True
+        print(f"[stratum] connected to {self.host}:{self.port}")
+        self._send({"id": 1, "method": "mining.subscribe", "params": ["smelly-cpu-miner"]})
+        self._send({"id": 4, "method": "mining.extranonce.subscribe", "params": []})
+        self._send({"id": 2, "method": "mining.authorize", "params": [self.address, self.password]})
+        self._send({"id": 3, "method": "mining.get_job", "params": []})
+
+    def _close(self):
+        self.connected = False
+        with self.lock:
+            self._job = None
+        for obj in (self._file, self._sock):
+            try:
+                if obj is not None:
+                    obj.close()
+            except Exception:
+                pass
+        self._file = None
+        self._sock = None
+
+    def _send(self, obj: dict):
+        data = (json.dumps(obj) + "\n").encode("utf-8")
+        with self._send_lock:
+            if self._file is None:
+                raise OSError("not connected")
+            self._file.write(data)
+            self._file.flush()
+
+    def _read_loop(self):
+        while not self._stop.is_set():
+            line = self._file.readline()
+            if not line:
+                return
+            try:
+                msg = json.loads(line.decode("utf-8").strip())
+            except ValueError:
+                continue
+            self._process_msg(msg)
+
+    # ----- protocol -----
+
+    def _process_msg(self, msg: dict):
+        if msg.get("method") == "mining.notify":
+            self._set_job(msg.get("params") or {})
+            return
+        if msg.get("method") == "mining.set_extranonce":
+            self._set_extranonce(msg.get("params") or [])
+            return
+        if msg.get("method") == "mining.ping":
+            self._send({"id": msg.get("id"), "result": "pong", "error": None})
+            return
+        mid = msg.get("id")
+        if mid in (0, 1) and isinstance(msg.get("result"), list):
+            # subscribe result: [session, extranonce1_hex?, extranonce2_size?]
+            res = msg["result"]
+            if len(res) >= 3:
+                self._set_extranonce(res[1:3])
+            return
+        if mid == 2 and msg.get("error"):
+            print(f"[stratum] authorize failed: {msg.get('error')}")
+            return
+        if mid == 3 and isinstance(msg.get("result"), dict):
+            self._set_job(msg["result"])
+            return
+        if isinstance(mid, int) and mid >= self.SUBMIT_ID_BASE:
+            if msg.get("error") or msg.get("result") is False:
+                self.rejected += 1
+                print(f"[stratum] share rejected: {msg.get('error')}")
+            else:
+                self.accepted += 1
+
+    def _set_extranonce(self, params: list):
+        try:
+            e1 = int(str(params[0]), 16)
+            size = max(1, min(8, int(params[1])))
+        except (IndexError, TypeError, ValueError):
+            e1, size = 0, 8
+        with self.lock:
+            if (e1, size) != (self.extranonce1, self.extranonce2_size):
+                self.extranonce1, self.extranonce2_size = e1, size
+                self.extranonce_epoch += 1
+
+    def _set_job(self, params: dict):
+        tmpl = params.get("template") or {}
+        if not params.get("job_id"):
+            return
+        job = Work(
+            job_id=str(params.get("job_id")),
+            height=-1,
+            prev_hash=str(tmpl.get("prev_hash") or "").lower(),
+            target_hex=str(tmpl.get("target") or "").lower(),
+            version=int(tmpl.get("version", 1)),
+            timestamp=int(tmpl.get("timestamp", int(time.time()))),
+            txids=[str(t).lower() for t in (tmpl.get("txids") or [])],  # coinbase first, pool order
+            miner_hint=self.address.split(".", 1)[0],  # header commits to the payout address, not the worker
+            share_target_hex=str(params.get("pool_target") or tmpl.get("target") or "").lower(),
+        )
+        with self.lock:
+            self._job = job
+
+    # ----- miner-facing API -----
+
+    def get_work(self, _miner_address: Optional[str] = None) -> Optional[Work]:
+        with self.lock:
+            return self._job
+
+    def nonce_base(self) -> int:
+        return (self.extranonce1 << (8 * self.extranonce2_size)) & ((1 << 64) - 1)
+
+    def submit(self, job_id: str, miner_address: str, nonce: int, version: int, timestamp: int,
+               merkle_root_hex: str, prev_hash_hex: str = "") -> Tuple[bool, Optional[str]]:
+        """Fire-and-forget share submit; the pool's verdict is counted in accepted/rejected."""
+        with self.lock:
+            self._submit_id += 1
+            sid = self._submit_id
+        try:
+            self._send({"id": sid, "method": "mining.submit",
+                        "params": [miner_address, job_id, int(nonce), int(timestamp), merkle_root_hex,
+                                   int(version), prev_hash_hex]})
+        except OSError as e:
+            return False, f"stale: {e}"
+        return True, "submitted"
@@ -4,10 +4,14 @@ import time
 import requests
 import socket
 import json
-from typing import Dict, Set, Tuple, List
+import sys
+from typing import Dict, Optional, Set, Tuple, List
 
 from core.rpc import run_rpc_server
-from core.config import get_config
+from core import addrman
+from core import banman
+from core import checkpoints
+from core.config import get_config, load_config
 from core.utils import ensure_dirs, now_ms
 from core.db import get_db, BlockHeader, MempoolTx, Transaction
 from core.consensus import (
@@ -15,30 +19,314 @@ from core.consensus import (
     get_chain_height,
     get_headers_range,
     accept_external_header,
+    target_within_retarget,
     Header,
 )
-from core.pow.randomx_stub import difficulty_to_target
+from core.target import difficulty_to_target, hash_meets_target
+from core.crypto import tx_digest_hex
+from core.pow.pow_backend import pow_hash
+from core import safemode
+from core import compact
+from core import blockfilter
+from core import blocksync
+from core import metrics
+from core import txrelay
+from core import headeronly
+from core import nettime
+from core import eviction
+from core import rpcauth
+from core import confirmations
+from core import netcompress
+from core import netlocal
+from core import portmap
+from core import p2pstate
+from core import logs
+from core.blockstore import read_block
 
 
 # ----------------- P2P (JSON line protocol: VERSION/VERACK, INV, GETDATA, BLOCKHDR, TX, PING/PONG) -----------------
 
+class NetCounters:
+    """
+    Bytes and per-message-type counts in each direction (one per peer plus a global total).
+    bytes_* are wire bytes; raw_bytes_* the same messages before compression (core.netcompress).
+    """
+
+    def __init__(self):
+        self.bytes_sent = 0
+        self.bytes_recv = 0
+        self.raw_bytes_sent = 0
+        self.raw_bytes_recv = 0
+        self.msgs_sent: Dict[str, int] = {}
+        self.msgs_recv: Dict[str, int] = {}
+        self.bytes_sent_per_msg: Dict[str, int] = {}
+        self.bytes_recv_per_msg: Dict[str, int] = {}
+
+    def add(self, direction: str, mtype: str, nbytes: int, raw_bytes: int):
+        if direction == "sent":
+            self.bytes_sent += nbytes
+            self.raw_bytes_sent += raw_bytes
+            self.msgs_sent[mtype] = self.msgs_sent.get(mtype, 0) + 1
+            self.bytes_sent_per_msg[mtype] = self.bytes_sent_per_msg.get(mtype, 0) + nbytes
+        else:
+            self.bytes_recv += nbytes
+            self.raw_bytes_recv += raw_bytes
+            self.msgs_recv[mtype] = self.msgs_recv.get(mtype, 0) + 1
+            self.bytes_recv_per_msg[mtype] = self.bytes_recv_per_msg.get(mtype, 0) + nbytes
+
+    def to_dict(self) -> dict:
+        return {
+            "bytes_sent": self.bytes_sent,
+            "bytes_recv": self.bytes_recv,
+            "raw_bytes_sent": self.raw_bytes_sent,
+            "raw_bytes_recv": self.raw_bytes_recv,
+            "msgs_sent": dict(self.msgs_sent),
+            "msgs_recv": dict(self.msgs_recv),
+            "bytes_sent_per_msg": dict(self.bytes_sent_per_msg),
+            "bytes_recv_per_msg": dict(self.bytes_recv_per_msg),
+        }
+
+
 class PeerState:
-    def __init__(self, addr: str, fp):
+    def __init__(self, addr: str, fp, inbound: bool = True):
         self.addr = addr
         self.fp = fp
+        self.inbound = inbound
+        self.connected_ms = now_ms()
         self.last_seen = now_ms()
+        self.net = NetCounters()
+        self.getaddr_served = False
+        self.compact = False  # peer advertised compact block relay in VERSION
+        self.compress: Optional[str] = None  # codec negotiated from the peer's VERSION (core.netcompress)
+        self.pending_cmpct: Dict[str, Tuple[dict, list]] = {}  # block hash -> (hdr, partial txid slots), oldest first
+        # txids this peer announced, sent us, or was sent: never INV these back
+        self.known_tx = txrelay.RecentSet(int(get_config().get("network.known_inv_cache", 5000)))
+        self.last_ping_ms = 0
+        self.sock: Optional[socket.socket] = None
+        self.rtt_ms: Optional[float] = None
+        self.last_block_ms = 0  # last block this peer delivered that we connected (eviction protection)
+        self.last_tx_ms = 0  # last tx from this peer we admitted to the mempool (eviction protection)
+        self.handshake_done = False  # peer's VERSION received
 
 
 _seen_hdr: Set[str] = set()
-_seen_tx: Set[str] = set()
-_peers: Dict[str, PeerState] = {}  # addr -> state
-_peers_lock = threading.Lock()
+_seen_tx = txrelay.RecentSet(50000)  # accepted or rejected relayed txids
+_tx_requested: Dict[str, int] = {}  # txid -> ms of GETDATA (one peer at a time per txid)
+_TX_REQUEST_TIMEOUT_MS = 60_000
+_MAX_PENDING_CMPCT = 8  # compact blocks per peer waiting for BLOCKTXN
+# Relayed tx rejections that cannot be explained by races (double spend, maturity, fee policy)
+_PUNISH_TX_REASONS = {"bad-format", "bad-version", "missing-io", "bad-input", "bad-input-ref",
+                      "bad-output", "bad-output-amt", "missing-sig", "bad-signature", "insufficient-input",
+                      "bad-input-address"}
+# validate_header reasons returned before the PoW check (core.consensus)
+_PRE_POW_HEADER_REASONS = {"invalid version", "prev link mismatch", "timestamp decreased", "timestamp too far in future"}
+_seen_forks = txrelay.RecentSet(4096)  # stale headers already checked for fork history (re-announced every 5s)
+_peers: Dict[str, PeerState] = p2pstate.peers  # addr -> state (shared with core.rpc)
+_peers_lock = p2pstate.peers_lock
+net_log = logs.get("net")
+sync_log = logs.get("sync")
+
+# Traffic accounting. Separate lock: _p2p_send is called while _peers_lock is held by broadcasters.
+_net_totals = NetCounters()
+_net_lock = threading.Lock()
+_net_started_ms = now_ms()
+_fp_peer: Dict[int, PeerState] = {}  # id(fp) -> state
+
+_P2P_PEERS = metrics.gauge("smelly_p2p_peers", "Connected P2P peers", ("direction",))
+_P2P_BYTES = metrics.counter("smelly_p2p_bytes_total", "P2P bytes by direction", ("direction",))
+_P2P_MSGS = metrics.counter("smelly_p2p_messages_total", "P2P messages by direction and type", ("direction", "type"))
+_P2P_MSG_BYTES = metrics.counter("smelly_p2p_message_bytes_total", "P2P wire bytes by direction and message type",
+                                 ("direction", "type"))
+_P2P_INBOUND_FULL = metrics.counter("smelly_p2p_inbound_full_total",
+                                    "Inbound connections arriving at max_inbound_connections", ("outcome",))
+
+# Message types we speak; anything else a peer sends is counted under "*other*" so a peer cannot
+# grow the per-type counters (and metric label sets) without bound.
+_MSG_TYPES = frozenset((
+    "VERSION", "VERACK", "ERR", "GETADDR", "ADDR", "PING", "PONG", "INV", "GETDATA", "TX", "BLOCKHDR",
+    "GETBLOCKS", "BLOCKS", "CMPCTBLOCK", "GETBLOCKTXN", "BLOCKTXN", "GETCFILTERS", "CFILTER",
+    "GETCFHEADERS", "CFHEADERS", "<invalid>",
+))
+
+
+def _collect_peer_metrics():
+    with _peers_lock:
+        inbound = sum(1 for ps in _peers.values() if ps.inbound)
+        total = len(_peers)
+    _P2P_PEERS.set(inbound, ("inbound",))
+    _P2P_PEERS.set(total - inbound, ("outbound",))
+
+
+metrics.register_collector(_collect_peer_metrics)
+
+
+def _register_peer(ps: PeerState):
+    with _peers_lock:
+        _peers[ps.addr] = ps
+    with _net_lock:
+        _fp_peer[id(ps.fp)] = ps
+
+
+def _unregister_peer(addr: str, fp):
+    with _peers_lock:
+        _peers.pop(addr, None)
+    _sync.peer_gone(addr)
+    with _net_lock:
+        _fp_peer.pop(id(fp), None)
+
+
+def _disconnect(addr: str, reason: str):
+    """Close a peer's socket; its reader loop then unregisters it."""
+    with _peers_lock:
+        ps = _peers.get(addr)
+    if ps is None or ps.sock is None:
+        return
+    net_log.info(f"disconnecting peer: {reason}", extra=logs.fields(peer=addr))
+    try:
+        ps.sock.shutdown(socket.SHUT_RDWR)
+    except OSError:
+        pass
+
+
+def _candidate(ps: PeerState) -> eviction.Candidate:
+    try:
+        group = netlocal.netgroup(netlocal.split_host_port(ps.addr)[0])
+    except ValueError:
+        group = ""
+    return eviction.Candidate(addr=ps.addr, group=group, connected_ms=ps.connected_ms, rtt_ms=ps.rtt_ms,
+                              last_block_ms=ps.last_block_ms, last_tx_ms=ps.last_tx_ms)
+
+
+def _make_inbound_room() -> bool:
+    """At network.max_inbound_connections evict one inbound peer (core.eviction); False = refuse the newcomer."""
+    limit = int(get_config().get("network.max_inbound_connections", 117))
+    with _peers_lock:
+        inbound = [ps for ps in _peers.values() if ps.inbound]
+    if len(inbound) < limit:
+        return True
+    victim = eviction.inbound_victim([_candidate(ps) for ps in inbound])
+    if victim is None:
+        _P2P_INBOUND_FULL.inc(labels=("refused",))
+        return False
+    _P2P_INBOUND_FULL.inc(labels=("evicted",))
+    _disconnect(victim, "evicted for a new inbound connection")
+    return True
+
+
+def _net_count(fp, direction: str, mtype: str, nbytes: int, raw_bytes: Optional[int] = None):
+    raw_bytes = nbytes if raw_bytes is None else raw_bytes
+    if mtype not in _MSG_TYPES:
+        mtype = "*other*"
+    _P2P_BYTES.inc(nbytes, (direction,))
+    _P2P_MSGS.inc(labels=(direction, mtype))
+    _P2P_MSG_BYTES.inc(nbytes, (direction, mtype))
+    with _net_lock:
+        _net_totals.add(direction, mtype, nbytes, raw_bytes)
+        ps = _fp_peer.get(id(fp))
+        if ps is not None:
+            ps.net.add(direction, mtype, nbytes, raw_bytes)
+
+
+def net_totals_snapshot() -> dict:
+    """Aggregate and per-peer traffic counters, bytes and counts per message type (served by /rpc/getnettotals)."""
+    nowm = now_ms()
+    with _net_lock:
+        totals = _net_totals.to_dict()
+        peers = [
+            {
+                "addr": ps.addr,
+                "inbound": ps.inbound,
+                "conn_ms": max(0, nowm - ps.connected_ms),
+                **ps.net.to_dict(),
+            }
+            for ps in _fp_peer.values()
+        ]
+    return {
+        "totalbytesrecv": totals["bytes_recv"],
+        "totalbytessent": totals["bytes_sent"],
+        "timemillis": nowm,
+        "uptime_ms": max(0, nowm - _net_started_ms),
+        "msgs_sent": totals["msgs_sent"],
+        "msgs_recv": totals["msgs_recv"],
+        "bytes_sent_per_msg": totals["bytes_sent_per_msg"],
+        "bytes_recv_per_msg": totals["bytes_recv_per_msg"],
+        "compression": {
+            "codecs": netcompress.supported(),
+            "raw_bytes_sent": totals["raw_bytes_sent"],
+            "raw_bytes_recv": totals["raw_bytes_recv"],
+            "saved_bytes": (totals["raw_bytes_sent"] + totals["raw_bytes_recv"]) - (totals["bytes_sent"] + totals["bytes_recv"]),
+        },
+        "peers": sorted(peers, key=lambda p: p["bytes_sent"] + p["bytes_recv"], reverse=True),
+    }
+
+
+def network_info_snapshot() -> dict:
+    """getnetworkinfo: connection counts, bound listeners, reachable networks and local addresses."""
+    with _peers_lock:
+        inbound = sum(1 for ps in _peers.values() if ps.inbound)
+        total = len(_peers)
+    return {
+        "connections": total,
+        "connections_in": inbound,
+        "connections_out": total - inbound,
+        "networkactive": True,
+        "listening": bool(netlocal.bound()),
+        "bound": netlocal.bound(),
+        "networks": netlocal.networks(),
+        "localaddresses": netlocal.local_addresses(),
+        "compression": netcompress.supported(),
+        "portmap": portmap.status(),
+        **nettime.info(),
+    }
+
+
+def peer_info_snapshot() -> List[dict]:
+    """Per-peer connection and traffic details in Bitcoin Core's getpeerinfo shape."""
+    from core import banman as _banman
+
+    sync_peers = {p["addr"]: p for p in _sync.snapshot()["peers"]}
+    with _peers_lock:
+        peers = list(_peers.values())
+    out = []
+    with _net_lock:
+        for n, ps in enumerate(sorted(peers, key=lambda p: p.connected_ms)):
+            net = ps.net
+            sp = sync_peers.get(ps.addr) or {}
+            out.append({
+                "id": n,
+                "addr": ps.addr,
+                "inbound": ps.inbound,
+                "conntime": ps.connected_ms // 1000,
+                "lastrecv": ps.last_seen // 1000,
+                "bytessent": net.bytes_sent,
+                "bytesrecv": net.bytes_recv,
+                "bytessent_per_msg": dict(net.bytes_sent_per_msg),
+                "bytesrecv_per_msg": dict(net.bytes_recv_per_msg),
+                "compression": ps.compress,
+                "compression_saved_bytes": (net.raw_bytes_sent + net.raw_bytes_recv) - (net.bytes_sent + net.bytes_recv),
+                "cmpct": ps.compact,
+                "pingtime": (sp["rtt_ms"] / 1000.0) if sp.get("rtt_ms") is not None else None,
+                "synced_height": sp.get("height", -1),
+                "banscore": _banman.score_of(ps.addr),
+                "last_block": ps.last_block_ms // 1000,
+                "last_transaction": ps.last_tx_ms // 1000,
+            })
+    return out
 
 
 def _p2p_send(fp, obj: dict):
     try:
-        fp.write((json.dumps(obj) + "\n").encode("utf-8"))
+        payload = json.dumps(obj).encode("utf-8")
+        with _net_lock:
+            ps = _fp_peer.get(id(fp))
+            codec = ps.compress if ps is not None else None
+        if codec and len(payload) >= netcompress.min_bytes():
+            data = netcompress.encode(codec, payload)
+        else:
+            data = payload + b"\n"
+        fp.write(data)
         fp.flush()
+        _net_count(fp, "sent", str(obj.get("type") or "?"), len(data), len(payload) + 1)
     except Exception:
         pass
 
@@ -50,44 +338,362 @@ def _announce_tip_to_peers():
         tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
         if not tip:
             return
-        inv = {"type": "INV", "items": [{"kind": "hdr", "hash": tip.hash_hex}]}
+        inv = {"type": "INV", "items": [{"kind": "hdr", "hash": tip.hash_hex, "height": tip.height}]}
     with _peers_lock:
         for ps in list(_peers.values()):
             _p2p_send(ps.fp, inv)
 
 
-def _broadcast_txinv(txid: str):
+# ----- parallel block download (core.blocksync) -----
+
+_MAX_GETBLOCKS = 500
+_sync_connect_lock = threading.Lock()
+
+
+def _send_getblocks(addr: str, start: int, count: int) -> bool:
+    with _peers_lock:
+        ps = _peers.get(addr)
+        if ps is None:
+            return False
+        _p2p_send(ps.fp, {"type": "GETBLOCKS", "start_height": start, "count": count})
+    return True
+
+
+_sync = blocksync.DownloadScheduler(_send_getblocks)
+
+
+def _header_item(h: BlockHeader) -> dict:
+    """BLOCKHDR item: header fields for accept_external_header plus the merkle txid ordering
+    from the flat-file store (empty if the block predates it)."""
+    rec = read_block(h.hash_hex)
+    return {
+        "prev": h.prev_hash_hex,
+        "merkle": h.merkle_root_hex,
+        "ver": h.version,
+        "ts": h.timestamp,
+        "target": h.target,
+        "nonce": int(h.nonce),
+        "miner": h.miner_address,
+        "hash": h.hash_hex,
+        "height": h.height,
+        "txids": rec.txids if rec else [],
+    }
+
+
+def _connect_downloaded():
+    """Connect buffered downloaded blocks in height order; one thread at a time."""
+    connected = 0
+    with _sync_connect_lock:
+        while True:
+            tip = get_chain_height()
+            ready = _sync.next_ready(tip)
+            if ready is None:
+                break
+            peer_addr, item = ready
+            # Bodies travel with the block so consensus can apply them (same as BLOCKTXN)
+            for t in item.get("txs") or []:
+                if isinstance(t, dict):
+                    _admit_block_tx(peer_addr, t)
+            if not _accept_relayed_header(peer_addr, item, relay=False):
+                _sync.discard_above(tip)
+                break
+            connected += 1
+    if connected:
+        _announce_tip_to_peers()
+        _retry_orphans()
+
+
+def _sync_loop():
+    while True:
+        try:
+            tip = get_chain_height()
+            if _sync.best_peer_height() > tip:
+                _sync.tick(tip)
+                _connect_downloaded()
+        except Exception as e:
+            sync_log.exception(f"block sync error: {e}")
+        time.sleep(0.5)
+
+
+def sync_status() -> dict:
+    return dict(_sync.snapshot(), tip_height=get_chain_height(), best_peer_height=_sync.best_peer_height())
+
+
+def _broadcast_txinv(txid: str, source_peer: Optional[str] = None):
+    """INV a mempool tx to every peer not already known to have it (registered as txrelay announcer)."""
+    _seen_tx.add(txid)
     inv = {"type": "INV", "items": [{"kind": "tx", "txid": txid}]}
     with _peers_lock:
         for ps in list(_peers.values()):
+            if ps.addr == source_peer or txid in ps.known_tx:
+                continue
+            ps.known_tx.add(txid)
             _p2p_send(ps.fp, inv)
 
 
-def _serve_peer(sock: socket.socket, peer_addr: str):
+def _retry_orphans():
+    txrelay.remove_block_conflicts()
+    for txid in txrelay.process_orphans():
+        logs.get("mempool").info("orphan tx accepted", extra=logs.fields(txid=txid))
+
+
+def _note_header_anomaly(peer_addr: str, err: str, prev: str, merkle: str, ver: int, ts: int,
+                         tgt: str, nonce: int, miner: str, txids_snap: list):
+    """Feed rejected relayed headers into safe-mode detection (core.safemode)."""
+    try:
+        db = get_db()
+        with db.session() as s:
+            tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+            tip_height = tip.height if tip else -1
+            tip_target = tip.target if tip else None
+            parent = s.query(BlockHeader).filter_by(hash_hex=(prev or "").lower()).first()
+            parent_height = parent.height if parent else None
+        hdr = Header(version=ver, prev_hash_hex=(prev or "").lower(), merkle_root_hex=(merkle or "").lower(), timestamp=ts,
+                     target=(tgt or "").lower(), nonce=nonce, miner_address=miner, tx_count=max(1, len(txids_snap)))
+        if err.startswith("header-invalid: pow"):
+            # Costs nothing to make; _accept_relayed_header already punishes the sender
+            return
+        if err.startswith("header-invalid") or err.startswith("merkle-mismatch"):
+            # Only work that was really done counts; validate_header rejects some headers before
+            # hashing them, so those are hashed here
+            reason = err.split(": ", 1)[-1]
+            if err.startswith("header-invalid") and reason in _PRE_POW_HEADER_REASONS:
+                if not hash_meets_target(pow_hash(hdr.serialize(), nonce, hdr.prev_hash_hex), hdr.target):
+                    safemode.note_pow_failure(peer_addr, hdr.hash_hex())
+                    return
+            safemode.note_invalid_header(peer_addr, hdr.hash_hex(), hdr.target, tip_target)
+        elif err == "stale-prev" and parent_height is not None and checkpoints.fork_below_checkpoint(parent_height):
+            # Rewrites history before the last checkpoint: never a real fork candidate
+            banman.punish(peer_addr, 20, "fork below checkpoint")
+        elif err == "stale-prev" and parent_height is not None:
+            # Fork history feeds confirmation safety scoring (core.confirmations) and safe mode, so
+            # a side-chain header only counts once it carries real work at the retarget's difficulty.
+            # Each header is checked once (peers re-announce their tips every 5s).
+            fork_key = hdr.hash_hex()
+            depth = tip_height - parent_height
+            if depth < 1 or fork_key in _seen_forks:
+                return
+            _seen_forks.add(fork_key)
+            with db.session() as s:
+                parent = s.query(BlockHeader).filter_by(hash_hex=hdr.prev_hash_hex).first()
+                target_ok = parent is not None and target_within_retarget(s, parent, hdr.target)
+            if not target_ok:
+                banman.punish(peer_addr, 20, "fork header target easier than the retarget")
+                return
+            if not hash_meets_target(pow_hash(hdr.serialize(), nonce, hdr.prev_hash_hex), hdr.target):
+                safemode.note_pow_failure(peer_addr, fork_key)
+                return
+            confirmations.record_fork(depth, peer_addr, fork_key, parent_height + 1)
+            if depth > int(get_config().get("safe_mode.max_fork_depth", 6)):
+                safemode.note_fork(peer_addr, parent_height, tip_height)
+    except Exception as e:
+        logs.get("consensus").warning(f"safe-mode check error: {e}")
+
+
+def _compact_enabled() -> bool:
+    return bool(get_config().get("network.compact_blocks", True))
+
+
+def _accept_relayed_header(peer_addr: str, h: dict, relay: bool = True) -> bool:
+    """
+    Validate and connect one relayed header (BLOCKHDR item, reconstructed compact block or
+    downloaded block). relay=False skips the per-block tip announcement (batch sync does it once).
+    """
+    try:
+        prev = h.get("prev")
+        merkle = h.get("merkle")
+        ver = int(h.get("ver", 1))
+        ts = int(h.get("ts", int(time.time())))
+        tgt = h.get("target")
+        nonce = int(h.get("nonce", 0))
+        miner = h.get("miner") or "SMELLY_PEER"
+        txids_snap = h.get("txids") or []
+    except Exception:
+        banman.punish(peer_addr, 10, "malformed header item")
+        return False
+
+    # Attempt accept; will reject stale-prev, mismatch, etc.
+    with logs.span(sync_log, "block", peer=peer_addr, prev=str(prev)[:16]):
+        hh, err = accept_external_header(
+            prev_hash_hex=prev,
+            merkle_root_hex=merkle,
+            version=ver,
+            timestamp=ts,
+            target_hex=tgt,
+            nonce=nonce,
+            miner_address=miner,
+            txids_snapshot=txids_snap,
+        )
+        if hh:
+            sync_log.info("block connected", extra=logs.fields(hash=hh, txs=len(txids_snap)))
+        elif err:
+            sync_log.debug(f"block rejected: {err}")
+    if hh:
+        _seen_hdr.add(hh.strip().lower())
+        with _peers_lock:
+            ps = _peers.get(peer_addr)
+            if ps is not None:
+                ps.last_block_ms = now_ms()
+        if relay:
+            # Re-announce header to other peers
+            _announce_tip_to_peers()
+            # New outputs may complete orphan transactions
+            _retry_orphans()
+        return True
+    if err:
+        _note_header_anomaly(peer_addr, err, prev, merkle, ver, ts, tgt, nonce, miner, txids_snap)
+        if err.startswith("header-invalid") and "pow" in err:
+            banman.punish(peer_addr, 100, "header fails PoW")
+        elif err.startswith("header-invalid") or err.startswith("merkle-mismatch"):
+            banman.punish(peer_addr, 20, err.split(":", 1)[0])
+        elif err.startswith("bad-diffbits"):
+            banman.punish(peer_addr, 20, "header target easier than the retarget")
+    return False
+
+
+def _block_txns(txids: List[str], indexes: list) -> List[dict]:
+    """Transactions at the requested merkle positions (GETBLOCKTXN), from confirmed or mempool rows."""
+    out: List[dict] = []
+    db = get_db()
+    with db.session() as s:
+        for raw_i in indexes[:len(txids)]:
+            try:
+                i = int(raw_i)
+            except (TypeError, ValueError):
+                continue
+            if not (0 <= i < len(txids)):
+                continue
+            txid = txids[i]
+            m = s.query(MempoolTx).filter_by(txid=txid).first()
+            t = None if m else s.query(Transaction).filter_by(txid=txid).first()
+            item = {"i": i, "txid": txid}
+            if m:
+                item.update(raw=m.raw, fee=m.fee)
+            elif t:
+                item.update(raw=t.raw, fee=t.fee)
+            out.append(item)
+    return out
+
+
+def _admit_block_tx(peer_addr: str, t: dict) -> Optional[str]:
+    """
+    Admit a tx body sent with a block (BLOCKTXN, BLOCKS) through txrelay like any relayed TX, so
+    accept_external_header can include it. Returns the txid when it matches the body, else None.
+    """
+    txid = str(t.get("txid") or "").strip().lower()
+    tx = txrelay.parse_raw(t.get("raw"))
+    if not txid or not tx:
+        return None
+    if tx_digest_hex(tx) != txid:
+        banman.punish(peer_addr, 10, "block tx txid does not match body")
+        return None
+    _seen_tx.add(txid)
+    if not txrelay.in_mempool(txid):
+        ok, reason, _ = txrelay.accept_to_mempool(tx, source_peer=peer_addr, relay=False)
+        if not ok and reason in _PUNISH_TX_REASONS:
+            banman.punish(peer_addr, 10, f"invalid block tx: {reason}")
+    return txid
+
+
+def _listen_port() -> int:
+    return int(get_config().get("network.p2p_port", 28447))
+
+
+def _serve_peer(sock: socket.socket, peer_addr: str, inbound: bool = True):
     fp = sock.makefile(mode="rwb")
+    ps = PeerState(peer_addr, fp, inbound=inbound)
+    ps.sock = sock
     try:
-        # handshake
-        _p2p_send(fp, {"type": "VERSION", "time": now_ms()})
+        _register_peer(ps)
+        # handshake (port lets the remote side gossip our listening address, addrs are our
+        # reachable addresses, addr_you tells the remote side how we see it)
+        _p2p_send(fp, {"type": "VERSION", "time": now_ms(), "port": _listen_port(), "cmpct": 1 if _compact_enabled() else 0,
+                       "height": get_chain_height(), "compress": netcompress.supported(),
+                       "services": addrman.local_services(), "addrs": netlocal.advertised(), "addr_you": peer_addr})
         _p2p_send(fp, {"type": "VERACK"})
-        with _peers_lock:
-            _peers[peer_addr] = PeerState(peer_addr, fp)
+        if not inbound:
+            _p2p_send(fp, {"type": "GETADDR"})
+            ps.last_ping_ms = now_ms()
+            _p2p_send(fp, {"type": "PING", "time": ps.last_ping_ms})
 
         # main loop
         while True:
+            if banman.is_banned(peer_addr):
+                net_log.info("disconnecting banned peer", extra=logs.fields(peer=peer_addr))
+                break
             line = fp.readline()
             if not line:
                 break
+            with _peers_lock:
+                if peer_addr in _peers:
+                    _peers[peer_addr].last_seen = now_ms()
             try:
-                msg = json.loads(line.decode("utf-8").strip())
-            except Exception:
+                msg, payload = netcompress.decode_message(line)
+            except ValueError:
+                _net_count(fp, "recv", "<invalid>", len(line))
+                banman.punish(peer_addr, 10, "malformed message")
                 continue
             mtype = msg.get("type")
+            _net_count(fp, "recv", str(mtype or "?"), len(line), len(payload) + (0 if payload is line else 1))
+
+            if mtype == "VERSION":
+                # Inbound peers connect from an ephemeral port; remember their advertised listener
+                try:
+                    port = int(msg.get("port") or 0)
+                except (TypeError, ValueError):
+                    port = 0
+                if inbound and port:
+                    addrman.add([netlocal.format_addr(netlocal.split_host_port(peer_addr)[0], port)], source=peer_addr)
+                theirs = msg.get("addrs")
+                if isinstance(theirs, list):
+                    addrman.add([str(a) for a in theirs[:netlocal.MAX_ADVERTISED]], source=peer_addr)
+                if not inbound and isinstance(msg.get("addr_you"), str):
+                    try:
+                        netlocal.seen_by_peer(netlocal.split_host_port(msg["addr_you"])[0], _listen_port())
+                    except ValueError:
+                        pass
+                if not inbound:
+                    nettime.add_sample(netlocal.split_host_port(peer_addr)[0], msg.get("time"))
+                    addrman.set_services(peer_addr, msg.get("services"))
+                    if not ps.handshake_done:
+                        addrman.mark_good(peer_addr)  # tried only once the peer answered our VERSION
+                ps.handshake_done = True
+                ps.compact = bool(msg.get("cmpct")) and _compact_enabled()
+                ps.compress = netcompress.negotiate(msg.get("compress"))
+                try:
+                    _sync.peer_height(peer_addr, int(msg.get("height", -1)))
+                except (TypeError, ValueError):
+                    pass
+                continue
+            if mtype in ("VERACK", "ERR"):
+                continue
+
+            # address gossip
+            if mtype == "GETADDR":
+                if not ps.getaddr_served:
+                    ps.getaddr_served = True
+                    own = [{"addr": a, "ts": now_ms()} for a in netlocal.advertised()]
+                    _p2p_send(fp, {"type": "ADDR", "addrs": (own + addrman.get_addrs())[:addrman.MAX_ADDRS_PER_MSG]})
+                continue
+            if mtype == "ADDR":
+                items = msg.get("addrs") or []
+                if isinstance(items, list) and len(items) <= addrman.MAX_ADDRS_PER_MSG:
+                    addrs = [it.get("addr") for it in items if isinstance(it, dict) and it.get("addr")]
+                    addrman.add(addrs, source=peer_addr)
+                else:
+                    banman.punish(peer_addr, 20, "oversized ADDR")
+                continue
 
             # keepalive
             if mtype == "PING":
-                _p2p_send(fp, {"type": "PONG", "time": now_ms()})
+                # echo lets the pinger measure round-trip time
+                _p2p_send(fp, {"type": "PONG", "time": now_ms(), "echo": msg.get("time")})
                 continue
             if mtype == "PONG":
+                echo = msg.get("echo")
+                if isinstance(echo, int) and echo == ps.last_ping_ms:
+                    ps.rtt_ms = float(now_ms() - echo)
+                    _sync.peer_rtt(peer_addr, now_ms() - echo)
                 continue
 
             if mtype == "INV":
@@ -97,12 +703,22 @@ def _serve_peer(sock: socket.socket, peer_addr: str):
                     kind = it.get("kind")
                     if kind == "hdr":
                         h = (it.get("hash") or "").strip().lower()
+                        if isinstance(it.get("height"), int):
+                            _sync.peer_height(peer_addr, it["height"])
                         if h and h not in _seen_hdr:
                             need_items.append({"kind": "hdr", "hash": h})
                     elif kind == "tx":
                         txid = (it.get("txid") or "").strip().lower()
-                        if txid and txid not in _seen_tx:
-                            need_items.append({"kind": "tx", "txid": txid})
+                        if not txid:
+                            continue
+                        ps.known_tx.add(txid)
+                        if txid in _seen_tx or txrelay.is_orphan(txid):
+                            continue
+                        nowm = now_ms()
+                        if nowm - _tx_requested.get(txid, 0) < _TX_REQUEST_TIMEOUT_MS:
+                            continue  # already fetching from another peer
+                        _tx_requested[txid] = nowm
+                        need_items.append({"kind": "tx", "txid": txid})
                 if need_items:
                     _p2p_send(fp, {"type": "GETDATA", "items": need_items})
                 continue
@@ -120,23 +736,12 @@ def _serve_peer(sock: socket.socket, peer_addr: str):
                             h = s.query(BlockHeader).filter_by(hash_hex=hh).first()
                             if not h:
                                 continue
-                            # For BLOCKHDR response, include enough fields for accept_external_header,
-                            # and also supply txids snapshot if known (we don't store full list; send empty for now)
-                            hdr_msg = {
-                                "type": "BLOCKHDR",
-                                "headers": [{
-                                    "prev": h.prev_hash_hex,
-                                    "merkle": h.merkle_root_hex,
-                                    "ver": h.version,
-                                    "ts": h.timestamp,
-                                    "target": h.target,
-                                    "nonce": int(h.nonce),
-                                    "miner": h.miner_address,
-                                    "txids": [],  # unknown snapshot; external nodes will rebuild/compare
-                                    "hash": h.hash_hex
-                                }]
-                            }
-                            _p2p_send(fp, hdr_msg)
+                            item = _header_item(h)
+                            txids = item.pop("txids")
+                            if ps.compact and txids:
+                                _p2p_send(fp, compact.build(item, txids))
+                            else:
+                                _p2p_send(fp, {"type": "BLOCKHDR", "headers": [dict(item, txids=txids)]})
                         elif kind == "tx":
                             txid = (it.get("txid") or "").strip().lower()
                             if not txid:
@@ -148,94 +753,141 @@ def _serve_peer(sock: socket.socket, peer_addr: str):
                                 tx_obj = json.loads(m.raw) if m.raw else {}
                             except Exception:
                                 tx_obj = {}
+                            ps.known_tx.add(txid)
                             _p2p_send(fp, {"type": "TX", "tx": tx_obj, "txid": txid})
                 continue
 
             if mtype == "BLOCKHDR":
                 headers = msg.get("headers") or []
                 for h in headers:
+                    _accept_relayed_header(peer_addr, h)
+                continue
+
+            # height-window block download (core.blocksync)
+            if mtype == "GETBLOCKS":
+                try:
+                    start = int(msg.get("start_height"))
+                    count = max(0, min(int(msg.get("count", 0)), _MAX_GETBLOCKS))
+                except (TypeError, ValueError):
+                    banman.punish(peer_addr, 10, "malformed GETBLOCKS")
+                    continue
+                headers_out = []
+                db = get_db()
+                with db.session() as s:
+                    pruned = headeronly.is_pruned(s, start)
+                # header-only node: bodies below the window are gone, an empty reply sends the peer elsewhere
+                for h in ([] if pruned else get_headers_range(start, count)):
+                    item = _header_item(h)
+                    item["txs"] = [t for t in _block_txns(item["txids"], list(range(len(item["txids"])))) if t.get("raw")]
+                    headers_out.append(item)
+                _p2p_send(fp, {"type": "BLOCKS", "start_height": start, "headers": headers_out})
+                continue
+            if mtype == "BLOCKS":
+                try:
+                    start = int(msg.get("start_height"))
+                except (TypeError, ValueError):
+                    banman.punish(peer_addr, 10, "malformed BLOCKS")
+                    continue
+                headers = msg.get("headers") or []
+                if isinstance(headers, list) and _sync.on_blocks(peer_addr, start, headers):
+                    _connect_downloaded()
+                continue
+
+            # compact block relay (core.compact)
+            if mtype == "CMPCTBLOCK":
+                hdr = msg.get("hdr") or {}
+                hh = str(hdr.get("hash") or "").strip().lower()
+                if not hh or hh in _seen_hdr:
+                    continue
+                db = get_db()
+                with db.session() as s:
+                    mem_txids = [t for (t,) in s.query(MempoolTx.txid).all()]
+                slots, missing = compact.reconstruct(msg, mem_txids)
+                if missing:
+                    ps.pending_cmpct[hh] = (hdr, slots)
+                    while len(ps.pending_cmpct) > _MAX_PENDING_CMPCT:
+                        ps.pending_cmpct.pop(next(iter(ps.pending_cmpct)))
+                    _p2p_send(fp, {"type": "GETBLOCKTXN", "hash": hh, "indexes": missing})
+                else:
+                    _accept_relayed_header(peer_addr, dict(hdr, txids=slots))
+                continue
+            if mtype == "GETBLOCKTXN":
+                hh = str(msg.get("hash") or "").strip().lower()
+                rec = read_block(hh) if hh else None
+                if rec is None:
+                    continue
+                _p2p_send(fp, {"type": "BLOCKTXN", "hash": hh, "txs": _block_txns(rec.txids, msg.get("indexes") or [])})
+                continue
+            if mtype in ("GETCFILTERS", "GETCFHEADERS"):
+                try:
+                    start = int(msg.get("start_height"))
+                    stop = str(msg.get("stop_hash") or "").strip().lower()
+                except (TypeError, ValueError):
+                    banman.punish(peer_addr, 10, f"malformed {mtype}")
+                    continue
+                if int(msg.get("filter_type", blockfilter.FILTER_TYPE_BASIC)) != blockfilter.FILTER_TYPE_BASIC:
+                    continue
+                if mtype == "GETCFILTERS":
+                    for item in blockfilter.get_cfilters(start, stop):
+                        _p2p_send(fp, dict(item, type="CFILTER"))
+                else:
+                    res = blockfilter.get_cfheaders(start, stop)
+                    if res is not None:
+                        _p2p_send(fp, dict(res, type="CFHEADERS"))
+                continue
+            if mtype == "BLOCKTXN":
+                hh = str(msg.get("hash") or "").strip().lower()
+                pending = ps.pending_cmpct.pop(hh, None)
+                if pending is None:
+                    continue
+                hdr, slots = pending
+                for t in msg.get("txs") or []:
                     try:
-                        prev = h.get("prev")
-                        merkle = h.get("merkle")
-                        ver = int(h.get("ver", 1))
-                        ts = int(h.get("ts", int(time.time())))
-                        tgt = h.get("target")
-                        nonce = int(h.get("nonce", 0))
-                        miner = h.get("miner") or "SMELLY_PEER"
-                        txids_snap = h.get("txids") or []
-                    except Exception:
+                        i = int(t.get("i"))
+                        txid = str(t.get("txid") or "").strip().lower()
+                    except (TypeError, ValueError, AttributeError):
                         continue
-
-                    # Attempt accept; will reject stale-prev, mismatch, etc.
-                    hh, err = accept_external_header(
-                        prev_hash_hex=prev,
-                        merkle_root_hex=merkle,
-                        version=ver,
-                        timestamp=ts,
-                        target_hex=tgt,
-                        nonce=nonce,
-                        miner_address=miner,
-                        txids_snapshot=txids_snap,
-                    )
-                    if hh:
-                        _seen_hdr.add(hh.strip().lower())
-                        # Re-announce header to other peers
-                        _announce_tip_to_peers()
+                    if 0 <= i < len(slots) and txid and slots[i] is None:
+                        slots[i] = _admit_block_tx(peer_addr, dict(t, txid=txid))
+                if any(x is None for x in slots):
+                    sync_log.warning("compact block reconstruction failed", extra=logs.fields(hash=hh, peer=peer_addr))
+                    banman.punish(peer_addr, 10, "incomplete BLOCKTXN")
+                    continue
+                _accept_relayed_header(peer_addr, dict(hdr, txids=slots))
                 continue
 
             if mtype == "TX":
                 txid = (msg.get("txid") or "").strip().lower()
                 if not txid:
+                    banman.punish(peer_addr, 10, "TX without txid")
                     continue
-                if txid in _seen_tx:
+                ps.known_tx.add(txid)
+                _tx_requested.pop(txid, None)
+                if txid in _seen_tx or txrelay.is_orphan(txid):
                     continue
-                # Store in mempool DB if valid via RPC endpoint for consistency,
-                # but here we directly insert to mempool to avoid recursion.
-                # We rely on consensus.validate_mempool_tx during mining/accept.
-                db = get_db()
-                with db.session() as s:
-                    existing = s.query(MempoolTx).filter_by(txid=txid).first()
-                    if not existing:
-                        tx_obj = msg.get("tx") or {}
-                        try:
-                            raw_compact = json.dumps(tx_obj, separators=(",", ":"), sort_keys=True)
-                        except Exception:
-                            raw_compact = ""
-                        fee = float((tx_obj or {}).get("fee", 0.0))
-                        from_addr = None
-                        to_addr = None
-                        amount = None
-                        try:
-                            if tx_obj.get("outputs"):
-                                to_addr = tx_obj["outputs"][0].get("address")
-                                amount = float(tx_obj["outputs"][0].get("amount", 0.0))
-                            if tx_obj.get("inputs"):
-                                from_addr = tx_obj["inputs"][0].get("address")
-                        except Exception:
-                            pass
-                        s.add(MempoolTx(
-                            txid=txid,
-                            raw=raw_compact,
-                            added_ms=now_ms(),
-                            fee=fee,
-                            from_addr=from_addr,
-                            to_addr=to_addr,
-                            amount=amount,
-                        ))
-                        s.commit()
+                ok, reason, real_txid = txrelay.accept_to_mempool(msg.get("tx") or {}, source_peer=peer_addr)
                 _seen_tx.add(txid)
-                # Re-announce
-                _broadcast_txinv(txid)
+                if real_txid and real_txid != txid:
+                    banman.punish(peer_addr, 10, "TX txid does not match body")
+                    continue
+                if ok:
+                    ps.last_tx_ms = now_ms()
+                if reason == "orphan":
+                    # Ask the announcer for the missing parents it may have in its mempool
+                    parents = [p for p in txrelay.orphan_parents(txid) if p not in _seen_tx]
+                    if parents:
+                        _p2p_send(fp, {"type": "GETDATA", "items": [{"kind": "tx", "txid": p} for p in parents]})
+                elif not ok and reason in _PUNISH_TX_REASONS:
+                    banman.punish(peer_addr, 10, f"invalid tx: {reason}")
                 continue
 
             # Unknown message
             _p2p_send(fp, {"type": "ERR", "detail": f"unknown {mtype}"})
     except Exception as e:
-        print("P2P conn error:", peer_addr, e)
+        net_log.warning(f"connection error: {e}", extra=logs.fields(peer=peer_addr))
     finally:
         try:
-            with _peers_lock:
-                _peers.pop(peer_addr, None)
+            _unregister_peer(peer_addr, fp)
             fp.close()
             sock.close()
         except Exception:
@@ -243,55 +895,189 @@ def _serve_peer(sock: socket.socket, peer_addr: str):
 
 
 def start_p2p():
-    # Allow overriding P2P bind via environment for multi-node testing
-    import os
-    cfg = get_config()
-    host = os.environ.get("SMELLY_P2P_HOST", cfg.get("network.rpc_host", "127.0.0.1"))
-    port = int(os.environ.get("SMELLY_P2P_PORT", cfg.get("network.p2p_port", 28447)))
-    s = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
-    s.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
-    s.bind((host, port))
-    s.listen(50)
-    print(f"P2P listening on {host}:{port}")
-
-    def _accept_loop():
+    # Binds come from the merged config (file < SMELLY_P2P_HOST/PORT env < CLI flags); network.listen
+    # (--bind) lists several IPv4/IPv6 addresses, otherwise p2p_host alone is bound
+    socks = []
+    for host, port in netlocal.listen_addresses():
+        try:
+            socks.append(netlocal.bind_socket(host, port))
+        except OSError as e:
+            net_log.error(f"cannot listen on {netlocal.format_addr(host, port)}: {e}")
+            continue
+        net_log.info(f"listening on {netlocal.format_addr(host, port)}")
+    if not socks:
+        raise RuntimeError("P2P could not bind any listen address")
+    netlocal.set_bound([netlocal.format_addr(*s.getsockname()[:2]) for s in socks])
+    netlocal.discover(_listen_port())
+    # Opt-in UPnP/NAT-PMP mapping; its external address joins the advertised local addresses
+    portmap.start(_listen_port())
+
+    def _accept_loop(s: socket.socket):
         while True:
-            conn, (h, p) = s.accept()
-            threading.Thread(target=_serve_peer, args=(conn, f"{h}:{p}"), daemon=True).start()
+            conn, sa = s.accept()
+            peer = netlocal.format_addr(sa[0], sa[1])
+            if banman.is_banned(peer) or not _make_inbound_room():
+                conn.close()
+                continue
+            threading.Thread(target=_serve_peer, args=(conn, peer), daemon=True).start()
 
-    threading.Thread(target=_accept_loop, daemon=True).start()
+    for s in socks:
+        threading.Thread(target=_accept_loop, args=(s,), daemon=True).start()
 
     # Periodic announcer
     def _periodic():
         while True:
             try:
                 _announce_tip_to_peers()
+                # Locally mined blocks connect outside the P2P path; pick up their outputs too
+                if txrelay.orphan_count():
+                    _retry_orphans()
+                headeronly.prune_best_effort()
+                safemode.note_tip(get_chain_height())
+                nowm = now_ms()
+                for t in [t for t, ms in list(_tx_requested.items()) if nowm - ms > _TX_REQUEST_TIMEOUT_MS]:
+                    _tx_requested.pop(t, None)
+                # Keep RTT estimates fresh for the download scheduler
+                ping_ms = int(get_config().get("network.ping_interval_sec", 30)) * 1000
+                with _peers_lock:
+                    for ps in list(_peers.values()):
+                        if nowm - ps.last_ping_ms >= ping_ms:
+                            ps.last_ping_ms = nowm
+                            _p2p_send(ps.fp, {"type": "PING", "time": nowm})
             except Exception:
                 pass
             time.sleep(5)
 
+    txrelay.set_announcer(_broadcast_txinv)
+    p2pstate.register(announce_tip=_announce_tip_to_peers, sync_status=sync_status, net_totals=net_totals_snapshot,
+                      network_info=network_info_snapshot, peer_info=peer_info_snapshot, connect_peer=connect_peer)
     threading.Thread(target=_periodic, daemon=True).start()
+    threading.Thread(target=_sync_loop, name="block-sync", daemon=True).start()
+    threading.Thread(target=_maintain_outbound, daemon=True).start()
 
 
 def connect_peer(addr: str):
+    with _peers_lock:
+        if addr in _peers:
+            return True
+    if banman.is_banned(addr):
+        return False
+    addrman.mark_attempt(addr)
     try:
-        host, port_str = addr.split(":")
-        port = int(port_str)
-        s = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
-        s.settimeout(5.0)
-        s.connect((host, port))
-        fp = s.makefile(mode="rwb")
-        # handshake
-        _p2p_send(fp, {"type": "VERSION", "time": now_ms()})
-        _p2p_send(fp, {"type": "VERACK"})
-        with _peers_lock:
-            _peers[addr] = PeerState(addr, fp)
-        # On connect, ask for peer tip by sending an empty INV to trigger GETDATA or direct BLOCKHDR
-        _p2p_send(fp, {"type": "PING", "time": now_ms()})
-        return True
+        host, port = netlocal.split_host_port(addr)
+        s = socket.create_connection((host, port), timeout=5.0)
+        s.settimeout(None)
     except Exception as e:
-        print("connect_peer error:", addr, e)
+        net_log.info(f"connect failed: {e}", extra=logs.fields(peer=addr))
+        addrman.mark_failed(addr)
         return False
+    # Outbound peers get the same reader loop as inbound ones (handshake, GETADDR, PING sent there)
+    threading.Thread(target=_serve_peer, args=(s, addr, False), daemon=True).start()
+    return True
+
+
+def _self_addrs() -> Set[str]:
+    cfg = get_config()
+    port = _listen_port()
+    hosts = {"127.0.0.1", "localhost", "0.0.0.0", "::1", "::", str(cfg.get("network.p2p_host") or cfg.get("network.rpc_host", "127.0.0.1"))}
+    return {netlocal.format_addr(h, port) for h in hosts} | set(netlocal.bound()) | set(netlocal.advertised())
+
+
+def _bootstrap_addrs():
+    """Seed the address manager from static seed_peers and DNS seeds."""
+    cfg = get_config()
+    seeds = list(cfg.get("network.seed_peers", []) or [])
+    if seeds:
+        addrman.add(seeds, source="seed", allow_hostnames=True)
+    dns = addrman.resolve_dns_seeds(cfg.get("network.dns_seeds", []) or [], _listen_port())
+    if dns:
+        addrman.add(dns, source="dns")
+    net_log.info(f"bootstrap: {len(seeds)} static seeds, {len(dns)} DNS seed addresses")
+
+
+def _feeler(addr: str):
+    """Short-lived connection to a new-bucket address: a completed VERSION exchange moves it to tried."""
+    addrman.mark_attempt(addr)
+    try:
+        host, port = netlocal.split_host_port(addr)
+        with socket.create_connection((host, port), timeout=5.0) as s, s.makefile(mode="rwb") as fp:
+            _p2p_send(fp, {"type": "VERSION", "time": now_ms(), "port": _listen_port(), "height": get_chain_height(),
+                           "services": addrman.local_services(), "addr_you": addr})
+            for _ in range(4):  # VERSION normally comes first; allow a few other lines before giving up
+                line = fp.readline()
+                if not line:
+                    raise ConnectionError("closed before VERSION")
+                msg, _ = netcompress.decode_message(line)
+                if msg.get("type") == "VERSION":
+                    break
+            else:
+                raise ConnectionError("no VERSION")
+    except Exception as e:
+        net_log.debug(f"feeler failed: {e}", extra=logs.fields(peer=addr))
+        addrman.mark_failed(addr)
+        return
+    net_log.debug("feeler ok", extra=logs.fields(peer=addr))
+    addrman.mark_good(addr)
+
+
+def _maintain_outbound():
+    """
+    Keep max_outbound_connections filled from the address manager, one peer per network group,
+    plus feeler connections and outbound rotation (core.eviction).
+    """
+    seeded = False
+    first_round = True
+    last_feeler = last_rotate = time.time()
+    while True:
+        try:
+            cfg = get_config()
+            target = int(cfg.get("network.max_outbound_connections", 8))
+            with _peers_lock:
+                connected = set(_peers.keys())
+                out_peers = [ps for ps in _peers.values() if not ps.inbound]
+            outbound = len(out_peers)
+            groups = {c.group for c in map(_candidate, out_peers) if c.group}
+            exclude = connected | _self_addrs()
+            if outbound < target:
+                st = addrman.stats()
+                if not seeded or st["new"] + st["tried"] == 0:
+                    _bootstrap_addrs()
+                    seeded = True
+                # after a restart, reconnect to recently good peers before sampling the buckets
+                anchors = addrman.preferred(target - outbound, exclude, groups) if first_round else []
+                first_round = False
+                for _ in range(target - outbound):
+                    cand = anchors.pop(0) if anchors else addrman.select(exclude, exclude_groups=groups)
+                    if not cand:
+                        break
+                    exclude.add(cand)
+                    try:
+                        g = netlocal.netgroup(netlocal.split_host_port(cand)[0])
+                    except ValueError:
+                        g = ""
+                    if g:
+                        groups.add(g)
+                    threading.Thread(target=connect_peer, args=(cand,), daemon=True).start()
+            feeler_sec = int(cfg.get("network.feeler_interval_sec", 120))
+            if feeler_sec > 0 and time.time() - last_feeler >= feeler_sec:
+                last_feeler = time.time()
+                cand = addrman.select_new(exclude)
+                if cand:
+                    threading.Thread(target=_feeler, args=(cand,), daemon=True).start()
+            rotate_sec = int(cfg.get("network.outbound_rotate_sec", 0))
+            if rotate_sec > 0 and time.time() - last_rotate >= rotate_sec:
+                last_rotate = time.time()
+                if outbound >= target:
+                    scores = addrman.scores([ps.addr for ps in out_peers])
+                    cands = [_candidate(ps) for ps in out_peers]
+                    for c in cands:
+                        c.score = scores.get(c.addr, 0.0)
+                    victim = eviction.rotation_victim(cands, now_ms())
+                    if victim:
+                        _disconnect(victim, "outbound rotation")
+        except Exception as e:
+            net_log.exception(f"outbound maintenance error: {e}")
+        time.sleep(int(get_config().get("network.outbound_check_sec", 10)))
 
 
 def sync_headers_from_peer(peer_host: str, peer_port: int):
@@ -300,22 +1086,7 @@ def sync_headers_from_peer(peer_host: str, peer_port: int):
 
 
 def start_rpc():
-    # Allow overriding RPC bind via environment for multi-node testing
-    import os
-    from core.config import get_config
-    cfg = get_config()
-    env_host = os.environ.get("SMELLY_RPC_HOST")
-    env_port = os.environ.get("SMELLY_RPC_PORT")
-    if env_host or env_port:
-        # Monkey-patch cfg getter so core.rpc.run_rpc_server picks these up
-        try:
-            cfg._overrides = getattr(cfg, "_overrides", {})
-            if env_host:
-                cfg._overrides["network.rpc_host"] = env_host
-            if env_port:
-                cfg._overrides["network.rpc_port"] = int(env_port)
-        except Exception:
-            pass
+    # RPC bind overrides (SMELLY_RPC_HOST/PORT, --rpc-host/--rpc-port) are already merged into get_config()
     run_rpc_server()
 
 
@@ -324,12 +1095,76 @@ def main():
     parser.add_argument("--mine", action="store_true", help="Continuously mine headers on this node")
     parser.add_argument("--miner-address", type=str, default="SMELLY_LOCAL_MINER")
     parser.add_argument("--peer", type=str, default="", help="Optional peer host:port to header-sync from")
+    parser.add_argument("--config", type=str, default=None, help="Config YAML (defaults to SMELLY_CONFIG or configs/defaults.yaml)")
+    parser.add_argument("--rpc-host", type=str, default=None, help="Override network.rpc_host")
+    parser.add_argument("--rpc-port", type=int, default=None, help="Override network.rpc_port")
+    parser.add_argument("--p2p-host", type=str, default=None, help="Override network.p2p_host")
+    parser.add_argument("--p2p-port", type=int, default=None, help="Override network.p2p_port")
+    parser.add_argument("--bind", action="append", default=None, metavar="ADDR",
+                        help="P2P listen address, IPv4 or IPv6 (repeatable; overrides network.listen)")
+    parser.add_argument("--portmap", action="store_true", help="Map the P2P port on the router via UPnP/NAT-PMP")
+    parser.add_argument("--db", type=str, default=None, help="Override database.sqlite_path")
+    parser.add_argument("--reindex", action="store_true",
+                        help="Rebuild headers, UTXO set and indexes from the stored block files before starting")
+    parser.add_argument("--set", action="append", default=[], metavar="KEY=VALUE",
+                        help="Override any config value by dot path, e.g. --set sync.max_peers=32 (repeatable)")
     args = parser.parse_args()
 
+    # Config must be final before any subsystem (DB, RPC, P2P) reads it
+    cfg = load_config(args.config) if args.config else get_config()
+    try:
+        cfg.apply_overrides(args.set)
+    except ValueError as e:
+        parser.error(str(e))
+    for key, val in (
+        ("network.rpc_host", args.rpc_host),
+        ("network.rpc_port", args.rpc_port),
+        ("network.p2p_host", args.p2p_host),
+        ("network.p2p_port", args.p2p_port),
+        ("network.listen", args.bind),
+        ("network.portmap", True if args.portmap else None),
+        ("database.sqlite_path", args.db),
+    ):
+        if val is not None:
+            cfg.set_override(key, val)
+    problems = cfg.validate()
+    if problems:
+        for p in problems:
+            print("config error:", p)
+        sys.exit(2)
+    logs.setup()
+    log = logs.get("node")
+    log.info(f"config: {cfg.path} (overrides: {', '.join(sorted(cfg._overrides)) or 'none'})")
+    if cfg.get("storage.backend", "sqlite") == "memory":
+        log.warning("storage.backend=memory; the chain is discarded when the node exits")
+    if headeronly.enabled():
+        log.info("storage.mode=header-only; block bodies kept for the last blocks only",
+                 extra=logs.fields(keep_blocks=headeronly.keep_blocks()))
+
     ensure_dirs()
     get_db()
     add_genesis_if_needed()
 
+    # --reindex, or an interrupted reindex from a previous run: finish it before serving anything
+    from core import reindex
+    if args.reindex and headeronly.enabled():
+        log.error("--reindex needs every block body; not available with storage.mode=header-only")
+        sys.exit(2)
+    if args.reindex or reindex.state() is not None:
+        log.info("reindexing chain state from block files")
+        res, err = reindex.reindex()
+        if err:
+            log.error(f"reindex failed: {err}")
+            sys.exit(1)
+        log.info("reindex complete", extra=logs.fields(height=res.get("height"), connected=res.get("connected")))
+
+    # A loaded snapshot whose history failed background validation: nothing on top of it is trusted
+    from core import snapshot
+    snap_err = snapshot.failed()
+    if snap_err:
+        log.error(f"assumeutxo snapshot history is invalid ({snap_err}); load a trusted snapshot into a fresh data dir or sync from genesis")
+        sys.exit(1)
+
     # Start RPC server in background thread
     t = threading.Thread(target=start_rpc, daemon=True)
     t.start()
@@ -338,30 +1173,29 @@ def main():
     tp2p = threading.Thread(target=start_p2p, daemon=True)
     tp2p.start()
 
-    print("SMELLY Node RPC + P2P started.")
-    cfg = get_config()
+    log.info("SMELLY Node RPC + P2P started")
     rpc_url = f"http://{cfg.get('network.rpc_host','127.0.0.1')}:{cfg.get('network.rpc_port',28445)}"
 
     # Optional one-shot header sync from peer
     if args.peer:
         try:
             ph, pp = args.peer.split(":")
-            print(f"Syncing headers from peer {args.peer} ...")
+            log.info(f"syncing headers from peer {args.peer}")
             sync_headers_from_peer(ph, int(pp))
         except Exception as e:
-            print("Peer sync arg invalid:", e)
+            log.error(f"peer sync arg invalid: {e}")
 
     if args.mine:
-        print("Mining enabled. Submitting header-only blocks...")
+        log.info("mining enabled, submitting header-only blocks")
         while True:
             try:
-                r = requests.post(f"{rpc_url}/rpc/mine_one", json={"miner_address": args.miner_address}, timeout=10)
+                r = requests.post(f"{rpc_url}/rpc/mine_one", json={"miner_address": args.miner_address}, auth=rpcauth.client_auth(), timeout=10)
                 if r.status_code == 200:
-                    print("Mined header hash:", r.json().get("hash"))
+                    log.info("mined block", extra=logs.fields(hash=r.json().get("hash")))
                 else:
-                    print("Mine error:", r.text)
+                    log.warning(f"mine error: {r.text}")
             except Exception as e:
-                print("Mining request failed:", e)
+                log.warning(f"mining request failed: {e}")
             time.sleep(0.5)
 
     # Keep main thread alive
@@ -369,7 +1203,7 @@ def main():
         while True:
             time.sleep(1)
     except KeyboardInterrupt:
-        print("Shutting down.")
+        log.info("shutting down")
 
 
 if __name__ == "__main__":
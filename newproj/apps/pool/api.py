@@ -0,0 +1,255 @@
+from __future__ import annotations
+
+import json
+import re
+import threading
+from typing import Any, Callable, Dict, List, Optional, Tuple
+from urllib.parse import parse_qs, unquote, urlsplit
+
+from sqlalchemy import func
+
+from core.config import get_config
+from core.crypto import check_address
+from core.db import get_db, nonce_from_db, PoolBlock, PoolMiner, PoolPayout, PoolShare
+from core import logs
+from apps.pool.hashrate import WINDOWS
+
+
+# Pool HTTP JSON API for miner dashboards, served from a thread of the Stratum process
+# (pool.api_port, 0 = off):
+#   GET /api/pool/stats                        hashrate (1m/15m/1h), sessions, miners, blocks, payout settings
+#   GET /api/miner/<address>                   balances and share totals, live workers and their hashrate
+#   GET /api/miner/<address>/shares?worker=&status=&page=&limit=
+#                                              recent share verdicts from the share log (pool_shares):
+#                                              job, nonce, computed hash, target, accepted/rejected + reason
+#   GET /api/blocks?page=&limit=               blocks found by the pool, newest first, with maturity status
+#   GET /api/payments?address=&page=&limit=    payouts, newest first (optionally for one address)
+# Balances, blocks and payouts come from the pool tables (pool_miners, pool_blocks, pool_payouts;
+# the pool flushes its accounting every few seconds), hashrate and sessions live from the
+# StratumPool. Lists are paginated: page is 1-based, limit defaults to pool.api_page_size and is
+# capped at pool.api_max_page_size, and the response is {items, page, limit, total}.
+# pool.api_cors_origins lists the browser origins allowed to read the API ("*" = any) so a static
+# frontend hosted elsewhere can use it. Errors are {"error": ...} with a 4xx status.
+
+Response = Tuple[int, Dict[str, Any]]
+log = logs.get("pool")
+
+
+class ApiError(Exception):
+    def __init__(self, status: int, error: str):
+        super().__init__(error)
+        self.status = status
+        self.error = error
+
+
+def _page(query: Dict[str, List[str]]) -> Tuple[int, int]:
+    cfg = get_config()
+    cap = max(1, int(cfg.get("pool.api_max_page_size", 100)))
+    try:
+        page = int((query.get("page") or ["1"])[0])
+        limit = int((query.get("limit") or [str(cfg.get("pool.api_page_size", 25))])[0])
+    except ValueError:
+        raise ApiError(400, "page and limit must be integers")
+    if page < 1 or limit < 1:
+        raise ApiError(400, "page and limit must be positive")
+    return page, min(limit, cap)
+
+
+def _paginated(q, page: int, limit: int, row: Callable[[Any], Dict[str, Any]]) -> Dict[str, Any]:
+    total = q.count()
+    items = [row(r) for r in q.offset((page - 1) * limit).limit(limit).all()]
+    return {"items": items, "page": page, "limit": limit, "total": total}
+
+
+def _block(b: PoolBlock) -> Dict[str, Any]:
+    return {
+        "height": b.height,
+        "hash": b.hash_hex,
+        "finder": b.finder_address,
+        "reward": b.reward,
+        "status": b.status,
+        "confirmations": b.confirmations,
+        "time_ms": b.created_ms,
+        "status_ms": b.status_ms,
+    }
+
+
+def _share(sh: PoolShare) -> Dict[str, Any]:
+    return {
+        "time_ms": sh.created_ms,
+        "worker": sh.worker,
+        "job_id": sh.job_id,
+        "nonce": nonce_from_db(sh.nonce),
+        "share_diff": sh.share_diff,
+        "hash": sh.hash_hex,
+        "target": sh.target_hex,
+        "accepted": bool(sh.accepted),
+        "reason": sh.reason,
+    }
+
+
+def _payment(p: PoolPayout) -> Dict[str, Any]:
+    return {"address": p.address, "amount": p.amount, "txid": p.txid, "status": p.status, "time_ms": p.created_ms}
+
+
+class PoolApi:
+    def __init__(self, pool):
+        self.pool = pool
+        self.routes: List[Tuple[re.Pattern, Callable[..., Response]]] = [
+            (re.compile(r"^/api/pool/stats$"), self.pool_stats),
+            (re.compile(r"^/api/miner/([^/]+)$"), self.miner),
+            (re.compile(r"^/api/miner/([^/]+)/shares$"), self.miner_shares),
+            (re.compile(r"^/api/blocks$"), self.blocks),
+            (re.compile(r"^/api/payments$"), self.payments),
+        ]
+
+    def dispatch(self, path: str, query: Dict[str, List[str]]) -> Response:
+        for pattern, handler in self.routes:
+            m = pattern.match(path.rstrip("/") or "/")
+            if m:
+                try:
+                    return handler(query, *[unquote(g) for g in m.groups()])
+                except ApiError as e:
+                    return e.status, {"error": e.error}
+        return 404, {"error": "not found"}
+
+    def pool_stats(self, query) -> Response:
+        pool = self.pool
+        with pool.lock:
+            sessions = len(pool.clients)
+            authorized = sum(1 for c in pool.clients.values() if c.address)
+        db = get_db()
+        with db.session() as s:
+            miners = s.query(func.count(PoolMiner.id)).scalar() or 0
+            blocks = s.query(func.count(PoolBlock.id)).filter(PoolBlock.status != "orphaned").scalar() or 0
+            last = s.query(PoolBlock).order_by(PoolBlock.height.desc()).first()
+            paid = s.query(func.coalesce(func.sum(PoolMiner.paid_total), 0.0)).scalar() or 0.0
+            last_block = _block(last) if last else None
+        return 200, {
+            "hashrate": pool.hashrate.pool(),
+            "workers": len(pool.hashrate.workers()),
+            "sessions": sessions,
+            "authorized_sessions": authorized,
+            "miners": int(miners),
+            "blocks_found": int(blocks),
+            "last_block": last_block,
+            "share_diff": pool.pool_diff,
+            "paid_total": float(paid),
+            "min_payout": float(get_config().get("pool.min_payout", 1.0)),
+            "payouts_paused": pool.payouts_paused(),
+        }
+
+    def miner(self, query, address: str) -> Response:
+        err = check_address(address)
+        if err:
+            raise ApiError(400, f"invalid address: {err}")
+        with self.pool.lock:
+            online = [c for c in self.pool.clients.values() if c.address == address]
+            sessions = [{"worker": c.worker, "share_diff": c.share_diff, "accepted": c.accepted_shares,
+                         "rejected": c.rejected_shares, "last_submit_ms": c.last_submit_ms} for c in online]
+        workers = [w for w in self.pool.hashrate.workers() if w["address"] == address]
+        db = get_db()
+        with db.session() as s:
+            row = s.query(PoolMiner).filter_by(address=address).first()
+            if row is None and not sessions and not workers:
+                raise ApiError(404, "unknown miner")
+            # a miner connected moments ago may not be flushed to pool_miners yet
+            out: Dict[str, Any] = {
+                "address": address,
+                "accepted_shares": row.accepted_shares if row else 0,
+                "rejected_shares": row.rejected_shares if row else 0,
+                "blocks_found": row.blocks_found if row else 0,
+                "immature_balance": row.immature_balance if row else 0.0,
+                "pending_balance": row.pending_balance if row else 0.0,
+                "paid_total": row.paid_total if row else 0.0,
+                "last_submit_ms": row.last_submit_ms if row else 0,
+            }
+        out["hashrate"] = {name: sum(w["hashrate"][name] for w in workers) for name, _ in WINDOWS}
+        out["workers"] = [{"worker": w["worker"], "hashrate": w["hashrate"]} for w in workers]
+        out["sessions"] = sessions
+        return 200, out
+
+    def miner_shares(self, query, address: str) -> Response:
+        page, limit = _page(query)
+        worker = (query.get("worker") or [None])[0]
+        status = (query.get("status") or [""])[0]
+        if status not in ("", "accepted", "rejected"):
+            raise ApiError(400, "status must be accepted or rejected")
+        db = get_db()
+        with db.session() as s:
+            q = s.query(PoolShare).filter(PoolShare.address == address)
+            if worker is not None:
+                q = q.filter(PoolShare.worker == worker)
+            if status:
+                q = q.filter(PoolShare.accepted.is_(status == "accepted"))
+            q = q.order_by(PoolShare.created_ms.desc(), PoolShare.id.desc())
+            return 200, _paginated(q, page, limit, _share)
+
+    def blocks(self, query) -> Response:
+        page, limit = _page(query)
+        db = get_db()
+        with db.session() as s:
+            q = s.query(PoolBlock).order_by(PoolBlock.height.desc(), PoolBlock.id.desc())
+            return 200, _paginated(q, page, limit, _block)
+
+    def payments(self, query) -> Response:
+        page, limit = _page(query)
+        address = (query.get("address") or [""])[0]
+        db = get_db()
+        with db.session() as s:
+            q = s.query(PoolPayout)
+            if address:
+                q = q.filter(PoolPayout.address == address)
+            q = q.order_by(PoolPayout.created_ms.desc(), PoolPayout.id.desc())
+            return 200, _paginated(q, page, limit, _payment)
+
+
+def _cors_origin(origins: List[str], origin: Optional[str]) -> Optional[str]:
+    if "*" in origins:
+        return "*"
+    return origin if origin and origin in origins else None
+
+
+def serve(pool, host: str, port: int):
+    """Start the API server in the background; returns the server (shutdown() to stop)."""
+    from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+    api = PoolApi(pool)
+    origins = [str(o) for o in (get_config().get("pool.api_cors_origins", []) or [])]
+
+    class Handler(BaseHTTPRequestHandler):
+        def _cors(self):
+            allow = _cors_origin(origins, self.headers.get("Origin"))
+            if allow:
+                self.send_header("Access-Control-Allow-Origin", allow)
+                self.send_header("Vary", "Origin")
+
+        def do_OPTIONS(self):
+            self.send_response(204)
+            self._cors()
+            self.send_header("Access-Control-Allow-Methods", "GET, OPTIONS")
+            self.send_header("Access-Control-Allow-Headers", "Content-Type")
+            self.send_header("Access-Control-Max-Age", "600")
+            self.end_headers()
+
+        def do_GET(self):
+            url = urlsplit(self.path)
+            try:
+                status, obj = api.dispatch(url.path, parse_qs(url.query))
+            except Exception as e:
+                log.error(f"pool api {url.path} failed: {e}")
+                status, obj = 500, {"error": "internal error"}
+            body = json.dumps(obj, separators=(",", ":")).encode("utf-8")
+            self.send_response(status)
+            self._cors()
+            self.send_header("Content-Type", "application/json")
+            self.send_header("Content-Length", str(len(body)))
+            self.end_headers()
+            self.wfile.write(body)
+
+        def log_message(self, *args):
+            pass
+
+    srv = ThreadingHTTPServer((host, port), Handler)
+    threading.Thread(target=srv.serve_forever, name="pool-api", daemon=True).start()
+    return srv
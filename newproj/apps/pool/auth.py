@@ -0,0 +1,116 @@
+from __future__ import annotations
+
+import hashlib
+import hmac
+import os
+from typing import Dict, Optional
+
+from core.config import get_config
+from core.crypto import check_address
+from core.db import get_db, PoolMiner
+from core.utils import now_ms
+
+
+# Stratum worker authentication (mining.authorize ["address.worker", "password"]).
+#
+# Providers answer authenticate(address, worker, password) -> error string or None:
+#   address  any address that passes core.crypto.check_address (open pool, the default)
+#   static   only addresses listed in pool.auth_static {address: password}; "" = no password
+#   db       only addresses registered in pool_miners with a password (register_account, or
+#            `python apps/pool/stratum_server.py --register ADDRESS`, which prompts for the password
+#            or reads it from stdin with --password-stdin, so it never appears in argv)
+# Every mode still requires a well-formed address for this network. Failed attempts are counted
+# per remote host by the Stratum server, which bans the host after pool.max_auth_failures within
+# pool.auth_failure_window_sec (reconnecting does not reset the count).
+
+_PBKDF2_ROUNDS = 100_000
+
+
+class AuthProvider:
+    name = "base"
+
+    def authenticate(self, address: str, worker: str, password: str) -> Optional[str]:
+        return check_address(address)
+
+
+class AddressFormatAuth(AuthProvider):
+    name = "address"
+
+
+class StaticAuth(AuthProvider):
+    name = "static"
+
+    def __init__(self, entries: Dict[str, str]):
+        self.entries = {str(k): str(v or "") for k, v in (entries or {}).items()}
+
+    def authenticate(self, address: str, worker: str, password: str) -> Optional[str]:
+        err = check_address(address)
+        if err:
+            return err
+        if address not in self.entries:
+            return "address not registered with this pool"
+        want = self.entries[address]
+        if want and not hmac.compare_digest(want.encode("utf-8"), (password or "").encode("utf-8")):
+            return "bad password"
+        return None
+
+
+def _hash_password(password: str, salt: Optional[bytes] = None) -> str:
+    salt = salt or os.urandom(16)
+    dk = hashlib.pbkdf2_hmac("sha256", password.encode("utf-8"), salt, _PBKDF2_ROUNDS)
+    return f"{salt.hex()}${dk.hex()}"
+
+
+def _check_password(stored: str, password: str) -> bool:
+    try:
+        salt_hex, _ = stored.split("$", 1)
+        return hmac.compare_digest(_hash_password(password or "", bytes.fromhex(salt_hex)), stored)
+    except ValueError:
+        return False
+
+
+class PoolStoreAuth(AuthProvider):
+    name = "db"
+
+    def authenticate(self, address: str, worker: str, password: str) -> Optional[str]:
+        err = check_address(address)
+        if err:
+            return err
+        db = get_db()
+        with db.session() as s:
+            row = s.query(PoolMiner).filter_by(address=address).first()
+            stored = row.auth_hash if row else None
+        if not stored:
+            return "address not registered with this pool"
+        if not _check_password(stored, password):
+            return "bad password"
+        return None
+
+
+def register_account(address: str, password: str) -> None:
+    """Create or update the pool_miners row for address with a login password (db mode)."""
+    err = check_address(address)
+    if err:
+        raise ValueError(err)
+    if not password:
+        raise ValueError("password required")
+    db = get_db()
+    with db.session() as s:
+        row = s.query(PoolMiner).filter_by(address=address).first()
+        if row is None:
+            row = PoolMiner(address=address, created_ms=now_ms())
+            s.add(row)
+        row.auth_hash = _hash_password(password)
+        s.commit()
+
+
+def make_auth_provider(mode: Optional[str] = None) -> AuthProvider:
+    cfg = get_config()
+    mode = (mode or str(cfg.get("pool.auth_mode", "address"))).strip().lower()
+    if mode == "static":
+        return StaticAuth(cfg.get("pool.auth_static", {}) or {})
+    if mode == "db":
+        return PoolStoreAuth()
+    if mode != "address":
+        raise ValueError(f"unknown pool.auth_mode {mode!r} (address|static|db)")
+    return AddressFormatAuth()
@@ -0,0 +1,77 @@
+from __future__ import annotations
+
+import threading
+from collections import deque
+from typing import Deque, Dict, List, Optional, Tuple
+
+from core.utils import now_ms
+
+
+# Hashrate estimation from accepted shares.
+#
+# A share at difficulty d (target = U256_MAX // d, core.target) takes d hashes on average, so the
+# work behind a window of shares is the sum of their difficulties and the hashrate is that sum
+# over the window length. Counting shares alone breaks whenever vardiff retargets a session.
+# Shares are kept per (address, worker) for the longest window (WINDOWS) and summed on demand;
+# a worker seen for less than a window is divided by the time since its first share instead
+# (at least MIN_SPAN_SEC), so a new worker is not reported at a fraction of its real rate.
+
+WINDOWS: Tuple[Tuple[str, int], ...] = (("1m", 60), ("15m", 900), ("1h", 3600))
+MIN_SPAN_SEC = 60
+
+Key = Tuple[str, str]  # (address, worker)
+
+
+class HashrateTracker:
+    def __init__(self):
+        self._lock = threading.Lock()
+        self._shares: Dict[Key, Deque[Tuple[int, int]]] = {}  # key -> [(ms, share_diff)]
+        self._first_ms: Dict[Key, int] = {}
+
+    def add(self, address: str, worker: str, share_diff: int, ts_ms: Optional[int] = None):
+        ts = now_ms() if ts_ms is None else int(ts_ms)
+        key = (address, worker or "")
+        with self._lock:
+            self._shares.setdefault(key, deque()).append((ts, max(1, int(share_diff))))
+            self._first_ms[key] = min(ts, self._first_ms.get(key, ts))
+
+    def prune(self, nowm: Optional[int] = None):
+        """Forget shares older than the longest window, and workers with none left."""
+        cutoff = (now_ms() if nowm is None else nowm) - WINDOWS[-1][1] * 1000
+        with self._lock:
+            for key in list(self._shares):
+                q = self._shares[key]
+                while q and q[0][0] < cutoff:
+                    q.popleft()
+                if not q:
+                    del self._shares[key]
+                    self._first_ms.pop(key, None)
+
+    def _rates(self, keys: List[Key], nowm: int) -> Dict[str, float]:
+        out: Dict[str, float] = {}
+        first = min((self._first_ms[k] for k in keys), default=nowm)
+        for name, sec in WINDOWS:
+            start = nowm - sec * 1000
+            work = sum(d for k in keys for ts, d in self._shares[k] if ts >= start)
+            span = min(sec, max(MIN_SPAN_SEC, (nowm - first) / 1000.0))
+            out[name] = work / span
+        return out
+
+    def worker(self, address: str, worker: str = "", nowm: Optional[int] = None) -> Dict[str, float]:
+        nowm = now_ms() if nowm is None else nowm
+        key = (address, worker or "")
+        with self._lock:
+            return self._rates([key] if key in self._shares else [], nowm)
+
+    def workers(self, nowm: Optional[int] = None) -> List[Dict[str, object]]:
+        nowm = now_ms() if nowm is None else nowm
+        with self._lock:
+            return [
+                {"address": a, "worker": w, "hashrate": self._rates([(a, w)], nowm)}
+                for (a, w) in sorted(self._shares)
+            ]
+
+    def pool(self, nowm: Optional[int] = None) -> Dict[str, float]:
+        nowm = now_ms() if nowm is None else nowm
+        with self._lock:
+            return self._rates(list(self._shares), nowm)
@@ -0,0 +1,73 @@
+from __future__ import annotations
+
+import json
+from typing import Any, NamedTuple, Optional
+
+from core.crypto import parse_worker
+
+
+# Stratum wire parsing, kept free of server state so it can be fuzzed on its own (tools/fuzz.py).
+#
+# A request is one JSON object per line. parse_request and parse_submit raise ValueError (and only
+# ValueError) for anything malformed; the server turns that into "Parse error" / "Invalid params".
+
+MAX_U64 = (1 << 64) - 1
+
+
+class Submit(NamedTuple):
+    address: str
+    worker: str
+    job_id: str
+    nonce: int
+    timestamp: int
+    merkle_root_hex: str
+    version: int
+    prev_hash_hex: Optional[str]  # lowercase, or None for the old 6-field schema
+
+
+def parse_request(line: bytes) -> dict:
+    try:
+        msg = json.loads(line.decode("utf-8").strip())
+    except UnicodeDecodeError as e:
+        raise ValueError(f"not utf-8: {e}")
+    except RecursionError:
+        raise ValueError("nested too deeply")
+    if not isinstance(msg, dict):
+        raise ValueError("not an object")
+    return msg
+
+
+def _int(val: Any, name: str, lo: int, hi: int) -> int:
+    # bool is an int subclass; "nonce": true is a client bug, not nonce 1
+    if isinstance(val, bool) or not isinstance(val, (int, str)):
+        raise ValueError(f"{name}: integer expected")
+    try:
+        n = int(val)
+    except ValueError:
+        raise ValueError(f"{name}: integer expected")
+    if not (lo <= n <= hi):
+        raise ValueError(f"{name}: out of range")
+    return n
+
+
+def parse_submit(params: Any) -> Submit:
+    """mining.submit params: [address[.worker], job_id, nonce, timestamp, merkle_root_hex, version, prev_hash_hex?]."""
+    if not isinstance(params, list) or len(params) < 6:
+        raise ValueError("expected at least 6 params")
+    login, job_id, nonce, timestamp, merkle_root_hex, version = params[:6]
+    if not isinstance(login, str) or not isinstance(job_id, str) or not isinstance(merkle_root_hex, str):
+        raise ValueError("address, job_id and merkle_root must be strings")
+    prev = params[6] if len(params) >= 7 else None
+    if prev is not None and not isinstance(prev, str):
+        raise ValueError("prev_hash must be a string")
+    address, worker = parse_worker(login)
+    return Submit(
+        address=address,
+        worker=worker,
+        job_id=job_id,
+        nonce=_int(nonce, "nonce", 0, MAX_U64),
+        timestamp=_int(timestamp, "timestamp", 0, MAX_U64),
+        merkle_root_hex=merkle_root_hex,
+        version=_int(version, "version", 0, 0xFFFFFFFF),
+        prev_hash_hex=prev.lower() if prev else None,
+    )
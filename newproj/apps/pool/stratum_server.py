@@ -2,31 +2,66 @@ from __future__ import annotations
 
 import socket
 import threading
+from concurrent.futures import ThreadPoolExecutor
 import json
 import time
-from typing import Dict, Optional, List, Tuple
+from collections import OrderedDict
+from typing import Dict, Optional, List, Set, Tuple
 
 import httpx
+from sqlalchemy import and_, or_
+from sqlalchemy.exc import OperationalError
 import traceback
 import sys
 
 from core.config import get_config
 from core.utils import now_ms, sha3_256_hex
-from core.consensus import Header, get_chain_height, get_header_by_height
-from core.pow.randomx_stub import difficulty_to_target
+from core.crypto import parse_worker
+from core.merkle import merkle_root_mutated
+from core.consensus import Header, get_chain_height, compute_block_reward, coinbase_maturity
+from core.target import difficulty_to_target, hash_meets_target
 from core.pow.pow_backend import pow_hash
-from core.db import get_db, KV
+from core.db import get_db, nonce_to_db, BlockHeader, KV, PoolMiner, PoolShare, PoolBlock, PoolBlockEvent
+from core import safemode
+from core import metrics
+from core import logs
+from core import rpcauth
+from apps.pool.auth import AuthProvider, make_auth_provider, register_account
+from apps.pool.hashrate import HashrateTracker
+from apps.pool.protocol import parse_request, parse_submit
 
 
 # Minimal Stratum-like protocol (enhanced)
 # Messages are JSON per line. Methods:
 # - mining.subscribe -> {id, result: [session_id], error:null}
-# - mining.authorize {"params":[address]} -> ok
+# - mining.authorize {"params":["address" or "address.worker", password?]} -> ok per pool.auth_mode (apps.pool.auth)
+# - mining.extranonce.subscribe -> true, then mining.set_extranonce [extranonce1_hex, extranonce2_size]
 # - mining.get_job -> returns current job {job_id, template:{prev_hash,version,target,txids,timestamp}, pool_target}
 # - mining.submit {"params":[address, job_id, nonce, timestamp, merkle_root_hex, version]} -> share accept/reject
+#   (address must be the one the session authorized as; resubmitting the same nonce/timestamp/version
+#   for a job is rejected as "Duplicate share" without hashing)
+# - mining.ping -> "pong"; the server also sends {"id": "ping-N", "method": "mining.ping"} to sessions
+#   quiet for pool.keepalive_interval_sec and expects any reply within pool.keepalive_timeout_sec
+# The same messages are accepted as WebSocket text frames on pool.ws_port (apps.pool.ws_bridge).
 #
 # Server verifies share using pow_backend; if hash <= network target, promotes via accept_external_header()
 # to append a block. KV stats can be read by explorer for a dashboard.
+#
+# Dead sessions are reaped by the keepalive thread: a session that answered pings before and now
+# sends nothing within the response timeout, one silent for pool.idle_timeout_sec (clients without
+# ping support), or one whose write has been blocked past the response timeout (half-open TCP with
+# a full send buffer). Dropping shuts the socket down, which fails any blocked write and ends the
+# reader thread, and removes the session from the client map at once; a failed write drops the
+# session the same way. Broadcasts write outside the client-map lock so one stuck peer cannot
+# stall the others.
+
+
+_SESSIONS = metrics.gauge("smelly_stratum_sessions", "Open Stratum sessions", ("state",))
+_SHARES = metrics.counter("smelly_stratum_shares_total", "Shares by result", ("result",))
+_BLOCKS = metrics.counter("smelly_stratum_blocks_found_total", "Blocks found by pool miners")
+_HASHRATE = metrics.gauge("smelly_pool_hashrate", "Pool hashrate from accepted share difficulty (H/s)", ("window",))
+_REFUSED = metrics.counter("smelly_stratum_refused_total", "Connections refused or dropped by limits", ("reason",))
+_REAPED = metrics.counter("smelly_stratum_reaped_total", "Sessions dropped as dead", ("reason",))
 
 
 class MiningJob:
@@ -36,10 +71,33 @@ class MiningJob:
         self.version = version
         self.target_hex = target_hex  # network target hint (from node tip or easy bootstrap)
         self.timestamp = timestamp
-        self.txids = txids  # coinbase + snapshot txids
+        self.txids = txids  # coinbase + snapshot txids, exactly as the node issued them for job_id
+        # Submissions must commit to this root; the node rebuilds the block from the same txids
+        self.merkle_root_hex, self.merkle_mutated = merkle_root_mutated(txids)
         self.pool_diff = pool_diff
         self.pool_target_hex = difficulty_to_target(pool_diff)
         self.created_ms = now_ms()
+        # Accounting (updated under StratumPool.lock): who was sent the job and what it earned
+        self.sessions: Set[str] = set()  # conn.addr of sessions notified with this job
+        self.shares_accepted = 0
+        self.shares_rejected = 0
+        self.work_by_address: Dict[str, int] = {}  # sum of accepted share difficulty per payout address
+        self.blocks_found = 0
+        self.submissions: Set[Tuple[str, int, int, int]] = set()  # (address, nonce, timestamp, version) seen for this job
+
+    def stats(self) -> Dict[str, object]:
+        return {
+            "job_id": self.job_id,
+            "prev_hash": self.prev_hash,
+            "created_ms": self.created_ms,
+            "tx_count": len(self.txids),
+            "sessions": len(self.sessions),
+            "shares_accepted": self.shares_accepted,
+            "shares_rejected": self.shares_rejected,
+            "share_work": sum(self.work_by_address.values()),
+            "work_by_address": dict(self.work_by_address),
+            "blocks_found": self.blocks_found,
+        }
 
     def to_template(self) -> Dict[str, object]:
         return {
@@ -52,24 +110,97 @@ class MiningJob:
 
 
 class MinerConn:
-    def __init__(self, sock: socket.socket, addr: str):
+    def __init__(self, sock: socket.socket, addr: str, file=None):
         self.sock = sock
         self.addr = addr
-        self.file = sock.makefile(mode="rwb")
+        self.file = file if file is not None else sock.makefile(mode="rwb")  # WS sessions pass a WsLineStream
+        self.send_lock = threading.Lock()  # replies come from the verify pool as well as the session thread
+        self.vardiff_lock = threading.Lock()  # shares of one session may settle on several verify threads
         self.address: Optional[str] = None
+        self.worker = ""
         self.alive = True
         self.accepted_shares = 0
         self.rejected_shares = 0
         self.last_submit_ms = 0
         self.hashes_5s = 0  # rough hashrate proxy from share attempts
+        # Per-session share difficulty, retargeted by vardiff from the observed share rate
+        self.share_diff = max(1, int(get_config().get("pool.start_share_diff", 1)))
+        self.vardiff_window_start_ms = now_ms()
+        self.vardiff_shares = 0
+        self.extranonce1 = 0  # high nonce bytes reserved for this session (advisory)
+        self.host = addr.rsplit(":", 1)[0]
+        self.bucket: Optional[TokenBucket] = None
+        self.parse_errors = 0
+        self.rate_strikes = 0
+        self.extranonce_enforced = False  # set once the client sends mining.subscribe itself
+        self.extranonce_subscribed = False  # mining.extranonce.subscribe (NiceHash-style)
+        self.cid = -1
+        # Keepalive (StratumPool.reap_sessions)
+        self.last_recv_ms = now_ms()
+        self.ping_id: Optional[str] = None
+        self.ping_sent_ms = 0
+        self.answers_pings = False  # replied to one of our pings, so a missed reply means dead
+        self.write_started_ms = 0  # set while a write is in progress
 
 
-def _c(code: str, text: str) -> str:
-    # ANSI color helper (works in most terminals; Windows Terminal/VSCode okay)
-    return f"\033[{code}m{text}\033[0m"
+class TokenBucket:
+    """Request rate limiter: `rate` tokens/sec refill up to `burst`; each request takes one."""
+
+    def __init__(self, rate: float, burst: float):
+        self.rate = float(rate)
+        self.burst = float(burst)
+        self.tokens = float(burst)
+        self.last = time.monotonic()
+
+    def take(self) -> bool:
+        nowt = time.monotonic()
+        self.tokens = min(self.burst, self.tokens + (nowt - self.last) * self.rate)
+        self.last = nowt
+        if self.tokens < 1.0:
+            return False
+        self.tokens -= 1.0
+        return True
+
+
+class MinerInfo:
+    """Per-address pool accounting; persisted to pool_miners and survives restarts."""
+
+    def __init__(self, address: str):
+        self.address = address
+        self.accepted_shares = 0
+        self.rejected_shares = 0
+        self.blocks_found = 0
+        self.immature_balance = 0.0  # rewards from found blocks still below coinbase maturity
+        self.pending_balance = 0.0  # matured (spendable) and not yet paid out
+        self.paid_total = 0.0
+        self.last_submit_ms = 0
+        self.dirty = False
+
+
+class SubmitResult:
+    """Outcome of process_submission: a pool share, a share that also solves the block, or a rejection."""
+
+    SHARE_ACCEPTED = "ShareAccepted"
+    BLOCK_FOUND = "BlockFound"
+    REJECTED = "Rejected"
+
+    def __init__(self, kind: str, digest: bytes = b"", reason: Optional[str] = None):
+        self.kind = kind
+        self.digest = digest
+        self.reason = reason
+
+    @property
+    def accepted(self) -> bool:
+        return self.kind != SubmitResult.REJECTED
+
+
+slog = logs.get("stratum")
+plog = logs.get("pool")
 
 
 class StratumPool:
+    MAX_PENDING_SHARES = 100_000  # share rows held for retry while the database is unavailable
+
     def __init__(self, host: str, port: int):
         self.host = host
         self.port = port
@@ -84,304 +215,1064 @@ class StratumPool:
         # rolling counters for dashboard
         self._accepted_recent: List[Tuple[int, str]] = []  # [(ms, addr), ...]
         self._rejected_recent: List[Tuple[int, str]] = []
+        self.hashrate = HashrateTracker()  # difficulty-weighted shares per worker (apps.pool.hashrate)
         # Node RPC base for job templating
         cfg = get_config()
+        # Recently issued jobs by id, so a share for a just-rotated job is checked against its own tx set.
+        # Kept up to pool.max_jobs and pool.job_expiry_sec (the node forgets job ids after 5 minutes).
+        self.recent_jobs: "OrderedDict[str, MiningJob]" = OrderedDict()
+        self._job_lock = threading.Lock()  # guards current_job swaps (job loop vs rotation after a block)
+        self.max_jobs = max(1, int(cfg.get("pool.max_jobs", 16)))
+        self.job_expiry_ms = int(cfg.get("pool.job_expiry_sec", 300)) * 1000
         self.node_base = f"http://{cfg.get('network.rpc_host','127.0.0.1')}:{cfg.get('network.rpc_port',28445)}"
         # Static job mode (disables rotation except on successful block or explicit tip advance)
         self.static_job_mode = True
+        # Persistent accounting (pool_miners/pool_shares/pool_blocks); loaded in start(), flushed by snapshot loop and stop()
+        self.miners: Dict[str, MinerInfo] = {}
+        self._pending_shares: List[dict] = []
+        self._stopping = threading.Event()
+        # Abuse limits (pool.* config; attributes may be overridden before start())
+        self.max_conns = int(cfg.get("pool.max_conns", 1024))
+        self.max_conns_per_ip = int(cfg.get("pool.max_conns_per_ip", 16))
+        self.rate_per_sec = float(cfg.get("pool.rate_limit_per_sec", 20))
+        self.rate_burst = float(cfg.get("pool.rate_limit_burst", 60))
+        self.max_rate_strikes = int(cfg.get("pool.max_rate_strikes", 20))
+        self.max_parse_errors = int(cfg.get("pool.max_parse_errors", 5))
+        self.max_line_bytes = int(cfg.get("pool.max_line_bytes", 16384))
+        self.ban_sec = int(cfg.get("pool.ban_sec", 600))
+        self._banned: Dict[str, int] = {}  # host -> banned_until_ms
+        self.max_auth_failures = int(cfg.get("pool.max_auth_failures", 5))
+        self.auth_failure_window_ms = int(cfg.get("pool.auth_failure_window_sec", 600)) * 1000
+        self._auth_failures: Dict[str, List[int]] = {}  # host -> failed authorize times (ms), kept per host across reconnects
+        self.enforce_extranonce = bool(cfg.get("pool.enforce_extranonce", True))
+        # Share verification pool: PoW checks run off the session threads, at most pool.verify_queue
+        # outstanding (further submits get "Server busy" instead of piling up behind slow hashes)
+        self._verify_pool = ThreadPoolExecutor(max_workers=max(1, int(cfg.get("pool.verify_workers", 4))),
+                                               thread_name_prefix="share-verify")
+        self._verify_slots = threading.BoundedSemaphore(max(1, int(cfg.get("pool.verify_queue", 256))))
+        # Stratum over WebSocket for browser miners (apps.pool.ws_bridge); 0 = off
+        self.ws_port = int(cfg.get("pool.ws_port", 0))
+        self.ws_origins: List[str] = [str(o) for o in (cfg.get("pool.ws_origins", []) or [])]
+        self._ws_server = None
+        # Miner dashboard HTTP API (apps.pool.api); 0 = off
+        self.api_port = int(cfg.get("pool.api_port", 0))
+        self._api_server = None
+        self._next_extranonce1 = 0
+        # Worker authentication (pool.auth_mode: address | static | db); replaceable before start()
+        self.auth: AuthProvider = make_auth_provider()
+        # Keepalive pings and dead-session reaping (0 disables the respective check)
+        self.keepalive_interval_ms = int(float(cfg.get("pool.keepalive_interval_sec", 60)) * 1000)
+        self.keepalive_timeout_ms = int(float(cfg.get("pool.keepalive_timeout_sec", 30)) * 1000)
+        self.idle_timeout_ms = int(float(cfg.get("pool.idle_timeout_sec", 300)) * 1000)
+        self._ping_seq = 0
+        # Pings are written off the reaper thread so a peer with a full send buffer cannot block it
+        self._ping_pool = ThreadPoolExecutor(max_workers=2, thread_name_prefix="stratum-ping")
 
     def start(self):
+        self._load_state()
         s = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
         s.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
         s.bind((self.host, self.port))
         s.listen(50)
         self.server = s
-        print(_c("1;33", f"Stratum pool listening on {self.host}:{self.port}"))
-        print(_c("36", f"[DEBUG] static_job_mode={self.static_job_mode} node_base={self.node_base}"))
+        slog.info(f"Stratum pool listening on {self.host}:{self.port}")
+        slog.debug(f"static_job_mode={self.static_job_mode} node_base={self.node_base}")
+        metrics_port = int(get_config().get("pool.metrics_port", 0))
+        if metrics_port > 0:
+            metrics.start_http_server(self.host, metrics_port)
+            slog.info(f"Pool metrics on http://{self.host}:{metrics_port}/metrics")
+
+        if self.ws_port > 0:
+            from apps.pool import ws_bridge
+
+            self._ws_server = ws_bridge.serve(self._ws_session, self.host, self.ws_port, self.ws_origins,
+                                              self.max_line_bytes)
+            origins = ", ".join(self.ws_origins) or "any origin"
+            slog.info(f"Stratum WebSocket bridge on ws://{self.host}:{self.ws_port} ({origins})")
+
+        if self.api_port > 0:
+            from apps.pool import api
+
+            self._api_server = api.serve(self, self.host, self.api_port)
+            plog.info(f"Pool API on http://{self.host}:{self.api_port}/api/pool/stats")
 
         # Job producer and snapshot threads
         threading.Thread(target=self._job_loop, daemon=True).start()
         threading.Thread(target=self._snapshot_loop, daemon=True).start()
+        threading.Thread(target=self._job_maintenance_loop, daemon=True).start()
+        threading.Thread(target=self._keepalive_loop, daemon=True).start()
 
-        while True:
-            client_sock, (chost, cport) = s.accept()
+        while not self._stopping.is_set():
+            try:
+                client_sock, (chost, cport) = s.accept()
+            except OSError:
+                break
+            refuse = self._refuse_reason(chost)
+            if refuse:
+                _REFUSED.inc(labels=(refuse.split(" (")[0],))
+                plog.warning(f"refused {chost}:{cport}: {refuse}")
+                try:
+                    client_sock.close()
+                except OSError:
+                    pass
+                continue
+            try:
+                # OS-level probes as a backstop for half-open peers between our own pings
+                client_sock.setsockopt(socket.SOL_SOCKET, socket.SO_KEEPALIVE, 1)
+            except OSError:
+                pass
             conn = MinerConn(client_sock, f"{chost}:{cport}")
-            with self.lock:
-                cid = self._client_id
-                self._client_id += 1
-                self.clients[cid] = conn
+            cid = self._register(conn)
             threading.Thread(target=self._handle_client, args=(cid, conn), daemon=True).start()
 
-    def _broadcast_job(self):
-        if not self.current_job:
+    def _register(self, conn: MinerConn) -> int:
+        conn.bucket = TokenBucket(self.rate_per_sec, self.rate_burst)
+        with self.lock:
+            cid = self._client_id
+            self._client_id += 1
+            conn.extranonce1 = self._alloc_extranonce1()
+            conn.cid = cid
+            self.clients[cid] = conn
+        return cid
+
+    def _ws_session(self, ws):
+        """One WebSocket miner, run on the bridge's connection thread (same limits as TCP)."""
+        from apps.pool.ws_bridge import WsLineStream
+
+        chost, cport = ws.remote_address[:2]
+        refuse = self._refuse_reason(chost)
+        if refuse:
+            _REFUSED.inc(labels=(refuse.split(" (")[0],))
+            plog.warning(f"refused ws {chost}:{cport}: {refuse}")
+            ws.close(1008, refuse[:120])
+            return
+        conn = MinerConn(ws.socket, f"{chost}:{cport}", file=WsLineStream(ws))
+        self._handle_client(self._register(conn), conn)
+
+    # ----- connection limits / bans -----
+
+    def _refuse_reason(self, host: str) -> Optional[str]:
+        nowm = now_ms()
+        with self.lock:
+            until = self._banned.get(host, 0)
+            if until > nowm:
+                return "banned"
+            self._banned.pop(host, None)
+            if len(self.clients) >= self.max_conns:
+                return f"server full ({self.max_conns})"
+            if sum(1 for c in self.clients.values() if c.host == host) >= self.max_conns_per_ip:
+                return f"too many connections from host ({self.max_conns_per_ip})"
+        return None
+
+    def ban(self, host: str, reason: str, duration_sec: Optional[int] = None):
+        duration_sec = int(self.ban_sec if duration_sec is None else duration_sec)
+        until = now_ms() + duration_sec * 1000
+        with self.lock:
+            self._banned[host] = until
+            victims = [c for c in self.clients.values() if c.host == host]
+        for c in victims:
+            self._drop(c)
+        _REFUSED.inc(labels=("banned_live",))
+        plog.warning(f"banned {host} for {duration_sec}s: {reason}")
+
+    def _drop(self, conn: MinerConn):
+        """End a session now: unblocks its reader and any stuck writer, and forgets it."""
+        conn.alive = False
+        with self.lock:
+            if self.clients.get(conn.cid) is conn:
+                del self.clients[conn.cid]
+        try:
+            conn.sock.shutdown(socket.SHUT_RDWR)
+        except OSError:
+            pass
+
+    def _note_auth_failure(self, host: str) -> int:
+        """Count a failed authorize against host; returns its failures within the window (bans at the limit)."""
+        nowm = now_ms()
+        cutoff = nowm - self.auth_failure_window_ms
+        with self.lock:
+            for h in [h for h, ts in self._auth_failures.items() if ts[-1] < cutoff]:
+                del self._auth_failures[h]
+            failures = [t for t in self._auth_failures.get(host, []) if t >= cutoff] + [nowm]
+            self._auth_failures[host] = failures
+            if len(failures) >= self.max_auth_failures:
+                del self._auth_failures[host]
+        if len(failures) >= self.max_auth_failures:
+            self.ban(host, f"{len(failures)} failed authorizations in {self.auth_failure_window_ms // 1000}s")
+        return len(failures)
+
+    def list_banned(self) -> Dict[str, int]:
+        nowm = now_ms()
+        with self.lock:
+            return {h: u for h, u in self._banned.items() if u > nowm}
+
+    def stop(self):
+        """Stop accepting miners and flush accounting so balances survive restarts."""
+        self._stopping.set()
+        try:
+            if self.server:
+                self.server.close()
+            if self._ws_server is not None:
+                self._ws_server.shutdown()
+            if self._api_server is not None:
+                self._api_server.shutdown()
+        except Exception:
+            pass
+        # Let queued shares finish so their accounting is flushed below
+        self._ping_pool.shutdown(wait=False)
+        self._verify_pool.shutdown(wait=True)
+        self._flush_state()
+        plog.info("stopped; accounting flushed")
+
+    # ----- persistent accounting -----
+
+    def _load_state(self):
+        db = get_db()
+        with db.session() as s:
+            for row in s.query(PoolMiner).all():
+                mi = MinerInfo(row.address)
+                mi.accepted_shares = int(row.accepted_shares or 0)
+                mi.rejected_shares = int(row.rejected_shares or 0)
+                mi.blocks_found = int(row.blocks_found or 0)
+                mi.pending_balance = float(row.pending_balance or 0.0)
+                mi.immature_balance = float(row.immature_balance or 0.0)
+                mi.paid_total = float(row.paid_total or 0.0)
+                mi.last_submit_ms = int(row.last_submit_ms or 0)
+                self.miners[row.address] = mi
+        plog.info(f"loaded {len(self.miners)} miners from pool DB")
+
+    def _miner(self, address: str) -> MinerInfo:
+        # caller holds self.lock
+        mi = self.miners.get(address)
+        if mi is None:
+            mi = MinerInfo(address)
+            self.miners[address] = mi
+        return mi
+
+    def _record_share(self, address: str, job_id: str, nonce: int, accepted: bool, reason: Optional[str] = None,
+                      share_diff: Optional[int] = None, worker: str = "", digest: bytes = b"", target_hex: Optional[str] = None):
+        """
+        Count the share and queue its row for pool_shares, the append-only share log miners can
+        query (/api/miner/<address>/shares): verdict and reason plus the computed hash and the
+        share target it was checked against (none for shares rejected before hashing).
+        """
+        nowm = now_ms()
+        _SHARES.inc(labels=("accepted" if accepted else "rejected",))
+        with self.lock:
+            mi = self._miner(address)
+            if accepted:
+                mi.accepted_shares += 1
+            else:
+                mi.rejected_shares += 1
+            mi.last_submit_ms = nowm
+            mi.dirty = True
+            self._pending_shares.append({
+                "address": address,
+                "worker": worker or "",
+                "job_id": str(job_id),
+                "nonce": int(nonce) & ((1 << 64) - 1),
+                "share_diff": int(share_diff if share_diff is not None else self.pool_diff),
+                "hash_hex": digest.hex() if digest else None,
+                "target_hex": target_hex,
+                "accepted": accepted,
+                "reason": reason,
+                "created_ms": nowm,
+            })
+
+    def _record_block(self, address: str, hash_hex: str, height: int):
+        # Reward stays immature until the block is required_confirmations() deep (see _track_blocks).
+        # The finder's coinbase is (1 - fairness.pool_ratio) of the subsidy; the rest accrues to the epoch pool.
+        pool_ratio = float(get_config().get("fairness.pool_ratio", 0.30))
+        reward = compute_block_reward(height) * (1.0 - pool_ratio) if height >= 0 else 0.0
+        _BLOCKS.inc()
+        with self.lock:
+            mi = self._miner(address)
+            mi.blocks_found += 1
+            mi.immature_balance += reward
+            mi.dirty = True
+        db = get_db()
+        try:
+            with db.session() as s:
+                if not s.query(PoolBlock).filter_by(hash_hex=hash_hex).first():
+                    s.add(PoolBlock(height=height, hash_hex=hash_hex, finder_address=address, reward=reward,
+                                    status="immature", created_ms=now_ms(), confirmations=1, status_ms=now_ms()))
+                    s.commit()
+        except Exception as e:
+            plog.error(f"record block error: {e}")
+        self._flush_state()
+
+    def required_confirmations(self) -> int:
+        """pool.block_confirmations, never below coinbase maturity (0 = coinbase maturity)."""
+        return max(coinbase_maturity(), int(get_config().get("pool.block_confirmations", 0)))
+
+    def _track_blocks(self):
+        """
+        Follow pool-found blocks until final. Rewards are held as immature (pending) meanwhile:
+          immature -> orphaned  the main chain no longer has it at its height; reward taken back
+          orphaned -> immature  a reorg brought it back before it was final; reward held again
+          immature -> mature    required_confirmations() deep; reward moves to spendable
+        An orphaned block is final once the chain is that deep past its height. Confirmations are
+        updated on every pass and each transition is recorded in pool_block_events.
+        (Rows with legacy status "found" were credited as spendable when found and are left alone.)
+        """
+        tip = get_chain_height()
+        required = self.required_confirmations()
+        nowm = now_ms()
+        db = get_db()
+        try:
+            with db.session() as s:
+                rows = (
+                    s.query(PoolBlock)
+                    .filter(or_(PoolBlock.status == "immature",
+                                and_(PoolBlock.status == "orphaned", PoolBlock.height > tip - required)))
+                    .order_by(PoolBlock.height.asc())
+                    .all()
+                )
+                changed = False
+                for pb in rows:
+                    main = s.query(BlockHeader.hash_hex).filter_by(height=pb.height).first()
+                    on_chain = main is not None and main[0] == pb.hash_hex
+                    confirmations = tip - pb.height + 1 if on_chain else 0
+                    if pb.confirmations != confirmations:
+                        pb.confirmations = confirmations
+                        changed = True
+                    old = pb.status
+                    if old == "immature" and not on_chain:
+                        new = "orphaned"
+                    elif old == "orphaned" and on_chain:
+                        new = "immature"
+                    elif old == "immature" and confirmations >= required:
+                        new = "mature"
+                    else:
+                        continue
+                    with self.lock:
+                        mi = self._miner(pb.finder_address)
+                        if old == "immature":
+                            mi.immature_balance = max(0.0, mi.immature_balance - pb.reward)
+                        if new == "immature":
+                            mi.immature_balance += pb.reward
+                        if new == "mature":
+                            mi.pending_balance += pb.reward
+                        mi.dirty = True
+                    pb.status = new
+                    pb.status_ms = nowm
+                    s.add(PoolBlockEvent(hash_hex=pb.hash_hex, height=pb.height, from_status=old, to_status=new,
+                                         confirmations=confirmations, tip_height=tip, created_ms=nowm))
+                    changed = True
+                    plog.info(f"block h={pb.height} {pb.hash_hex[:16]}.. {old} -> {new} "
+                              f"({confirmations}/{required} conf); {pb.reward:.8f} -> {pb.finder_address}")
+                if changed:
+                    s.commit()
+        except Exception as e:
+            plog.error(f"block tracking error: {e}")
+
+    def payout_eligible(self, mi: MinerInfo, paused: Optional[bool] = None) -> bool:
+        # Only matured balance counts; immature rewards can still be orphaned
+        min_payout = float(get_config().get("pool.min_payout", 1.0))
+        if paused is None:
+            paused = self.payouts_paused()
+        return not paused and mi.pending_balance + 1e-12 >= min_payout
+
+    def payouts_paused(self) -> bool:
+        # Payouts stay paused while the node is in safe mode (consensus anomaly; see core.safemode)
+        try:
+            return safemode.is_active()
+        except Exception:
+            return True
+
+    def _flush_state(self):
+        with self.lock:
+            shares = self._pending_shares
+            self._pending_shares = []
+            dirty = [mi for mi in self.miners.values() if mi.dirty]
+            for mi in dirty:
+                mi.dirty = False
+        if not shares and not dirty:
+            return
+        retry = self._write_shares(shares)
+        if retry:
+            with self.lock:
+                self._pending_shares = (retry + self._pending_shares)[-self.MAX_PENDING_SHARES:]
+        if not dirty:
             return
-        msg = {
+        db = get_db()
+        try:
+            with db.session() as s:
+                for mi in dirty:
+                    row = s.query(PoolMiner).filter_by(address=mi.address).first()
+                    if row is None:
+                        row = PoolMiner(address=mi.address, created_ms=now_ms())
+                        s.add(row)
+                    row.accepted_shares = mi.accepted_shares
+                    row.rejected_shares = mi.rejected_shares
+                    row.blocks_found = mi.blocks_found
+                    row.pending_balance = mi.pending_balance
+                    row.immature_balance = mi.immature_balance
+                    row.paid_total = mi.paid_total
+                    row.last_submit_ms = mi.last_submit_ms
+                s.commit()
+        except Exception as e:
+            # keep data for the next flush attempt
+            plog.error(f"flush error: {e}")
+            with self.lock:
+                for mi in dirty:
+                    mi.dirty = True
+
+    def _write_shares(self, shares: List[dict]) -> List[dict]:
+        """
+        Insert queued share rows; returns the rows to retry on the next flush. A database that is
+        busy or unavailable keeps the batch, but a row that cannot be stored at all is logged and
+        dropped (after retrying the batch row by row), so it cannot hold back the share log.
+        """
+        if not shares:
+            return []
+        db = get_db()
+        try:
+            with db.session() as s:
+                s.add_all([PoolShare(**dict(sh, nonce=nonce_to_db(sh["nonce"]))) for sh in shares])
+                s.commit()
+            return []
+        except OperationalError as e:
+            plog.error(f"share log flush error: {e}")
+            return shares
+        except Exception as e:
+            plog.warning(f"share log batch failed, storing rows one by one: {e}")
+        retry: List[dict] = []
+        for sh in shares:
+            try:
+                with db.session() as s:
+                    s.add(PoolShare(**dict(sh, nonce=nonce_to_db(sh["nonce"]))))
+                    s.commit()
+            except OperationalError:
+                retry.append(sh)
+            except Exception as e:
+                plog.error(f"dropping share row that cannot be stored: {e}",
+                           extra=logs.fields(addr=sh.get("address"), job=sh.get("job_id"), nonce=sh.get("nonce")))
+        return retry
+
+    def _notify_msg(self, job: MiningJob, conn: MinerConn) -> dict:
+        # pool_target is the session's vardiff share target, not the job-wide default
+        job.sessions.add(conn.addr)
+        return {
             "id": None,
             "method": "mining.notify",
             "params": {
-                "job_id": self.current_job.job_id,
-                "template": self.current_job.to_template(),
-                "pool_target": self.current_job.pool_target_hex,
-                "share_diff": self.pool_diff,
+                "job_id": job.job_id,
+                "template": job.to_template(),
+                "pool_target": difficulty_to_target(conn.share_diff),
+                "share_diff": conn.share_diff,
             },
         }
-        self._broadcast(msg)
+
+    def _broadcast_job(self):
+        job = self.current_job
+        if not job:
+            return
+        with self.lock:
+            conns = list(self.clients.values())
+        for conn in conns:
+            try:
+                self._send(conn, self._notify_msg(job, conn))
+            except Exception:
+                pass  # _send dropped the session
 
     def _broadcast(self, obj: dict):
-        data = (json.dumps(obj) + "\n").encode("utf-8")
         with self.lock:
-            to_drop = []
-            for cid, conn in self.clients.items():
-                try:
-                    conn.file.write(data)
-                    conn.file.flush()
-                except Exception:
-                    to_drop.append(cid)
-            for cid in to_drop:
-                try:
-                    self.clients[cid].file.close()
-                    self.clients[cid].sock.close()
-                except Exception:
-                    pass
-                del self.clients[cid]
+            conns = list(self.clients.values())
+        for conn in conns:
+            try:
+                self._send(conn, obj)
+            except Exception:
+                pass
+
+    def _fetch_template(self, longpollid: Optional[str] = None) -> dict:
+        """
+        The node's shared template (core.blocktemplate): the same job get_work/get_block_template
+        serve for this tip. With longpollid, waits up to pool.template_longpoll_sec for a new one.
+        """
+        wait = float(get_config().get("pool.template_longpoll_sec", 30))
+        body = {"miner_address": None, "longpollid": longpollid, "timeout_sec": wait if longpollid else None}
+        with httpx.Client(timeout=(wait if longpollid else 0.0) + 10.0, auth=rpcauth.client_auth()) as c:
+            r = c.post(f"{self.node_base}/rpc/get_block_template", json=body)
+        if r.status_code != 200:
+            raise RuntimeError(f"get_block_template {r.status_code} {r.text}")
+        return r.json() or {}
+
+    def _install_job(self, job_json: dict) -> bool:
+        """Make the node's template the current job; broadcasts if it is new (or no job is current)."""
+        # Normalize fields and enforce lowercase for prev/txids consistency
+        txids = [str(t).lower() for t in (job_json.get("txids") or [])]
+        jid = str(job_json.get("job_id") or str(now_ms()))
+        job = MiningJob(
+            job_id=jid,
+            prev_hash=str(job_json.get("prev_hash") or "").lower(),
+            version=int(job_json.get("version") or 1),
+            target_hex=str(job_json.get("target") or "").lower(),
+            timestamp=int(job_json.get("timestamp") or int(time.time())),
+            txids=txids,
+            pool_diff=max(1, self.pool_diff),
+        )
+        if job.merkle_mutated:
+            slog.error(f"node issued a mutated txid list; skipping job {jid}")
+            return False
+        with self._job_lock:
+            if self.current_job and self.current_job.job_id == job.job_id:
+                return False
+            self._remember_job(job)
+            self.current_job = job
+        slog.debug(f"built job id={job.job_id} prev={job.prev_hash[:16]}.. target={job.target_hex[:8]}.. txids={len(job.txids)}")
+        self._broadcast_job()
+        return True
 
     def _job_loop(self):
         """
-        Fetch node-issued jobs to eliminate prev/target drift.
-        If height >= 200, expect tx snapshot (txids) in get_work and propagate to miners
-        so their computed merkle matches node's rebuild.
+        Follow the node's template by long-polling get_block_template, so pool jobs and RPC
+        templates never diverge. If height >= 200 the template carries the tx snapshot (txids),
+        propagated to miners so their computed merkle matches the node's rebuild.
         """
-        last_job_id = None
-        while True:
+        longpollid: Optional[str] = None
+        while not self._stopping.is_set():
             try:
-                with httpx.Client(timeout=5.0) as c:
-                    r = c.post(f"{self.node_base}/rpc/get_work", json={"miner_address": None})
-                    if r.status_code != 200:
-                        print("Job loop error: get_work", r.status_code, r.text)
-                        time.sleep(2.0)
-                        continue
-                    job_json = r.json() or {}
-                    # Normalize fields and enforce lowercase for prev/txids consistency
-                    txids = [str(t).lower() for t in (job_json.get("txids") or [])]
-                    prev = str(job_json.get("prev_hash") or "").lower()
-                    tgt = str(job_json.get("target") or "").lower()
-                    ver = int(job_json.get("version") or 1)
-                    ts = int(job_json.get("timestamp") or int(time.time()))
-                    jid = str(job_json.get("job_id") or str(now_ms()))
-                    job = MiningJob(
-                        job_id=jid,
-                        prev_hash=prev,
-                        version=ver,
-                        target_hex=tgt,
-                        timestamp=ts,
-                        txids=txids,
-                        pool_diff=max(1, self.pool_diff),
-                    )
-                    # Only broadcast if changed
-                    if not self.current_job or job.job_id != last_job_id:
-                        self.current_job = job
-                        last_job_id = job.job_id
-                        print(_c("34", f"[DEBUG] built job id={job.job_id} prev={job.prev_hash[:16]}.. target={job.target_hex[:8]}.. txids={len(job.txids)}"))
-                        self._broadcast_job()
-                time.sleep(2.0)
+                job_json = self._fetch_template(longpollid)
+                self._install_job(job_json)
+                longpollid = job_json.get("longpollid")
             except Exception as e:
-                print("Job loop error:", e)
+                slog.warning(f"job loop error: {e}")
+                longpollid = None
                 time.sleep(2.0)
 
     def _handle_client(self, cid: int, conn: MinerConn):
-        print(_c("32", f"Client connected: {cid} {conn.addr}"))
-        print(_c("36", f"[DEBUG] send subscribe to cid={cid}"))
+        slog.info(f"Client connected: {cid} {conn.addr}")
+        slog.debug(f"send subscribe to cid={cid}")
         try:
             # Send welcome
-            self._send(conn, {"id": 0, "result": ["smelly-session"], "error": None, "method": "mining.subscribe"})
+            self._send(conn, {"id": 0, "result": self._subscribe_result(conn), "error": None, "method": "mining.subscribe"})
             if self.current_job:
-                notify = {
-                    "id": None,
-                    "method": "mining.notify",
-                    "params": {
-                        "job_id": self.current_job.job_id,
-                        "template": self.current_job.to_template(),
-                        "pool_target": self.current_job.pool_target_hex,
-                        "share_diff": self.pool_diff,
-                    },
-                }
-                print(_c("36", f"[DEBUG] initial notify to cid={cid}: job_id={self.current_job.job_id} prev={self.current_job.prev_hash[:16]}.. pool_target={self.current_job.pool_target_hex[:8]}.."))
+                notify = self._notify_msg(self.current_job, conn)
+                slog.debug(f"initial notify to cid={cid}: job_id={self.current_job.job_id} prev={self.current_job.prev_hash[:16]}.. share_diff={conn.share_diff}")
                 self._send(conn, notify)
             while conn.alive:
-                line = conn.file.readline()
+                line = conn.file.readline(self.max_line_bytes + 1)
                 if not line:
                     break
-                msg = json.loads(line.decode("utf-8").strip())
+                conn.last_recv_ms = now_ms()
+                if len(line) > self.max_line_bytes:
+                    self.ban(conn.host, f"line over {self.max_line_bytes} bytes")
+                    break
+                if not conn.bucket.take():
+                    conn.rate_strikes += 1
+                    if conn.rate_strikes >= self.max_rate_strikes:
+                        self.ban(conn.host, "request rate limit exceeded")
+                        break
+                    self._reply(conn, None, result=None, error="Rate limited")
+                    continue
+                try:
+                    msg = parse_request(line)
+                except ValueError:
+                    conn.parse_errors += 1
+                    if conn.parse_errors >= self.max_parse_errors:
+                        self.ban(conn.host, f"{conn.parse_errors} malformed messages")
+                        break
+                    self._reply(conn, None, result=None, error="Parse error")
+                    continue
+                if "method" not in msg and ("result" in msg or "error" in msg):
+                    # a reply to one of our requests (mining.ping), not a request
+                    if conn.ping_id is not None and msg.get("id") == conn.ping_id:
+                        conn.ping_id = None
+                        conn.answers_pings = True
+                    continue
                 self._process_msg(conn, msg)
         except Exception as e:
-            print(_c("31", f"Client error: {cid} {e}"))
+            if conn.alive:
+                slog.warning(f"Client error: {cid} {e}")
         finally:
+            conn.alive = False
             try:
                 conn.file.close()
                 conn.sock.close()
             except Exception:
                 pass
             with self.lock:
-                if cid in self.clients:
+                if self.clients.get(cid) is conn:
                     del self.clients[cid]
-            print(_c("33", f"Client disconnected: {cid}"))
+            slog.info(f"Client disconnected: {cid}")
 
     def _send(self, conn: MinerConn, obj: dict):
+        """Write one message; a failed write drops the session before the error propagates."""
         data = (json.dumps(obj) + "\n").encode("utf-8")
-        conn.file.write(data)
-        conn.file.flush()
+        with conn.send_lock:
+            conn.write_started_ms = now_ms()
+            try:
+                conn.file.write(data)
+                conn.file.flush()
+            except Exception:
+                if conn.alive:
+                    slog.debug(f"write to {conn.addr} failed; dropping session")
+                    self._drop(conn)
+                raise
+            finally:
+                conn.write_started_ms = 0
 
     def _reply(self, conn: MinerConn, id_val, result=None, error=None):
         self._send(conn, {"id": id_val, "result": result, "error": error})
 
+    # ----- extranonce -----
+    #
+    # The coinbase txid is fixed by consensus (sha3("COINBASE:<height>")), so there is no
+    # coinbase field to roll; instead the 64-bit header nonce is split:
+    #   nonce = extranonce1 (EXTRANONCE1_SIZE bytes, unique per live session) || extranonce2
+    # Sessions that subscribe get their extranonce1 enforced on submit, so two workers on the same
+    # address never hash (or get credited for) the same nonce range.
+
+    EXTRANONCE1_SIZE = 2
+    EXTRANONCE2_SIZE = 6
+
+    def _alloc_extranonce1(self) -> int:
+        # caller holds self.lock
+        used = {c.extranonce1 for c in self.clients.values()}
+        space = 1 << (8 * self.EXTRANONCE1_SIZE)
+        for _ in range(space):
+            e1 = self._next_extranonce1 % space
+            self._next_extranonce1 = e1 + 1
+            if e1 not in used:
+                return e1
+        raise RuntimeError("extranonce1 space exhausted")
+
+    def _extranonce_params(self, conn: MinerConn) -> list:
+        return [f"{conn.extranonce1:0{2 * self.EXTRANONCE1_SIZE}x}", self.EXTRANONCE2_SIZE]
+
+    def _subscribe_result(self, conn: MinerConn) -> list:
+        # [session, extranonce1_hex, extranonce2_size]
+        return ["smelly-session"] + self._extranonce_params(conn)
+
+    def set_extranonce(self, conn: MinerConn, reassign: bool = False):
+        """Push mining.set_extranonce to a subscribed session (optionally with a fresh extranonce1)."""
+        if reassign:
+            with self.lock:
+                conn.extranonce1 = self._alloc_extranonce1()
+        if conn.extranonce_subscribed:
+            self._send(conn, {"id": None, "method": "mining.set_extranonce", "params": self._extranonce_params(conn)})
+
+    def _nonce_in_range(self, conn: MinerConn, nonce: int) -> bool:
+        if not conn.extranonce_enforced:
+            return True
+        e2_bits = 8 * self.EXTRANONCE2_SIZE
+        return 0 <= nonce < (1 << 64) and (nonce >> e2_bits) == conn.extranonce1
+
     def _process_msg(self, conn: MinerConn, msg: dict):
         method = msg.get("method")
+        if method == "mining.ping":
+            return self._reply(conn, msg.get("id"), result="pong", error=None)
+        if method == "mining.subscribe":
+            conn.extranonce_enforced = self.enforce_extranonce
+            return self._reply(conn, msg.get("id"), result=self._subscribe_result(conn), error=None)
+        if method == "mining.extranonce.subscribe":
+            conn.extranonce_subscribed = True
+            self._reply(conn, msg.get("id"), result=True, error=None)
+            return self.set_extranonce(conn)
         if method == "mining.authorize":
             params = msg.get("params") or []
             if not params:
                 return self._reply(conn, msg.get("id"), result=False, error="Address required")
-            conn.address = params[0]
-            print(_c("36", f"[DEBUG] authorize ok addr={conn.address} cid={id(conn)}"))
+            address, worker = parse_worker(str(params[0]))
+            password = str(params[1]) if len(params) > 1 and params[1] is not None else ""
+            err = self.auth.authenticate(address, worker, password)
+            if err:
+                plog.warning(f"authorize failed addr={address} worker={worker} ({self.auth.name}): {err}")
+                self._note_auth_failure(conn.host)
+                return self._reply(conn, msg.get("id"), result=False, error=f"Unauthorized: {err}")
+            conn.address = address
+            conn.worker = worker
+            slog.debug(f"authorize ok addr={conn.address} worker={worker or '-'} cid={id(conn)}")
             return self._reply(conn, msg.get("id"), result=True, error=None)
 
         if method == "mining.get_job":
             if not self.current_job:
                 return self._reply(conn, msg.get("id"), result=None, error="No job")
             job = self.current_job
-            print(_c("36", f"[DEBUG] get_job -> job_id={job.job_id} prev={job.prev_hash[:16]}.. target={job.target_hex[:8]}.."))
+            slog.debug(f"get_job -> job_id={job.job_id} prev={job.prev_hash[:16]}.. target={job.target_hex[:8]}..")
             return self._reply(conn, msg.get("id"), result={
                 "job_id": job.job_id,
                 "template": job.to_template(),
-                "pool_target": job.pool_target_hex,
-                "share_diff": self.pool_diff,
+                "pool_target": difficulty_to_target(conn.share_diff),
+                "share_diff": conn.share_diff,
             }, error=None)
 
         if method == "mining.submit":
-            params = msg.get("params") or []
             try:
                 # New schema includes prev_hash to harden against rotated job_id but same prev races:
                 # [address, job_id, nonce, timestamp, merkle_root_hex, version, prev_hash_hex?]
-                address, job_id, nonce, timestamp, merkle_root_hex, version = params[:6]
-                prev_from_submit = params[6] if len(params) >= 7 else None
-                nonce = int(nonce)
-                timestamp = int(timestamp)
-                version = int(version)
-            except Exception:
-                print(_c("31", f"[DEBUG] invalid submit params: {msg}"))
+                sub = parse_submit(msg.get("params"))
+            except ValueError as e:
+                slog.debug(f"invalid submit params ({e}): {msg}")
                 return self._reply(conn, msg.get("id"), result=False, error="Invalid params")
+            address, worker, job_id, nonce = sub.address, sub.worker, sub.job_id, sub.nonce
+            timestamp, merkle_root_hex, version, prev_from_submit = sub.timestamp, sub.merkle_root_hex, sub.version, sub.prev_hash_hex
+            # Shares are only credited to the address this session authorized as
+            if not conn.address:
+                return self._reply(conn, msg.get("id"), result=False, error="Unauthorized: authorize first")
+            if address != conn.address or (worker and worker != conn.worker):
+                return self._reply(conn, msg.get("id"), result=False, error="Unauthorized: worker does not match session")
+
+            def _reject(reason: str, error: str):
+                # rejected before hashing: logged without hash or target
+                self._record_share(address, job_id, nonce, accepted=False, reason=reason,
+                                   share_diff=conn.share_diff, worker=conn.worker)
+                return self._reply(conn, msg.get("id"), result=False, error=error)
+
+            if not self._nonce_in_range(conn, nonce):
+                return _reject("nonce-out-of-range", f"Nonce outside session extranonce {self._extranonce_params(conn)[0]}")
             # Stale job check; allow small grace if prev_hash matches but job_id rotated recently
             if not self.current_job:
-                print(_c("33", f"[DEBUG] stale job: no current_job"))
-                return self._reply(conn, msg.get("id"), result=False, error="Stale job")
+                slog.debug("stale job: no current_job")
+                return _reject("stale-job", "Stale job")
             if job_id != self.current_job.job_id:
                 # Allow only if prev matches; otherwise stale
                 current_prev = (self.current_job.prev_hash or "").lower()
-                if prev_from_submit:
-                    prev_from_submit = prev_from_submit.lower()
                 if not prev_from_submit:
-                    print(_c("33", f"[DEBUG] stale job (no prev provided) cur_job_id={self.current_job.job_id} submit_job_id={job_id}"))
-                    return self._reply(conn, msg.get("id"), result=False, error="Stale job")
+                    slog.debug(f"stale job (no prev provided) cur_job_id={self.current_job.job_id} submit_job_id={job_id}")
+                    return _reject("stale-job", "Stale job")
                 if prev_from_submit != current_prev:
-                    print(_c("33", f"[DEBUG] stale job: prev mismatch submit_prev={prev_from_submit[:16]}.. cur_prev={current_prev[:16]}.."))
-                    return self._reply(conn, msg.get("id"), result=False, error="Stale job")
-                print(_c("35", f"[DEBUG] accept rotated job_id with same prev={current_prev[:16]}.."))
+                    slog.debug(f"stale job: prev mismatch submit_prev={prev_from_submit[:16]}.. cur_prev={current_prev[:16]}..")
+                    return _reject("stale-prev", "Stale job")
+                if job_id not in self.recent_jobs:
+                    slog.debug(f"stale job: unknown job_id={job_id}")
+                    return _reject("unknown-job", "Stale job")
+                slog.debug(f"accept rotated job_id with same prev={current_prev[:16]}..")
 
-            job = self.current_job
-            # Build header bytes exactly like miners and consensus
-            fields = [
-                ("version", version),
-                ("prev_hash_hex", (job.prev_hash or "").lower()),
-                ("merkle_root_hex", (merkle_root_hex or "").lower()),
-                ("timestamp", timestamp),
-                ("target", (job.target_hex or "").lower()),
-                ("nonce", nonce),
-                ("miner_address", address),
-                ("tx_count", len(job.txids)),
-            ]
-            hdr_bytes = json.dumps(fields, separators=(",", ":"), sort_keys=False).encode("utf-8")
-            # Use prev_from_submit (already lowercase) to ensure identical digest path with miner
-            digest = pow_hash(hdr_bytes, nonce, prev_from_submit or (job.prev_hash or ""))
-            print(_c("36", f"[DEBUG] share submit addr={address} job_id={job_id} cur_job={job.job_id} prev={job.prev_hash[:16]}.. nonce={nonce} ts={timestamp} digest={digest.hex()[:16]}.. pool_target={job.pool_target_hex[:8]}.. net_target={job.target_hex[:8]}.."))
-
-            # Share target check (pool difficulty). If pool_diff <= 1, accept all shares.
-            if self.pool_diff > 1 and int(digest.hex(), 16) > int(job.pool_target_hex, 16):
+            job = self.recent_jobs.get(job_id) or self.current_job
+            # The nonce carries extranonce1/extranonce2, so this key is (job, extranonce, ntime, nonce)
+            key = (address, int(nonce), int(timestamp), int(version))
+            with self.lock:
+                duplicate = key in job.submissions
+                job.submissions.add(key)
+            if duplicate:
+                return _reject("duplicate", "Duplicate share")
+            # PoW verification runs on the verify pool so this session keeps reading and other
+            # sessions' shares are not queued behind it; the reply is sent from the pool thread.
+            if not self._verify_slots.acquire(blocking=False):
+                _REFUSED.inc(labels=("verify queue full",))
+                return _reject("server-busy", "Server busy, retry")
+            args = (conn, msg.get("id"), job, job_id, address, nonce, timestamp, merkle_root_hex, version,
+                    prev_from_submit, conn.share_diff)
+            try:
+                self._verify_pool.submit(self._verify_submission, *args)
+            except RuntimeError:  # pool shut down
+                self._verify_slots.release()
+                return self._reply(conn, msg.get("id"), result=False, error="Server shutting down")
+            return None
+
+        # Unknown
+        return self._reply(conn, msg.get("id"), result=None, error="Unknown method")
+
+
+    def _verify_submission(self, conn: MinerConn, msg_id, job: MiningJob, job_id: str, address: str, nonce: int,
+                           timestamp: int, merkle_root_hex: str, version: int, prev_from_submit: Optional[str],
+                           share_diff: int):
+        """Verify pool worker: check one share, account it, reply, and hand blocks to the promoter."""
+        try:
+            with logs.span(slog, "share", addr=address, job=job_id, nonce=nonce):
+                self._settle_submission(conn, msg_id, job, job_id, address, nonce, timestamp, merkle_root_hex, version,
+                                        prev_from_submit, share_diff)
+        except Exception as e:
+            plog.error(f"share verification error: {e}")
+        finally:
+            self._verify_slots.release()
+
+    def _settle_submission(self, conn: MinerConn, msg_id, job: MiningJob, job_id: str, address: str, nonce: int,
+                           timestamp: int, merkle_root_hex: str, version: int, prev_from_submit: Optional[str],
+                           share_diff: int):
+        res = self.process_submission(job, address, nonce, timestamp, merkle_root_hex, version,
+                                      prev_from_submit, difficulty_to_target(share_diff))
+        digest = res.digest
+        slog.debug(f"share submit addr={address} job_id={job_id} cur_job={job.job_id} prev={job.prev_hash[:16]}.. nonce={nonce} ts={timestamp} digest={digest.hex()[:16]}.. share_diff={share_diff} net_target={job.target_hex[:8]}.. -> {res.kind}")
+
+        if res.kind == SubmitResult.REJECTED:
+            with self.lock:
                 conn.rejected_shares += 1
-                with self.lock:
-                    self._rejected_recent.append((now_ms(), address))
-                print(_c("33", f"[DEBUG] share low diff digest={digest.hex()[:16]}.. > pool_target (share_diff={self.pool_diff})"))
-                return self._reply(conn, msg.get("id"), result=False, error="Low difficulty share")
+                self._rejected_recent.append((now_ms(), address))
+                job.shares_rejected += 1
+            self._record_share(address, job_id, nonce, accepted=False, reason=res.reason, share_diff=share_diff,
+                               worker=conn.worker, digest=digest, target_hex=difficulty_to_target(share_diff))
+            slog.debug(f"share rejected ({res.reason}) digest={digest.hex()[:16]}.. share_diff={share_diff}")
+            if res.reason == "merkle-mismatch":
+                return self._reply(conn, msg_id, result=False, error="Merkle root does not match job transactions")
+            return self._reply(conn, msg_id, result=False, error="Low difficulty share")
 
-            # Accept share
+        # Accept share (a found block is also a share for accounting)
+        with self.lock:
             conn.accepted_shares += 1
             conn.last_submit_ms = now_ms()
-            with self.lock:
-                self._accepted_recent.append((conn.last_submit_ms, address))
-            self._reply(conn, msg.get("id"), result=True, error=None)
-            print(_c("32", f"[DEBUG] share accepted addr={address} accepted={conn.accepted_shares} rejected={conn.rejected_shares}"))
+            self._accepted_recent.append((conn.last_submit_ms, address))
+            self.hashrate.add(address, conn.worker, share_diff, conn.last_submit_ms)
+            job.shares_accepted += 1
+            job.work_by_address[address] = job.work_by_address.get(address, 0) + int(share_diff)
+            if res.kind == SubmitResult.BLOCK_FOUND:
+                job.blocks_found += 1
+        self._record_share(address, job_id, nonce, accepted=True, share_diff=share_diff,
+                           worker=conn.worker, digest=digest, target_hex=difficulty_to_target(share_diff))
+        self._reply(conn, msg_id, result=True, error=None)
+        slog.debug(f"share accepted addr={address} accepted={conn.accepted_shares} rejected={conn.rejected_shares}")
+        with conn.vardiff_lock:
+            self._vardiff(conn)
 
-            # If meets network target, promote via node; select merkle strategy based on height/job
-            if int(digest.hex(), 16) <= int(job.target_hex, 16):
+        # Block found: promote via node; select merkle strategy based on height/job
+        if res.kind == SubmitResult.BLOCK_FOUND:
+            try:
+                # Query height to decide bootstrap vs mempool-merkle mode
+                height_now = -1
+                with httpx.Client(timeout=3.0, auth=rpcauth.client_auth()) as c:
+                    r_h = c.get(f"{self.node_base}/rpc/get_height")
+                    if r_h.status_code == 200:
+                        height_now = int((r_h.json() or {}).get("height", -1))
+                # Forward the job's own txid set (already checked against the submitted merkle in
+                # process_submission); the node rebuilds the block from its snapshot for job_id and
+                # rejects the submit if the two sets disagree.
+                payload = {
+                    "job_id": job.job_id,
+                    "miner_address": address,
+                    "nonce": int(nonce),
+                    "timestamp": int(timestamp),
+                    "version": int(version),
+                    "merkle_root_hex": job.merkle_root_hex,
+                    "prev_hash_hex": (prev_from_submit or job.prev_hash).lower(),
+                    "txids": list(job.txids),
+                }
+                with httpx.Client(timeout=10.0, auth=rpcauth.client_auth()) as c:
+                    resp = c.post(f"{self.node_base}/rpc/submit_work", json=payload)
+                if resp.status_code == 200 and isinstance(resp.json(), dict) and resp.json().get("accepted"):
+                    hh = resp.json().get("hash")
+                    plog.info(f"FOUND BLOCK {hh} by {address} (h={height_now+1} prev={job.prev_hash[:16]}.. target={job.target_hex[:8]}.. merkle={'coinbase' if height_now<200 else 'txs'})")
+                    self._record_block(address, hh, int(resp.json().get("height", height_now + 1)))
+                    self._rotate_job_async()
+                    return None
+                # Rejection diagnostics
                 try:
-                    # Query height to decide bootstrap vs mempool-merkle mode
-                    height_now = -1
-                    with httpx.Client(timeout=3.0) as c:
-                        r_h = c.get(f"{self.node_base}/rpc/get_height")
-                        if r_h.status_code == 200:
-                            height_now = int((r_h.json() or {}).get("height", -1))
-                    # Always forward the miner-provided merkle and the exact txids snapshot;
-                    # the node will decide to rebuild coinbase-only when height < bootstrap_cutoff.
-                    payload = {
-                        "job_id": job.job_id,
-                        "miner_address": address,
-                        "nonce": int(nonce),
-                        "timestamp": int(timestamp),
-                        "version": int(version),
-                        "merkle_root_hex": (merkle_root_hex or "").lower(),
-                        "prev_hash_hex": (prev_from_submit or job.prev_hash).lower(),
-                        "txids": [t.lower() for t in (job.txids or [])],
-                    }
-                    with httpx.Client(timeout=10.0) as c:
-                        resp = c.post(f"{self.node_base}/rpc/submit_work", json=payload)
-                    if resp.status_code == 200 and isinstance(resp.json(), dict) and resp.json().get("accepted"):
-                        hh = resp.json().get("hash")
-                        print(_c("1;32", f"[POOL] FOUND BLOCK {hh} by {address} (h={height_now+1} prev={job.prev_hash[:16]}.. target={job.target_hex[:8]}.. merkle={'coinbase' if height_now<200 else 'txs'})"))
+                    detail = resp.json()
+                except Exception:
+                    detail = {"text": resp.text}
+                plog.error(f"promotion rejected by node: {detail}")
+                if isinstance(detail, dict):
+                    det = detail.get("detail") or detail
+                    err = str(det.get("error") if isinstance(det, dict) and "error" in det else det).lower()
+                    # If merkle mismatch at >=200, force job refresh from node to sync txids snapshot
+                    if "merkle" in err or "txids" in err:
+                        slog.debug("refreshing job from node due to merkle mismatch")
                         self._rotate_job_async()
-                        return None
-                    # Rejection diagnostics
-                    try:
-                        detail = resp.json()
-                    except Exception:
-                        detail = {"text": resp.text}
-                    print(_c("1;31", f"[POOL] promotion rejected by node: {detail}"))
-                    if isinstance(detail, dict):
-                        det = detail.get("detail") or detail
-                        err = str(det.get("error") if isinstance(det, dict) and "error" in det else det).lower()
-                        # If merkle mismatch at >=200, force job refresh from node to sync txids snapshot
-                        if "merkle" in err or "txids" in err:
-                            print(_c("35", "[DEBUG] refreshing job from node due to merkle mismatch"))
-                            self._rotate_job_async()
-                        # If prev/lease issues, rotate as well
-                        if any(k in err for k in ["stale", "prev", "expired", "unknown job"]):
-                            print(_c("35", "[DEBUG] rotating job due to lease/prev issue"))
-                            self._rotate_job_async()
-                except Exception as e:
-                    print(_c("1;31", f"[POOL] Promotion exception: {e}"))
-                    traceback.print_exc()
-                    self._rotate_job_async()
-            return None
+                    # If prev/lease issues, rotate as well
+                    if any(k in err for k in ["stale", "prev", "expired", "unknown job"]):
+                        slog.debug("rotating job due to lease/prev issue")
+                        self._rotate_job_async()
+            except Exception as e:
+                plog.error(f"Promotion exception: {e}")
+                traceback.print_exc()
+                self._rotate_job_async()
+        return None
 
-        # Unknown
-        return self._reply(conn, msg.get("id"), result=None, error="Unknown method")
+    def process_submission(self, job: MiningJob, address: str, nonce: int, timestamp: int, merkle_root_hex: str,
+                           version: int, prev_hex: Optional[str], share_target_hex: str) -> SubmitResult:
+        """
+        Classify a submission against the session's share target and the job's network target.
+        Header bytes are built exactly like miners and consensus. The merkle root must be the one
+        computed from the job's txids, otherwise a "block" could not be reconstructed from the job.
+        """
+        if (merkle_root_hex or "").lower() != job.merkle_root_hex:
+            return SubmitResult(SubmitResult.REJECTED, reason="merkle-mismatch")
+        fields = [
+            ("version", version),
+            ("prev_hash_hex", (job.prev_hash or "").lower()),
+            ("merkle_root_hex", (merkle_root_hex or "").lower()),
+            ("timestamp", timestamp),
+            ("target", (job.target_hex or "").lower()),
+            ("nonce", nonce),
+            ("miner_address", address),
+            ("tx_count", len(job.txids)),
+        ]
+        hdr_bytes = json.dumps(fields, separators=(",", ":"), sort_keys=False).encode("utf-8")
+        # Use prev from the submit (already lowercase) to ensure identical digest path with miner
+        digest = pow_hash(hdr_bytes, nonce, prev_hex or (job.prev_hash or ""))
+        if hash_meets_target(digest, job.target_hex):
+            return SubmitResult(SubmitResult.BLOCK_FOUND, digest)
+        if hash_meets_target(digest, share_target_hex):
+            return SubmitResult(SubmitResult.SHARE_ACCEPTED, digest)
+        return SubmitResult(SubmitResult.REJECTED, digest, reason="low-difficulty")
+
+    def _vardiff(self, conn: MinerConn):
+        """Count an accepted share and retarget the session if its window is over."""
+        conn.vardiff_shares += 1
+        if self._retarget(conn):
+            self._send_share_target(conn)
 
+    def _retarget(self, conn: MinerConn) -> bool:
+        """
+        Retarget the session share difficulty toward pool.vardiff_shares_per_min once
+        pool.vardiff_retarget_sec has passed. Caller holds conn.vardiff_lock. Returns True if changed.
+        """
+        cfg = get_config()
+        target_spm = max(0.1, float(cfg.get("pool.vardiff_shares_per_min", 6)))
+        retarget_ms = int(cfg.get("pool.vardiff_retarget_sec", 60)) * 1000
+        max_diff = max(1, int(cfg.get("pool.vardiff_max", 1 << 20)))
+        nowm = now_ms()
+        elapsed = nowm - conn.vardiff_window_start_ms
+        if elapsed < retarget_ms:
+            return False
+        rate = conn.vardiff_shares * 60000.0 / max(1, elapsed)
+        factor = max(0.5, min(2.0, rate / target_spm))
+        new_diff = max(1, min(max_diff, int(round(conn.share_diff * factor))))
+        conn.vardiff_window_start_ms = nowm
+        conn.vardiff_shares = 0
+        if new_diff == conn.share_diff:
+            return False
+        slog.debug(f"vardiff addr={conn.address} {conn.share_diff} -> {new_diff} ({rate:.1f} shares/min)")
+        conn.share_diff = new_diff
+        return True
+
+    def _send_share_target(self, conn: MinerConn):
+        if self.current_job:
+            try:
+                self._send(conn, self._notify_msg(self.current_job, conn))
+            except Exception:
+                pass
+
+    def retarget_quiet_sessions(self) -> int:
+        """
+        Timer-driven vardiff: sessions whose difficulty is too high to find shares never reach the
+        accepted-share path, so every authorized session is retargeted here as well (zero shares in
+        a window halves the difficulty). Notifies go through the ping pool, off this thread.
+        """
+        with self.lock:
+            conns = [c for c in self.clients.values() if c.alive and c.address]
+        changed = 0
+        for conn in conns:
+            with conn.vardiff_lock:
+                if not self._retarget(conn):
+                    continue
+            changed += 1
+            try:
+                self._ping_pool.submit(self._send_share_target, conn)
+            except RuntimeError:  # pool shut down
+                pass
+        return changed
+
+    def _remember_job(self, job: MiningJob):
+        with self.lock:
+            self.recent_jobs[job.job_id] = job
+            while len(self.recent_jobs) > self.max_jobs:
+                self.recent_jobs.popitem(last=False)
+
+    def clean_expired_jobs(self) -> int:
+        """Drop jobs older than pool.job_expiry_sec (the current job is always kept)."""
+        cutoff = now_ms() - self.job_expiry_ms
+        current = self.current_job.job_id if self.current_job else None
+        with self.lock:
+            expired = [jid for jid, j in self.recent_jobs.items() if j.created_ms < cutoff and jid != current]
+            for jid in expired:
+                del self.recent_jobs[jid]
+        return len(expired)
+
+    def job_stats(self) -> List[Dict[str, object]]:
+        """Per-job share accounting for retained jobs, oldest first."""
+        with self.lock:
+            return [j.stats() for j in self.recent_jobs.values()]
+
+    def prune_share_log(self) -> int:
+        """Delete pool_shares rows older than pool.share_log_retention_days (0 = keep forever)."""
+        days = float(get_config().get("pool.share_log_retention_days", 7))
+        if days <= 0:
+            return 0
+        cutoff = now_ms() - int(days * 86_400_000)
+        db = get_db()
+        with db.session() as s:
+            n = s.query(PoolShare).filter(PoolShare.created_ms < cutoff).delete(synchronize_session=False)
+            s.commit()
+        return int(n or 0)
+
+    def _job_maintenance_loop(self):
+        last_prune = 0.0
+        while not self._stopping.wait(30.0):
+            try:
+                n = self.clean_expired_jobs()
+                if n:
+                    slog.debug(f"expired {n} old job(s); retained={len(self.recent_jobs)}")
+                self.retarget_quiet_sessions()
+                if time.time() - last_prune >= 600:
+                    last_prune = time.time()
+                    n = self.prune_share_log()
+                    if n:
+                        plog.info(f"share log: pruned {n} row(s) past retention")
+            except Exception as e:
+                plog.error(f"job maintenance error: {e}")
+
+    # ----- keepalive -----
+
+    def _dead_reason(self, conn: MinerConn, nowm: int) -> Optional[str]:
+        timeout = self.keepalive_timeout_ms
+        if timeout > 0 and conn.write_started_ms and nowm - conn.write_started_ms > timeout:
+            return "write_stalled"
+        if conn.ping_id is not None and conn.last_recv_ms < conn.ping_sent_ms:
+            if timeout > 0 and nowm - conn.ping_sent_ms > timeout:
+                if conn.answers_pings:
+                    return "ping_timeout"
+                conn.ping_id = None  # client without ping support: only the idle timeout applies
+        else:
+            conn.ping_id = None  # anything received since the ping proves the peer is there
+        if self.idle_timeout_ms > 0 and nowm - conn.last_recv_ms > self.idle_timeout_ms:
+            return "idle"
+        return None
+
+    def _ping(self, conn: MinerConn, nowm: int):
+        self._ping_seq += 1
+        conn.ping_id = f"ping-{self._ping_seq}"
+        conn.ping_sent_ms = nowm
+        msg = {"id": conn.ping_id, "method": "mining.ping", "params": []}
+
+        def _do():
+            try:
+                self._send(conn, msg)
+            except Exception:
+                pass  # _send dropped the session
+
+        try:
+            self._ping_pool.submit(_do)
+        except RuntimeError:  # pool shut down
+            pass
+
+    def reap_sessions(self) -> int:
+        """Drop dead sessions and ping quiet ones; returns the number dropped."""
+        nowm = now_ms()
+        with self.lock:
+            conns = list(self.clients.values())
+        dropped = 0
+        for conn in conns:
+            reason = self._dead_reason(conn, nowm)
+            if reason:
+                _REAPED.inc(labels=(reason,))
+                slog.info(f"dropping session {conn.cid} {conn.addr}: {reason}")
+                self._drop(conn)
+                dropped += 1
+            elif (self.keepalive_interval_ms > 0 and conn.ping_id is None
+                  and nowm - max(conn.last_recv_ms, conn.ping_sent_ms) >= self.keepalive_interval_ms):
+                self._ping(conn, nowm)
+        return dropped
+
+    def _keepalive_loop(self):
+        tick = max(1.0, min(5.0, self.keepalive_timeout_ms / 4000.0 or 5.0))
+        while not self._stopping.wait(tick):
+            try:
+                self.reap_sessions()
+            except Exception as e:
+                plog.error(f"keepalive error: {e}")
 
     def _rotate_job_async(self):
         # Trigger job rebuild without blocking submit thread
         def _do():
             try:
-                # Simple way: set current_job to None; job loop will rebuild next tick
-                self.current_job = None
-            except Exception:
-                pass
+                # Fetch the node's current template now rather than waiting for the long-poll
+                with self._job_lock:
+                    self.current_job = None
+                self._install_job(self._fetch_template())
+            except Exception as e:
+                slog.warning(f"job rotate error: {e}")
         threading.Thread(target=_do, daemon=True).start()
 
 
@@ -389,40 +1280,57 @@ class StratumPool:
         """
         Every 5s compute a lightweight snapshot and persist to KV for Explorer /pool.
         Stores:
-        - miners: [{addr, accepted, rejected, last_submit_ms, hashrate}]
-        - share_diff, accepted_5m, rejected_5m, total_hashrate
+        - miners: [{addr, worker, accepted, rejected, last_submit_ms, hashrate, hashrates}] per session
+        - workers: [{address, worker, hashrate: {1m, 15m, 1h}}] for workers with recent shares
+        - share_diff, accepted_5m, rejected_5m, total_hashrate (15m), hashrate {1m, 15m, 1h}
+        Hashrates are H/s from accepted share difficulty (apps.pool.hashrate).
         """
         db = get_db()
         WINDOW_MS = 5 * 60 * 1000
-        while True:
+        while not self._stopping.is_set():
             try:
                 nowm = now_ms()
+                paused = self.payouts_paused()
                 with self.lock:
                     # prune recent lists
                     self._accepted_recent = [(t, a) for (t, a) in self._accepted_recent if nowm - t <= WINDOW_MS]
                     self._rejected_recent = [(t, a) for (t, a) in self._rejected_recent if nowm - t <= WINDOW_MS]
+                    self.hashrate.prune(nowm)
+                    pool_hr = self.hashrate.pool(nowm)
                     miners = []
-                    total_h = 0.0
                     for _, conn in list(self.clients.items()):
-                        # Estimate hashrate from accepted shares in window per miner (very rough)
-                        acc = len([1 for (t, a) in self._accepted_recent if a == (conn.address or "")])
-                        # scale shares per window by share difficulty into an H/s-ish proxy
-                        # share_target ~ difficulty_to_target(share_diff); we simply use acc/window as proxy
-                        hr = acc / max(1.0, WINDOW_MS / 1000.0)
-                        total_h += hr
+                        rates = self.hashrate.worker(conn.address or "", conn.worker, nowm) if conn.address else {}
+                        hr = rates.get("15m", 0.0)
+                        mi = self.miners.get(conn.address or "")
                         miners.append({
                             "addr": conn.address or "(unauth)",
+                            "worker": conn.worker,
                             "accepted": conn.accepted_shares,
                             "rejected": conn.rejected_shares,
                             "last_submit_ms": conn.last_submit_ms,
                             "hashrate": f"{hr:.2f}",
+                            "hashrates": rates,
+                            "share_diff": conn.share_diff,
+                            "blocks_found": mi.blocks_found if mi else 0,
+                            "immature_balance": mi.immature_balance if mi else 0.0,
+                            "spendable_balance": mi.pending_balance if mi else 0.0,
+                            "payout_eligible": self.payout_eligible(mi, paused) if mi else False,
                         })
+                    for window, rate in pool_hr.items():
+                        _HASHRATE.set(rate, (window,))
+                    authed = sum(1 for c in self.clients.values() if c.address)
+                    _SESSIONS.set(authed, ("authorized",))
+                    _SESSIONS.set(len(self.clients) - authed, ("unauthorized",))
                     snap = {
                         "miners": miners,
                         "share_diff": self.pool_diff,
                         "accepted_5m": len(self._accepted_recent),
                         "rejected_5m": len(self._rejected_recent),
-                        "total_hashrate": total_h,
+                        "total_hashrate": pool_hr["15m"],
+                        "hashrate": pool_hr,
+                        "workers": self.hashrate.workers(nowm),
+                        "payouts_paused": paused,
+                        "jobs": [j.stats() for j in self.recent_jobs.values()],
                         "ts": nowm,
                     }
                 # persist
@@ -432,17 +1340,44 @@ class StratumPool:
                     s.merge(row)
                     s.commit()
             except Exception as e:
-                print("[POOL] snapshot error:", e)
+                plog.error(f"snapshot error: {e}")
+            self._track_blocks()
+            self._flush_state()
             time.sleep(5)
 
 
 def run_pool():
     cfg = get_config()
+    logs.setup()
     host = cfg.get("network.rpc_host", "127.0.0.1")
     port = int(cfg.get("network.pool_port", 28446))
     pool = StratumPool(host, port)
-    pool.start()
+    try:
+        pool.start()
+    except KeyboardInterrupt:
+        pass
+    finally:
+        pool.stop()
 
 
 if __name__ == "__main__":
-    run_pool()
+    import argparse
+    parser = argparse.ArgumentParser(description="SMELLY Stratum pool")
+    parser.add_argument("--register", type=str, default="", help="register ADDRESS for pool.auth_mode=db and exit")
+    parser.add_argument("--password-stdin", action="store_true", help="read the --register password from stdin instead of prompting")
+    args = parser.parse_args()
+    if args.register:
+        if args.password_stdin:
+            password = sys.stdin.readline().rstrip("\r\n")
+        else:
+            import getpass
+            password = getpass.getpass(f"Password for {args.register}: ")
+            if getpass.getpass("Repeat password: ") != password:
+                sys.exit("passwords do not match")
+        try:
+            register_account(args.register, password)
+        except ValueError as e:
+            sys.exit(str(e))
+        print(f"registered {args.register}")
+    else:
+        run_pool()
@@ -0,0 +1,77 @@
+from __future__ import annotations
+
+import threading
+from collections import deque
+from typing import Callable, Deque, List, Optional
+
+
+# Stratum over WebSocket (browser miners cannot open raw TCP sockets).
+#
+# Each WebSocket text frame carries one Stratum JSON message (a frame holding several
+# newline-separated messages is split). WsLineStream makes a connection look like the
+# socket.makefile("rwb") object the TCP path uses, so StratumPool._handle_client runs the
+# session unchanged: readline() yields one message + b"\n", write() sends each line as a frame.
+#
+# Served by the `websockets` threaded server (pool.ws_port, 0 = off). Browser Origin headers
+# are checked against pool.ws_origins (empty = any origin); connection caps, rate limits and
+# bans are the pool's own and apply to WS sessions exactly like TCP ones.
+
+
+class WsLineStream:
+    def __init__(self, ws):
+        self.ws = ws
+        self._pending: Deque[bytes] = deque()
+        self._send_lock = threading.Lock()
+
+    def readline(self, limit: int = -1) -> bytes:
+        from websockets.exceptions import ConnectionClosed
+
+        while not self._pending:
+            try:
+                msg = self.ws.recv()
+            except ConnectionClosed:
+                return b""
+            data = msg.encode("utf-8") if isinstance(msg, str) else bytes(msg)
+            for line in data.split(b"\n"):
+                if line.strip():
+                    self._pending.append(line + b"\n")
+        line = self._pending.popleft()
+        # Oversized messages are returned whole so the caller's length check (and ban) fires
+        return line
+
+    def write(self, data: bytes) -> int:
+        from websockets.exceptions import ConnectionClosed
+
+        lines = [ln for ln in data.split(b"\n") if ln.strip()]
+        with self._send_lock:
+            try:
+                for ln in lines:
+                    self.ws.send(ln.decode("utf-8"))
+            except ConnectionClosed as e:
+                raise OSError(f"websocket closed: {e}") from e
+        return len(data)
+
+    def flush(self):
+        pass
+
+    def close(self):
+        try:
+            self.ws.close()
+        except Exception:
+            pass
+
+
+def serve(handler: Callable[[object], None], host: str, port: int, origins: Optional[List[str]], max_bytes: int):
+    """Start the threaded WebSocket server in the background; handler(ws) runs one session."""
+    from websockets.sync.server import serve as ws_serve
+
+    srv = ws_serve(
+        handler,
+        host,
+        port,
+        origins=origins or None,
+        max_size=max_bytes,
+        compression=None,
+    )
+    threading.Thread(target=srv.serve_forever, name="stratum-ws", daemon=True).start()
+    return srv
@@ -2,7 +2,7 @@ from __future__ import annotations
 
 import os
 import secrets
-from typing import Optional, List
+from typing import Any, Optional, List
 
 from fastapi import FastAPI, HTTPException, Request, Response, Depends
 from fastapi.responses import HTMLResponse, JSONResponse, RedirectResponse
@@ -16,7 +16,10 @@ import hashlib
 from core.config import get_config
 from core.utils import ensure_dirs, now_ms
 from core.db import get_db, WalletAccount, SubAddress, UTXO, Reward, Transaction, MempoolTx, User
-from core.crypto import generate_seed, ed25519_keypair_from_seed, encode_address, derive_subaddress
+from core.crypto import generate_seed, ed25519_keypair_from_seed, encode_address, derive_subaddress, check_address, is_script_address
+from core import safemode
+from core import rpcauth
+from apps.wallet import wallets
 import httpx
 
 # Note: For production, add session signing keys loaded from config/secret
@@ -78,6 +81,7 @@ class SendRequest(BaseModel):
 def on_startup():
     ensure_dirs()
     get_db()  # ensure DB initialized
+    wallets.load_startup_wallets()
 
 
 # ------------ Session + CSRF (minimal demo) -------------
@@ -523,13 +527,243 @@ def api_mempool(addr: str | None = None):
             "added_ms": m.added_ms
         } for m in rows]
 
+# ----- Multi-wallet RPCs (Bitcoin Core naming; see apps/wallet/wallets.py) -----
+# Registered ahead of the /rpc/{path} node proxy so these names are served here.
+
+class CreateWalletRpcRequest(BaseModel):
+    wallet_name: str
+    passphrase: str
+    language: str = "english"
+    load_on_startup: Optional[bool] = None
+
+
+class LoadWalletRpcRequest(BaseModel):
+    filename: str
+    load_on_startup: Optional[bool] = None
+
+
+class UnloadWalletRpcRequest(BaseModel):
+    wallet_name: Optional[str] = None
+    load_on_startup: Optional[bool] = None
+
+
+class GetNewAddressRpcRequest(BaseModel):
+    label: str = ""
+
+
+class SendToAddressRpcRequest(BaseModel):
+    address: str
+    amount: float
+    fee: float = 0.0001
+    comment: Optional[str] = ""
+
+
+def _wallet_http_error(e: wallets.WalletError) -> HTTPException:
+    status = 404 if e.code == wallets.RPC_WALLET_NOT_FOUND else 400
+    return HTTPException(status_code=status, detail={"code": e.code, "message": e.message})
+
+
+def _target_wallet(request: Request, wallet_name: Optional[str]) -> wallets.LoadedWallet:
+    uid, _ = require_auth(request)
+    try:
+        return wallets.resolve(wallet_name, uid)
+    except wallets.WalletError as e:
+        raise _wallet_http_error(e)
+
+
+@app.post("/rpc/createwallet")
+def rpc_createwallet(req: CreateWalletRpcRequest, request: Request):
+    uid, _ = require_auth(request)
+    try:
+        return wallets.create(req.wallet_name, req.passphrase, uid, language=req.language, load_on_startup=req.load_on_startup)
+    except wallets.WalletError as e:
+        raise _wallet_http_error(e)
+
+
+@app.post("/rpc/loadwallet")
+def rpc_loadwallet(req: LoadWalletRpcRequest, request: Request):
+    uid, _ = require_auth(request)
+    try:
+        return wallets.load(req.filename, uid, load_on_startup=req.load_on_startup)
+    except wallets.WalletError as e:
+        raise _wallet_http_error(e)
+
+
+@app.post("/rpc/unloadwallet")
+@app.post("/wallet/{wallet_name}/unloadwallet")
+def rpc_unloadwallet(request: Request, req: UnloadWalletRpcRequest | None = None, wallet_name: Optional[str] = None):
+    req = req or UnloadWalletRpcRequest()
+    if wallet_name and req.wallet_name and wallet_name != req.wallet_name:
+        raise HTTPException(status_code=400, detail={"code": -8, "message": "RPC endpoint wallet and wallet_name parameter specify different wallets"})
+    w = _target_wallet(request, wallet_name or req.wallet_name)
+    try:
+        return wallets.unload(w.name, load_on_startup=req.load_on_startup)
+    except wallets.WalletError as e:
+        raise _wallet_http_error(e)
+
+
+@app.get("/rpc/listwallets")
+def rpc_listwallets(request: Request):
+    uid, _ = require_auth(request)
+    return wallets.list_loaded(uid)
+
+
+@app.get("/rpc/listwalletdir")
+def rpc_listwalletdir(request: Request):
+    require_auth(request)
+    return {"wallets": wallets.list_dir()}
+
+
+@app.get("/rpc/getwalletinfo")
+@app.get("/wallet/{wallet_name}/getwalletinfo")
+def rpc_getwalletinfo(request: Request, wallet_name: Optional[str] = None):
+    return wallets.info(_target_wallet(request, wallet_name))
+
+
+@app.get("/rpc/getbalance")
+@app.get("/wallet/{wallet_name}/getbalance")
+def rpc_getbalance(request: Request, wallet_name: Optional[str] = None):
+    return sum(wallets.balances(_target_wallet(request, wallet_name)).values())
+
+
+@app.get("/rpc/listaddresses")
+@app.get("/wallet/{wallet_name}/listaddresses")
+def rpc_listaddresses(request: Request, wallet_name: Optional[str] = None):
+    w = _target_wallet(request, wallet_name)
+    bal = wallets.balances(w)
+    return [dict(a, balance=bal.get(a["address"], 0.0)) for a in wallets.addresses(w)]
+
+
+@app.post("/rpc/getnewaddress")
+@app.post("/wallet/{wallet_name}/getnewaddress")
+def rpc_getnewaddress(request: Request, req: GetNewAddressRpcRequest | None = None, wallet_name: Optional[str] = None):
+    w = _target_wallet(request, wallet_name)
+    try:
+        return wallets.new_address(w, label=(req.label if req else ""))
+    except (OSError, ValueError) as e:
+        raise HTTPException(status_code=500, detail={"code": wallets.RPC_WALLET_ERROR, "message": f"wallet file error: {e}"})
+
+
+@app.post("/rpc/sendtoaddress")
+@app.post("/wallet/{wallet_name}/sendtoaddress")
+def rpc_sendtoaddress(req: SendToAddressRpcRequest, request: Request, wallet_name: Optional[str] = None):
+    w = _target_wallet(request, wallet_name)
+    source, best = wallets.pick_source(w, float(req.amount) + float(req.fee))
+    if source is None:
+        raise HTTPException(status_code=400, detail={"code": -6, "message": f"Insufficient funds: no single wallet address holds {float(req.amount) + float(req.fee):.6f} (largest {best:.6f})"})
+    res = api_send(SendRequest(from_address=source, to_address=req.address, amount=req.amount, fee=req.fee, memo=req.comment), request)
+    return dict(res, wallet=w.name)
+
+
+class AddMultisigAddressRequest(BaseModel):
+    nrequired: int
+    keys: List[str]
+    label: str = ""
+
+
+@app.post("/rpc/addmultisigaddress")
+@app.post("/wallet/{wallet_name}/addmultisigaddress")
+def rpc_addmultisigaddress(req: AddMultisigAddressRequest, request: Request, wallet_name: Optional[str] = None):
+    w = _target_wallet(request, wallet_name)
+    try:
+        res = wallets.add_multisig(w, req.nrequired, req.keys, label=req.label)
+    except ValueError as e:
+        raise HTTPException(status_code=400, detail={"code": -8, "message": str(e)})
+    return dict(res, wallet_can_sign=wallets.spend_pubkey(w) in res["redeem"]["pubkeys"])
+
+
+class FundRawTransactionRequest(BaseModel):
+    hexstring: Any  # the structured tx, as an object or JSON string
+    options: dict = {}  # changeAddress, feeRate (per kB), subtractFeeFromOutputs, replaceable
+
+
+@app.post("/rpc/fundrawtransaction")
+@app.post("/wallet/{wallet_name}/fundrawtransaction")
+def rpc_fundrawtransaction(req: FundRawTransactionRequest, request: Request, wallet_name: Optional[str] = None):
+    from core import rawtx
+
+    w = _target_wallet(request, wallet_name)
+    opts = req.options or {}
+    try:
+        tx = rawtx.load_tx(req.hexstring)
+        need = sum(float(o.get("amount", 0.0)) for o in tx["outputs"])
+        owned = set(wallets.balances(w))
+        preset = {i.get("address") for i in tx["inputs"] if i.get("address")}
+        if preset:
+            source = next(iter(preset)) if len(preset) == 1 else None
+            if source is None or source not in owned:
+                raise ValueError("preset inputs must spend from a single wallet address")
+        else:
+            source, _ = wallets.pick_source(w, need)
+            if source is None:
+                raise ValueError("Insufficient funds")
+        # Change returns to the spending address at connect, so changeAddress can only name it
+        if opts.get("changeAddress") not in (None, source):
+            raise ValueError(f"changeAddress must be the funding address {source}")
+        return rawtx.fund(tx, source, fee_rate_per_kb=opts.get("feeRate"),
+                          subtract_fee_from=opts.get("subtractFeeFromOutputs"), replaceable=bool(opts.get("replaceable")))
+    except (ValueError, TypeError) as e:
+        raise HTTPException(status_code=400, detail={"code": -4, "message": str(e)})
+
+
+class WalletProcessPsbtRequest(BaseModel):
+    psbt: str
+    sign: bool = True
+    signer: str = "auto"  # "software" (needs passphrase), "external" (hardware via wallet.external_signer), "auto"
+    passphrase: Optional[str] = None
+    fingerprint: Optional[str] = None
+    finalize: bool = True
+
+
+@app.post("/rpc/walletprocesspsbt")
+@app.post("/wallet/{wallet_name}/walletprocesspsbt")
+def rpc_walletprocesspsbt(req: WalletProcessPsbtRequest, request: Request, wallet_name: Optional[str] = None):
+    from core import psbt
+    from apps.wallet import signers
+
+    w = _target_wallet(request, wallet_name)
+    try:
+        p = psbt.decode(req.psbt)
+    except ValueError as e:
+        raise HTTPException(status_code=400, detail={"code": -22, "message": str(e)})
+    psbt.update(p)
+    signed: List[int] = []
+    if req.sign:
+        kind = req.signer if req.signer != "auto" else ("software" if req.passphrase else "external")
+        if kind == "software":
+            if not req.passphrase:
+                raise HTTPException(status_code=400, detail={"code": -13, "message": "Please enter the wallet passphrase"})
+            signer = signers.SoftwareSigner(wallets.encrypted_seed(w), req.passphrase)
+        elif kind == "external":
+            signer = signers.ExternalSigner(req.fingerprint)
+        else:
+            raise HTTPException(status_code=400, detail={"code": -8, "message": f"unknown signer {req.signer!r}"})
+        try:
+            signed = signer.sign(p, [a["address"] for a in wallets.addresses(w)])
+        except signers.SignerError as e:
+            raise HTTPException(status_code=400, detail={"code": wallets.RPC_WALLET_ERROR, "message": str(e)})
+    complete = psbt.finalize(p)[1] if req.finalize else False
+    return {"psbt": psbt.encode(p), "complete": complete, "signed_inputs": signed}
+
+
+@app.get("/rpc/enumeratesigners")
+def rpc_enumeratesigners(request: Request):
+    from apps.wallet import signers
+
+    require_auth(request)
+    try:
+        return {"signers": signers.enumerate_signers()}
+    except signers.SignerError as e:
+        raise HTTPException(status_code=400, detail={"code": wallets.RPC_WALLET_ERROR, "message": str(e)})
+
+
 # ----- Node RPC proxy + height helper -----
 @app.get("/api/v1/node/height")
 def api_node_height():
     cfg = get_config()
     node_url = f"http://{cfg.get('network.rpc_host','127.0.0.1')}:{cfg.get('network.rpc_port',28445)}"
     try:
-        r = httpx.get(f"{node_url}/rpc/get_height", timeout=5.0)
+        r = httpx.get(f"{node_url}/rpc/get_height", timeout=5.0, auth=rpcauth.client_auth())
         r.raise_for_status()
         j = r.json()
         return {"height": j.get("height", -1)}
@@ -544,9 +778,11 @@ def proxy_rpc(path: str, request: Request):
     cfg = get_config()
     node_base = f"http://{cfg.get('network.rpc_host','127.0.0.1')}:{cfg.get('network.rpc_port',28445)}"
     target = f"{node_base}/rpc/{path}"
+    # forward the caller's credentials so node ACLs apply to them, not to this service
+    fwd = {"Authorization": request.headers["authorization"]} if "authorization" in request.headers else {}
     try:
         if request.method == "GET":
-            r = httpx.get(target, params=dict(request.query_params), timeout=10.0)
+            r = httpx.get(target, params=dict(request.query_params), headers=fwd, timeout=10.0)
         else:
             body = {}
             try:
@@ -561,7 +797,7 @@ def proxy_rpc(path: str, request: Request):
                     body = request._body if hasattr(request, "_body") else {}
                 except Exception:
                     body = {}
-            r = httpx.post(target, json=body if isinstance(body, dict) else None, timeout=20.0)
+            r = httpx.post(target, json=body if isinstance(body, dict) else None, headers=fwd, timeout=20.0)
         return JSONResponse(status_code=r.status_code, content=r.json() if r.headers.get("content-type","").startswith("application/json") else {"text": r.text})
     except Exception as e:
         raise HTTPException(status_code=502, detail=f"RPC proxy error: {e}")
@@ -604,6 +840,13 @@ def api_export_mnemonic(req: ExportMnemonicRequest, request: Request):
 def api_send(req: SendRequest, request: Request):
     _require_csrf(request)
     require_auth(request)
+    if safemode.is_active():
+        raise HTTPException(status_code=503, detail="; ".join(safemode.warnings()) or "Safe mode active: sending disabled")
+    addr_err = check_address(req.to_address)
+    if addr_err:
+        raise HTTPException(status_code=400, detail=f"Invalid destination address: {addr_err}")
+    if is_script_address(req.from_address):
+        raise HTTPException(status_code=400, detail="Multisig addresses spend through createpsbt/walletprocesspsbt/finalizepsbt")
     db = get_db()
     id_key = req.dedupe_key()
     nowm = now_ms()
@@ -0,0 +1,117 @@
+from __future__ import annotations
+
+import json
+import shlex
+import subprocess
+from typing import Any, Dict, Iterable, List, Optional, Tuple
+
+from core import psbt
+from core.config import get_config
+from core.crypto import ed25519_keypair_from_seed
+
+
+# Signers for PSBTs (core/psbt.py). A signer takes a decoded PSBT and adds signatures for the
+# inputs it holds keys for; walletprocesspsbt picks one per call:
+#   SoftwareSigner  the wallet's own spend key, recovered from the encrypted mnemonic with the
+#                   wallet passphrase for the duration of the call (hot signing)
+#   ExternalSigner  a hardware wallet reached through the wallet.external_signer command, in the
+#                   style of Bitcoin Core's -signer / HWI:
+#                     <cmd> enumerate                         -> [{"fingerprint", "model"}]
+#                     <cmd> --fingerprint <fp> signtx <psbt>  -> {"psbt": <base64>}
+#                   The device shows and signs the sighashes; keys never reach this process.
+# A fully offline signer needs no class here: export the PSBT, sign it elsewhere, and bring it
+# back through combinepsbt/finalizepsbt. Signatures a signer returns are verified when merged.
+
+
+class SignerError(Exception):
+    pass
+
+
+class Signer:
+    name = "signer"
+
+    def sign(self, p: Dict[str, Any], addresses: Iterable[str]) -> List[int]:
+        """Sign inputs of p spending from addresses; returns the input indexes signed."""
+        raise NotImplementedError
+
+
+class SoftwareSigner(Signer):
+    name = "software"
+
+    def __init__(self, enc: Dict[str, Any], passphrase: str):
+        """enc: the wallet file's enc_mnemonic/enc_salt/enc_nonce."""
+        self._enc = enc
+        self._passphrase = passphrase
+
+    def _keys(self) -> Tuple[bytes, bytes]:
+        """(spend secret key, public view key)."""
+        from mnemonic import Mnemonic
+        from apps.wallet.wallets import decrypt_mnemonic
+
+        try:
+            words = decrypt_mnemonic(self._enc, self._passphrase)
+        except ValueError as e:
+            raise SignerError(str(e))
+        seed = Mnemonic("english").to_seed(words, passphrase="")
+        sk_spend, _ = ed25519_keypair_from_seed(seed, ctx=b"smelly-spend")
+        _, pk_view = ed25519_keypair_from_seed(seed, ctx=b"smelly-view")
+        return sk_spend[:32], pk_view
+
+    def sign(self, p: Dict[str, Any], addresses: Iterable[str]) -> List[int]:
+        sk, pk_view = self._keys()
+        return psbt.sign_with_key(p, sk, addresses, pk_view)
+
+
+def _external_command() -> List[str]:
+    cmd = str(get_config().get("wallet.external_signer", "") or "").strip()
+    if not cmd:
+        raise SignerError("No external signer configured (wallet.external_signer)")
+    return shlex.split(cmd)
+
+
+def _run(args: List[str]) -> Any:
+    timeout = float(get_config().get("wallet.external_signer_timeout_sec", 120))
+    try:
+        res = subprocess.run(_external_command() + args, capture_output=True, text=True, timeout=timeout)
+    except (OSError, subprocess.TimeoutExpired) as e:
+        raise SignerError(f"External signer failed: {e}")
+    if res.returncode != 0:
+        raise SignerError(f"External signer exited {res.returncode}: {res.stderr.strip()[:200]}")
+    try:
+        return json.loads(res.stdout)
+    except ValueError:
+        raise SignerError("External signer returned invalid JSON")
+
+
+def enumerate_signers() -> List[Dict[str, Any]]:
+    out = _run(["enumerate"])
+    if not isinstance(out, list):
+        raise SignerError("External signer returned invalid JSON")
+    return [d for d in out if isinstance(d, dict) and d.get("fingerprint")]
+
+
+class ExternalSigner(Signer):
+    name = "external"
+
+    def __init__(self, fingerprint: Optional[str] = None):
+        self.fingerprint = fingerprint
+
+    def sign(self, p: Dict[str, Any], addresses: Iterable[str]) -> List[int]:
+        fingerprint = self.fingerprint
+        if not fingerprint:
+            devices = enumerate_signers()
+            if len(devices) != 1:
+                raise SignerError(f"{len(devices)} external signers found; pass a fingerprint")
+            fingerprint = devices[0]["fingerprint"]
+        out = _run(["--fingerprint", str(fingerprint), "signtx", psbt.encode(p)])
+        if not isinstance(out, dict) or not out.get("psbt"):
+            raise SignerError(str(out.get("error") if isinstance(out, dict) else "") or "External signer returned no PSBT")
+        try:
+            signed = psbt.decode(out["psbt"])
+            before = {i for i, m in enumerate(p["inputs"]) if m["partial_sigs"]}
+            psbt.merge_signatures(p, signed)
+        except ValueError as e:
+            raise SignerError(f"External signer returned a bad PSBT: {e}")
+        wanted = set(addresses)
+        return [i for i, m in enumerate(p["inputs"])
+                if m["partial_sigs"] and i not in before and (m.get("prev") or {}).get("address") in wanted]
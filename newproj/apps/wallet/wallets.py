@@ -0,0 +1,379 @@
+from __future__ import annotations
+
+import hashlib
+import json
+import os
+import re
+import threading
+from base64 import b64decode, b64encode
+from dataclasses import dataclass
+from typing import Any, Dict, List, Optional, Tuple
+
+from argon2 import PasswordHasher
+
+from core.config import get_config
+from core.db import get_db, KV, SubAddress, UTXO, WalletAccount
+from core.crypto import generate_seed, ed25519_keypair_from_seed, encode_address, derive_subaddress, is_script_address, multisig_redeem, script_address
+from core.utils import now_ms
+from core import logs
+
+
+# Named wallets (multi-wallet), following Bitcoin Core's createwallet/loadwallet/unloadwallet/listwallets.
+#
+# A wallet is a JSON file <wallet.wallets_dir>/<name>.json holding the public view/spend keys, the
+# Argon2id/AES-GCM encrypted mnemonic and the subaddresses handed out. The WalletAccount and
+# SubAddress rows the web UI works with are its index: loadwallet (re)creates them from the file,
+# matched on the public keys, so a file copied from another node loads as-is. getnewaddress writes
+# the file before the row. Multisig (script) addresses added with addmultisigaddress are kept in
+# the file with their redeem script and indexed as SubAddress rows with index_major = MULTISIG_MAJOR;
+# the wallet watches them (balances) and signs its share through walletprocesspsbt.
+#
+# Wallet RPCs address one loaded wallet: /wallet/<name>/<method>. The plain /rpc/<method> form only
+# works while exactly one wallet (visible to the caller) is loaded, otherwise it fails with
+# RPC_WALLET_NOT_SPECIFIED (-19); an unknown or unloaded name is RPC_WALLET_NOT_FOUND (-18).
+# The loaded set is in-process; names passed with load_on_startup=true are kept in KV
+# (wallets_load_on_startup_json) and loaded again when the wallet backend starts.
+
+RPC_WALLET_NOT_FOUND = -18
+RPC_WALLET_NOT_SPECIFIED = -19
+RPC_WALLET_ERROR = -4
+RPC_WALLET_ALREADY_LOADED = -35
+WALLET_FILE_VERSION = 1
+MULTISIG_MAJOR = -1
+_STARTUP_KEY = "wallets_load_on_startup_json"
+_NAME_RE = re.compile(r"^[A-Za-z0-9_][A-Za-z0-9_.-]{0,63}$")
+PH = PasswordHasher()
+
+
+class WalletError(Exception):
+    def __init__(self, code: int, message: str):
+        super().__init__(message)
+        self.code = code
+        self.message = message
+
+
+@dataclass
+class LoadedWallet:
+    name: str
+    account_id: int
+    owner_user_id: Optional[int]
+    path: str
+    loaded_ms: int
+
+
+_lock = threading.Lock()
+log = logs.get("wallet")
+_loaded: Dict[str, LoadedWallet] = {}
+
+
+def wallets_dir() -> str:
+    return str(get_config().get("wallet.wallets_dir", "data/wallets"))
+
+
+def _path(name: str) -> str:
+    if not _NAME_RE.match(name or ""):
+        raise WalletError(RPC_WALLET_ERROR, f"Invalid wallet name {name!r}")
+    return os.path.join(wallets_dir(), name + ".json")
+
+
+def _read_file(path: str) -> Dict[str, Any]:
+    with open(path, "r", encoding="utf-8") as f:
+        data = json.load(f)
+    if not isinstance(data, dict) or data.get("version") != WALLET_FILE_VERSION:
+        raise ValueError("not a version-1 wallet file")
+    return data
+
+
+def _write_file(path: str, data: Dict[str, Any]):
+    os.makedirs(os.path.dirname(os.path.abspath(path)), exist_ok=True)
+    tmp = path + ".tmp"
+    with open(tmp, "w", encoding="utf-8") as f:
+        json.dump(data, f, indent=1, sort_keys=True)
+        f.flush()
+        os.fsync(f.fileno())
+    os.replace(tmp, path)
+
+
+def encrypt_mnemonic(words: str, passphrase: str) -> Dict[str, str]:
+    """Same scheme as the web wallet: sha256(argon2(passphrase + salt)) as the AES-GCM key."""
+    from cryptography.hazmat.primitives.ciphers.aead import AESGCM
+
+    salt = os.urandom(16)
+    phs = PH.hash(passphrase + b64encode(salt).decode("utf-8"))
+    key = hashlib.sha256(phs.encode("utf-8")).digest()
+    nonce = os.urandom(12)
+    ct = AESGCM(key).encrypt(nonce, words.encode("utf-8"), None)
+    return {
+        "enc_mnemonic": b64encode(ct).decode("utf-8"),
+        "enc_salt": b64encode(salt).decode("utf-8"),
+        "enc_nonce": b64encode(nonce).decode("utf-8"),
+    }
+
+
+def decrypt_mnemonic(enc: Dict[str, Any], passphrase: str) -> str:
+    """Inverse of encrypt_mnemonic (as /api/v1/wallet/export_mnemonic); raises ValueError."""
+    from cryptography.hazmat.primitives.ciphers.aead import AESGCM
+
+    phs = PH.hash(passphrase + enc["enc_salt"])
+    key = hashlib.sha256(phs.encode("utf-8")).digest()
+    try:
+        return AESGCM(key).decrypt(b64decode(enc["enc_nonce"]), b64decode(enc["enc_mnemonic"]), None).decode("utf-8")
+    except Exception:
+        raise ValueError("Invalid passphrase")
+
+
+def _index(data: Dict[str, Any], owner_user_id: Optional[int]) -> int:
+    """Make sure the WalletAccount/SubAddress rows for a wallet file exist; returns the account id."""
+    db = get_db()
+    with db.session() as s:
+        acc = (
+            s.query(WalletAccount)
+            .filter_by(public_view_key=data["public_view_key"], public_spend_key=data["public_spend_key"])
+            .order_by(WalletAccount.id.asc())
+            .first()
+        )
+        if acc is None:
+            acc = WalletAccount(
+                name=data["name"],
+                public_view_key=data["public_view_key"],
+                public_spend_key=data["public_spend_key"],
+                owner_user_id=owner_user_id,
+                enc_mnemonic=data.get("enc_mnemonic"),
+                enc_salt=data.get("enc_salt"),
+                enc_nonce=data.get("enc_nonce"),
+                created_ms=int(data.get("created_ms") or now_ms()),
+            )
+            s.add(acc)
+            s.flush()
+        elif acc.owner_user_id not in (None, owner_user_id):
+            raise WalletError(RPC_WALLET_ERROR, f"Wallet {data['name']!r} belongs to another user")
+        subs = list(data.get("subaddresses") or [])
+        subs += [dict(ms, major=MULTISIG_MAJOR) for ms in data.get("multisig") or []]
+        for sub in subs:
+            existing = s.query(SubAddress).filter_by(address=sub["address"]).first()
+            if existing is None:
+                s.add(SubAddress(account_id=acc.id, index_major=int(sub["major"]), index_minor=int(sub["minor"]),
+                                 address=sub["address"], label=sub.get("label") or ""))
+        s.commit()
+        return acc.id
+
+
+def _set_load_on_startup(name: str, flag: Optional[bool]):
+    if flag is None:
+        return
+    db = get_db()
+    with db.session() as s:
+        row = s.get(KV, _STARTUP_KEY) or KV(k=_STARTUP_KEY, v="[]")
+        try:
+            names = set(json.loads(row.v or "[]"))
+        except ValueError:
+            names = set()
+        if flag:
+            names.add(name)
+        else:
+            names.discard(name)
+        row.v = json.dumps(sorted(names))
+        s.merge(row)
+        s.commit()
+
+
+def _warnings(flag: Optional[bool]) -> List[str]:
+    if flag is None:
+        return []
+    return ["Wallet will be loaded on startup." if flag else "Wallet will no longer be loaded on startup."]
+
+
+def create(name: str, passphrase: str, owner_user_id: Optional[int], language: str = "english",
+           load_on_startup: Optional[bool] = None) -> Dict[str, Any]:
+    path = _path(name)
+    if not passphrase or len(passphrase) < 4:
+        raise WalletError(RPC_WALLET_ERROR, "Passphrase required")
+    with _lock:
+        if name in _loaded or os.path.exists(path):
+            raise WalletError(RPC_WALLET_ERROR, f"Wallet file verification failed. Failed to create database path '{path}'. Database already exists.")
+        words, seed = generate_seed(language=language)
+        _, pk_spend = ed25519_keypair_from_seed(seed, ctx=b"smelly-spend")
+        _, pk_view = ed25519_keypair_from_seed(seed, ctx=b"smelly-view")
+        data = dict(
+            encrypt_mnemonic(words, passphrase),
+            version=WALLET_FILE_VERSION,
+            name=name,
+            public_view_key=pk_view.hex(),
+            public_spend_key=pk_spend.hex(),
+            created_ms=now_ms(),
+            subaddresses=[{"major": 0, "minor": 0, "address": encode_address(pk_view, pk_spend), "label": "Primary"}],
+        )
+        _write_file(path, data)
+        account_id = _index(data, owner_user_id)
+        _loaded[name] = LoadedWallet(name=name, account_id=account_id, owner_user_id=owner_user_id, path=path, loaded_ms=now_ms())
+    _set_load_on_startup(name, load_on_startup)
+    return {"name": name, "warnings": _warnings(load_on_startup)}
+
+
+def load(name: str, owner_user_id: Optional[int], load_on_startup: Optional[bool] = None) -> Dict[str, Any]:
+    path = _path(name)
+    with _lock:
+        if name in _loaded:
+            raise WalletError(RPC_WALLET_ALREADY_LOADED, f"Wallet {name!r} is already loaded.")
+        if not os.path.exists(path):
+            raise WalletError(RPC_WALLET_NOT_FOUND, f"Wallet file verification failed. Failed to load database path '{path}'. Path does not exist.")
+        try:
+            data = _read_file(path)
+        except (OSError, ValueError) as e:
+            raise WalletError(RPC_WALLET_ERROR, f"Wallet file verification failed: {e}")
+        account_id = _index(data, owner_user_id)
+        with get_db().session() as s:
+            owner = s.get(WalletAccount, account_id).owner_user_id
+        _loaded[name] = LoadedWallet(name=name, account_id=account_id, owner_user_id=owner, path=path, loaded_ms=now_ms())
+    _set_load_on_startup(name, load_on_startup)
+    return {"name": name, "warnings": _warnings(load_on_startup)}
+
+
+def unload(name: str, load_on_startup: Optional[bool] = None) -> Dict[str, Any]:
+    with _lock:
+        if _loaded.pop(name, None) is None:
+            raise WalletError(RPC_WALLET_NOT_FOUND, "Requested wallet does not exist or is not loaded")
+    _set_load_on_startup(name, load_on_startup)
+    return {"warnings": _warnings(load_on_startup)}
+
+
+def _visible(w: LoadedWallet, user_id: Optional[int]) -> bool:
+    return w.owner_user_id in (None, user_id)
+
+
+def list_loaded(user_id: Optional[int]) -> List[str]:
+    with _lock:
+        return sorted(n for n, w in _loaded.items() if _visible(w, user_id))
+
+
+def list_dir() -> List[Dict[str, str]]:
+    d = wallets_dir()
+    if not os.path.isdir(d):
+        return []
+    return [{"name": f[:-5]} for f in sorted(os.listdir(d)) if f.endswith(".json") and _NAME_RE.match(f[:-5])]
+
+
+def resolve(name: Optional[str], user_id: Optional[int]) -> LoadedWallet:
+    """The wallet a wallet RPC targets: the named one, or the only loaded one."""
+    with _lock:
+        if name is not None:
+            w = _loaded.get(name)
+            if w is None or not _visible(w, user_id):
+                raise WalletError(RPC_WALLET_NOT_FOUND, "Requested wallet does not exist or is not loaded")
+            return w
+        visible = [w for w in _loaded.values() if _visible(w, user_id)]
+    if not visible:
+        raise WalletError(RPC_WALLET_NOT_FOUND, "No wallet is loaded. Load a wallet using loadwallet or create a new one with createwallet.")
+    if len(visible) > 1:
+        raise WalletError(RPC_WALLET_NOT_SPECIFIED, "Wallet file not specified (must request wallet RPC through /wallet/<filename> uri-path).")
+    return visible[0]
+
+
+def addresses(w: LoadedWallet) -> List[Dict[str, Any]]:
+    db = get_db()
+    with db.session() as s:
+        subs = (
+            s.query(SubAddress)
+            .filter_by(account_id=w.account_id)
+            .order_by(SubAddress.index_major, SubAddress.index_minor)
+            .all()
+        )
+        return [{"major": x.index_major, "minor": x.index_minor, "address": x.address, "label": x.label or ""} for x in subs]
+
+
+def balances(w: LoadedWallet) -> Dict[str, float]:
+    """Unspent amount per wallet address (addresses without coins included at 0)."""
+    addrs = [a["address"] for a in addresses(w)]
+    out = {a: 0.0 for a in addrs}
+    if not addrs:
+        return out
+    db = get_db()
+    with db.session() as s:
+        for u in s.query(UTXO).filter(UTXO.address.in_(addrs), UTXO.spent.is_(False)).all():
+            out[u.address] += float(u.amount)
+    return out
+
+
+def info(w: LoadedWallet) -> Dict[str, Any]:
+    bal = balances(w)
+    return {
+        "walletname": w.name,
+        "account_id": w.account_id,
+        "walletversion": WALLET_FILE_VERSION,
+        "balance": sum(bal.values()),
+        "address_count": len(bal),
+        "pubkey": spend_pubkey(w),
+        "loaded_ms": w.loaded_ms,
+        "path": w.path,
+    }
+
+
+def new_address(w: LoadedWallet, label: str = "") -> str:
+    """Next subaddress in account 0; recorded in the wallet file, then indexed."""
+    with _lock:
+        data = _read_file(w.path)
+        subs = data.setdefault("subaddresses", [])
+        minor = max((int(x["minor"]) for x in subs if int(x["major"]) == 0), default=0) + 1
+        addr = derive_subaddress(bytes.fromhex(data["public_view_key"]), bytes.fromhex(data["public_spend_key"]), 0, minor)
+        subs.append({"major": 0, "minor": minor, "address": addr, "label": label or f"0/{minor}"})
+        _write_file(w.path, data)
+        _index(data, w.owner_user_id)
+    return addr
+
+
+def add_multisig(w: LoadedWallet, m: int, pubkeys: List[str], label: str = "") -> Dict[str, Any]:
+    """Watch an m-of-n script address (raises ValueError on a bad redeem script)."""
+    redeem = multisig_redeem(m, pubkeys)
+    addr = script_address(redeem)
+    with _lock:
+        data = _read_file(w.path)
+        entries = data.setdefault("multisig", [])
+        if not any(e["address"] == addr for e in entries):
+            minor = max((int(e["minor"]) for e in entries), default=-1) + 1
+            entries.append({"minor": minor, "address": addr, "redeem": redeem, "label": label or f"multisig {redeem['m']}-of-{len(pubkeys)}"})
+            _write_file(w.path, data)
+        _index(data, w.owner_user_id)
+    return {"address": addr, "redeem": redeem}
+
+
+def spend_pubkey(w: LoadedWallet) -> str:
+    """The wallet's key for multisig cosigning (the spend key walletprocesspsbt signs with)."""
+    return _read_file(w.path)["public_spend_key"]
+
+
+def encrypted_seed(w: LoadedWallet) -> Dict[str, str]:
+    """enc_mnemonic/enc_salt/enc_nonce from the wallet file (for apps.wallet.signers)."""
+    data = _read_file(w.path)
+    return {k: data.get(k) or "" for k in ("enc_mnemonic", "enc_salt", "enc_nonce")}
+
+
+def pick_source(w: LoadedWallet, need: float) -> Tuple[Optional[str], float]:
+    """Wallet address with the largest balance, and that balance (sends are single-address, key addresses only)."""
+    bal = {a: v for a, v in balances(w).items() if not is_script_address(a)}
+    if not bal:
+        return None, 0.0
+    addr = max(bal, key=lambda a: (bal[a], a))
+    return (addr if bal[addr] + 1e-12 >= need else None), bal[addr]
+
+
+def load_startup_wallets() -> List[str]:
+    db = get_db()
+    with db.session() as s:
+        row = s.get(KV, _STARTUP_KEY)
+    try:
+        names = json.loads(row.v) if row and row.v else []
+    except ValueError:
+        names = []
+    loaded = []
+    for name in names:
+        try:
+            data = _read_file(_path(name))
+            with db.session() as s:
+                acc = s.query(WalletAccount).filter_by(public_view_key=data["public_view_key"],
+                                                       public_spend_key=data["public_spend_key"]).first()
+                owner = acc.owner_user_id if acc else None
+            load(name, owner)
+            loaded.append(name)
+        except (WalletError, OSError, ValueError, KeyError) as e:
+            log.warning(f"could not load wallet {name!r} at startup: {e}", extra=logs.fields(wallet=name))
+    return loaded
@@ -0,0 +1,180 @@
+from __future__ import annotations
+
+from typing import Any, Dict, Iterable, List, Optional
+
+from sqlalchemy import func
+
+from core.config import get_config
+from core.db import get_db, AddressBalance, BlockHeader, KV, UTXO
+from core.utxohash import amount_sats, block_created, SATS_PER_COIN
+from core import logs
+
+
+# Address balance index: address -> (unspent value, output count), for explorers and rich lists.
+#
+# Balances are the sum of an address's unspent outputs, kept in the address_balances table so a
+# lookup or a top-N query does not scan the UTXO table. Maintained the same way as the UTXO set
+# hash (core.utxohash): apply_block() folds in a connected block's delta (outputs it created,
+# outputs it spent), core.invalidation calls undo_block() with what it deletes and restores, and
+# the KV row address_balances_tip names the block the table describes. When that is not the
+# parent of the block being applied (or at RPC startup, not the tip) the table is rebuilt from
+# the UTXO set. Controlled by storage.address_index; rows whose balance drops to zero are removed.
+
+_TIP_KEY = "address_balances_tip"
+log = logs.get("storage")
+
+
+def enabled() -> bool:
+    return bool(get_config().get("storage.address_index", True))
+
+
+def _tip(s) -> Optional[str]:
+    row = s.get(KV, _TIP_KEY)
+    return row.v if row and row.v else None
+
+
+def _set_tip(s, block: Optional[BlockHeader]):
+    row = s.get(KV, _TIP_KEY) or KV(k=_TIP_KEY, v="")
+    row.v = block.hash_hex if block else ""
+    s.merge(row)
+
+
+def _add(s, address: str, sats: int, outputs: int, height: Optional[int]):
+    row = s.query(AddressBalance).filter_by(address=address).first()
+    if row is None:
+        if sats <= 0 and outputs <= 0:
+            return
+        row = AddressBalance(address=address, balance_sats=0, utxo_count=0)
+        s.add(row)
+    row.balance_sats = int(row.balance_sats or 0) + sats
+    row.utxo_count = int(row.utxo_count or 0) + outputs
+    row.last_height = height
+    if row.utxo_count <= 0:
+        s.delete(row)
+
+
+def _apply_delta(s, added: Iterable[UTXO], removed: Iterable[UTXO], height: Optional[int]):
+    delta: Dict[str, List[int]] = {}
+    for u, sign in [(u, 1) for u in added] + [(u, -1) for u in removed]:
+        d = delta.setdefault(u.address, [0, 0])
+        d[0] += sign * amount_sats(u.amount)
+        d[1] += sign
+    for address, (sats, outputs) in delta.items():
+        if sats or outputs:
+            _add(s, address, sats, outputs, height)
+    s.flush()
+
+
+def rebuild() -> int:
+    """Recompute every balance from the unspent outputs; returns the number of addresses."""
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        s.query(AddressBalance).delete(synchronize_session=False)
+        rows = (
+            s.query(UTXO.address, func.sum(UTXO.amount), func.count(UTXO.id))
+            .filter(UTXO.spent.is_(False))
+            .group_by(UTXO.address)
+            .all()
+        )
+        for address, total, count in rows:
+            s.add(AddressBalance(address=address, balance_sats=amount_sats(total), utxo_count=int(count),
+                                 last_height=tip.height if tip else None))
+        _set_tip(s, tip)
+        s.commit()
+    return len(rows)
+
+
+def apply_block(hash_hex: str):
+    db = get_db()
+    with db.session() as s:
+        block = s.query(BlockHeader).filter_by(hash_hex=hash_hex).first()
+        if block is None:
+            return
+        if _tip(s) != block.prev_hash_hex:
+            s.rollback()
+            rebuild()
+            return
+        spent = s.query(UTXO).filter(UTXO.spent_txid == hash_hex).all()
+        _apply_delta(s, block_created(s, block), spent, block.height)
+        _set_tip(s, block)
+        s.commit()
+
+
+def apply_block_best_effort(hash_hex: Optional[str]):
+    if not hash_hex or not enabled():
+        return
+    try:
+        apply_block(hash_hex)
+    except Exception as e:
+        log.error(f"addrindex: update failed: {e}", extra=logs.fields(hash=hash_hex))
+
+
+def undo_block(s, block: BlockHeader, deleted: Iterable[UTXO], restored: Iterable[UTXO]):
+    """Reverse a disconnected block in the caller's session; skipped if the table is not at block."""
+    if not enabled() or _tip(s) != block.hash_hex:
+        return
+    prev = s.query(BlockHeader).filter_by(hash_hex=block.prev_hash_hex).first()
+    _apply_delta(s, restored, [u for u in deleted if not u.spent], prev.height if prev else None)
+    _set_tip(s, prev)
+
+
+def backfill() -> bool:
+    """Rebuild at startup if the table does not describe the current tip."""
+    if not enabled():
+        return False
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        current = _tip(s)
+    if current == (tip.hash_hex if tip else None) and current is not None:
+        return False
+    rebuild()
+    return True
+
+
+def _row(r: AddressBalance) -> Dict[str, Any]:
+    return {
+        "address": r.address,
+        "balance": int(r.balance_sats) / SATS_PER_COIN,
+        "balance_sats": int(r.balance_sats),
+        "utxos": int(r.utxo_count),
+        "last_height": r.last_height,
+    }
+
+
+def get_address_balance(address: str) -> Dict[str, Any]:
+    db = get_db()
+    with db.session() as s:
+        r = s.query(AddressBalance).filter_by(address=address).first()
+        if r is None:
+            return {"address": address, "balance": 0.0, "balance_sats": 0, "utxos": 0, "last_height": None}
+        return _row(r)
+
+
+def get_top_addresses(n: int) -> List[Dict[str, Any]]:
+    db = get_db()
+    with db.session() as s:
+        rows = (
+            s.query(AddressBalance)
+            .order_by(AddressBalance.balance_sats.desc(), AddressBalance.address)
+            .limit(max(0, int(n)))
+            .all()
+        )
+        return [_row(r) for r in rows]
+
+
+def info() -> Dict[str, Any]:
+    db = get_db()
+    with db.session() as s:
+        count, total = s.query(func.count(AddressBalance.id), func.coalesce(func.sum(AddressBalance.balance_sats), 0)).one()
+        tip = _tip(s)
+        block = s.query(BlockHeader).filter_by(hash_hex=tip).first() if tip else None
+    return {
+        "enabled": enabled(),
+        "addresses": int(count or 0),
+        "total_amount": int(total or 0) / SATS_PER_COIN,
+        "bestblock": tip,
+        "height": block.height if block else None,
+    }
+
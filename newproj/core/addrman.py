@@ -0,0 +1,342 @@
+from __future__ import annotations
+
+import ipaddress
+import random
+import socket
+import threading
+from typing import Any, Dict, Iterable, List, Optional, Set, Tuple
+
+from core.config import get_config
+from core.db import get_db, Peer
+from core.netlocal import format_addr, is_reachable, netgroup, network_of, split_host_port
+from core.utils import now_ms
+from core import logs
+
+
+# Persistent peer address manager (peers table).
+#
+# Addresses live in one of two buckets:
+#   new   - learned from DNS seeds, static seeds or ADDR gossip, never connected
+#   tried - we completed an outbound handshake with it at least once
+# Both buckets are capped; when full, the least useful entry is evicted (new: most failed
+# attempts / oldest; tried: oldest success, demoted back to new). Addresses that fail
+# repeatedly without ever succeeding are dropped. Addresses are stored in core.netlocal's
+# canonical form (IPv6 in brackets); select() only offers ones on a reachable network. Only
+# configured seeds and manual addnode may be hostnames: gossiped addresses (VERSION addrs, ADDR)
+# must be IP literals, so a peer cannot make us resolve names it chooses.
+#
+# Quality: every successful outbound handshake adds 1 to an address's reputation, every failed
+# connect subtracts 1 (clamped to +-100). Reputation decays toward 0 with a half-life of
+# network.addrman_score_halflife_hours, measured from the last try or success, so a peer that
+# was good last month but has not been seen since no longer outranks a fresh one. select() picks
+# within a bucket weighted by score, and preferred() hands the node's first outbound round the
+# best-scored tried addresses that succeeded within one half-life, so a restart reconnects to
+# known-good peers before falling back to gossip and seeds. Services (NODE_* bits) are what the
+# peer advertised in its last VERSION to us.
+
+MAX_ADDRS_PER_MSG = 1000
+_MAX_FAILURES_NEW = 5
+MAX_REPUTATION = 100.0
+
+NODE_NETWORK = 1  # serves every block body
+NODE_NETWORK_LIMITED = 2  # header-only node: bodies of recent blocks only (core.headeronly)
+NODE_COMPACT = 4  # compact block relay (core.compact)
+_SERVICE_NAMES = ((NODE_NETWORK, "NETWORK"), (NODE_NETWORK_LIMITED, "NETWORK_LIMITED"), (NODE_COMPACT, "COMPACT"))
+_lock = threading.Lock()
+
+
+def _limits() -> Tuple[int, int]:
+    cfg = get_config()
+    return int(cfg.get("network.addrman_new_max", 1024)), int(cfg.get("network.addrman_tried_max", 256))
+
+
+def normalize(addr: str, allow_hostnames: bool = False) -> Optional[str]:
+    """Return canonical host:port ([v6]:port for IPv6) or None if malformed or, unless allowed, not an IP literal."""
+    try:
+        host, port = split_host_port(addr)
+        if not allow_hostnames:
+            ipaddress.ip_address(host)
+    except (ValueError, TypeError):
+        return None
+    return format_addr(host, port)
+
+
+def local_services() -> int:
+    from core import headeronly
+
+    bits = NODE_NETWORK_LIMITED if headeronly.enabled() else NODE_NETWORK
+    if get_config().get("network.compact_blocks", True):
+        bits |= NODE_COMPACT
+    return bits
+
+
+def service_names(bits: int) -> List[str]:
+    return [name for bit, name in _SERVICE_NAMES if int(bits or 0) & bit]
+
+
+def _halflife_ms() -> float:
+    return max(0.001, float(get_config().get("network.addrman_score_halflife_hours", 24))) * 3_600_000
+
+
+def score(row: Peer, nowm: Optional[int] = None) -> float:
+    """Reputation decayed from the last try or success (see module comment)."""
+    nowm = now_ms() if nowm is None else nowm
+    since = max(int(row.last_try_ms or 0), int(row.last_success_ms or 0))
+    age = max(0, nowm - since) if since else 0
+    return float(row.reputation or 0.0) * 0.5 ** (age / _halflife_ms())
+
+
+def _weight(row: Peer, nowm: int) -> float:
+    return 2.0 ** (max(-20.0, min(20.0, score(row, nowm))) / 4.0)
+
+
+def _reachable(addr: str) -> bool:
+    try:
+        return is_reachable(network_of(split_host_port(addr)[0]))
+    except ValueError:
+        return False
+
+
+def _evict_new(s, new_max: int):
+    count = s.query(Peer).filter(Peer.tried == False).count()  # noqa: E712
+    if count <= new_max:
+        return
+    victims = (
+        s.query(Peer)
+        .filter(Peer.tried == False)  # noqa: E712
+        .order_by(Peer.attempts.desc(), Peer.last_seen_ms.asc())
+        .limit(count - new_max)
+        .all()
+    )
+    for v in victims:
+        s.delete(v)
+
+
+def add(addrs: Iterable[str], source: str, seen_ms: Optional[int] = None, allow_hostnames: bool = False) -> int:
+    """
+    Add addresses to the new bucket (refreshing last_seen for known ones). Returns count inserted.
+    allow_hostnames is for operator-supplied sources (seed_peers, addnode); gossip stays IP-only.
+    """
+    new_max, _ = _limits()
+    ts = seen_ms or now_ms()
+    added = 0
+    with _lock, get_db().session() as s:
+        for raw in list(addrs)[:MAX_ADDRS_PER_MSG]:
+            addr = normalize(raw, allow_hostnames)
+            if addr is None:
+                continue
+            row = s.query(Peer).filter_by(address=addr).first()
+            if row is None:
+                s.add(Peer(address=addr, last_seen_ms=ts, tried=False, source=source[:64]))
+                added += 1
+            elif ts > (row.last_seen_ms or 0):
+                row.last_seen_ms = ts
+        s.flush()
+        _evict_new(s, new_max)
+        s.commit()
+    return added
+
+
+def mark_attempt(addr: str):
+    with _lock, get_db().session() as s:
+        row = s.query(Peer).filter_by(address=addr).first()
+        if row is not None:
+            row.attempts = (row.attempts or 0) + 1
+            row.last_try_ms = now_ms()
+            s.commit()
+
+
+def mark_good(addr: str):
+    """Outbound handshake succeeded: move to tried (demoting the oldest tried entry if full)."""
+    _, tried_max = _limits()
+    nowm = now_ms()
+    with _lock, get_db().session() as s:
+        row = s.query(Peer).filter_by(address=addr).first()
+        if row is None:
+            row = Peer(address=addr, source="outbound")
+            s.add(row)
+        row.reputation = min(MAX_REPUTATION, score(row, nowm) + 1.0)
+        row.tried = True
+        row.attempts = 0
+        row.last_success_ms = nowm
+        row.last_seen_ms = nowm
+        s.flush()
+        count = s.query(Peer).filter(Peer.tried == True).count()  # noqa: E712
+        if count > tried_max:
+            for old in (
+                s.query(Peer)
+                .filter(Peer.tried == True, Peer.address != addr)  # noqa: E712
+                .order_by(Peer.last_success_ms.asc())
+                .limit(count - tried_max)
+                .all()
+            ):
+                old.tried = False
+        s.commit()
+
+
+def mark_failed(addr: str):
+    with _lock, get_db().session() as s:
+        row = s.query(Peer).filter_by(address=addr).first()
+        if row is None:
+            return
+        row.reputation = max(-MAX_REPUTATION, score(row) - 1.0)
+        row.failures = (row.failures or 0) + 1
+        if not row.tried and (row.attempts or 0) >= _MAX_FAILURES_NEW:
+            s.delete(row)
+        s.commit()
+
+
+def set_services(addr: str, services: Any):
+    """Record the NODE_* bits an outbound peer advertised in VERSION."""
+    try:
+        bits = max(0, int(services or 0))
+    except (TypeError, ValueError):
+        return
+    with _lock, get_db().session() as s:
+        row = s.query(Peer).filter_by(address=addr).first()
+        if row is not None and row.services != bits:
+            row.services = bits
+            s.commit()
+
+
+def _group(addr: str) -> str:
+    try:
+        return netgroup(split_host_port(addr)[0])
+    except ValueError:
+        return ""
+
+
+def select(exclude: Set[str], tried_bias: float = 0.5, exclude_groups: Optional[Set[str]] = None) -> Optional[str]:
+    """
+    Pick a connection candidate: tried with probability tried_bias, else new, weighted by score
+    within the bucket; skips excluded/recently tried addresses and ones in exclude_groups
+    (core.netlocal.netgroup, outbound diversity).
+    """
+    nowm = now_ms()
+    cutoff = nowm - int(get_config().get("network.addrman_retry_sec", 60)) * 1000
+    groups = exclude_groups or set()
+    with get_db().session() as s:
+        rows = [(r.address, bool(r.tried), _weight(r, nowm)) for r in s.query(Peer).all()
+                if r.address not in exclude and (r.last_try_ms or 0) < cutoff]
+    rows = [r for r in rows if _reachable(r[0]) and not (groups and _group(r[0]) in groups)]
+    tried = [r for r in rows if r[1]]
+    new = [r for r in rows if not r[1]]
+    buckets = (tried, new) if random.random() < tried_bias else (new, tried)
+    for b in buckets:
+        if b:
+            return random.choices([a for a, _, _ in b], weights=[w for _, _, w in b])[0]
+    return None
+
+
+def select_new(exclude: Set[str]) -> Optional[str]:
+    """Feeler candidate: a new-bucket address not tried recently (None if there is none)."""
+    cutoff = now_ms() - int(get_config().get("network.addrman_retry_sec", 60)) * 1000
+    with get_db().session() as s:
+        rows = [a for (a, lt) in s.query(Peer.address, Peer.last_try_ms).filter(Peer.tried == False).all()  # noqa: E712
+                if a not in exclude and (lt or 0) < cutoff]
+    rows = [a for a in rows if _reachable(a)]
+    return random.choice(rows) if rows else None
+
+
+def preferred(limit: int, exclude: Set[str], exclude_groups: Optional[Set[str]] = None) -> List[str]:
+    """Best-scored tried addresses that succeeded within one score half-life (startup reconnects)."""
+    nowm = now_ms()
+    since = nowm - int(_halflife_ms())
+    with get_db().session() as s:
+        rows = (
+            s.query(Peer)
+            .filter(Peer.tried == True, Peer.last_success_ms >= since)  # noqa: E712
+            .all()
+        )
+        ranked = sorted(rows, key=lambda r: (score(r, nowm), r.last_success_ms or 0), reverse=True)
+        cands = [r.address for r in ranked if r.address not in exclude and _reachable(r.address)]
+    # one per network group, like select()
+    groups = set(exclude_groups or set())
+    out: List[str] = []
+    for addr in cands:
+        g = _group(addr)
+        if g and g in groups:
+            continue
+        if g:
+            groups.add(g)
+        out.append(addr)
+    return out[:max(0, limit)]
+
+
+def get_addrs(limit: int = 250) -> List[dict]:
+    """Addresses to answer GETADDR with: tried first, then freshest new entries."""
+    with get_db().session() as s:
+        rows = (
+            s.query(Peer)
+            .order_by(Peer.tried.desc(), Peer.last_seen_ms.desc())
+            .limit(max(1, min(limit, MAX_ADDRS_PER_MSG)))
+            .all()
+        )
+        return [{"addr": r.address, "ts": int(r.last_seen_ms or 0)} for r in rows]
+
+
+def scores(addrs: List[str]) -> Dict[str, float]:
+    nowm = now_ms()
+    with get_db().session() as s:
+        return {r.address: score(r, nowm) for r in s.query(Peer).filter(Peer.address.in_(addrs)).all()}
+
+
+def node_addresses(count: int = 1, network: Optional[str] = None) -> List[Dict[str, Any]]:
+    """getnodeaddresses: known addresses, best score first (count 0 = all)."""
+    nowm = now_ms()
+    with get_db().session() as s:
+        rows = s.query(Peer).all()
+        out: List[Dict[str, Any]] = []
+        for r in rows:
+            try:
+                host, port = split_host_port(r.address)
+                net = network_of(host)
+            except ValueError:
+                continue
+            if network and net != network:
+                continue
+            out.append({
+                "address": host,
+                "port": port,
+                "network": net,
+                "time": int(r.last_seen_ms or 0) // 1000,
+                "services": int(r.services or 0),
+                "servicesnames": service_names(r.services or 0),
+                "tried": bool(r.tried),
+                "last_success": int(r.last_success_ms or 0) // 1000,
+                "last_try": int(r.last_try_ms or 0) // 1000,
+                "failures": int(r.failures or 0),
+                "score": round(score(r, nowm), 3),
+            })
+    out.sort(key=lambda a: (a["score"], a["last_success"], a["time"]), reverse=True)
+    return out if count <= 0 else out[:count]
+
+
+def stats() -> dict:
+    with get_db().session() as s:
+        tried = s.query(Peer).filter(Peer.tried == True).count()  # noqa: E712
+        new = s.query(Peer).filter(Peer.tried == False).count()  # noqa: E712
+    return {"tried": tried, "new": new}
+
+
+def resolve_dns_seeds(names: Iterable[str], default_port: int) -> List[str]:
+    """Resolve DNS seed hostnames (A/AAAA records) into host:port strings. Failures are logged and skipped."""
+    out: List[str] = []
+    for name in names or []:
+        name = str(name).strip()
+        if not name:
+            continue
+        try:
+            host, port = split_host_port(name, default_port)
+        except ValueError:
+            continue
+        try:
+            infos = socket.getaddrinfo(host, port, socket.AF_UNSPEC, socket.SOCK_STREAM)
+        except OSError as e:
+            logs.get("net").warning(f"dns seed lookup failed: {name}: {e}")
+            continue
+        for info in infos:
+            ip = str(info[4][0])
+            if is_reachable(network_of(ip)):
+                out.append(format_addr(ip, port))
+    return sorted(set(out))
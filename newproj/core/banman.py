@@ -0,0 +1,148 @@
+from __future__ import annotations
+
+import ipaddress
+import json
+import threading
+from typing import Any, Dict, List, Optional
+
+from core.config import get_config
+from core.db import get_db
+from core.utils import now_ms
+from core import logs
+
+
+# Peer misbehavior scoring and the ban list.
+#
+# Message handlers call punish(addr, score, reason) when a peer sends something invalid
+# (bad PoW, malformed or oversized messages, broken compact blocks, ...). Scores accumulate
+# per host (inbound ports are ephemeral) and reset on restart; once a host reaches
+# `network.banscore` it is banned for `network.ban_time_sec`. Bans are persisted in KV so they
+# survive restarts and can be managed over RPC (setban / listbanned / clearbanned).
+#
+# Ban entries are subnets: a plain host is stored as /32 (IPv4) or /128 (IPv6); hostnames
+# that are not IP literals are matched verbatim.
+
+_KV_KEY = "banlist_json"
+_lock = threading.Lock()
+_scores: Dict[str, int] = {}
+_bans: Optional[Dict[str, Dict[str, Any]]] = None  # subnet -> entry, loaded lazily from KV
+
+
+def host_of(addr: str) -> str:
+    host = str(addr or "").strip()
+    if host.count(":") == 1 or host.startswith("["):
+        host = host.rsplit(":", 1)[0]
+    return host.strip("[]")
+
+
+def _normalize_subnet(subnet: str) -> str:
+    s = str(subnet or "").strip()
+    try:
+        return str(ipaddress.ip_network(s if "/" in s else host_of(s), strict=False))
+    except ValueError:
+        return host_of(s)
+
+
+def _load() -> Dict[str, Dict[str, Any]]:
+    # caller holds _lock
+    global _bans
+    if _bans is None:
+        try:
+            _bans = json.loads(get_db().get_kv(_KV_KEY) or "{}")
+        except Exception:
+            _bans = {}
+    return _bans
+
+
+def _save():
+    # caller holds _lock
+    get_db().set_kv(_KV_KEY, json.dumps(_bans or {}, separators=(",", ":")))
+
+
+def _sweep(bans: Dict[str, Dict[str, Any]]) -> bool:
+    nowm = now_ms()
+    expired = [k for k, e in bans.items() if int(e.get("banned_until", 0)) <= nowm]
+    for k in expired:
+        del bans[k]
+    return bool(expired)
+
+
+def _matches(subnet: str, host: str) -> bool:
+    if subnet == host:
+        return True
+    try:
+        return ipaddress.ip_address(host) in ipaddress.ip_network(subnet, strict=False)
+    except ValueError:
+        return False
+
+
+def is_banned(addr: str) -> bool:
+    host = host_of(addr)
+    with _lock:
+        bans = _load()
+        if _sweep(bans):
+            _save()
+        return any(_matches(subnet, host) for subnet in bans)
+
+
+def ban(subnet: str, duration_sec: Optional[int] = None, reason: str = "manually added", absolute: bool = False) -> Dict[str, Any]:
+    """Ban a host or subnet; duration_sec is relative unless absolute (then a unix timestamp)."""
+    if duration_sec is None or duration_sec <= 0:
+        duration_sec = int(get_config().get("network.ban_time_sec", 24 * 3600))
+        absolute = False
+    nowm = now_ms()
+    until = int(duration_sec) * 1000 if absolute else nowm + int(duration_sec) * 1000
+    key = _normalize_subnet(subnet)
+    entry = {"address": key, "ban_created": nowm, "banned_until": until, "reason": reason}
+    with _lock:
+        _load()[key] = entry
+        _save()
+    return entry
+
+
+def unban(subnet: str) -> bool:
+    key = _normalize_subnet(subnet)
+    with _lock:
+        bans = _load()
+        if key not in bans:
+            return False
+        del bans[key]
+        _save()
+    return True
+
+
+def list_banned() -> List[Dict[str, Any]]:
+    with _lock:
+        bans = _load()
+        if _sweep(bans):
+            _save()
+        return sorted(bans.values(), key=lambda e: e.get("ban_created", 0))
+
+
+def clear_banned() -> int:
+    with _lock:
+        n = len(_load())
+        _bans.clear()
+        _save()
+    return n
+
+
+def punish(addr: str, score: int, reason: str) -> bool:
+    """Add misbehavior score for a peer; returns True if this pushed it over the ban threshold."""
+    host = host_of(addr)
+    threshold = int(get_config().get("network.banscore", 100))
+    with _lock:
+        total = _scores.get(host, 0) + max(0, int(score))
+        _scores[host] = total
+    logs.get("net").info(f"misbehavior +{score} ({reason}) -> {total}/{threshold}", extra=logs.fields(peer=host))
+    if total < threshold:
+        return False
+    with _lock:
+        _scores.pop(host, None)
+    ban(host, reason=f"misbehaving: {reason}")
+    return True
+
+
+def score_of(addr: str) -> int:
+    with _lock:
+        return _scores.get(host_of(addr), 0)
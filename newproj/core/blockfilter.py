@@ -0,0 +1,316 @@
+from __future__ import annotations
+
+import struct
+from typing import Dict, Iterable, List, Optional, Set, Tuple
+
+from core.config import get_config
+from core.db import get_db, BlockHeader, BlockFilter, UTXO
+from core.utils import sha3_256_hex
+from core import logs
+
+
+# Compact block filters (BIP158 "basic" filters) for light clients.
+#
+# A block's filter is a Golomb-coded set of the addresses it touches: outputs created by its
+# transactions (coinbase included), outputs it spends, and the miner address. Light wallets
+# download filters, test their own addresses with match_any, and fetch only blocks that match.
+#
+# Encoding follows BIP158 with P=19, M=784931: items hash with SipHash-2-4 keyed by the first
+# 16 bytes of the block hash, map into [0, N*M), and the sorted deltas are Golomb-Rice coded
+# after a CompactSize N. Filter headers chain like BIP157 but with this chain's hash:
+#   filter_hash = sha3-256(filter), header = sha3-256(filter_hash || prev_header), prev of genesis = 0*32.
+#
+# Filters are built when a block connects (consensus) and backfilled at RPC startup.
+
+FILTER_TYPE_BASIC = 0
+GCS_P = 19
+GCS_M = 784931
+ZERO_HEADER = "00" * 32
+MAX_CFILTERS = 1000
+MAX_CFHEADERS = 2000
+
+_MASK64 = (1 << 64) - 1
+
+
+def enabled() -> bool:
+    return bool(get_config().get("light.blockfilter_index", True))
+
+
+# ----- SipHash-2-4 -----
+
+def _rotl(x: int, b: int) -> int:
+    return ((x << b) | (x >> (64 - b))) & _MASK64
+
+
+def siphash24(key: bytes, data: bytes) -> int:
+    k0, k1 = struct.unpack("<QQ", key[:16])
+    v0 = k0 ^ 0x736F6D6570736575
+    v1 = k1 ^ 0x646F72616E646F6D
+    v2 = k0 ^ 0x6C7967656E657261
+    v3 = k1 ^ 0x7465646279746573
+
+    def rounds(n: int):
+        nonlocal v0, v1, v2, v3
+        for _ in range(n):
+            v0 = (v0 + v1) & _MASK64
+            v1 = _rotl(v1, 13) ^ v0
+            v0 = _rotl(v0, 32)
+            v2 = (v2 + v3) & _MASK64
+            v3 = _rotl(v3, 16) ^ v2
+            v0 = (v0 + v3) & _MASK64
+            v3 = _rotl(v3, 21) ^ v0
+            v2 = (v2 + v1) & _MASK64
+            v1 = _rotl(v1, 17) ^ v2
+            v2 = _rotl(v2, 32)
+
+    tail = len(data) % 8
+    for (m,) in struct.iter_unpack("<Q", data[:len(data) - tail]):
+        v3 ^= m
+        rounds(2)
+        v0 ^= m
+    last = ((len(data) & 0xFF) << 56) | int.from_bytes(data[len(data) - tail:], "little")
+    v3 ^= last
+    rounds(2)
+    v0 ^= last
+    v2 ^= 0xFF
+    rounds(4)
+    return v0 ^ v1 ^ v2 ^ v3
+
+
+# ----- Golomb-coded sets -----
+
+class _BitWriter:
+    def __init__(self):
+        self.out = bytearray()
+        self.acc = 0
+        self.nbits = 0
+
+    def write(self, value: int, nbits: int):
+        for i in range(nbits - 1, -1, -1):
+            self.acc = (self.acc << 1) | ((value >> i) & 1)
+            self.nbits += 1
+            if self.nbits == 8:
+                self.out.append(self.acc)
+                self.acc, self.nbits = 0, 0
+
+    def flush(self) -> bytes:
+        if self.nbits:
+            self.out.append(self.acc << (8 - self.nbits))
+            self.acc, self.nbits = 0, 0
+        return bytes(self.out)
+
+
+class _BitReader:
+    def __init__(self, data: bytes):
+        self.data = data
+        self.pos = 0
+
+    def read(self, nbits: int) -> int:
+        v = 0
+        for _ in range(nbits):
+            byte = self.pos >> 3
+            if byte >= len(self.data):
+                raise ValueError("filter truncated")
+            v = (v << 1) | ((self.data[byte] >> (7 - (self.pos & 7))) & 1)
+            self.pos += 1
+        return v
+
+
+def _compact_size(n: int) -> bytes:
+    if n < 0xFD:
+        return bytes([n])
+    if n <= 0xFFFF:
+        return b"\xfd" + struct.pack("<H", n)
+    if n <= 0xFFFFFFFF:
+        return b"\xfe" + struct.pack("<I", n)
+    return b"\xff" + struct.pack("<Q", n)
+
+
+def _read_compact_size(data: bytes) -> Tuple[int, int]:
+    if not data:
+        raise ValueError("empty filter")
+    first = data[0]
+    if first < 0xFD:
+        return first, 1
+    size = {0xFD: 2, 0xFE: 4, 0xFF: 8}[first]
+    return int.from_bytes(data[1:1 + size], "little"), 1 + size
+
+
+def _hashed_set(key: bytes, items: Iterable[bytes], n: int) -> List[int]:
+    f = n * GCS_M
+    return sorted({(siphash24(key, it) * f) >> 64 for it in items})
+
+
+def _filter_key(block_hash_hex: str) -> bytes:
+    return bytes.fromhex(block_hash_hex)[:16]
+
+
+def build_gcs(block_hash_hex: str, items: Set[bytes]) -> bytes:
+    n = len(items)
+    w = _BitWriter()
+    last = 0
+    for v in _hashed_set(_filter_key(block_hash_hex), items, n):
+        delta = v - last
+        last = v
+        q = delta >> GCS_P
+        w.write((1 << (q + 1)) - 2, q + 1)  # q ones then a zero
+        w.write(delta & ((1 << GCS_P) - 1), GCS_P)
+    return _compact_size(n) + w.flush()
+
+
+def _decode_gcs(filter_bytes: bytes) -> Tuple[int, List[int]]:
+    n, off = _read_compact_size(filter_bytes)
+    r = _BitReader(filter_bytes[off:])
+    values: List[int] = []
+    last = 0
+    for _ in range(n):
+        q = 0
+        while r.read(1):
+            q += 1
+        last += (q << GCS_P) | r.read(GCS_P)
+        values.append(last)
+    return n, values
+
+
+def match_any(filter_hex: str, block_hash_hex: str, items: Iterable[bytes]) -> bool:
+    """Client-side test: could the block touch any of items (false positive rate ~1/M)?"""
+    n, values = _decode_gcs(bytes.fromhex(filter_hex))
+    if n == 0:
+        return False
+    wanted = set(_hashed_set(_filter_key(block_hash_hex), items, n))
+    return any(v in wanted for v in values)
+
+
+def filter_header(filter_hex: str, prev_header_hex: str) -> Tuple[str, str]:
+    """Returns (filter_hash, header)."""
+    fh = sha3_256_hex(bytes.fromhex(filter_hex))
+    return fh, sha3_256_hex(bytes.fromhex(fh) + bytes.fromhex(prev_header_hex))
+
+
+# ----- indexing -----
+
+def _block_items(s, block: BlockHeader, txids: List[str]) -> Set[bytes]:
+    items: Set[bytes] = set()
+    if block.miner_address:
+        items.add(block.miner_address.encode("utf-8"))
+    if txids:
+        for (addr,) in s.query(UTXO.address).filter(UTXO.txid.in_(txids)).all():
+            items.add(addr.encode("utf-8"))
+        for (addr,) in s.query(UTXO.address).filter(UTXO.spent_txid.in_(txids)).all():
+            items.add(addr.encode("utf-8"))
+    return items
+
+
+def _block_txids_for(s, block: BlockHeader) -> List[str]:
+    from core.blockstore import read_block
+    from core.blockio import _block_txids
+
+    rec = read_block(block.hash_hex)
+    return rec.txids if rec else _block_txids(s, block)
+
+
+def index_block(hash_hex: str, txids: Optional[List[str]] = None) -> bool:
+    """Build and store the filter for a connected block; its parent must already be indexed."""
+    db = get_db()
+    with db.session() as s:
+        if s.query(BlockFilter).filter_by(hash_hex=hash_hex).first() is not None:
+            return False
+        block = s.query(BlockHeader).filter_by(hash_hex=hash_hex).first()
+        if block is None:
+            return False
+        prev_header = ZERO_HEADER
+        if block.height > 0:
+            prev = s.query(BlockFilter).filter_by(height=block.height - 1).first()
+            if prev is None:
+                return False
+            prev_header = prev.header
+        if txids is None:
+            txids = _block_txids_for(s, block)
+        fhex = build_gcs(hash_hex, _block_items(s, block, txids)).hex()
+        fh, header = filter_header(fhex, prev_header)
+        s.add(BlockFilter(hash_hex=hash_hex, height=block.height, filter_hex=fhex, filter_hash=fh, header=header))
+        s.commit()
+    return True
+
+
+def index_block_best_effort(hash_hex: Optional[str], txids: Optional[List[str]] = None):
+    if not hash_hex or not enabled():
+        return
+    try:
+        if not index_block(hash_hex, txids):
+            backfill()  # parent (e.g. genesis) not indexed yet: catch up from the last filter
+    except Exception as e:
+        logs.get("storage").error(f"blockfilter: index failed: {e}", extra=logs.fields(hash=hash_hex))
+
+
+def backfill(batch: int = 500) -> int:
+    """Index connected blocks that have no filter yet (in height order, so headers chain)."""
+    if not enabled():
+        return 0
+    db = get_db()
+    with db.session() as s:
+        last = s.query(BlockFilter).order_by(BlockFilter.height.desc()).first()
+        start = 0 if last is None else last.height + 1
+        hashes = [
+            h for (h,) in s.query(BlockHeader.hash_hex)
+            .filter(BlockHeader.height >= start)
+            .order_by(BlockHeader.height.asc())
+            .all()
+        ]
+    done = 0
+    for hh in hashes:
+        if not index_block(hh):
+            break
+        done += 1
+    return done
+
+
+# ----- queries (RPC / P2P) -----
+
+def get_filter(hash_hex: str) -> Optional[Dict[str, object]]:
+    db = get_db()
+    with db.session() as s:
+        row = s.query(BlockFilter).filter_by(hash_hex=(hash_hex or "").lower()).first()
+        if row is None:
+            return None
+        return {"height": row.height, "blockhash": row.hash_hex, "filter": row.filter_hex, "header": row.header}
+
+
+def _range(s, start_height: int, stop_hash: str, limit: int) -> Tuple[Optional[BlockHeader], List[BlockFilter]]:
+    stop = s.query(BlockHeader).filter_by(hash_hex=(stop_hash or "").lower()).first()
+    if stop is None or start_height < 0 or start_height > stop.height or stop.height - start_height >= limit:
+        return None, []
+    rows = (
+        s.query(BlockFilter)
+        .filter(BlockFilter.height >= start_height, BlockFilter.height <= stop.height)
+        .order_by(BlockFilter.height.asc())
+        .all()
+    )
+    return stop, rows
+
+
+def get_cfilters(start_height: int, stop_hash: str) -> List[Dict[str, object]]:
+    db = get_db()
+    with db.session() as s:
+        _, rows = _range(s, start_height, stop_hash, MAX_CFILTERS)
+        return [{"filter_type": FILTER_TYPE_BASIC, "hash": r.hash_hex, "filter": r.filter_hex} for r in rows]
+
+
+def get_cfheaders(start_height: int, stop_hash: str) -> Optional[Dict[str, object]]:
+    db = get_db()
+    with db.session() as s:
+        stop, rows = _range(s, start_height, stop_hash, MAX_CFHEADERS)
+        if stop is None:
+            return None
+        prev_header = ZERO_HEADER
+        if start_height > 0:
+            prev = s.query(BlockFilter).filter_by(height=start_height - 1).first()
+            if prev is None:
+                return None
+            prev_header = prev.header
+        return {
+            "filter_type": FILTER_TYPE_BASIC,
+            "stop_hash": stop.hash_hex,
+            "prev_header": prev_header,
+            "filter_hashes": [r.filter_hash for r in rows],
+        }
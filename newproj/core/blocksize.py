@@ -0,0 +1,47 @@
+from __future__ import annotations
+
+from typing import Iterable, Optional
+
+from core.config import get_config
+
+
+# Block size accounting.
+#
+# A block's serialized size is its canonical header serialization (consensus.Header.serialize)
+# plus the canonical bytes of every transaction it confirms, i.e. the stored raw (compact JSON
+# for structured txs, see core.chainstats) — the same size the mempool and txgraph count — plus
+# 32 bytes for the coinbase, which is identified by its txid and has no body.
+#
+# consensus.max_block_size is a consensus rule: accept_external_header rejects a larger block
+# with BLOCK_TOO_LARGE before anything is connected. Templates (get_work, solo tickets, the
+# built-in miner) select transactions against template_max_bytes(), the limit minus
+# consensus.block_size_safety_margin, which leaves room for the header and coinbase so an
+# assembled template never comes close to the rule.
+
+BLOCK_TOO_LARGE = "block-too-large"
+COINBASE_BYTES = 32
+
+
+def max_block_size() -> int:
+    return int(get_config().get("consensus.max_block_size", 1_000_000))
+
+
+def template_max_bytes() -> int:
+    margin = int(get_config().get("consensus.block_size_safety_margin", 4000))
+    return max(0, max_block_size() - max(0, margin))
+
+
+def tx_bytes(raw: Optional[str]) -> int:
+    return len((raw or "").encode("utf-8"))
+
+
+def block_size(header_bytes: bytes, raws: Iterable[Optional[str]]) -> int:
+    return len(header_bytes) + COINBASE_BYTES + sum(tx_bytes(r) for r in raws)
+
+
+def check(size: int) -> Optional[str]:
+    """Consensus check; returns the rejection reason for an oversize block."""
+    limit = max_block_size()
+    if size > limit:
+        return f"{BLOCK_TOO_LARGE}: size={size} max={limit}"
+    return None
@@ -0,0 +1,178 @@
+from __future__ import annotations
+
+import os
+import threading
+from typing import Iterator, List, Optional, Tuple
+
+from core.config import get_config
+from core.db import get_db, BlockHeader, BlockFileIndex
+from core.blockio import BlockRecord, encode_record, decode_payload, record_for_row
+from core import logs
+
+
+# Cold tier for block data: append-only blkNNNNN.dat files (same record format as core.blockio)
+# with (file_no, offset, length) indexed in SQLite. The hot tier (block_headers, utxos, ...)
+# stays in the database; flat files make export/bootstrap a byte copy and pruning a file delete.
+
+_write_lock = threading.Lock()
+
+
+def enabled() -> bool:
+    # A memory-backed chain is gone after restart; flat files would outlive their index
+    return str(get_config().get("storage.backend", "sqlite")) != "memory"
+
+
+def blocks_dir() -> str:
+    d = str(get_config().get("storage.blocks_dir", os.path.join("data", "blocks")))
+    os.makedirs(d, exist_ok=True)
+    return d
+
+
+def _max_file_bytes() -> int:
+    return int(get_config().get("storage.max_block_file_mb", 128)) * 1024 * 1024
+
+
+def file_path(file_no: int) -> str:
+    return os.path.join(blocks_dir(), f"blk{file_no:05d}.dat")
+
+
+def _current_file_no(s) -> int:
+    last = s.query(BlockFileIndex).order_by(BlockFileIndex.file_no.desc()).first()
+    if last is None:
+        return 0
+    if os.path.exists(file_path(last.file_no)) and os.path.getsize(file_path(last.file_no)) >= _max_file_bytes():
+        return last.file_no + 1
+    return last.file_no
+
+
+def store_block(hash_hex: str, txids: Optional[List[str]] = None) -> bool:
+    """
+    Append a connected block to the current flat file and index it. Idempotent per hash.
+    txids should be the exact merkle ordering when known (coinbase first).
+    """
+    if not enabled():
+        return False
+    db = get_db()
+    with _write_lock, db.session() as s:
+        if s.query(BlockFileIndex).filter_by(hash_hex=hash_hex).first():
+            return False
+        row = s.query(BlockHeader).filter_by(hash_hex=hash_hex).first()
+        if row is None:
+            return False
+        data = encode_record(record_for_row(s, row, txids))
+        file_no = _current_file_no(s)
+        path = file_path(file_no)
+        with open(path, "ab") as f:
+            offset = f.tell()
+            f.write(data)
+            f.flush()
+            os.fsync(f.fileno())
+        s.add(BlockFileIndex(hash_hex=hash_hex, height=row.height, file_no=file_no, offset=offset, length=len(data)))
+        s.commit()
+        return True
+
+
+def store_blocks(items: List[Tuple[str, Optional[List[str]]]]) -> int:
+    """
+    Batched store_block for initial sync / migration: one session, one commit and one fsync per
+    touched file instead of per block. items are (hash_hex, txids) in connect order.
+    Returns number of blocks appended.
+    """
+    if not items or not enabled():
+        return 0
+    db = get_db()
+    stored = 0
+    max_bytes = _max_file_bytes()
+    with _write_lock, db.session() as s:
+        indexed = {h for (h,) in s.query(BlockFileIndex.hash_hex).filter(
+            BlockFileIndex.hash_hex.in_([h for h, _ in items])).all()}
+        file_no = _current_file_no(s)
+        f = open(file_path(file_no), "ab")
+        try:
+            for hash_hex, txids in items:
+                if hash_hex in indexed:
+                    continue
+                row = s.query(BlockHeader).filter_by(hash_hex=hash_hex).first()
+                if row is None:
+                    continue
+                data = encode_record(record_for_row(s, row, txids))
+                if f.tell() > 0 and f.tell() + len(data) > max_bytes:
+                    f.flush()
+                    os.fsync(f.fileno())
+                    f.close()
+                    file_no += 1
+                    f = open(file_path(file_no), "ab")
+                offset = f.tell()
+                f.write(data)
+                s.add(BlockFileIndex(hash_hex=hash_hex, height=row.height, file_no=file_no, offset=offset, length=len(data)))
+                indexed.add(hash_hex)
+                stored += 1
+            f.flush()
+            os.fsync(f.fileno())
+        finally:
+            f.close()
+        s.commit()
+    return stored
+
+
+def store_block_best_effort(hash_hex: Optional[str], txids: Optional[List[str]] = None):
+    if not hash_hex:
+        return
+    try:
+        store_block(hash_hex, txids)
+    except Exception as e:
+        logs.get("storage").error(f"blockstore: append failed: {e}", extra=logs.fields(hash=hash_hex))
+
+
+def read_raw(hash_hex: str) -> Optional[bytes]:
+    """Full framed record bytes (magic | len | payload) for a block, or None if not in flat files."""
+    db = get_db()
+    with db.session() as s:
+        ix = s.query(BlockFileIndex).filter_by(hash_hex=hash_hex).first()
+        if ix is None:
+            return None
+        file_no, offset, length = ix.file_no, ix.offset, ix.length
+    try:
+        with open(file_path(file_no), "rb") as f:
+            f.seek(offset)
+            data = f.read(length)
+    except FileNotFoundError:
+        return None
+    return data if len(data) == length else None
+
+
+def read_block(hash_hex: str) -> Optional[BlockRecord]:
+    raw = read_raw(hash_hex)
+    if raw is None:
+        return None
+    return decode_payload(raw[8:])
+
+
+def iter_files() -> Iterator[Tuple[int, str, int]]:
+    """Yields (file_no, path, size) for existing block files in order."""
+    d = blocks_dir()
+    for name in sorted(os.listdir(d)):
+        if name.startswith("blk") and name.endswith(".dat"):
+            try:
+                n = int(name[3:-4])
+            except ValueError:
+                continue
+            p = os.path.join(d, name)
+            yield n, p, os.path.getsize(p)
+
+
+def migrate_to_flat_files(batch: int = 500) -> int:
+    """
+    Upgrade path for databases created before the flat-file tier: append every connected
+    block that has no index row yet, in height order. Returns number of blocks migrated.
+    """
+    db = get_db()
+    migrated = 0
+    with db.session() as s:
+        indexed = {h for (h,) in s.query(BlockFileIndex.hash_hex).all()}
+        hashes = [h for (h,) in s.query(BlockHeader.hash_hex).order_by(BlockHeader.height.asc()).all() if h not in indexed]
+    for i in range(0, len(hashes), batch):
+        migrated += store_blocks([(hh, None) for hh in hashes[i:i + batch]])
+    if migrated:
+        logs.get("storage").info(f"blockstore: migrated {migrated} blocks into {blocks_dir()}")
+    return migrated
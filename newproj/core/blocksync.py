@@ -0,0 +1,211 @@
+from __future__ import annotations
+
+import threading
+from dataclasses import dataclass, field
+from typing import Any, Callable, Dict, List, Optional, Set, Tuple
+
+from core.config import get_config
+from core.utils import now_ms
+
+
+# Parallel block download (catch-up sync) scheduler.
+#
+# Tip INV relay only moves a node forward one block at a time, so a node far behind downloads
+# height windows instead: GETBLOCKS {start_height, count} answered by BLOCKS {start_height,
+# headers: [BLOCKHDR items with height and txids]}. The scheduler decides which peer fetches
+# which window; the P2P layer (apps.node.main) does the I/O through the send callback and
+# connects buffered blocks strictly in height order.
+#
+# Peer choice: among peers whose advertised height covers the window and that have fewer than
+# sync.max_inflight_per_peer windows outstanding, pick the lowest expected completion time:
+# ping RTT (PING/PONG) + (in-flight + 1) * measured per-window download time (EWMA). Unmeasured
+# peers get the request timeout as their estimate, so measured fast peers are preferred but new
+# peers still get work. A window not answered within max(sync.request_timeout_sec, 4 x RTT) is
+# reassigned to another peer and the slow peer's estimate is doubled. Downloads stop
+# sync.max_buffer_blocks above the tip until the buffer drains.
+
+EWMA_ALPHA = 0.3
+
+
+@dataclass
+class SyncPeer:
+    addr: str
+    height: int = -1
+    rtt_ms: Optional[float] = None
+    window_ms: Optional[float] = None  # EWMA time to deliver one window
+    inflight: Set[int] = field(default_factory=set)  # window start heights
+    delivered: int = 0
+    timeouts: int = 0
+
+    def expected_ms(self, timeout_ms: int) -> float:
+        per_window = self.window_ms if self.window_ms is not None else float(timeout_ms)
+        return (self.rtt_ms or 0.0) + (len(self.inflight) + 1) * per_window
+
+    def to_dict(self) -> Dict[str, Any]:
+        return {
+            "addr": self.addr,
+            "height": self.height,
+            "rtt_ms": self.rtt_ms,
+            "window_ms": self.window_ms,
+            "inflight": sorted(self.inflight),
+            "delivered_windows": self.delivered,
+            "timeouts": self.timeouts,
+        }
+
+
+@dataclass
+class Window:
+    start: int
+    count: int
+    peer: str
+    sent_ms: int
+    tried: Set[str] = field(default_factory=set)
+
+
+def _ewma(old: Optional[float], sample: float) -> float:
+    return sample if old is None else old * (1.0 - EWMA_ALPHA) + sample * EWMA_ALPHA
+
+
+class DownloadScheduler:
+    def __init__(self, send: Callable[[str, int, int], bool]):
+        """send(peer_addr, start_height, count) issues a GETBLOCKS; False if the peer is gone."""
+        self._send = send
+        self._lock = threading.Lock()
+        self._peers: Dict[str, SyncPeer] = {}
+        self._windows: Dict[int, Window] = {}  # start height -> in-flight window
+        self._buffer: Dict[int, Tuple[str, Dict[str, Any]]] = {}  # height -> (peer, header item)
+
+    # ----- peer bookkeeping -----
+
+    def peer_height(self, addr: str, height: int):
+        with self._lock:
+            p = self._peers.setdefault(addr, SyncPeer(addr))
+            p.height = max(p.height, int(height))
+
+    def peer_rtt(self, addr: str, rtt_ms: float):
+        with self._lock:
+            p = self._peers.setdefault(addr, SyncPeer(addr))
+            p.rtt_ms = _ewma(p.rtt_ms, max(0.0, float(rtt_ms)))
+
+    def peer_gone(self, addr: str):
+        with self._lock:
+            p = self._peers.pop(addr, None)
+            if p is None:
+                return
+            for start in p.inflight:
+                self._windows.pop(start, None)  # rescheduled on the next tick
+
+    def best_peer_height(self) -> int:
+        with self._lock:
+            return max((p.height for p in self._peers.values()), default=-1)
+
+    # ----- scheduling -----
+
+    def _cfg(self) -> Tuple[int, int, int, int]:
+        cfg = get_config()
+        return (
+            max(1, int(cfg.get("sync.blocks_per_batch", 64))),
+            max(1, int(cfg.get("sync.max_inflight_per_peer", 2))),
+            int(float(cfg.get("sync.request_timeout_sec", 10)) * 1000),
+            max(1, int(cfg.get("sync.max_buffer_blocks", 1024))),
+        )
+
+    def _timeout_for(self, p: Optional[SyncPeer], base_ms: int) -> int:
+        return int(max(base_ms, 4.0 * (p.rtt_ms or 0.0))) if p else base_ms
+
+    def _pick(self, end: int, inflight_cap: int, timeout_ms: int, exclude: Set[str]) -> Optional[SyncPeer]:
+        ready = [p for p in self._peers.values()
+                 if p.height >= end and len(p.inflight) < inflight_cap and p.addr not in exclude]
+        if not ready:
+            return None
+        return min(ready, key=lambda p: (p.expected_ms(timeout_ms), p.addr))
+
+    def tick(self, tip_height: int) -> List[Tuple[str, int, int]]:
+        """Expire stalled windows and assign new ones above tip_height; returns requests sent."""
+        batch, inflight_cap, base_timeout, max_buffer = self._cfg()
+        nowm = now_ms()
+        sent: List[Tuple[str, int, int]] = []
+        with self._lock:
+            for h in [h for h in self._buffer if h <= tip_height]:
+                del self._buffer[h]
+            retry: Dict[int, Set[str]] = {}
+            for start, w in list(self._windows.items()):
+                p = self._peers.get(w.peer)
+                if start + w.count - 1 <= tip_height:
+                    del self._windows[start]
+                    if p:
+                        p.inflight.discard(start)
+                elif nowm - w.sent_ms > self._timeout_for(p, base_timeout):
+                    del self._windows[start]
+                    if p:
+                        p.inflight.discard(start)
+                        p.timeouts += 1
+                        p.window_ms = (p.window_ms or float(base_timeout)) * 2.0
+                    retry[start] = w.tried | {w.peer}
+            target = max((p.height for p in self._peers.values()), default=-1)
+            limit = min(target, tip_height + max_buffer)
+            # Windows are aligned to the batch size so they stay the same as the tip moves
+            start = (tip_height + 1) - (tip_height + 1) % batch
+            while start <= limit:
+                count = min(batch, limit - start + 1)
+                if start in self._windows or all(h in self._buffer for h in range(max(start, tip_height + 1), start + count)):
+                    start += batch
+                    continue
+                tried = retry.get(start, set())
+                p = self._pick(start + count - 1, inflight_cap, base_timeout, tried)
+                if p is None and tried:
+                    p = self._pick(start + count - 1, inflight_cap, base_timeout, set())  # only the slow peer has it
+                if p is None:
+                    break
+                p.inflight.add(start)
+                self._windows[start] = Window(start=start, count=count, peer=p.addr, sent_ms=nowm, tried=tried | {p.addr})
+                sent.append((p.addr, start, count))
+                start += batch
+        for addr, s, c in sent:
+            if not self._send(addr, s, c):
+                self.peer_gone(addr)
+        return sent
+
+    def on_blocks(self, addr: str, start: int, headers: List[Dict[str, Any]]) -> bool:
+        """Buffer a BLOCKS reply; False if it answers no window we assigned to this peer."""
+        with self._lock:
+            w = self._windows.get(start)
+            p = self._peers.get(addr)
+            if w is None or w.peer != addr or p is None:
+                return False
+            del self._windows[start]
+            p.inflight.discard(start)
+            if not headers:
+                # Peer no longer has these blocks (reorged or overstated height)
+                p.height = min(p.height, start - 1)
+                return True
+            p.delivered += 1
+            p.window_ms = _ewma(p.window_ms, float(now_ms() - w.sent_ms))
+            for item in headers:
+                try:
+                    h = int(item.get("height"))
+                except (TypeError, ValueError, AttributeError):
+                    continue
+                if start <= h < start + w.count:
+                    self._buffer[h] = (addr, item)
+            return True
+
+    def next_ready(self, tip_height: int) -> Optional[Tuple[str, Dict[str, Any]]]:
+        """Pop the buffered block at tip_height + 1, if downloaded."""
+        with self._lock:
+            return self._buffer.pop(tip_height + 1, None)
+
+    def discard_above(self, height: int):
+        """Drop buffered blocks above height (after one failed to connect)."""
+        with self._lock:
+            for h in [h for h in self._buffer if h > height]:
+                del self._buffer[h]
+
+    def snapshot(self) -> Dict[str, Any]:
+        with self._lock:
+            return {
+                "peers": [p.to_dict() for p in sorted(self._peers.values(), key=lambda p: p.addr)],
+                "inflight": [{"start": w.start, "count": w.count, "peer": w.peer, "sent_ms": w.sent_ms}
+                             for w in sorted(self._windows.values(), key=lambda w: w.start)],
+                "buffered": len(self._buffer),
+            }
@@ -0,0 +1,118 @@
+from __future__ import annotations
+
+import threading
+import time
+from typing import Any, Callable, Dict, Optional, Tuple
+
+from sqlalchemy import func
+
+from core.config import get_config
+from core.db import get_db, BlockHeader, MempoolTx
+from core.utils import now_ms
+
+
+# One block template per tip for every work consumer.
+#
+# BlockTemplateProvider owns the node's current template. /rpc/get_work, /rpc/get_block_template
+# (including long-polls) and the Stratum pool's job refresh task (apps.pool.stratum_server, which
+# long-polls get_block_template) all read it, so a pool job and an RPC template for the same tip
+# are the same job: same job_id, txids, timestamp and longpollid. A new template is built only
+# when a refresh trigger fires:
+#   new tip                 always (work on a stale tip is wasted)
+#   mempool change          mining.template_min_new_txs txs in/out, or total fees up by
+#                           mining.template_min_fee_gain_pct percent
+#   age                     mining.template_max_age_sec (default: half the job TTL)
+#   force                   local block found / submitted, tx admitted through RPC
+# The watcher thread checks the triggers every mining.template_watch_interval_sec so tips and txs
+# arriving over P2P wake long-pollers promptly. Each rebuild bumps longpollid ("<prev_hash>:<seq>").
+
+
+class BlockTemplateProvider:
+    def __init__(self, build: Callable[[], Dict[str, Any]], on_new: Callable[[Dict[str, Any]], None], ttl_ms: int):
+        """build() makes a fresh job; on_new(job) registers it for submit_work."""
+        self._build = build
+        self._on_new = on_new
+        self._ttl_ms = ttl_ms
+        self._cond = threading.Condition()
+        self._job: Optional[Dict[str, Any]] = None
+        self._seq = 0
+        self._tip: Optional[str] = None
+        self._mem_sig: Tuple[int, float] = (0, 0.0)
+        self._last_reason = ""
+
+    @staticmethod
+    def _state() -> Tuple[Optional[str], Tuple[int, float]]:
+        db = get_db()
+        with db.session() as s:
+            tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+            cnt, fees = s.query(func.count(MempoolTx.id), func.coalesce(func.sum(MempoolTx.fee), 0.0)).one()
+        return (tip.hash_hex if tip else None), (int(cnt or 0), float(fees or 0.0))
+
+    def _significant(self, mem_sig: Tuple[int, float]) -> bool:
+        cfg = get_config()
+        min_txs = int(cfg.get("mining.template_min_new_txs", 1))
+        min_fee_pct = float(cfg.get("mining.template_min_fee_gain_pct", 5.0))
+        old_cnt, old_fees = self._mem_sig
+        cnt, fees = mem_sig
+        if abs(cnt - old_cnt) >= min_txs:
+            return True
+        return fees > old_fees * (1.0 + min_fee_pct / 100.0) + 1e-12
+
+    def _trigger(self, force: bool, tip: Optional[str], mem_sig: Tuple[int, float]) -> Optional[str]:
+        cfg = get_config()
+        if force:
+            return "force"
+        if self._job is None:
+            return "initial"
+        if tip != self._tip:
+            return "tip"
+        max_age_ms = float(cfg.get("mining.template_max_age_sec", self._ttl_ms / 2000.0)) * 1000
+        if now_ms() - int(self._job.get("issued_ms", 0)) > max_age_ms:
+            return "age"
+        if self._significant(mem_sig):
+            return "mempool"
+        return None
+
+    def refresh(self, force: bool = False) -> Dict[str, Any]:
+        tip, mem_sig = self._state()
+        with self._cond:
+            job = self._job
+            reason = self._trigger(force, tip, mem_sig)
+            if reason:
+                job = self._build()
+                self._seq += 1
+                job["longpollid"] = f"{job['prev_hash']}:{self._seq}"
+                self._on_new(job)
+                self._job, self._tip, self._mem_sig, self._last_reason = job, tip, mem_sig, reason
+                self._cond.notify_all()
+            return job
+
+    def current(self) -> Dict[str, Any]:
+        return self.refresh()
+
+    def wait_for_change(self, longpollid: str, timeout_sec: float) -> Dict[str, Any]:
+        """Block until the template's longpollid differs from the caller's, or the timeout fires."""
+        deadline = time.time() + max(0.0, timeout_sec)
+        job = self.refresh()
+        with self._cond:
+            while self._job is not None and self._job.get("longpollid") == longpollid:
+                remaining = deadline - time.time()
+                if remaining <= 0:
+                    break
+                self._cond.wait(timeout=min(remaining, 1.0))
+            job = self._job or job
+        return job
+
+    def info(self) -> Dict[str, Any]:
+        with self._cond:
+            job = self._job or {}
+            return {
+                "longpollid": job.get("longpollid"),
+                "job_id": job.get("job_id"),
+                "height": job.get("height"),
+                "prev_hash": job.get("prev_hash"),
+                "tx_count": len(job.get("txids") or []),
+                "age_ms": max(0, now_ms() - int(job["issued_ms"])) if job else None,
+                "templates_built": self._seq,
+                "last_trigger": self._last_reason or None,
+            }
@@ -0,0 +1,138 @@
+from __future__ import annotations
+
+import json
+from typing import Any, Dict, List, Optional, Tuple
+
+from sqlalchemy import func
+
+from core.db import get_db, BlockHeader, Reward, Transaction
+from core import headeronly
+
+
+# Analytics over connected blocks (RPC getblockstats / getchaintxstats).
+#
+# Sizes are the length of the canonical serialization (compact JSON, sorted keys, signatures
+# included) — the same bytes stored as MempoolTx/Transaction.raw. Feerates are coin per kB
+# (1000 bytes) of that size. The coinbase is counted in tx totals but has no fee or size.
+
+Stats = Dict[str, Any]
+
+
+def tx_size(tx: Dict[str, Any]) -> int:
+    return len(json.dumps(tx, separators=(",", ":"), sort_keys=True).encode("utf-8"))
+
+
+def _median(values: List[float]) -> float:
+    if not values:
+        return 0.0
+    v = sorted(values)
+    mid = len(v) // 2
+    return v[mid] if len(v) % 2 else (v[mid - 1] + v[mid]) / 2.0
+
+
+def _find_block(s, hash_or_height: str) -> Optional[BlockHeader]:
+    key = str(hash_or_height).strip().lower()
+    if key.isdigit() and len(key) < 64:
+        return s.query(BlockHeader).filter_by(height=int(key)).first()
+    return s.query(BlockHeader).filter_by(hash_hex=key).first()
+
+
+def block_stats(hash_or_height: str) -> Tuple[Optional[Stats], Optional[str]]:
+    """Returns (stats, error) for a block given its hash or height."""
+    db = get_db()
+    with db.session() as s:
+        blk = _find_block(s, hash_or_height)
+        if blk is None:
+            return None, "block not found"
+        if headeronly.is_pruned(s, blk.height):
+            return None, headeronly.BODY_PRUNED
+        txs = s.query(Transaction).filter(Transaction.in_block_hash == blk.hash_hex).order_by(Transaction.id.asc()).all()
+        reward = s.query(Reward).filter_by(height=blk.height).first()
+        fees: List[float] = []
+        feerates: List[float] = []
+        sizes: List[int] = []
+        total_out = 0.0
+        n_inputs = n_outputs = 0
+        for t in txs:
+            try:
+                tx = json.loads(t.raw or "{}")
+            except ValueError:
+                continue
+            size = tx_size(tx)
+            fee = float(t.fee or 0.0)
+            outs = tx.get("outputs") or []
+            n_inputs += len(tx.get("inputs") or [])
+            n_outputs += len(outs)
+            total_out += sum(float(o.get("amount", 0.0)) for o in outs if isinstance(o, dict))
+            sizes.append(size)
+            fees.append(fee)
+            feerates.append(fee * 1000.0 / size if size else 0.0)
+        subsidy = float(reward.amount) if reward else 0.0
+        out: Stats = {
+            "blockhash": blk.hash_hex,
+            "height": blk.height,
+            "time": blk.timestamp,
+            "txs": int(blk.tx_count),
+            "ins": n_inputs,
+            "outs": n_outputs,
+            "total_out": total_out,
+            "subsidy": subsidy,
+            "totalfee": sum(fees),
+            "minfee": min(fees) if fees else 0.0,
+            "maxfee": max(fees) if fees else 0.0,
+            "medianfee": _median(fees),
+            "avgfee": sum(fees) / len(fees) if fees else 0.0,
+            "minfeerate": min(feerates) if feerates else 0.0,
+            "maxfeerate": max(feerates) if feerates else 0.0,
+            "medianfeerate": _median(feerates),
+            "avgfeerate": sum(fees) * 1000.0 / sum(sizes) if sum(sizes) else 0.0,
+            "total_size": sum(sizes),
+            "mintxsize": min(sizes) if sizes else 0,
+            "maxtxsize": max(sizes) if sizes else 0,
+            "mediantxsize": _median([float(x) for x in sizes]),
+        }
+    return out, None
+
+
+def chain_tx_stats(nblocks: Optional[int] = None, blockhash: Optional[str] = None,
+                   default_window: int = 1440) -> Tuple[Optional[Stats], Optional[str]]:
+    """
+    Transaction rate over the `nblocks` blocks ending at `blockhash` (default: tip).
+    txcount is cumulative (coinbases included) up to the final block.
+    """
+    db = get_db()
+    with db.session() as s:
+        if blockhash:
+            end = s.query(BlockHeader).filter_by(hash_hex=blockhash.strip().lower()).first()
+            if end is None:
+                return None, "block not found"
+        else:
+            end = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+            if end is None:
+                return None, "no blocks"
+        if nblocks is None:
+            nblocks = min(default_window, end.height)
+        if nblocks < 0 or nblocks > end.height:
+            return None, f"invalid block count: should be between 0 and {end.height}"
+
+        txcount = int(s.query(func.coalesce(func.sum(BlockHeader.tx_count), 0)).filter(BlockHeader.height <= end.height).scalar() or 0)
+        out: Stats = {
+            "time": end.timestamp,
+            "txcount": txcount,
+            "window_final_block_hash": end.hash_hex,
+            "window_final_block_height": end.height,
+            "window_block_count": nblocks,
+        }
+        if nblocks > 0:
+            start = s.query(BlockHeader).filter_by(height=end.height - nblocks).first()
+            window_tx = int(
+                s.query(func.coalesce(func.sum(BlockHeader.tx_count), 0))
+                .filter(BlockHeader.height > end.height - nblocks, BlockHeader.height <= end.height)
+                .scalar() or 0
+            )
+            interval = int(end.timestamp) - int(start.timestamp) if start else 0
+            out["window_tx_count"] = window_tx
+            out["window_interval"] = interval
+            if interval > 0:
+                out["txrate"] = window_tx / float(interval)
+    return out, None
@@ -0,0 +1,56 @@
+from __future__ import annotations
+
+from typing import Dict, Optional
+
+from core.config import get_config
+
+
+# Checkpoints and assumevalid.
+#
+# Checkpoints pin (height -> block hash) per network: a header at a checkpointed height must
+# have exactly that hash, and forks off the chain below the last checkpoint are never
+# considered (they are not counted as fork history or safe-mode triggers either).
+# Built-in entries are per network.name; `consensus.checkpoints` in config adds to or
+# overrides them (e.g. for private networks).
+#
+# assumevalid (`consensus.assumevalid`, a block hash) lets bootstrap imports skip the PoW
+# re-hash for that block and its ancestors once the file proves the ancestry (see
+# core.blockio.import_blocks). Relayed P2P headers are always fully validated.
+
+_BUILTIN: Dict[str, Dict[int, str]] = {
+    # Filled in at release time from a synced node (height: hash).
+    "smelly-mainnet": {},
+}
+
+
+def checkpoints() -> Dict[int, str]:
+    cfg = get_config()
+    out = dict(_BUILTIN.get(str(cfg.get("network.name", "")), {}))
+    for h, hh in (cfg.get("consensus.checkpoints", {}) or {}).items():
+        try:
+            out[int(h)] = str(hh).strip().lower()
+        except (TypeError, ValueError):
+            continue
+    return out
+
+
+def last_checkpoint_height() -> int:
+    cps = checkpoints()
+    return max(cps) if cps else -1
+
+
+def check(height: int, hash_hex: str) -> Optional[str]:
+    """Error string when a header at `height` contradicts a checkpoint, else None."""
+    want = checkpoints().get(int(height))
+    if want and want != (hash_hex or "").lower():
+        return f"checkpoint mismatch at height {height}"
+    return None
+
+
+def fork_below_checkpoint(parent_height: int) -> bool:
+    """True if a block building on parent_height would rewrite history before the last checkpoint."""
+    return parent_height < last_checkpoint_height()
+
+
+def assumevalid() -> str:
+    return str(get_config().get("consensus.assumevalid", "") or "").strip().lower()
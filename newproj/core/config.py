@@ -1,11 +1,24 @@
 import os
 import yaml
-from typing import Any, Dict
+from typing import Any, Dict, Iterable, List, Optional
+
+
+# Environment variables honoured on load (kept for multi-node testing scripts).
+# Precedence: CLI overrides > environment > YAML file > code defaults.
+ENV_OVERRIDES = {
+    "SMELLY_RPC_HOST": ("network.rpc_host", str),
+    "SMELLY_RPC_PORT": ("network.rpc_port", int),
+    "SMELLY_P2P_HOST": ("network.p2p_host", str),
+    "SMELLY_P2P_PORT": ("network.p2p_port", int),
+    "SMELLY_DB_PATH": ("database.sqlite_path", str),
+}
 
 
 class Config:
-    def __init__(self, data: Dict[str, Any]):
+    def __init__(self, data: Dict[str, Any], path: Optional[str] = None):
         self.data = data
+        self.path = path
+        self._overrides: Dict[str, Any] = {}
 
     @classmethod
     def load(cls, path: str = None) -> "Config":
@@ -15,12 +28,19 @@ class Config:
         cfg_path = path or os.environ.get("SMELLY_CONFIG") or os.path.join("configs", "defaults.yaml")
         with open(cfg_path, "r", encoding="utf-8") as f:
             data = yaml.safe_load(f) or {}
-        return cls(data)
+        cfg = cls(data, cfg_path)
+        for env, (key, conv) in ENV_OVERRIDES.items():
+            val = os.environ.get(env)
+            if val:
+                cfg.set_override(key, conv(val))
+        return cfg
 
     def get(self, key_path: str, default=None):
         """
         Get nested config value via dot path, e.g. 'network.rpc_port'
         """
+        if key_path in self._overrides:
+            return self._overrides[key_path]
         parts = key_path.split(".")
         cur = self.data
         for p in parts:
@@ -29,6 +49,76 @@ class Config:
             cur = cur[p]
         return cur
 
+    def set_override(self, key_path: str, value: Any):
+        """
+        Override a dot-path value for this process (CLI flags, env). Overrides win over the file.
+        """
+        self._overrides[key_path] = value
+
+    def apply_overrides(self, pairs: Iterable[str]):
+        """
+        Apply repeated `--set key.path=value` flags. Values are parsed as YAML scalars,
+        so `--set sync.max_peers=32` yields an int and `--set fairness.enabled=false` a bool.
+        """
+        for pair in pairs or []:
+            if "=" not in pair:
+                raise ValueError(f"invalid override (expected key.path=value): {pair}")
+            key, raw = pair.split("=", 1)
+            key = key.strip()
+            if not key:
+                raise ValueError(f"invalid override (empty key): {pair}")
+            self.set_override(key, yaml.safe_load(raw) if raw.strip() else "")
+
+    def validate(self) -> List[str]:
+        """
+        Sanity-check the merged config. Returns a list of problems (empty when valid).
+        """
+        errors: List[str] = []
+        ports = {}
+        for key in ("network.rpc_port", "network.p2p_port", "network.pool_port"):
+            val = self.get(key)
+            if val is None:
+                continue
+            try:
+                port = int(val)
+            except (TypeError, ValueError):
+                errors.append(f"{key}: not an integer ({val!r})")
+                continue
+            if not (1 <= port <= 65535):
+                errors.append(f"{key}: out of range ({port})")
+            elif port in ports:
+                errors.append(f"{key}: same port as {ports[port]} ({port})")
+            else:
+                ports[port] = key
+        if not str(self.get("network.magic", "") or ""):
+            errors.append("network.magic: must not be empty")
+        backend = str(self.get("storage.backend", "sqlite"))
+        if backend not in ("sqlite", "memory"):
+            errors.append(f"storage.backend: must be sqlite or memory ({backend!r})")
+        if str(self.get("database.driver", "sqlite")) not in ("sqlite", "postgres"):
+            errors.append(f"database.driver: must be sqlite or postgres ({self.get('database.driver')!r})")
+        if backend == "sqlite" and str(self.get("database.driver", "sqlite")) == "sqlite" and not self.get("database.sqlite_path"):
+            errors.append("database.sqlite_path: must be set for the sqlite driver")
+        storage_mode = str(self.get("storage.mode", "full"))
+        if storage_mode not in ("full", "header-only"):
+            errors.append(f"storage.mode: must be full or header-only ({storage_mode!r})")
+        try:
+            if int(self.get("storage.header_only_keep_blocks", 288)) < 1:
+                errors.append("storage.header_only_keep_blocks: must be at least 1")
+        except (TypeError, ValueError):
+            errors.append("storage.header_only_keep_blocks: not an integer")
+        try:
+            if int(self.get("storage.max_block_file_mb", 128)) <= 0:
+                errors.append("storage.max_block_file_mb: must be positive")
+        except (TypeError, ValueError):
+            errors.append("storage.max_block_file_mb: not an integer")
+        try:
+            if float(self.get("consensus.target_block_time_sec", 15)) <= 0:
+                errors.append("consensus.target_block_time_sec: must be positive")
+        except (TypeError, ValueError):
+            errors.append("consensus.target_block_time_sec: not a number")
+        return errors
+
 
 _global_config: Config | None = None
 
@@ -38,3 +128,12 @@ def get_config() -> Config:
     if _global_config is None:
         _global_config = Config.load()
     return _global_config
+
+
+def load_config(path: Optional[str] = None) -> Config:
+    """
+    (Re)load the process-wide config from an explicit file. Must run before get_db()/subsystems start.
+    """
+    global _global_config
+    _global_config = Config.load(path)
+    return _global_config
@@ -0,0 +1,111 @@
+from __future__ import annotations
+
+import json
+import math
+import threading
+from typing import Any, Dict, List, Optional
+
+from core.config import get_config
+from core.db import get_db, BlockHeader
+from core.target import block_work
+from core.utils import now_ms
+
+
+# Fork-aware confirmation scoring.
+#
+# A raw confirmation count says nothing about how deep forks actually get on this network.
+# We keep a rolling history of observed fork depths (relayed headers that build on a block
+# below our tip and pass the PoW and retarget checks, so forged headers cannot inflate it) and estimate hashrate volatility from recent block intervals and targets.
+#
+#   risk_depth  = deepest fork seen within the history window
+#   scale       = confirmation.decay_blocks * (1 + volatility)
+#   safety      = 1 - exp(-(conf - risk_depth) / scale)     (0 when conf <= risk_depth)
+#
+# recommended_confirmations is the smallest conf with safety >= confirmation.target_safety.
+
+_KV_KEY = "fork_history_json"
+_MAX_EVENTS = 200
+_lock = threading.Lock()
+
+
+def record_fork(depth: int, peer: str = "", tip_hash: str = "", height: int = -1):
+    """
+    Remember a fork of the given depth (1 = competing block for our tip).
+    tip_hash/height identify the side-chain header (listed by getchaintips).
+    """
+    if depth <= 0:
+        return
+    db = get_db()
+    with _lock:
+        try:
+            hist = json.loads(db.get_kv(_KV_KEY) or "[]")
+        except Exception:
+            hist = []
+        hist.append({"ts": now_ms(), "depth": int(depth), "peer": peer, "hash": tip_hash, "height": int(height)})
+        db.set_kv(_KV_KEY, json.dumps(hist[-_MAX_EVENTS:]))
+
+
+def fork_history(window_sec: Optional[int] = None) -> List[Dict[str, Any]]:
+    if window_sec is None:
+        window_sec = int(get_config().get("confirmation.fork_window_sec", 7 * 24 * 3600))
+    cutoff = now_ms() - window_sec * 1000
+    try:
+        hist = json.loads(get_db().get_kv(_KV_KEY) or "[]")
+    except Exception:
+        return []
+    return [e for e in hist if int(e.get("ts", 0)) >= cutoff]
+
+
+def hashrate_volatility(blocks: Optional[int] = None) -> float:
+    """
+    Coefficient of variation of per-block hashrate estimates (work / interval) over recent blocks.
+    0 means perfectly steady; values around 1 mean hashrate swings by its own magnitude.
+    """
+    n = int(blocks or get_config().get("confirmation.volatility_blocks", 120))
+    with get_db().session() as s:
+        rows = s.query(BlockHeader.timestamp, BlockHeader.target).order_by(BlockHeader.height.desc()).limit(n + 1).all()
+    rows = list(reversed(rows))
+    rates: List[float] = []
+    for (ts0, _), (ts1, tgt1) in zip(rows, rows[1:]):
+        dt = max(1, int(ts1) - int(ts0))
+        work = float(block_work(tgt1))
+        if work <= 0:
+            continue
+        rates.append(work / dt)
+    if len(rates) < 2:
+        return 0.0
+    mean = sum(rates) / len(rates)
+    if mean <= 0:
+        return 0.0
+    var = sum((r - mean) ** 2 for r in rates) / (len(rates) - 1)
+    return math.sqrt(var) / mean
+
+
+def _params() -> Dict[str, float]:
+    cfg = get_config()
+    hist = fork_history()
+    return {
+        "risk_depth": float(max([int(e.get("depth", 0)) for e in hist] or [0])),
+        "volatility": hashrate_volatility(),
+        "decay": float(cfg.get("confirmation.decay_blocks", 2.0)),
+        "target": float(cfg.get("confirmation.target_safety", 0.999)),
+        "forks_seen": float(len(hist)),
+    }
+
+
+def score(confirmations: int, params: Optional[Dict[str, float]] = None) -> Dict[str, Any]:
+    p = params or _params()
+    scale = max(0.1, p["decay"] * (1.0 + p["volatility"]))
+    excess = confirmations - p["risk_depth"]
+    safety = 0.0 if confirmations <= 0 or excess <= 0 else 1.0 - math.exp(-excess / scale)
+    target = min(0.999999, max(0.5, p["target"]))
+    recommended = int(p["risk_depth"] + math.ceil(-math.log(1.0 - target) * scale))
+    return {
+        "confirmations": int(confirmations),
+        "safety_score": round(safety, 6),
+        "recommended_confirmations": max(1, recommended),
+        "target_safety": target,
+        "fork_depth_max": int(p["risk_depth"]),
+        "forks_seen": int(p["forks_seen"]),
+        "hashrate_volatility": round(p["volatility"], 4),
+    }
@@ -0,0 +1,105 @@
+from __future__ import annotations
+
+import json
+import threading
+from typing import Any, Dict, List, Optional
+
+from core.config import get_config
+from core.db import get_db, KV, SubAddress
+from core.utils import now_ms
+from core import logs
+from core import notify
+
+
+# Double-spend / conflict tracking for wallet transactions.
+#
+# A transaction is conflicted when another one spending the same outpoint wins:
+#   replaced   an RBF replacement evicted it from the mempool (core.txrelay)
+#   block      a connected block spent one of its inputs (txrelay.remove_block_conflicts, run
+#              after blocks connect); it and its in-mempool descendants leave the mempool. The
+#              UTXO set records spends per block, so conflicting_txid is the block's hash here
+# Conflicts involving a wallet address (any SubAddress row, as input or output) are recorded in
+# KV (wallet_conflicts_json, newest wallet.max_conflicts kept) so gettransaction can report the
+# tx as "conflicted" with its walletconflicts, also after the tx has left the mempool. Every
+# wallet conflict is published on the "conflicts" topic (core.notify) as
+#   {"event": "txconflict", "txid", "conflicting_txid", "reason", "addresses", "time_ms"}.
+
+_KV_KEY = "wallet_conflicts_json"
+_lock = threading.Lock()
+log = logs.get("mempool")
+
+
+def tx_addresses(raw: Optional[str]) -> List[str]:
+    """Addresses a stored raw touches: structured inputs/outputs, or legacy from=/to= fields."""
+    if not raw:
+        return []
+    out: List[str] = []
+    if raw.lstrip().startswith("{"):
+        try:
+            tx = json.loads(raw)
+        except ValueError:
+            return []
+        if not isinstance(tx, dict):
+            return []
+        for part in (tx.get("inputs") or []) + (tx.get("outputs") or []):
+            if isinstance(part, dict) and part.get("address"):
+                out.append(str(part["address"]))
+    else:
+        for field in raw.split(";"):
+            k, _, v = field.partition("=")
+            if k in ("from", "to") and v:
+                out.append(v)
+    return list(dict.fromkeys(out))
+
+
+def _load(s) -> Dict[str, Dict[str, Any]]:
+    row = s.get(KV, _KV_KEY)
+    try:
+        data = json.loads(row.v) if row and row.v else {}
+    except ValueError:
+        data = {}
+    return data if isinstance(data, dict) else {}
+
+
+def record(txid: str, raw: Optional[str], conflicting_txid: str, reason: str) -> bool:
+    """Note that txid lost to conflicting_txid; returns True if it touched a wallet address."""
+    addrs = tx_addresses(raw)
+    if not addrs:
+        return False
+    db = get_db()
+    with _lock, db.session() as s:
+        mine = [a for (a,) in s.query(SubAddress.address).filter(SubAddress.address.in_(addrs)).all()]
+        if not mine:
+            return False
+        data = _load(s)
+        entry = data.get(txid) or {"conflicting": [], "addresses": mine}
+        if conflicting_txid not in entry["conflicting"]:
+            entry["conflicting"].append(conflicting_txid)
+        entry.update(reason=reason, time_ms=now_ms())
+        data.pop(txid, None)
+        data[txid] = entry
+        keep = max(1, int(get_config().get("wallet.max_conflicts", 1000)))
+        for old in list(data)[:-keep]:
+            del data[old]
+        row = s.get(KV, _KV_KEY) or KV(k=_KV_KEY, v="")
+        row.v = json.dumps(data, separators=(",", ":"))
+        s.merge(row)
+        s.commit()
+    log.info("wallet tx conflicted", extra=logs.fields(txid=txid, conflicting_txid=conflicting_txid, reason=reason))
+    notify.publish("conflicts", {
+        "event": "txconflict",
+        "txid": txid,
+        "conflicting_txid": conflicting_txid,
+        "reason": reason,
+        "addresses": mine,
+        "time_ms": entry["time_ms"],
+    })
+    return True
+
+
+def get(txid: str) -> Optional[Dict[str, Any]]:
+    """{"conflicting": [...], "reason", "addresses", "time_ms"} for a conflicted wallet tx, else None."""
+    db = get_db()
+    with db.session() as s:
+        return _load(s).get(txid)
+
@@ -8,13 +8,25 @@ from dataclasses import dataclass, asdict
 from typing import List, Optional, Tuple, Dict, Any
 
 from core.db import get_db, BlockHeader, Transaction, UTXO, Reward, MempoolTx, KV, FairnessEpoch, FairnessCredit
-from sqlalchemy import func
+from sqlalchemy import func, or_
 from core.config import get_config
-from core.utils import now_ms, sha3_256_hex as _sha3_256_hex
-from core.pow.randomx_stub import difficulty_to_target
+from core.utils import now_ms, now_sec, sha3_256_hex as _sha3_256_hex
+from core.merkle import merkle_root, merkle_root_mutated
+from core.target import (
+    bits_to_target, difficulty_to_target, hash_meets_target, target_to_bits, target_to_difficulty, to_int, chainwork,
+)
 from core.pow.pow_backend import pow_hash, backend_name
 from sqlalchemy.dialects.sqlite import insert as sqlite_insert
-from core.crypto import tx_digest_hex, ed25519_verify_hex
+from core.crypto import data_output_bytes, is_script_address, tx_digest_hex, verify_transaction_input
+from core.blockstore import store_block_best_effort
+from core.blockfilter import index_block_best_effort
+from core import txindex
+from core import utxohash
+from core import addrindex
+from core import checkpoints
+from core import versionbits
+from core import blocksize
+from core import nettime
 
 # SQLite busy retry helper
 def _with_retry(op, *args, **kwargs):
@@ -69,20 +81,7 @@ class Header:
 
 
 def calc_merkle_root(txids: List[str]) -> str:
-    # Local helper hash that cannot be shadowed by imported names
-    def _h(b: bytes) -> str:
-        return _sha3_256_hex(b)
-    if not txids:
-        return _h(b"")
-    layer = [bytes.fromhex(t) for t in txids]
-    while len(layer) > 1:
-        nxt = []
-        if len(layer) % 2 == 1:
-            layer.append(layer[-1])
-        for i in range(0, len(layer), 2):
-            nxt.append(bytes.fromhex(_h(layer[i] + layer[i + 1])))
-        layer = nxt
-    return layer[0].hex()
+    return merkle_root(txids)
 
 
 def initial_difficulty() -> int:
@@ -133,6 +132,39 @@ def next_difficulty(prev_headers: List[BlockHeader], target_block_time: int) ->
     return new_diff
 
 
+NONCE_RANGE = f"{0:016x}{(1 << 64) - 1:016x}"  # getblocktemplate noncerange: 8-byte nonce, min || max
+
+
+def next_block_difficulty(s, tip: Optional[BlockHeader]) -> int:
+    """Difficulty for the block after tip: 1 through the 200-block bootstrap, then next_difficulty."""
+    if tip is None or tip.height + 1 < 200:
+        return 1
+    recent = s.query(BlockHeader).filter(BlockHeader.height <= tip.height).order_by(BlockHeader.height.desc()).limit(30).all()
+    target_block_time = int(get_config().get("consensus.target_block_time_sec", 60))
+    return next_difficulty(list(reversed(recent)), target_block_time)
+
+
+def next_block_target(s, tip: Optional[BlockHeader]) -> Tuple[str, int]:
+    """
+    (target_hex, bits) for templates handed to external miners. The target is rounded through the
+    compact encoding so bits and target describe exactly the same threshold.
+    """
+    bits = target_to_bits(difficulty_to_target(next_block_difficulty(s, tip)))
+    return bits_to_target(bits), bits
+
+
+def target_within_retarget(s, parent: Optional[BlockHeader], target_hex: str) -> bool:
+    """
+    Whether a header on top of parent (any block of our chain, not only the tip) claims a target no
+    easier than the retarget allows. Templates round the target down through bits and local mining
+    uses it unrounded, so both pass.
+    """
+    try:
+        return to_int(target_hex) <= to_int(difficulty_to_target(next_block_difficulty(s, parent)))
+    except ValueError:
+        return False
+
+
 def cumulative_work_of_chain_tip() -> Tuple[int, Optional[BlockHeader]]:
     db = get_db()
     with db.session() as s:
@@ -142,6 +174,71 @@ def cumulative_work_of_chain_tip() -> Tuple[int, Optional[BlockHeader]]:
         return int(tip.work, 16), tip
 
 
+def chain_work(upto_height: Optional[int] = None) -> int:
+    """Expected hashes behind the chain (sum of 2^256/(target+1)), unlike the difficulty-sum `work` column."""
+    db = get_db()
+    with db.session() as s:
+        q = s.query(BlockHeader.target)
+        if upto_height is not None:
+            q = q.filter(BlockHeader.height <= upto_height)
+        return chainwork(t for (t,) in q.all())
+
+
+def network_hashps(blocks: int = 120, height: Optional[int] = None) -> float:
+    """
+    Estimated network hashes/sec: expected work of the last `blocks` blocks ending at `height`
+    (default tip) divided by the time they took. 0.0 with fewer than two blocks or no elapsed time.
+    """
+    blocks = max(1, int(blocks))
+    db = get_db()
+    with db.session() as s:
+        q = s.query(BlockHeader.timestamp, BlockHeader.target)
+        if height is not None:
+            q = q.filter(BlockHeader.height <= height)
+        rows = q.order_by(BlockHeader.height.desc()).limit(blocks + 1).all()
+    if len(rows) < 2:
+        return 0.0
+    span = max(int(ts) for ts, _ in rows) - min(int(ts) for ts, _ in rows)
+    if span <= 0:
+        return 0.0
+    # rows[-1] only marks the start of the window; its own work happened before it
+    return float(chainwork(t for _, t in rows[:-1])) / span
+
+
+def expire_mempool() -> int:
+    """
+    Drop mempool entries older than mempool.expiry_sec (node clock, mockable), with their
+    in-mempool descendants, which cannot be mined without them. Returns rows removed.
+    """
+    from core import txgraph
+
+    expiry_ms = int(get_config().get("mempool.expiry_sec", 14 * 24 * 3600)) * 1000
+    cutoff = now_ms() - expiry_ms
+    db = get_db()
+    with db.session() as s:
+        rows = s.query(MempoolTx.txid, MempoolTx.raw, MempoolTx.fee, MempoolTx.added_ms).all()
+        old = {m.txid for m in rows if int(m.added_ms or 0) < cutoff}
+        if not old:
+            return 0
+        g = txgraph.build(rows)
+        drop = set(old)
+        for t in old:
+            drop |= txgraph.descendants(g, t)
+        n = s.query(MempoolTx).filter(MempoolTx.txid.in_(drop)).delete(synchronize_session=False)
+        s.commit()
+    return int(n or 0)
+
+
+def coinbase_maturity() -> int:
+    """Blocks a coinbase output must wait before it can be spent."""
+    return int(get_config().get("consensus.coinbase_maturity", 10))
+
+
+def spendable_at(height: int):
+    """UTXO filter for inputs of a block at `height`: coinbase outputs need coinbase_maturity depth."""
+    return or_(UTXO.coinbase == False, UTXO.height == None, UTXO.height <= height - coinbase_maturity())  # noqa: E711,E712
+
+
 def get_chain_height() -> int:
     db = get_db()
     with db.session() as s:
@@ -207,6 +304,25 @@ def get_txids_for_merkle(height: int, included_txids: List[str]) -> List[str]:
     return [expected_coinbase] + rest
 
 
+def merkle_snapshot_mutated(height: int, included_txids: List[str]) -> bool:
+    """
+    Whether the submitted txid list, before get_txids_for_merkle drops duplicates, is a
+    CVE-2012-2459 padding of a shorter list. Checking only the deduplicated list would let
+    [cb, a, b, b] through: it dedups to [cb, a, b], whose root is the same.
+    """
+    if height < 200:
+        return False
+    raw = [str(t or "").strip().lower() for t in (included_txids or [])]
+    raw = [t for t in raw if t]
+    expected_coinbase = _sha3_256_hex(f"COINBASE:{height}".encode("utf-8")).lower()
+    if not raw or raw[0] != expected_coinbase:
+        raw = [expected_coinbase] + raw
+    try:
+        return merkle_root_mutated(raw)[1]
+    except ValueError:
+        return False  # non-hex txids never match a mempool entry; the merkle comparison rejects them
+
+
 def compute_block_reward(height: int) -> float:
     cfg = get_config()
     initial = float(cfg.get("consensus.initial_block_reward", 50.0))
@@ -236,10 +352,10 @@ def within_max_supply(next_height: int) -> bool:
     return True
 
 
-def validate_header(new_header: Header, prev: Optional[BlockHeader]) -> Tuple[bool, str]:
+def validate_header(new_header: Header, prev: Optional[BlockHeader], skip_pow: bool = False) -> Tuple[bool, str]:
     cfg = get_config()
-    # version
-    if new_header.version != int(cfg.get("consensus.block_version", 1)):
+    # version: legacy block_version or versionbits signalling (core.versionbits)
+    if not versionbits.valid_version(new_header.version):
         return False, "invalid version"
     # link
     if prev:
@@ -248,15 +364,23 @@ def validate_header(new_header: Header, prev: Optional[BlockHeader]) -> Tuple[bo
         # Allow equal timestamp if clocks are coarse; require non-decreasing
         if new_header.timestamp < prev.timestamp:
             return False, "timestamp decreased"
+    # Reject headers too far in the future of network-adjusted time (core.nettime; mockable on regtest)
+    if new_header.timestamp > nettime.adjusted_time() + int(cfg.get("consensus.max_future_block_time_sec", 7200)):
+        return False, "timestamp too far in future"
     # target
     # difficulty encoded as "work" hex in DB; for validation we check hash <= target
     # Use selected PoW backend (RandomX DLL via ctypes if available, else Argon2id)
+    # (skip_pow: assumevalid ancestors during bootstrap import, see core.checkpoints)
     prev_hex = prev.hash_hex if prev else "00" * 32
-    h_bytes = pow_hash(new_header.serialize(), new_header.nonce, prev_hex)
-    if int(h_bytes.hex(), 16) > int(new_header.target, 16):
-        return False, "pow target not met"
-    # supply cap
+    if not skip_pow:
+        h_bytes = pow_hash(new_header.serialize(), new_header.nonce, prev_hex)
+        if not hash_meets_target(h_bytes, new_header.target):
+            return False, "pow target not met"
     height = 0 if prev is None else prev.height + 1
+    cp_err = checkpoints.check(height, new_header.hash_hex())
+    if cp_err:
+        return False, cp_err
+    # supply cap
     if not within_max_supply(height):
         return False, "exceeds max supply cap"
     # tx_count should be >= 1 due to coinbase
@@ -271,18 +395,30 @@ def _utxo_sum_for_address(s, address: str) -> float:
     return float(s.query(func.coalesce(func.sum(UTXO.amount), 0.0)).filter_by(address=address, spent=False).scalar() or 0.0)  # type: ignore
 
 
-def validate_mempool_tx(tx: Dict[str, Any], height: int) -> Tuple[bool, str, str]:
+def _unsigned_script_spend(m: MempoolTx, from_addr: str) -> bool:
+    """Legacy "from=..;to=.." entries carry no signatures and cannot spend from a multisig address."""
+    return is_script_address(from_addr) and not (m.raw or "").lstrip().startswith("{")
+
+
+def validate_mempool_tx(tx: Dict[str, Any], height: int,
+                        unconfirmed: Optional[Dict[Tuple[str, int], Dict[str, Any]]] = None) -> Tuple[bool, str, str]:
     """
     Validate a transaction for mempool admission.
     Returns (ok, reason, txid)
+    unconfirmed: (txid, vout) -> {"address", "amount"} outputs of in-mempool parents that inputs
+    may spend (see core.txgraph); without it every input must be in the UTXO set.
     tx schema (confirmed with user):
     {
       "version": 1,
-      "inputs": [{"txid":"hex","vout":0,"address":"SMELLY_...","pubkey":"hex32","sig":"hex64"}],
+      "inputs": [{"txid":"hex","vout":0,"address":"SMELLY_...","pubkey":"hex32","viewkey":"hex32","sig":"hex64"}],
       "outputs": [{"address":"SMELLY_...","amount":1.23}],
       "fee": 0.00002,
       "timestamp": 1690000000
     }
+    Inputs spending a script (multisig) address carry "redeem" and "sigs" instead of pubkey/sig.
+    An input's "address", when given, must be the spent output's address; its keys must derive
+    that address (core.crypto.key_address_matches), so a valid signature by some other key fails.
+    Outputs after the first may be data outputs {"data": hex} (see core.crypto.data_output_bytes).
     """
     cfg = get_config()
     min_fee = float(cfg.get("mempool.min_fee", 0.00001))
@@ -298,13 +434,13 @@ def validate_mempool_tx(tx: Dict[str, Any], height: int) -> Tuple[bool, str, str
         return False, "fee-too-low", ""
     if not inputs or not outputs:
         return False, "missing-io", ""
-    # compute txid as digest excluding signatures field
+    # compute txid as digest excluding signatures (tx-level and per-input)
     txid = tx_digest_hex(tx)
 
     db = get_db()
     with db.session() as s:
-        # Double-spend check against mempool + utxo
-        # For each input, verify referenced utxo exists and is unspent; also ensure not already referenced by another mempool tx
+        # Double-spend check against the utxo set; mempool conflicts are resolved by core.txrelay
+        # For each input, verify referenced utxo exists and is unspent
         for i in inputs:
             if not isinstance(i, dict):
                 return False, "bad-input", txid
@@ -312,47 +448,55 @@ def validate_mempool_tx(tx: Dict[str, Any], height: int) -> Tuple[bool, str, str
             vout = int(i.get("vout", -1))
             if not ref_txid or vout < 0:
                 return False, "bad-input-ref", txid
-            # Check UTXO set
+            # Check UTXO set (or an unconfirmed parent's output)
             u = s.query(UTXO).filter(UTXO.txid == ref_txid, UTXO.vout == vout).first()
+            if u is None and unconfirmed and (ref_txid, vout) in unconfirmed:
+                if i.get("address") and i.get("address") != unconfirmed[(ref_txid, vout)].get("address"):
+                    return False, "bad-input-address", txid
+                continue
             if not u or u.spent:
                 return False, "utxo-missing-or-spent", txid
-            # Coinbase maturity
+            if i.get("address") and i.get("address") != u.address:
+                return False, "bad-input-address", txid
+            # Coinbase maturity: origin height is stored on the UTXO (rewards table for legacy rows)
             if u.coinbase:
-                # infer coinbase height from its txid pattern COINBASE:{height}
-                try:
-                    if ref_txid.startswith(_sha3_256_hex(b"COINBASE:")[:8]):
-                        # heuristic fallback; better approach is store coinbase height with utxo
-                        pass
-                except Exception:
-                    pass
-                # strict: block height from coinbase id
-                try:
-                    # our coinbase txid is sha3("COINBASE:{h}"), we can't get height directly; use a DB lookup by matching reward table
+                origin = u.height
+                if origin is None:
                     r = s.query(Reward).filter_by(txid=u.txid).first()
-                    if r and height < r.height + 10:
-                        return False, "coinbase-immature", txid
-                except Exception:
-                    # If cannot resolve, allow but this should be rare
-                    pass
+                    origin = r.height if r else None
+                if origin is not None and height < origin + coinbase_maturity():
+                    return False, "coinbase-immature", txid
 
         # Basic amount checks
         total_out = 0.0
-        for o in outputs:
+        for n, o in enumerate(outputs):
             if not isinstance(o, dict):
                 return False, "bad-output", txid
+            try:
+                is_data = data_output_bytes(o) is not None
+            except (ValueError, TypeError):
+                return False, "bad-output-data", txid
+            if is_data:
+                if n == 0:
+                    return False, "bad-output-data", txid
+                continue
             addr = o.get("address") or ""
             amt = float(o.get("amount", -1.0))
             if not addr or amt <= 0:
                 return False, "bad-output-amt", txid
             total_out += amt
-        # Verify signatures: for each input, verify sig over canonical digest with pubkey
-        digest_bytes = bytes.fromhex(txid)
-        for i in inputs:
-            pubkey_hex = i.get("pubkey") or ""
-            sig_hex = i.get("sig") or ""
-            if not pubkey_hex or not sig_hex:
+        # Verify signatures: each input signs its sighash, committing to the output it spends;
+        # outputs paying to a script address need the redeem script and m-of-n sigs instead
+        for idx, i in enumerate(inputs):
+            ref = ((i.get("txid") or "").strip().lower(), int(i.get("vout", -1)))
+            u = s.query(UTXO).filter(UTXO.txid == ref[0], UTXO.vout == ref[1]).first()
+            prev_output = {"address": u.address, "amount": u.amount} if u else (unconfirmed or {}).get(ref, {})
+            if is_script_address(str(prev_output.get("address") or "")):
+                if not i.get("redeem") or not i.get("sigs"):
+                    return False, "missing-sig", txid
+            elif not i.get("pubkey") or not i.get("sig"):
                 return False, "missing-sig", txid
-            if not ed25519_verify_hex(pubkey_hex, digest_bytes, sig_hex):
+            if not verify_transaction_input(tx, idx, prev_output):
                 return False, "bad-signature", txid
 
         # Balance check: sum of referenced UTXOs >= total_out + fee
@@ -361,16 +505,16 @@ def validate_mempool_tx(tx: Dict[str, Any], height: int) -> Tuple[bool, str, str
             ref_txid = (i.get("txid") or "").strip().lower()
             vout = int(i.get("vout", -1))
             u = s.query(UTXO).filter(UTXO.txid == ref_txid, UTXO.vout == vout).first()
+            if u is None and unconfirmed and (ref_txid, vout) in unconfirmed:
+                total_in += float(unconfirmed[(ref_txid, vout)].get("amount") or 0.0)
+                continue
             if not u or u.spent:
                 return False, "utxo-missing-or-spent", txid
             total_in += float(u.amount or 0.0)
         if total_in + 1e-12 < (total_out + fee):
             return False, "insufficient-input", txid
 
-        # Prevent double spend within mempool: ensure no other mempool tx spends same (txid,vout)
-        mem_conflict = s.query(MempoolTx).filter(MempoolTx.raw.like(f"%{ref_txid}%")).first()  # coarse check
-        # For accuracy, we could store inputs in mempool table; for now raw string coarse match.
-        # We'll ignore if not found.
+        # Conflicts with other mempool entries (and replace-by-fee) are handled by core.txrelay
 
         return True, "ok", txid
 
@@ -391,7 +535,7 @@ def add_genesis_if_needed():
             version=int(cfg.get("consensus.block_version", 1)),
             prev_hash_hex="00" * 32,
             merkle_root_hex=mr,
-            timestamp=int(time.time()),
+            timestamp=now_sec(),
             target=difficulty_to_target(initial_difficulty()),
             nonce=0,
             miner_address="SMELLY_GENESIS",
@@ -422,7 +566,6 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
     """
     db = get_db()
     cfg = get_config()
-    target_block_time = int(cfg.get("consensus.target_block_time_sec", 60))
     MIN_FEE = float(cfg.get("mempool.min_fee", 0.000001))
     TXS_PER_BLOCK = int(cfg.get("consensus.txs_per_block_cap", 200))
 
@@ -432,21 +575,16 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
         prev_hash = "00" * 32 if tip is None else tip.hash_hex
 
         # Difficulty
-        recent = s.query(BlockHeader).order_by(BlockHeader.height.asc()).all()
-        if not recent or len(recent) < 200:
-            diff = 1
-        else:
-            diff = next_difficulty(recent, target_block_time)
-
-        # Select mempool txs by fee desc (and sanity filters); ensure not already confirmed
-        mem_total = s.query(MempoolTx).count()
-        mem = (
-            s.query(MempoolTx)
-            .filter(MempoolTx.fee >= MIN_FEE)
-            .order_by(MempoolTx.fee.desc(), MempoolTx.added_ms.asc())
-            .limit(TXS_PER_BLOCK * 4)  # over-select; we will validate balances/spends
-            .all()
-        )
+        diff = next_block_difficulty(s, tip)
+
+        # Select mempool txs by ancestor-package feerate, parents first (and sanity filters); ensure not already confirmed
+        from core import txgraph
+
+        mem_rows = s.query(MempoolTx).all()
+        mem_total = len(mem_rows)
+        by_txid = {m.txid: m for m in mem_rows}
+        # over-select; we will validate balances/spends
+        mem = [by_txid[t] for t in txgraph.select_packages(txgraph.build(mem_rows), TXS_PER_BLOCK * 4, MIN_FEE)]
         # Filter out any txs that have been confirmed already (defensive)
         if mem:
             confirmed = {txid for (txid,) in s.query(Transaction.txid).filter(Transaction.in_block_hash.isnot(None), Transaction.txid.in_([m.txid for m in mem])).all()}
@@ -454,9 +592,12 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
 
         included_txids: List[str] = []
         total_fees = 0.0
+        block_bytes = 0
+        max_bytes = blocksize.template_max_bytes()
         skipped_insufficient: int = 0
         skipped_invalid: int = 0
         skipped_addr_miss: int = 0
+        skipped_size: int = 0
 
         def spend_from_address_multi(from_addr: str, amount: float, fee: float) -> Tuple[bool, float, List[UTXO]]:
             """
@@ -474,7 +615,7 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
             # fetch unspent, excluding those already picked
             utxos = (
                 s.query(UTXO)
-                .filter_by(address=from_addr, spent=False)
+                .filter_by(address=from_addr, spent=False).filter(spendable_at(height))
                 .order_by(UTXO.amount.desc())
                 .limit(2000)
                 .all()
@@ -546,8 +687,19 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
                 debug_reasons.append(f"{m.txid}: bad-amt-fee amt={amount} fee={fee}")
                 continue
 
+            if _unsigned_script_spend(m, from_addr):
+                skipped_invalid += 1
+                debug_reasons.append(f"{m.txid}: unsigned-script-spend from={from_addr[:10]}..")
+                continue
+
+            size = blocksize.tx_bytes(m.raw)
+            if block_bytes + size > max_bytes:
+                skipped_size += 1
+                debug_reasons.append(f"{m.txid}: block-full size={size} used={block_bytes}")
+                continue
+
             # Check total available minus those already tentatively picked
-            total_avail = float(s.query(func.coalesce(func.sum(UTXO.amount), 0.0)).filter_by(address=from_addr, spent=False).scalar() or 0.0)  # type: ignore
+            total_avail = float(s.query(func.coalesce(func.sum(UTXO.amount), 0.0)).filter_by(address=from_addr, spent=False).filter(spendable_at(height)).scalar() or 0.0)  # type: ignore
             if total_avail + 1e-12 < (amount + fee):
                 skipped_insufficient += 1
                 debug_reasons.append(f"{m.txid}: insufficient bal={total_avail:.6f} need={(amount+fee):.6f}")
@@ -600,6 +752,7 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
 
             included_txids.append(m.txid)
             total_fees += fee
+            block_bytes += size
 
             # Soft cap enforcement: if we hit block cap, stop
             if len(included_txids) >= TXS_PER_BLOCK:
@@ -611,10 +764,10 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
         mr = calc_merkle_root(txids)
 
         header = Header(
-            version=int(cfg.get("consensus.block_version", 1)),
+            version=versionbits.compute_block_version(tip),
             prev_hash_hex=prev_hash,
             merkle_root_hex=mr,
-            timestamp=int(time.time()),
+            timestamp=nettime.adjusted_time(),
             target=difficulty_to_target(diff),
             nonce=0,
             miner_address=miner_address,
@@ -622,19 +775,19 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
         )
 
         # Mine
-        target_int = int(header.target, 16)
+        target_int = to_int(header.target)
         max_tries = 5_000_000
         nonce = 0
         found = False
         while nonce < max_tries:
             h = pow_hash(header.serialize(), nonce, prev_hash)
-            if int(h.hex(), 16) <= target_int:
+            if hash_meets_target(h, target_int):
                 header.nonce = nonce
                 found = True
                 break
             nonce += 1
             if nonce % 5000 == 0:
-                now_ts = int(time.time())
+                now_ts = nettime.adjusted_time()
                 if now_ts > header.timestamp:
                     header.timestamp = now_ts
         if not found:
@@ -698,6 +851,7 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
                     spent=False,
                     spent_txid=None,
                     coinbase=True,
+                    height=height,
                 ))
                 s.flush()
             except Exception:
@@ -778,11 +932,11 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
             try:
                 from core.db import KV  # type: ignore
                 diag = s.get(KV, "diag_mempool_skips") or KV(k="diag_mempool_skips", v="0")
-                total_skips = skipped_insufficient + skipped_invalid + skipped_addr_miss
+                total_skips = skipped_insufficient + skipped_invalid + skipped_addr_miss + skipped_size
                 diag.v = str(int(diag.v) + total_skips)
                 s.merge(diag)
                 # Also store last reasons (truncate to keep small), and note mempool total
-                reasons = [f"mempool_total={mem_total}", f"included=0", f"skips: insufficient={skipped_insufficient} invalid={skipped_invalid}"]
+                reasons = [f"mempool_total={mem_total}", f"included=0", f"skips: insufficient={skipped_insufficient} invalid={skipped_invalid} size={skipped_size}"]
                 reasons.extend(debug_reasons[-10:])
                 last = s.get(KV, "diag_last_skip_reasons") or KV(k="diag_last_skip_reasons", v="")
                 last.v = "\n".join(reasons)
@@ -807,6 +961,13 @@ def append_block_header(miner_address: str) -> Tuple[Optional[str], Optional[str
             except Exception:
                 pass
 
+        # Cold tier: append to flat block files (best-effort; backfilled on next startup if missed)
+        store_block_best_effort(hh, txids)
+        index_block_best_effort(hh, txids)
+        txindex.index_block_best_effort(hh, txids)
+        utxohash.apply_block_best_effort(hh)
+        addrindex.apply_block_best_effort(hh)
+
         return hh, None
 
 
@@ -912,10 +1073,12 @@ def accept_external_header(
     nonce: int,
     miner_address: str,
     txids_snapshot: List[str],
+    assume_valid: bool = False,
 ) -> Tuple[Optional[str], Optional[str]]:
     """
     Accept an externally mined header (client-side mining).
-    Validates linkage to current tip, PoW target, and re-validates mempool snapshot for inclusion.
+    Validates linkage to current tip, PoW target, the target against the retarget, and re-validates
+    mempool snapshot for inclusion.
     On success, appends the block, credits coinbase+fees to miner, updates UTXOs, and clears included mempool entries.
     Returns (new_hash, error_message).
 
@@ -957,6 +1120,10 @@ def accept_external_header(
 
         height = 0 if tip is None else tip.height + 1
 
+        if merkle_snapshot_mutated(height, txids_snapshot or []):
+            # Duplicate sibling txids reproduce the root of a shorter list (CVE-2012-2459)
+            return None, f"merkle-mismatch: mutated txid list (duplicate siblings) height={height}"
+
         # Validate PoW target with provided fields (strictly matching miner serialization order)
         # Normalize hex fields to lowercase to avoid case-mismatch issues
         header = Header(
@@ -970,7 +1137,7 @@ def accept_external_header(
             tx_count=max(1, len(txids_snapshot)),  # at least coinbase
         )
 
-        ok, reason = validate_header(header, tip)
+        ok, reason = validate_header(header, tip, skip_pow=assume_valid)
         if not ok:
             # record last header bytes and prev used by consensus
             try:
@@ -982,6 +1149,10 @@ def accept_external_header(
             except Exception:
                 pass
             return None, f"header-invalid: {reason}"
+        if not target_within_retarget(s, tip, header.target):
+            # PoW above only proves the target the header claims; work, retargeting and the template
+            # for the next block all follow it, so it must be the one the retarget allows
+            return None, f"bad-diffbits: target {header.target} easier than the retarget allows at height={height}"
 
         # Re-validate snapshot txids: include highest-fee valid transactions up to cap.
         # We will only include txids that are still present and spendable; others are dropped.
@@ -1014,7 +1185,7 @@ def accept_external_header(
                 need = amount + fee
                 utxos = (
                     s.query(UTXO)
-                    .filter_by(address=from_addr, spent=False)
+                    .filter_by(address=from_addr, spent=False).filter(spendable_at(height))
                     .order_by(UTXO.amount.desc())
                     .limit(1000)
                     .all()
@@ -1059,8 +1230,10 @@ def accept_external_header(
                     continue
                 if amount <= 0 or fee < MIN_FEE:
                     continue
+                if _unsigned_script_spend(m, from_addr):
+                    continue
 
-                bal = s.query(UTXO).with_entities(func.coalesce(func.sum(UTXO.amount), 0.0)).filter_by(address=from_addr, spent=False).scalar()  # type: ignore
+                bal = s.query(UTXO).with_entities(func.coalesce(func.sum(UTXO.amount), 0.0)).filter_by(address=from_addr, spent=False).filter(spendable_at(height)).scalar()  # type: ignore
                 if (bal or 0.0) + 1e-12 < (amount + fee):
                     continue
 
@@ -1108,9 +1281,14 @@ def accept_external_header(
                 included_txids.append(txid)
                 total_fees += fee
 
+        # Block size (consensus.max_block_size) over the txs actually confirmed; nothing is committed yet
+        size_err = blocksize.check(blocksize.block_size(header.serialize(), [mem_map[t].raw for t in included_txids]))
+        if size_err:
+            return None, size_err
+
         # Rebuild authoritative merkle from snapshot (with boundary-safe ordering)
         txids_for_merkle_list = get_txids_for_merkle(height, txids_snapshot or [])
-        rebuilt_merkle = calc_merkle_root(txids_for_merkle_list).lower()
+        rebuilt_merkle = merkle_root(txids_for_merkle_list)
 
         submitted_merkle = (merkle_root_hex or "").lower()
         if height < 200:
@@ -1133,11 +1311,16 @@ def accept_external_header(
 
         # Save header row (idempotent on hash by uniqueness of (height, hash_hex) constraint)
         prev_work = 0 if tip is None else int(tip.work, 16)
-        new_work = prev_work + 1  # keep cumulative monotonic; could map target->diff properly later
+        # Same cumulative difficulty metric as locally mined blocks so retargeting sees the real step
+        new_work = prev_work + max(1, int(round(target_to_difficulty(header.target))))
         # Ensure header contains authoritative merkle before hashing
         if height >= 0 and height < 200:
             header.merkle_root_hex = rebuilt_merkle
         hh = header.hash_hex()
+        from core.invalidation import invalid_in_session
+        if invalid_in_session(s, hh):
+            # Operator marked this block invalid (invalidateblock); refused until reconsiderblock
+            return None, f"block-invalidated: {hh}"
 
         def _insert_header():
             existed_header = s.query(BlockHeader).filter_by(hash_hex=hh).first()
@@ -1207,6 +1390,7 @@ def accept_external_header(
                     spent=False,
                     spent_txid=None,
                     coinbase=True,
+                    height=height,
                 ))
                 s.flush()
             else:
@@ -1245,4 +1429,11 @@ def accept_external_header(
         except Exception:
             pass
 
+        # Cold tier: append to flat block files (best-effort; backfilled on next startup if missed)
+        store_block_best_effort(hh, txids_for_merkle_list)
+        index_block_best_effort(hh, txids_for_merkle_list)
+        txindex.index_block_best_effort(hh, txids_for_merkle_list)
+        utxohash.apply_block_best_effort(hh)
+        addrindex.apply_block_best_effort(hh)
+
         return hh, None
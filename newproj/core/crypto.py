@@ -18,12 +18,12 @@ from core.utils import sha3_256_hex
 # This module provides simple wrappers for:
 # - Ed25519 keypairs for spending and viewing (Monero-like split concept, simplified)
 # - Seed/mnemonic generation
-# - Address encoding with custom prefix "SMELLY_"
+# - Address encoding: network prefix + base58check(version || hash160 || checksum)
 # - Subaddress derivation pattern (Monero-inspired, simplified and NOT compatible with XMR)
 # - Transaction digesting and signature verification helpers
 
 
-ADDRESS_PREFIX = "SMELLY_"
+ADDRESS_PREFIX = "SMELLY_"  # legacy hex addresses (pre base58check)
 
 
 def generate_seed(entropy_bits: int = 256, language: str = "english") -> Tuple[str, bytes]:
@@ -54,26 +54,126 @@ def keccak256_hex(data: bytes) -> str:
     return hashlib.sha3_256(data).hexdigest()
 
 
-def encode_address(pub_view_key: bytes, pub_spend_key: bytes) -> str:
-    core = pub_view_key + pub_spend_key
-    checksum = keccak256_hex(core)[:8]
-    addr_body = (core + bytes.fromhex(checksum)).hex()
-    return f"{ADDRESS_PREFIX}{addr_body}"
+# ---------- Addresses ----------
+#
+# Canonical form: <prefix><base58(version || hash160 || checksum)>
+#   prefix    per network: "smc" (mainnet), "tsmc" (testnet), "rsmc" (regtest)
+#   version   1 byte: ADDRESS_VERSION (key address) or SCRIPT_ADDRESS_VERSION (pay-to-script-hash)
+#   hash160   first 20 bytes of sha3-256(pub_view || pub_spend), or of the canonical redeem
+#             script for script addresses (see multisig helpers below)
+#   checksum  first 4 bytes of sha3-256(sha3-256(prefix || version || hash160))
+# The prefix is covered by the checksum, so relabelling an address for another network fails
+# validation. Legacy "SMELLY_..." strings are only accepted where wallet.accept_legacy_addresses
+# allows it (old DB rows, placeholder miner names).
 
+ADDRESS_VERSION = 0x3C
+SCRIPT_ADDRESS_VERSION = 0x3D
+NETWORK_PREFIXES = {"mainnet": "smc", "testnet": "tsmc", "regtest": "rsmc"}
+_B58_ALPHABET = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz"
 
-def decode_address(address: str) -> Tuple[bytes, bytes]:
-    if not address.startswith(ADDRESS_PREFIX):
-        raise ValueError("Invalid address prefix")
-    body_hex = address[len(ADDRESS_PREFIX):]
-    body = bytes.fromhex(body_hex)
-    if len(body) != 32 + 32 + 4:
-        raise ValueError("Invalid address length")
-    pub_view = body[:32]
-    pub_spend = body[32:64]
-    checksum = body[64:]
-    if keccak256_hex(pub_view + pub_spend)[:8] != checksum.hex():
-        raise ValueError("Invalid address checksum")
-    return pub_view, pub_spend
+
+def b58encode(data: bytes) -> str:
+    n = int.from_bytes(data, "big")
+    out = ""
+    while n > 0:
+        n, rem = divmod(n, 58)
+        out = _B58_ALPHABET[rem] + out
+    pad = len(data) - len(data.lstrip(b"\x00"))
+    return "1" * pad + out
+
+
+def b58decode(text: str) -> bytes:
+    n = 0
+    for ch in text:
+        idx = _B58_ALPHABET.find(ch)
+        if idx < 0:
+            raise ValueError(f"invalid base58 character {ch!r}")
+        n = n * 58 + idx
+    body = n.to_bytes((n.bit_length() + 7) // 8, "big") if n else b""
+    pad = len(text) - len(text.lstrip("1"))
+    return b"\x00" * pad + body
+
+
+def hash160(data: bytes) -> bytes:
+    return bytes.fromhex(keccak256_hex(data))[:20]
+
+
+def network_prefix(network_name: Optional[str] = None) -> str:
+    """Address prefix for a network name (defaults to network.name from config)."""
+    if network_name is None:
+        from core.config import get_config
+        network_name = str(get_config().get("network.name", "smelly-mainnet"))
+    name = network_name.lower()
+    if "regtest" in name:
+        return NETWORK_PREFIXES["regtest"]
+    if "test" in name:
+        return NETWORK_PREFIXES["testnet"]
+    return NETWORK_PREFIXES["mainnet"]
+
+
+def _address_checksum(prefix: str, payload: bytes) -> bytes:
+    first = bytes.fromhex(keccak256_hex(prefix.encode("ascii") + payload))
+    return bytes.fromhex(keccak256_hex(first))[:4]
+
+
+def address_from_hash160(h160: bytes, prefix: Optional[str] = None, version: int = ADDRESS_VERSION) -> str:
+    if len(h160) != 20:
+        raise ValueError("hash160 must be 20 bytes")
+    prefix = prefix or network_prefix()
+    payload = bytes([version]) + h160
+    return prefix + b58encode(payload + _address_checksum(prefix, payload))
+
+
+def encode_address(pub_view_key: bytes, pub_spend_key: bytes, prefix: Optional[str] = None) -> str:
+    return address_from_hash160(hash160(pub_view_key + pub_spend_key), prefix)
+
+
+def decode_address(address: str, prefix: Optional[str] = None) -> Tuple[str, int, bytes]:
+    """
+    Parse a canonical address; returns (prefix, version, hash160) or raises ValueError.
+    With prefix=None the address must belong to the configured network.
+    """
+    address = (address or "").strip()
+    want = prefix or network_prefix()
+    # Longest first so "tsmc"/"rsmc" are not read as "smc" + body
+    found = next((p for p in sorted(NETWORK_PREFIXES.values(), key=len, reverse=True) if address.startswith(p)), None)
+    if found is None:
+        raise ValueError("unknown address prefix")
+    if found != want:
+        raise ValueError(f"address is for another network (prefix {found}, expected {want})")
+    raw = b58decode(address[len(found):])
+    if len(raw) != 1 + 20 + 4:
+        raise ValueError("invalid address length")
+    payload, checksum = raw[:21], raw[21:]
+    if _address_checksum(found, payload) != checksum:
+        raise ValueError("invalid address checksum")
+    if payload[0] not in (ADDRESS_VERSION, SCRIPT_ADDRESS_VERSION):
+        raise ValueError(f"unsupported address version {payload[0]}")
+    return found, payload[0], payload[1:]
+
+
+def check_address(address: str, allow_legacy: Optional[bool] = None) -> Optional[str]:
+    """Error string when `address` is not usable on this network, else None."""
+    if allow_legacy is None:
+        from core.config import get_config
+        allow_legacy = bool(get_config().get("wallet.accept_legacy_addresses", True))
+    if allow_legacy and (address or "").startswith(ADDRESS_PREFIX) and len(address) > len(ADDRESS_PREFIX):
+        return None
+    try:
+        decode_address(address)
+    except ValueError as e:
+        return str(e)
+    return None
+
+
+def is_valid_address(address: str, allow_legacy: Optional[bool] = None) -> bool:
+    return check_address(address, allow_legacy) is None
+
+
+def parse_worker(login: str) -> Tuple[str, str]:
+    """Split a Stratum login "address.worker" into (address, worker); base58 has no '.'."""
+    address, _, worker = (login or "").strip().partition(".")
+    return address, worker
 
 
 def derive_subaddress(pub_view_key: bytes, pub_spend_key: bytes, major: int, minor: int) -> str:
@@ -103,12 +203,20 @@ def verify(message: bytes, sig: bytes, pk: bytes) -> bool:
 
 # ---------- Transaction digesting + signature helpers ----------
 
+def _strip_input_sigs(inputs: Any, fields: Tuple[str, ...] = ("sig",)) -> Any:
+    if not isinstance(inputs, list):
+        return inputs
+    return [{k: v for k, v in i.items() if k not in fields} if isinstance(i, dict) else i for i in inputs]
+
+
 def tx_canonical_json(tx_obj: Dict[str, Any]) -> bytes:
     """
     Stable canonical JSON encoding for transactions.
-    Excludes any 'signatures' field to ensure deterministic digest for signing.
+    Excludes any 'signatures' field and per-input 'sig' so the txid does not depend on signatures.
     """
     filtered = {k: v for k, v in tx_obj.items() if k != "signatures"}
+    if "inputs" in filtered:
+        filtered["inputs"] = _strip_input_sigs(filtered["inputs"], ("sig", "sigs"))
     # Ensure stable key ordering and compact separators
     return json.dumps(filtered, sort_keys=True, separators=(",", ":")).encode("utf-8")
 
@@ -120,6 +228,190 @@ def tx_digest_hex(tx_obj: Dict[str, Any]) -> str:
     return sha3_256_hex(tx_canonical_json(tx_obj))
 
 
+# Signature hashing. Each input signs its own digest:
+#   sha3-256(canonical JSON of {tx without sigs/pubkeys, "input_index": i, "prev": spent output, "sighash": type})
+# where "prev" is the output being spent ({"address", "amount"}), so a signature commits to the
+# value it unlocks. Pubkeys are left out so inputs can be signed in any order. The input's "sig" is hex(ed25519 signature (64 bytes) || sighash type byte).
+# A signature alone proves nothing about ownership: the input's keys must also derive the spent
+# output's address (key_address_matches). A key address is hash160(view pubkey || spend pubkey),
+# so an input spending one carries "viewkey" (hex view pubkey) next to "pubkey" (the spend key
+# that signs); legacy SMELLY_ addresses embed the spend key and need no viewkey.
+
+SIGHASH_ALL = 0x01
+SIGHASH_ANYONECANPAY = 0x80
+_SIGHASH_TYPES = (SIGHASH_ALL, SIGHASH_ALL | SIGHASH_ANYONECANPAY)
+
+
+def sighash_preimage(tx_obj: Dict[str, Any], input_index: int, prev_output: Dict[str, Any],
+                     sighash_type: int = SIGHASH_ALL) -> bytes:
+    if sighash_type not in _SIGHASH_TYPES:
+        raise ValueError(f"unsupported sighash type {sighash_type:#x}")
+    inputs = _strip_input_sigs(tx_obj.get("inputs") or [], ("sig", "sigs", "pubkey", "viewkey"))
+    if not (0 <= input_index < len(inputs)):
+        raise IndexError("input_index out of range")
+    body = {k: v for k, v in tx_obj.items() if k not in ("signatures", "inputs")}
+    # ANYONECANPAY: commit to this input only so others can be added later
+    body["inputs"] = [inputs[input_index]] if sighash_type & SIGHASH_ANYONECANPAY else inputs
+    body["input_index"] = int(input_index)
+    body["prev"] = {"address": prev_output.get("address"), "amount": float(prev_output.get("amount", 0.0))}
+    body["sighash"] = int(sighash_type)
+    return json.dumps(body, sort_keys=True, separators=(",", ":")).encode("utf-8")
+
+
+def sighash(tx_obj: Dict[str, Any], input_index: int, prev_output: Dict[str, Any],
+            sighash_type: int = SIGHASH_ALL) -> bytes:
+    return bytes.fromhex(sha3_256_hex(sighash_preimage(tx_obj, input_index, prev_output, sighash_type)))
+
+
+def sign_transaction_input(tx_obj: Dict[str, Any], input_index: int, prev_output: Dict[str, Any], sk: bytes,
+                           sighash_type: int = SIGHASH_ALL, view_pubkey: Optional[bytes] = None) -> Dict[str, str]:
+    """
+    Sign one input with an Ed25519 seed/secret key. Returns {"pubkey", "sig"} (plus "viewkey" when
+    view_pubkey is given) to merge into the input.
+    """
+    key = nacl.signing.SigningKey(sk)
+    digest = sighash(tx_obj, input_index, prev_output, sighash_type)
+    sig = key.sign(digest).signature + bytes([sighash_type])
+    out = {"pubkey": key.verify_key.encode().hex(), "sig": sig.hex()}
+    if view_pubkey is not None:
+        out["viewkey"] = view_pubkey.hex()
+    return out
+
+
+def key_address_matches(address: str, pubkey_hex: str, viewkey_hex: str = "") -> bool:
+    """Whether the wallet derivation of (view, spend) pubkeys is address (legacy: its embedded spend key)."""
+    try:
+        spend = bytes.fromhex(pubkey_hex or "")
+        if len(spend) != 32:
+            return False
+        if (address or "").startswith(ADDRESS_PREFIX):
+            body = bytes.fromhex(address[len(ADDRESS_PREFIX):])
+            return len(body) == 32 + 32 + 4 and body[32:64] == spend
+        view = bytes.fromhex(viewkey_hex or "")
+        if len(view) != 32:
+            return False
+        _, version, h160 = decode_address(address)
+        return version == ADDRESS_VERSION and hmac.compare_digest(h160, hash160(view + spend))
+    except (ValueError, TypeError):
+        return False
+
+
+def verify_input_signature(tx_obj: Dict[str, Any], input_index: int, prev_output: Dict[str, Any],
+                           pubkey_hex: str, sig_hex: str) -> bool:
+    """One signature (hex sig || sighash byte) by pubkey over input_index's sighash."""
+    try:
+        sig = bytes.fromhex(sig_hex or "")
+        if len(sig) != 65:
+            return False
+        digest = sighash(tx_obj, input_index, prev_output, sig[64])
+        return ed25519_verify_hex(pubkey_hex or "", digest, sig[:64].hex())
+    except (IndexError, ValueError, AttributeError, TypeError):
+        return False
+
+
+def verify_transaction_input(tx_obj: Dict[str, Any], input_index: int, prev_output: Dict[str, Any]) -> bool:
+    """
+    Check an input against the sighash for the spent output: sig/pubkey (deriving the spent
+    address, with viewkey) for key addresses, redeem + sigs (m-of-n) when the spent output pays
+    to a script address.
+    """
+    try:
+        inp = (tx_obj.get("inputs") or [])[input_index]
+        address = str(prev_output.get("address") or "")
+        if is_script_address(address):
+            return verify_multisig_input(tx_obj, input_index, prev_output)
+        if not key_address_matches(address, inp.get("pubkey") or "", inp.get("viewkey") or ""):
+            return False
+        return verify_input_signature(tx_obj, input_index, prev_output, inp.get("pubkey") or "", inp.get("sig") or "")
+    except (IndexError, AttributeError, TypeError):
+        return False
+
+
+# Pay-to-script-hash multisig. The redeem script is a typed descriptor rather than opcodes:
+#   {"type": "multisig", "m": m, "pubkeys": [hex ed25519 pubkey, ...]}   1 <= m <= n <= MAX_MULTISIG_KEYS
+# Its address is SCRIPT_ADDRESS_VERSION || hash160(canonical JSON). An input spending such an output
+# carries "redeem" (committed to by txid and sighash) and "sigs": m signatures over its sighash in
+# the same order as their pubkeys appear in the redeem script, like OP_CHECKMULTISIG.
+
+MAX_MULTISIG_KEYS = 15
+
+# Data (OP_RETURN-style) outputs: {"data": hex payload} with no address and zero (or omitted)
+# amount. They are provably unspendable, never enter the UTXO set, and cannot be outputs[0] (the
+# output a tx materializes). Size and count limits are relay policy (core.txrelay), not consensus.
+
+MAX_DATA_OUTPUT_BYTES = 10_000
+
+
+def data_output_bytes(output: Any) -> Optional[bytes]:
+    """Payload of a data output, None for a value output; raises ValueError if malformed."""
+    if not isinstance(output, dict) or "data" not in output:
+        return None
+    try:
+        amount = float(output.get("amount") or 0.0)
+    except (TypeError, OverflowError):
+        raise ValueError("data output amount must be a number")
+    if output.get("address") or amount != 0.0:
+        raise ValueError("data outputs carry no address or value")
+    payload = bytes.fromhex(str(output["data"]))
+    if len(payload) > MAX_DATA_OUTPUT_BYTES:
+        raise ValueError("data output too large")
+    return payload
+
+
+def multisig_redeem(m: int, pubkeys: Any) -> Dict[str, Any]:
+    """Validated redeem script for m-of-len(pubkeys); raises ValueError."""
+    if not isinstance(pubkeys, list) or not pubkeys:
+        raise ValueError("keys must be a non-empty list of hex public keys")
+    keys = [str(k).strip().lower() for k in pubkeys]
+    for k in keys:
+        try:
+            nacl.signing.VerifyKey(bytes.fromhex(k))
+        except Exception:
+            raise ValueError(f"invalid public key {k!r}")
+    if len(set(keys)) != len(keys):
+        raise ValueError("duplicate public key")
+    if len(keys) > MAX_MULTISIG_KEYS:
+        raise ValueError(f"at most {MAX_MULTISIG_KEYS} keys are supported")
+    try:
+        m = int(m)
+    except (TypeError, ValueError, OverflowError):
+        raise ValueError("nrequired must be an integer")
+    if not (1 <= m <= len(keys)):
+        raise ValueError(f"nrequired must be between 1 and {len(keys)}")
+    return {"type": "multisig", "m": m, "pubkeys": keys}
+
+
+def redeem_script_bytes(redeem: Dict[str, Any]) -> bytes:
+    return json.dumps(redeem, sort_keys=True, separators=(",", ":")).encode("utf-8")
+
+
+def script_address(redeem: Dict[str, Any], prefix: Optional[str] = None) -> str:
+    return address_from_hash160(hash160(redeem_script_bytes(redeem)), prefix, SCRIPT_ADDRESS_VERSION)
+
+
+def is_script_address(address: str) -> bool:
+    try:
+        return decode_address(address)[1] == SCRIPT_ADDRESS_VERSION
+    except ValueError:
+        return False
+
+
+def verify_multisig_input(tx_obj: Dict[str, Any], input_index: int, prev_output: Dict[str, Any]) -> bool:
+    try:
+        inp = (tx_obj.get("inputs") or [])[input_index]
+        redeem = multisig_redeem(inp["redeem"]["m"], inp["redeem"]["pubkeys"])
+        if redeem != inp["redeem"] or script_address(redeem) != prev_output.get("address"):
+            return False
+        sigs = inp.get("sigs") or []
+        if not isinstance(sigs, list) or len(sigs) != redeem["m"]:
+            return False
+        # Each signature must match a key after the previous match (CHECKMULTISIG ordering)
+        keys = iter(redeem["pubkeys"])
+        return all(any(verify_input_signature(tx_obj, input_index, prev_output, k, sig) for k in keys) for sig in sigs)
+    except (IndexError, KeyError, ValueError, AttributeError, TypeError):
+        return False
+
+
 def ed25519_verify_hex(pubkey_hex: str, msg: bytes, sig_hex: str) -> bool:
     """
     Verify an Ed25519 signature from hex-encoded pubkey and signature.
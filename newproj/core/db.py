@@ -44,6 +44,14 @@ class Peer(Base):
     address = Column(String(255), unique=True, nullable=False)
     last_seen_ms = Column(Integer, nullable=False, default=0)
     reputation = Column(Float, nullable=False, default=0.0)
+    # Address manager (core.addrman): "new" = heard about, "tried" = connected successfully at least once
+    tried = Column(Boolean, nullable=False, default=False)
+    source = Column(String(64), nullable=True)
+    attempts = Column(Integer, nullable=False, default=0)
+    last_try_ms = Column(Integer, nullable=False, default=0)
+    last_success_ms = Column(Integer, nullable=False, default=0)
+    failures = Column(Integer, nullable=False, default=0)  # lifetime failed connects (attempts resets on success)
+    services = Column(Integer, nullable=False, default=0)  # core.addrman NODE_* bits from the peer's VERSION
 
 
 class BlockHeader(Base):
@@ -114,6 +122,7 @@ class UTXO(Base):
     spent = Column(Boolean, nullable=False, default=False)
     spent_txid = Column(String(64), nullable=True)
     coinbase = Column(Boolean, nullable=False, default=False)
+    height = Column(Integer, nullable=True)  # origin block height (coinbase maturity); null on legacy rows
     __table_args__ = (
         UniqueConstraint("txid", "vout", name="uq_tx_vout"),
     )
@@ -186,6 +195,128 @@ class MempoolTx(Base):
     amount = Column(Float, nullable=True)
 
 
+# ===== Flat block files (cold tier) index =====
+class BlockFileIndex(Base):
+    __tablename__ = "block_file_index"
+    id = Column(Integer, primary_key=True, autoincrement=True)
+    hash_hex = Column(String(64), unique=True, nullable=False, index=True)
+    height = Column(Integer, nullable=False, index=True)
+    file_no = Column(Integer, nullable=False)
+    offset = Column(Integer, nullable=False)
+    length = Column(Integer, nullable=False)
+
+
+# ===== Compact block filters (BIP158-style, see core.blockfilter) =====
+class BlockFilter(Base):
+    __tablename__ = "block_filters"
+    id = Column(Integer, primary_key=True, autoincrement=True)
+    hash_hex = Column(String(64), unique=True, nullable=False, index=True)
+    height = Column(Integer, nullable=False, index=True)
+    filter_hex = Column(Text, nullable=False)
+    filter_hash = Column(String(64), nullable=False)
+    header = Column(String(64), nullable=False)
+
+
+# ===== Transaction index (optional, see core.txindex) =====
+class TxIndex(Base):
+    __tablename__ = "tx_index"
+    id = Column(Integer, primary_key=True, autoincrement=True)
+    txid = Column(String(64), unique=True, nullable=False, index=True)
+    block_hash = Column(String(64), nullable=False, index=True)
+    height = Column(Integer, nullable=False, index=True)
+    position = Column(Integer, nullable=False)  # index in the block's merkle ordering (0 = coinbase)
+
+
+# ===== Address balances (optional, see core.addrindex) =====
+class AddressBalance(Base):
+    __tablename__ = "address_balances"
+    id = Column(Integer, primary_key=True, autoincrement=True)
+    address = Column(String(255), unique=True, nullable=False, index=True)
+    balance_sats = Column(Integer, nullable=False, default=0, index=True)
+    utxo_count = Column(Integer, nullable=False, default=0)
+    last_height = Column(Integer, nullable=True)  # last block that changed the balance
+
+
+# ===== Pool (Stratum) persistent accounting =====
+class PoolMiner(Base):
+    __tablename__ = "pool_miners"
+    id = Column(Integer, primary_key=True, autoincrement=True)
+    address = Column(String(255), unique=True, nullable=False, index=True)
+    accepted_shares = Column(Integer, nullable=False, default=0)
+    rejected_shares = Column(Integer, nullable=False, default=0)
+    blocks_found = Column(Integer, nullable=False, default=0)
+    pending_balance = Column(Float, nullable=False, default=0.0)  # matured (spendable), not yet paid out
+    immature_balance = Column(Float, nullable=False, default=0.0)  # from blocks younger than coinbase maturity
+    paid_total = Column(Float, nullable=False, default=0.0)
+    last_submit_ms = Column(Integer, nullable=False, default=0)
+    created_ms = Column(Integer, nullable=False, default=0)
+    auth_hash = Column(String(255), nullable=True)  # pbkdf2 "salt$hash" for pool.auth_mode=db
+
+
+class PoolShare(Base):
+    """Append-only share log (verdicts kept pool.share_log_retention_days for disputes)."""
+    __tablename__ = "pool_shares"
+    id = Column(Integer, primary_key=True, autoincrement=True)
+    address = Column(String(255), nullable=False, index=True)
+    worker = Column(String(64), nullable=False, default="")
+    job_id = Column(String(64), nullable=False)
+    nonce = Column(Integer, nullable=False)  # u64 header nonce stored as signed int64 (see nonce_to_db)
+    share_diff = Column(Integer, nullable=False, default=1)
+    hash_hex = Column(String(64), nullable=True)  # computed PoW hash; NULL if rejected before hashing
+    target_hex = Column(String(64), nullable=True)  # share target the hash was checked against
+    accepted = Column(Boolean, nullable=False, default=True)
+    reason = Column(String(64), nullable=True)
+    created_ms = Column(Integer, nullable=False, index=True)
+
+
+def nonce_to_db(nonce: int) -> int:
+    """A u64 nonce as the signed 64-bit value SQLite INTEGER columns hold (two's complement)."""
+    nonce = int(nonce)
+    if not 0 <= nonce < 1 << 64:
+        raise ValueError("nonce out of u64 range")
+    return nonce - (1 << 64) if nonce >= 1 << 63 else nonce
+
+
+def nonce_from_db(value: int) -> int:
+    return int(value) & ((1 << 64) - 1)
+
+
+class PoolBlock(Base):
+    __tablename__ = "pool_blocks"
+    id = Column(Integer, primary_key=True, autoincrement=True)
+    height = Column(Integer, nullable=False, index=True)
+    hash_hex = Column(String(64), unique=True, nullable=False)
+    finder_address = Column(String(255), nullable=False, index=True)
+    reward = Column(Float, nullable=False, default=0.0)
+    status = Column(String(32), nullable=False, default="found")  # immature | mature | orphaned (legacy: found)
+    created_ms = Column(Integer, nullable=False)
+    confirmations = Column(Integer, nullable=False, default=0)  # 0 while orphaned
+    status_ms = Column(Integer, nullable=False, default=0)  # last status change
+
+
+class PoolBlockEvent(Base):
+    """Status transitions of pool-found blocks (apps.pool StratumPool._track_blocks)."""
+    __tablename__ = "pool_block_events"
+    id = Column(Integer, primary_key=True, autoincrement=True)
+    hash_hex = Column(String(64), nullable=False, index=True)
+    height = Column(Integer, nullable=False)
+    from_status = Column(String(32), nullable=False)
+    to_status = Column(String(32), nullable=False)
+    confirmations = Column(Integer, nullable=False, default=0)
+    tip_height = Column(Integer, nullable=False)
+    created_ms = Column(Integer, nullable=False)
+
+
+class PoolPayout(Base):
+    __tablename__ = "pool_payouts"
+    id = Column(Integer, primary_key=True, autoincrement=True)
+    address = Column(String(255), nullable=False, index=True)
+    amount = Column(Float, nullable=False)
+    txid = Column(String(64), nullable=True)
+    status = Column(String(32), nullable=False, default="pending")
+    created_ms = Column(Integer, nullable=False)
+
+
 # ===== Engine/Session utilities =====
 
 @dataclass
@@ -201,13 +332,26 @@ def _build_engine(db_cfg: DBConfig):
         # - check_same_thread=False: allow cross-thread usage
         # - timeout: busy timeout for writer lock contention
         # - WAL journal mode and reasonable synchronous for less writer blocking
+        # - cached_statements: per-connection prepared statement cache (sqlite3 module)
+        # - pool_size: pooled connections; WAL lets readers proceed while one writer holds the lock
         url = f"sqlite:///{db_cfg.sqlite_path}"
+        cfg = get_config()
+        memory = db_cfg.sqlite_path == ":memory:"
+        pool_args = {} if memory else {
+            "pool_size": int(cfg.get("database.pool_size", 8)),
+            "max_overflow": int(cfg.get("database.pool_max_overflow", 8)),
+        }
         engine = create_engine(
             url,
-            connect_args={"check_same_thread": False, "timeout": 10.0},
-            poolclass=StaticPool if db_cfg.sqlite_path == ":memory:" else None,
+            connect_args={
+                "check_same_thread": False,
+                "timeout": 10.0,
+                "cached_statements": int(cfg.get("database.statement_cache_size", 256)),
+            },
+            poolclass=StaticPool if memory else None,
             echo=False,
             future=True,
+            **pool_args,
         )
         # Apply WAL+journal tuning (no-op on some platforms)
         try:
@@ -226,6 +370,14 @@ def _build_engine(db_cfg: DBConfig):
         raise ValueError(f"Unsupported DB driver: {db_cfg.driver}")
 
 
+def _prepare_sqlite_dir(sqlite_path: str):
+    """Create the directory holding the SQLite file and fail early if it is not writable."""
+    parent = os.path.dirname(os.path.abspath(sqlite_path))
+    os.makedirs(parent, exist_ok=True)
+    if not os.access(parent, os.W_OK):
+        raise RuntimeError(f"database directory is not writable: {parent}")
+
+
 class DB:
     def __init__(self):
         ensure_dirs()
@@ -233,6 +385,12 @@ class DB:
         driver = cfg.get("database.driver", "sqlite")
         sqlite_path = cfg.get("database.sqlite_path", "data/smelly.db")
         postgres_dsn = cfg.get("database.postgres_dsn", None)
+        # storage.backend: sqlite (persistent, default; database.driver may still pick postgres)
+        # or memory (throwaway chain for tests/regtest, nothing survives a restart)
+        if str(cfg.get("storage.backend", "sqlite")) == "memory":
+            driver, sqlite_path = "sqlite", ":memory:"
+        elif driver == "sqlite":
+            _prepare_sqlite_dir(sqlite_path)
         self.db_cfg = DBConfig(driver=driver, sqlite_path=sqlite_path, postgres_dsn=postgres_dsn)
         self.engine = _build_engine(self.db_cfg)
         self._SessionLocal = sessionmaker(bind=self.engine, autoflush=False, autocommit=False, class_=Session)
@@ -273,6 +431,70 @@ class DB:
             except Exception:
                 pass
 
+            # UTXO origin height; backfill coinbase outputs from the rewards table
+            try:
+                utxo_cols = {row[1] for row in conn.exec_driver_sql("PRAGMA table_info(utxos)").fetchall()}
+                if "height" not in utxo_cols:
+                    conn.exec_driver_sql("ALTER TABLE utxos ADD COLUMN height INTEGER")
+                    conn.exec_driver_sql(
+                        "UPDATE utxos SET height = (SELECT MAX(r.height) FROM rewards r WHERE r.txid = utxos.txid) "
+                        "WHERE coinbase = 1 AND height IS NULL"
+                    )
+            except Exception:
+                pass
+
+            # Pool immature balance column
+            try:
+                pool_cols = {row[1] for row in conn.exec_driver_sql("PRAGMA table_info(pool_miners)").fetchall()}
+                if "immature_balance" not in pool_cols:
+                    conn.exec_driver_sql("ALTER TABLE pool_miners ADD COLUMN immature_balance FLOAT NOT NULL DEFAULT 0")
+                if "auth_hash" not in pool_cols:
+                    conn.exec_driver_sql("ALTER TABLE pool_miners ADD COLUMN auth_hash VARCHAR(255)")
+            except Exception:
+                pass
+
+            # Share log proof columns
+            try:
+                share_cols = {row[1] for row in conn.exec_driver_sql("PRAGMA table_info(pool_shares)").fetchall()}
+                if "worker" not in share_cols:
+                    conn.exec_driver_sql("ALTER TABLE pool_shares ADD COLUMN worker VARCHAR(64) NOT NULL DEFAULT ''")
+                if "hash_hex" not in share_cols:
+                    conn.exec_driver_sql("ALTER TABLE pool_shares ADD COLUMN hash_hex VARCHAR(64)")
+                if "target_hex" not in share_cols:
+                    conn.exec_driver_sql("ALTER TABLE pool_shares ADD COLUMN target_hex VARCHAR(64)")
+            except Exception:
+                pass
+
+            # Pool block confirmation tracking columns
+            try:
+                block_cols = {row[1] for row in conn.exec_driver_sql("PRAGMA table_info(pool_blocks)").fetchall()}
+                if "confirmations" not in block_cols:
+                    conn.exec_driver_sql("ALTER TABLE pool_blocks ADD COLUMN confirmations INTEGER NOT NULL DEFAULT 0")
+                if "status_ms" not in block_cols:
+                    conn.exec_driver_sql("ALTER TABLE pool_blocks ADD COLUMN status_ms INTEGER NOT NULL DEFAULT 0")
+            except Exception:
+                pass
+
+            # Peer address-manager columns
+            try:
+                peer_cols = {row[1] for row in conn.exec_driver_sql("PRAGMA table_info(peers)").fetchall()}
+                if "tried" not in peer_cols:
+                    conn.exec_driver_sql("ALTER TABLE peers ADD COLUMN tried BOOLEAN NOT NULL DEFAULT 0")
+                if "source" not in peer_cols:
+                    conn.exec_driver_sql("ALTER TABLE peers ADD COLUMN source VARCHAR(64)")
+                if "attempts" not in peer_cols:
+                    conn.exec_driver_sql("ALTER TABLE peers ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0")
+                if "last_try_ms" not in peer_cols:
+                    conn.exec_driver_sql("ALTER TABLE peers ADD COLUMN last_try_ms INTEGER NOT NULL DEFAULT 0")
+                if "last_success_ms" not in peer_cols:
+                    conn.exec_driver_sql("ALTER TABLE peers ADD COLUMN last_success_ms INTEGER NOT NULL DEFAULT 0")
+                if "failures" not in peer_cols:
+                    conn.exec_driver_sql("ALTER TABLE peers ADD COLUMN failures INTEGER NOT NULL DEFAULT 0")
+                if "services" not in peer_cols:
+                    conn.exec_driver_sql("ALTER TABLE peers ADD COLUMN services INTEGER NOT NULL DEFAULT 0")
+            except Exception:
+                pass
+
             # Fairness tables exist check (SQLite dialect)
             try:
                 # Ensure indexes/uniques are present (CREATE IF NOT EXISTS semantics)
@@ -0,0 +1,98 @@
+from __future__ import annotations
+
+import hashlib
+import os
+from dataclasses import dataclass
+from typing import Dict, List, Optional
+
+from core.config import get_config
+
+
+# Eclipse-attack mitigation for the P2P layer (apps.node.main owns the sockets and timers; the
+# policy lives here so it can be reasoned about on its own):
+#
+#   outbound diversity  at most one outbound peer per network group (core.netlocal.netgroup:
+#                       IPv4 /16, IPv6 /32), so an attacker needs addresses in many networks
+#                       to fill our outbound slots. Local addresses have no group.
+#   feelers             every network.feeler_interval_sec one short-lived connection to an
+#                       address from the new bucket; a completed connect moves it to tried, so
+#                       tried fills with verified addresses instead of only what gossip says.
+#   inbound eviction    at network.max_inbound_connections a new inbound connection evicts an
+#                       existing one, chosen by inbound_victim(). Protected first: the peers of
+#                       network.evict_protect_netgroups distinct groups (picked with a per-node
+#                       secret so an attacker cannot predict which), the
+#                       network.evict_protect_ping lowest-RTT peers, the
+#                       network.evict_protect_blocks peers that most recently delivered a block,
+#                       the network.evict_protect_txs peers that most recently relayed a tx we
+#                       admitted, then the longest-connected half of the rest. The victim is the newest
+#                       peer of the group with most remaining peers; if nobody is left
+#                       unprotected the new connection is refused instead.
+#   rotation            every network.outbound_rotate_sec (0 = off) with all outbound slots
+#                       full, rotation_victim() names one outbound peer to drop (the lowest
+#                       address score among peers connected at least that long) and outbound
+#                       maintenance refills the slot, so a quietly captured set of peers does
+#                       not stay put forever.
+
+_SECRET = os.urandom(16)
+
+
+@dataclass
+class Candidate:
+    addr: str
+    group: str
+    connected_ms: int
+    rtt_ms: Optional[float] = None
+    last_block_ms: int = 0
+    last_tx_ms: int = 0
+    score: float = 0.0
+
+
+def _keyed(group: str) -> bytes:
+    return hashlib.sha256(_SECRET + group.encode("utf-8")).digest()
+
+
+def _protect(pool: List[Candidate], n: int, key, reverse: bool = False) -> List[Candidate]:
+    """Remove up to n candidates ranked first by key from pool; returns the rest."""
+    if n <= 0 or not pool:
+        return pool
+    ranked = sorted(pool, key=key, reverse=reverse)
+    protected = {id(c) for c in ranked[:n]}
+    return [c for c in pool if id(c) not in protected]
+
+
+def _protect_recent(pool: List[Candidate], n: int, attr: str) -> List[Candidate]:
+    """Like _protect for the n most recent non-zero timestamps in attr (peers that never delivered are not protected)."""
+    ranked = sorted((c for c in pool if getattr(c, attr)), key=lambda c: getattr(c, attr), reverse=True)
+    protected = {id(c) for c in ranked[:max(0, n)]}
+    return [c for c in pool if id(c) not in protected]
+
+
+def inbound_victim(peers: List[Candidate]) -> Optional[str]:
+    cfg = get_config()
+    pool = list(peers)
+    # One peer from each of a few groups, chosen by keyed hash of the group
+    n_groups = int(cfg.get("network.evict_protect_netgroups", 4))
+    groups = sorted({c.group for c in pool if c.group}, key=_keyed)[:max(0, n_groups)]
+    for g in groups:
+        oldest = min((c for c in pool if c.group == g), key=lambda c: c.connected_ms)
+        pool = [c for c in pool if c is not oldest]
+    pool = _protect(pool, int(cfg.get("network.evict_protect_ping", 8)),
+                    lambda c: c.rtt_ms if c.rtt_ms is not None else float("inf"))
+    pool = _protect_recent(pool, int(cfg.get("network.evict_protect_blocks", 4)), "last_block_ms")
+    pool = _protect_recent(pool, int(cfg.get("network.evict_protect_txs", 4)), "last_tx_ms")
+    pool = _protect(pool, len(pool) // 2, lambda c: c.connected_ms)
+    if not pool:
+        return None
+    by_group: Dict[str, List[Candidate]] = {}
+    for c in pool:
+        by_group.setdefault(c.group or c.addr, []).append(c)
+    biggest = max(by_group.values(), key=lambda cs: (len(cs), max(c.connected_ms for c in cs)))
+    return max(biggest, key=lambda c: c.connected_ms).addr
+
+
+def rotation_victim(outbound: List[Candidate], nowm: int) -> Optional[str]:
+    min_age_ms = int(get_config().get("network.outbound_rotate_sec", 0)) * 1000
+    old = [c for c in outbound if nowm - c.connected_ms >= min_age_ms]
+    if not old:
+        return None
+    return min(old, key=lambda c: (c.score, c.connected_ms)).addr
@@ -0,0 +1,128 @@
+from __future__ import annotations
+
+import os
+import threading
+from typing import Any, Dict, Optional
+
+from sqlalchemy import select
+
+from core.config import get_config
+from core.db import get_db, BlockHeader, BlockFileIndex, Transaction
+from core import logs
+
+
+# Header-only storage mode (storage.mode: header-only) for dedicated pool front-ends and mining
+# relays. Every block is still validated and connected, so the UTXO set, the mempool and the work
+# templates served to get_work / get_block_template / Stratum stay exact; what goes is history.
+# Block bodies are kept for the last storage.header_only_keep_blocks blocks only:
+#   flat files      a blkNNNNN.dat is deleted once every block in it is below the window (the
+#                   file being appended to is never deleted)
+#   transactions    confirmed transaction rows (the raw bodies) below the window
+# Headers, UTXOs, rewards, block filters and tx index entries are small and stay, so lookups can
+# tell a pruned body (BODY_PRUNED, HTTP 410) from an unknown one. RPCs that need an old body
+# (getrawtransaction, getblockstats, txproof, REST /block) refuse with BODY_PRUNED, reindexing
+# is refused, and GETBLOCKS windows that reach below the window are answered empty so syncing
+# peers fetch them elsewhere. Reorgs deeper than the window cannot be followed; keep it well above
+# any expected reorg depth. A fresh header-only node can skip downloading history entirely by
+# loading a UTXO snapshot (loadtxoutset, core.snapshot) before it syncs.
+
+MODE_FULL = "full"
+MODE_HEADER_ONLY = "header-only"
+BODY_PRUNED = "block-body-pruned"
+
+_prune_lock = threading.Lock()
+_last_pruned_tip = -1
+log = logs.get("storage")
+
+
+def mode() -> str:
+    return str(get_config().get("storage.mode", MODE_FULL))
+
+
+def enabled() -> bool:
+    return mode() == MODE_HEADER_ONLY
+
+
+def keep_blocks() -> int:
+    return max(1, int(get_config().get("storage.header_only_keep_blocks", 288)))
+
+
+def body_floor(s) -> int:
+    """Lowest height whose body is kept (0 when not header-only)."""
+    if not enabled():
+        return 0
+    tip = s.query(BlockHeader.height).order_by(BlockHeader.height.desc()).first()
+    return max(0, (tip[0] if tip else -1) - keep_blocks() + 1)
+
+
+def is_pruned(s, height: Optional[int]) -> bool:
+    return height is not None and enabled() and height < body_floor(s)
+
+
+def pruned_error(height: int, floor: int) -> Dict[str, Any]:
+    return {"error": BODY_PRUNED, "height": height, "pruneheight": floor}
+
+
+def prune() -> Dict[str, int]:
+    """Drop block bodies below the window. Returns counts of removed files and tx rows."""
+    from core import blockstore
+
+    out = {"files": 0, "transactions": 0}
+    if not enabled():
+        return out
+    db = get_db()
+    with _prune_lock, db.session() as s:
+        floor = body_floor(s)
+        if floor <= 0:
+            return out
+        below = select(BlockHeader.hash_hex).where(BlockHeader.height < floor)
+        out["transactions"] = int(
+            s.query(Transaction).filter(Transaction.in_block_hash.in_(below))
+            .delete(synchronize_session=False) or 0
+        )
+        last_file = s.query(BlockFileIndex.file_no).order_by(BlockFileIndex.file_no.desc()).first()
+        for file_no, _, _ in list(blockstore.iter_files()):
+            if last_file is None or file_no >= last_file[0]:
+                continue
+            kept = s.query(BlockFileIndex.id).filter(BlockFileIndex.file_no == file_no,
+                                                     BlockFileIndex.height >= floor).first()
+            if kept is not None:
+                continue
+            s.query(BlockFileIndex).filter(BlockFileIndex.file_no == file_no).delete(synchronize_session=False)
+            try:
+                os.remove(blockstore.file_path(file_no))
+            except FileNotFoundError:
+                pass
+            out["files"] += 1
+        s.commit()
+    if out["files"] or out["transactions"]:
+        log.info("header-only: pruned block bodies",
+                 extra=logs.fields(pruneheight=floor, files=out["files"], transactions=out["transactions"]))
+    return out
+
+
+def prune_best_effort():
+    """Prune once per new tip; called from the node's periodic loop."""
+    global _last_pruned_tip
+    if not enabled():
+        return
+    try:
+        db = get_db()
+        with db.session() as s:
+            tip = s.query(BlockHeader.height).order_by(BlockHeader.height.desc()).first()
+        height = tip[0] if tip else -1
+        if height == _last_pruned_tip:
+            return
+        prune()
+        _last_pruned_tip = height
+    except Exception as e:
+        log.error(f"header-only: prune failed: {e}")
+
+
+def info(s) -> Dict[str, Any]:
+    """getblockchaininfo fields."""
+    out: Dict[str, Any] = {"storage_mode": mode(), "pruned": enabled()}
+    if enabled():
+        out["pruneheight"] = body_floor(s)
+        out["keep_blocks"] = keep_blocks()
+    return out
@@ -0,0 +1,185 @@
+from __future__ import annotations
+
+import json
+from typing import Any, Dict, List, Optional, Tuple
+
+from core.db import get_db, BlockHeader, BlockFilter, KV, MempoolTx, Reward, Transaction, UTXO
+from core.utils import now_ms
+from core import addrindex, txindex, utxohash
+
+
+# Manual chain management (RPC invalidateblock / reconsiderblock).
+#
+# The node keeps a single header chain and only ever extends its tip, so "reorg to the best
+# valid chain" means disconnecting the invalidated block and everything above it; peers then
+# extend the remaining prefix. Disconnecting a block undoes what accept_external_header did:
+#   - outputs it created (recipient outputs, change outputs keyed by the block hash, coinbase)
+#     are deleted and outputs it spent (spent_txid = block hash) are unspent,
+#   - its transactions go back to the mempool, its Reward row and block filter are removed.
+# Fairness epoch accruals already settled are not reversed. Flat-file records are kept, so
+# reconsiderblock can reconnect the same blocks if the tip has not moved on since.
+#
+# The invalid set is persisted in KV (invalid_blocks_json) and consulted by consensus before a
+# header is connected, so an invalidated block is refused from peers and miners until reconsidered.
+
+_KV_KEY = "invalid_blocks_json"
+
+
+def _load(s) -> Dict[str, Dict[str, Any]]:
+    row = s.get(KV, _KV_KEY)
+    if row is None or not row.v:
+        return {}
+    try:
+        data = json.loads(row.v)
+        return data if isinstance(data, dict) else {}
+    except ValueError:
+        return {}
+
+
+def _save(s, data: Dict[str, Dict[str, Any]]):
+    row = s.get(KV, _KV_KEY) or KV(k=_KV_KEY, v="")
+    row.v = json.dumps(data, separators=(",", ":"), sort_keys=True)
+    s.merge(row)
+
+
+def invalid_in_session(s, hash_hex: str) -> bool:
+    return hash_hex in _load(s)
+
+
+def list_invalid() -> Dict[str, Dict[str, Any]]:
+    db = get_db()
+    with db.session() as s:
+        return _load(s)
+
+
+def requeue_tx(s, t: Transaction):
+    """Put a disconnected tx back in the mempool (also used by core.reindex)."""
+    from core.txrelay import spent_address
+
+    if s.query(MempoolTx.id).filter_by(txid=t.txid).first() is not None:
+        return
+    from_addr = to_addr = None
+    amount = None
+    try:
+        tx = json.loads(t.raw or "")
+        if isinstance(tx, dict):
+            if tx.get("outputs"):
+                to_addr = tx["outputs"][0].get("address")
+                amount = float(tx["outputs"][0].get("amount", 0.0))
+            from_addr = spent_address(s, tx)
+    except (ValueError, AttributeError, TypeError):
+        pass  # legacy "from=..;to=..;amount=.." raws are parsed again when mined
+    s.add(MempoolTx(txid=t.txid, raw=t.raw or "", added_ms=now_ms(), fee=float(t.fee or 0.0),
+                    from_addr=from_addr, to_addr=to_addr, amount=amount))
+
+
+def disconnect_tip(s) -> Optional[BlockHeader]:
+    """Undo the tip block's effects within session s; returns the removed header row (also used by core.reindex)."""
+    tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+    if tip is None:
+        return None
+    hh = tip.hash_hex
+    txs = s.query(Transaction).filter(Transaction.in_block_hash == hh).all()
+    txids = [t.txid for t in txs]
+    rewards = s.query(Reward).filter_by(height=tip.height).all()
+    # Outputs about to be deleted/unspent, for the UTXO set hash (core.utxohash)
+    deleted = s.query(UTXO).filter(UTXO.txid.in_(txids), UTXO.vout == 0).all() if txids else []
+    deleted += s.query(UTXO).filter(UTXO.txid == hh).all()
+    deleted += [u for r in rewards for u in s.query(UTXO).filter(UTXO.txid == r.txid, UTXO.vout == 0, UTXO.coinbase.is_(True)).all()]
+    deleted_ids = {u.id for u in deleted}
+    restored = [u for u in s.query(UTXO).filter(UTXO.spent_txid == hh).all() if u.id not in deleted_ids]
+    utxohash.undo_block(s, tip, deleted, restored)
+    addrindex.undo_block(s, tip, deleted, restored)
+    if txids:
+        s.query(UTXO).filter(UTXO.txid.in_(txids), UTXO.vout == 0).delete(synchronize_session=False)
+    s.query(UTXO).filter(UTXO.txid == hh).delete(synchronize_session=False)  # change outputs
+    for u in restored:
+        u.spent = False
+        u.spent_txid = None
+    for r in rewards:
+        s.query(UTXO).filter(UTXO.txid == r.txid, UTXO.vout == 0, UTXO.coinbase.is_(True)).delete(synchronize_session=False)
+        s.delete(r)
+    for t in txs:
+        t.in_block_hash = None
+        requeue_tx(s, t)
+    s.query(BlockFilter).filter_by(hash_hex=hh).delete(synchronize_session=False)
+    txindex.remove_block(s, hh)
+    s.delete(tip)
+    s.flush()
+    return tip
+
+
+def invalidate_block(hash_hex: str) -> Tuple[Optional[Dict[str, Any]], Optional[str]]:
+    """
+    Mark hash_hex invalid and disconnect it with all descendants.
+    Returns ({"invalidated", "disconnected": [hashes, tip first], "tip"}, error).
+    """
+    hh = (hash_hex or "").strip().lower()
+    db = get_db()
+    with db.session() as s:
+        blk = s.query(BlockHeader).filter_by(hash_hex=hh).first()
+        invalid = _load(s)
+        if blk is None:
+            if hh in invalid:
+                return {"invalidated": hh, "disconnected": [], "tip": None}, None
+            return None, "block not found"
+        if blk.height == 0:
+            return None, "cannot invalidate genesis"
+        disconnected: List[str] = []
+        while True:
+            row = disconnect_tip(s)
+            if row is None:
+                break
+            disconnected.append(row.hash_hex)
+            if row.hash_hex == hh:
+                break
+        invalid[hh] = {"height": blk.height, "marked_ms": now_ms(), "disconnected": disconnected}
+        _save(s, invalid)
+        s.commit()
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        return {"invalidated": hh, "disconnected": disconnected, "tip": tip.hash_hex if tip else None}, None
+
+
+def reconsider_block(hash_hex: str) -> Tuple[Optional[Dict[str, Any]], Optional[str]]:
+    """
+    Clear the invalid flag and, if the tip is still the invalidated block's parent, reconnect the
+    blocks disconnected with it (from flat files, through normal consensus validation).
+    """
+    from core.blockstore import read_block
+    from core.consensus import accept_external_header
+
+    hh = (hash_hex or "").strip().lower()
+    db = get_db()
+    with db.session() as s:
+        invalid = _load(s)
+        entry = invalid.pop(hh, None)
+        if entry is None:
+            return None, "block is not marked invalid"
+        _save(s, invalid)
+        s.commit()
+
+    reconnected: List[str] = []
+    note = None
+    for bh in reversed(entry.get("disconnected") or []):
+        rec = read_block(bh)
+        if rec is None:
+            note = f"no stored copy of {bh}; it will be accepted again when relayed"
+            break
+        new_hash, err = accept_external_header(
+            prev_hash_hex=rec.prev_hash_hex,
+            merkle_root_hex=rec.merkle_root_hex,
+            version=rec.version,
+            timestamp=rec.timestamp,
+            target_hex=rec.target,
+            nonce=rec.nonce,
+            miner_address=rec.miner_address,
+            txids_snapshot=rec.txids,
+        )
+        if err:
+            note = f"stopped at {bh}: {err}"
+            break
+        reconnected.append(new_hash or bh)
+    out: Dict[str, Any] = {"reconsidered": hh, "reconnected": reconnected}
+    if note:
+        out["note"] = note
+    return out, None
@@ -0,0 +1,127 @@
+from __future__ import annotations
+
+from typing import Any, Dict, Optional, Tuple
+
+from core.config import get_config
+from core.db import get_db, BlockHeader, Transaction, Reward
+from core.merkle import merkle_branch, root_from_branch, verify_branch
+
+
+# Light-client payment proofs.
+#
+# A bundle proves "txid is committed in block B, which is linked by headers to a checkpoint the
+# client already trusts, and is buried under N more headers":
+#   {"txid", "index", "branch": [sibling hex, ...], "block_hash", "height",
+#    "headers": [<header fields>, ... from checkpoint to tip]}
+# verify_bundle recomputes every header hash, checks prev links, rebuilds the merkle root from the
+# branch (core.merkle, same tree as block validation) and optionally checks PoW.
+
+_HEADER_FIELDS = ("version", "prev_hash_hex", "merkle_root_hex", "timestamp", "target", "nonce", "miner_address", "tx_count")
+
+
+def _header_dict(row: BlockHeader) -> Dict[str, Any]:
+    return {
+        "height": row.height,
+        "hash": row.hash_hex,
+        "version": row.version,
+        "prev_hash_hex": row.prev_hash_hex,
+        "merkle_root_hex": row.merkle_root_hex,
+        "timestamp": row.timestamp,
+        "target": row.target,
+        "nonce": int(row.nonce),
+        "miner_address": row.miner_address,
+        "tx_count": row.tx_count,
+    }
+
+
+def build_bundle(txid: str, checkpoint_height: Optional[int] = None) -> Tuple[Optional[Dict[str, Any]], Optional[str]]:
+    """Returns (bundle, error)."""
+    from core.blockstore import read_block
+    from core.blockio import _block_txids
+
+    txid = (txid or "").strip().lower()
+    max_headers = int(get_config().get("light.max_proof_headers", 2016))
+    db = get_db()
+    with db.session() as s:
+        block: Optional[BlockHeader] = None
+        t = s.query(Transaction).filter_by(txid=txid).first()
+        if t is not None and t.in_block_hash:
+            block = s.query(BlockHeader).filter_by(hash_hex=t.in_block_hash).first()
+        else:
+            r = s.query(Reward).filter_by(txid=txid).first()
+            if r is not None:
+                block = s.query(BlockHeader).filter_by(height=r.height).first()
+        if block is None:
+            return None, "tx not confirmed"
+
+        rec = read_block(block.hash_hex)
+        txids = rec.txids if rec else _block_txids(s, block)
+        if txid not in txids:
+            return None, "tx not found in stored merkle ordering"
+        index = txids.index(txid)
+        branch = merkle_branch(txids, index)
+        if root_from_branch(txid, branch, index) != block.merkle_root_hex.lower():
+            return None, "stored txid ordering does not reproduce the header merkle root"
+
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        start = block.height if checkpoint_height is None else int(checkpoint_height)
+        if start > block.height:
+            return None, "checkpoint is above the block containing the tx"
+        end = min(tip.height, start + max_headers - 1)
+        if end < block.height:
+            return None, f"checkpoint too far below block (max {max_headers} headers)"
+        rows = (
+            s.query(BlockHeader)
+            .filter(BlockHeader.height >= start, BlockHeader.height <= end)
+            .order_by(BlockHeader.height.asc())
+            .all()
+        )
+        return {
+            "txid": txid,
+            "index": index,
+            "branch": branch,
+            "block_hash": block.hash_hex,
+            "height": block.height,
+            "tip_height": tip.height,
+            "headers": [_header_dict(r) for r in rows],
+        }, None
+
+
+def verify_bundle(bundle: Dict[str, Any], checkpoint_hash: str, check_pow: bool = False) -> Tuple[bool, str, int]:
+    """
+    Verify a proof bundle against a trusted checkpoint hash (first header of the segment).
+    Returns (ok, reason, confirmations_within_segment).
+    """
+    from core.consensus import Header
+
+    headers = bundle.get("headers") or []
+    if not headers:
+        return False, "empty header chain", 0
+    prev_hash = None
+    block_pos = None
+    for pos, h in enumerate(headers):
+        hdr = Header(**{k: h[k] for k in _HEADER_FIELDS})
+        hh = hdr.hash_hex()
+        if hh != h.get("hash"):
+            return False, f"header {pos} hash mismatch", 0
+        if pos == 0 and hh != (checkpoint_hash or "").lower():
+            return False, "segment does not start at the trusted checkpoint", 0
+        if prev_hash is not None and hdr.prev_hash_hex != prev_hash:
+            return False, f"header {pos} does not link to previous", 0
+        if check_pow and pos > 0:
+            from core.pow.pow_backend import pow_hash
+            if int(pow_hash(hdr.serialize(), hdr.nonce, hdr.prev_hash_hex).hex(), 16) > int(hdr.target, 16):
+                return False, f"header {pos} fails PoW", 0
+        if hh == bundle.get("block_hash"):
+            block_pos = pos
+            merkle = hdr.merkle_root_hex
+            tx_count = hdr.tx_count
+        prev_hash = hh
+    if block_pos is None:
+        return False, "block not in header segment", 0
+    # An index past the last tx can still hash to the root through the odd-level duplicate
+    if int(bundle.get("index", 0)) >= tx_count:
+        return False, "tx index beyond block tx count", 0
+    if not verify_branch(str(bundle.get("txid")), list(bundle.get("branch") or []), int(bundle.get("index", 0)), merkle):
+        return False, "merkle branch does not match header", 0
+    return True, "ok", len(headers) - block_pos
@@ -0,0 +1,196 @@
+from __future__ import annotations
+
+import contextvars
+import itertools
+import json
+import logging
+import os
+import sys
+import threading
+import time
+from contextlib import contextmanager
+from typing import Any, Dict, Iterator
+
+from core.config import get_config
+
+
+# Structured logging with per-subsystem targets.
+#
+# Each subsystem logs to its own target (logger "smelly.<target>"):
+#   node       process lifecycle, config
+#   net        P2P connections, address manager, bans, port mapping
+#   sync       block download and relay
+#   consensus  block acceptance, safe mode
+#   mempool    tx admission and relay
+#   storage    block files, indexes (txindex, block filters, UTXO hash), snapshots
+#   rpc        JSON-RPC requests
+#   stratum    pool sessions, jobs, share validation
+#   pool       pool accounting, found blocks, payouts
+#   wallet     named wallet files (load, create, startup)
+# logging.level is the default level; logging.targets overrides it per target
+# ({net: DEBUG, stratum: WARNING}). setloglevel (RPC) changes either at runtime, not persisted.
+#
+# Output goes to stderr and, if logging.file is set, to that file. logging.format:
+#   text   "[NET] 2024-05-01 12:00:00 INFO message key=value ..." (level-coloured on a terminal)
+#   json   one object per line: ts, level, target, msg, then span and event fields
+# Event fields: log.info("peer connected", extra=logs.fields(peer=addr)).
+# Spans: `with logs.span(log, "share", addr=a, job=j):` tags every record logged inside (on this
+# thread / async task) with the span's fields and a span id, logs the span's end with duration_ms
+# at DEBUG, and logs an escaping exception at WARNING before re-raising it.
+
+TARGETS = ("node", "net", "sync", "consensus", "mempool", "storage", "rpc", "stratum", "pool", "wallet")
+ROOT = "smelly"
+_LEVELS = ("DEBUG", "INFO", "WARNING", "ERROR", "CRITICAL")
+
+_span_ctx: contextvars.ContextVar[Dict[str, Any]] = contextvars.ContextVar("smelly_log_span", default={})
+_span_ids = itertools.count(1)
+_setup_lock = threading.Lock()
+_handlers: list = []
+
+
+def get(target: str) -> logging.Logger:
+    if not _handlers:
+        setup()  # entry points call setup() again once CLI overrides are in
+    return logging.getLogger(f"{ROOT}.{target}")
+
+
+def fields(**kw: Any) -> Dict[str, Any]:
+    """`extra=` argument carrying structured event fields."""
+    return {"fields": kw}
+
+
+class _Color:
+    RESET = "\x1b[0m"
+    RED = "\x1b[31m"
+    YEL = "\x1b[33m"
+    CYA = "\x1b[36m"
+    DIM = "\x1b[2m"
+
+
+def _record_fields(record: logging.LogRecord) -> Dict[str, Any]:
+    out = dict(getattr(record, "span_fields", None) or {})
+    out.update(getattr(record, "fields", None) or {})
+    return out
+
+
+def _target(record: logging.LogRecord) -> str:
+    return record.name[len(ROOT) + 1:] if record.name.startswith(ROOT + ".") else record.name
+
+
+class TextFormatter(logging.Formatter):
+    def __init__(self, color: bool):
+        super().__init__()
+        self.color = color
+
+    def format(self, record: logging.LogRecord) -> str:
+        ts = time.strftime("%Y-%m-%d %H:%M:%S", time.localtime(record.created))
+        tag = f"[{_target(record).upper()}]"
+        if self.color:
+            lv = record.levelno
+            c = _Color.RED if lv >= logging.ERROR else _Color.YEL if lv >= logging.WARNING else _Color.CYA if lv >= logging.INFO else _Color.DIM
+            tag = f"{c}{tag}{_Color.RESET}"
+        line = f"{tag} {ts} {record.levelname} {record.getMessage()}"
+        kv = _record_fields(record)
+        if kv:
+            line += " " + " ".join(f"{k}={v}" for k, v in kv.items())
+        if record.exc_info:
+            line += "\n" + self.formatException(record.exc_info)
+        return line
+
+
+class JsonFormatter(logging.Formatter):
+    def format(self, record: logging.LogRecord) -> str:
+        obj: Dict[str, Any] = {
+            "ts": time.strftime("%Y-%m-%dT%H:%M:%S", time.gmtime(record.created)) + f".{int(record.msecs):03d}Z",
+            "level": record.levelname,
+            "target": _target(record),
+            "msg": record.getMessage(),
+        }
+        for k, v in _record_fields(record).items():
+            obj.setdefault(k, v)
+        if record.exc_info:
+            obj["exc"] = self.formatException(record.exc_info)
+        return json.dumps(obj, default=str, separators=(",", ":"))
+
+
+class _SpanFilter(logging.Filter):
+    def filter(self, record: logging.LogRecord) -> bool:
+        if not hasattr(record, "span_fields"):
+            record.span_fields = _span_ctx.get()
+        return True
+
+
+def setup() -> None:
+    """(Re)install handlers and levels from the current config. Call once config overrides are final."""
+    cfg = get_config()
+    fmt = str(cfg.get("logging.format", "text")).lower()
+    path = str(cfg.get("logging.file", "") or "")
+    with _setup_lock:
+        root = logging.getLogger(ROOT)
+        for h in _handlers:
+            root.removeHandler(h)
+            h.close()
+        _handlers.clear()
+        console = logging.StreamHandler(sys.stderr)
+        _handlers.append(console)
+        console.setFormatter(JsonFormatter() if fmt == "json" else TextFormatter(color=sys.stderr.isatty()))
+        if path:
+            try:
+                os.makedirs(os.path.dirname(path) or ".", exist_ok=True)
+                fh = logging.FileHandler(path, encoding="utf-8")
+                fh.setFormatter(JsonFormatter() if fmt == "json" else TextFormatter(color=False))
+                _handlers.append(fh)
+            except OSError as e:
+                print(f"log file {path} unavailable: {e}", file=sys.stderr)
+        for h in _handlers:
+            h.addFilter(_SpanFilter())
+            root.addHandler(h)
+        root.propagate = False
+        root.setLevel(_level_no(cfg.get("logging.level", "INFO")))
+        overrides = cfg.get("logging.targets", {}) or {}
+        for t in TARGETS:
+            logging.getLogger(f"{ROOT}.{t}").setLevel(_level_no(overrides[t]) if t in overrides else logging.NOTSET)
+
+
+def _level_no(level: Any) -> int:
+    name = str(level).upper()
+    if name not in _LEVELS:
+        raise ValueError(f"unknown log level {level!r} (one of {', '.join(_LEVELS)})")
+    return getattr(logging, name)
+
+
+def set_level(target: str, level: str) -> Dict[str, str]:
+    """Runtime level change; target "all" sets the default and drops per-target overrides."""
+    lv = _level_no(level)
+    if target == "all":
+        logging.getLogger(ROOT).setLevel(lv)
+        for t in TARGETS:
+            logging.getLogger(f"{ROOT}.{t}").setLevel(logging.NOTSET)
+    elif target in TARGETS:
+        logging.getLogger(f"{ROOT}.{target}").setLevel(lv)
+    else:
+        raise ValueError(f"unknown log target {target!r} (one of all, {', '.join(TARGETS)})")
+    return levels()
+
+
+def levels() -> Dict[str, str]:
+    """Effective level per target."""
+    return {t: logging.getLevelName(logging.getLogger(f"{ROOT}.{t}").getEffectiveLevel()) for t in TARGETS}
+
+
+@contextmanager
+def span(log: logging.Logger, name: str, **kw: Any) -> Iterator[Dict[str, Any]]:
+    ctx = dict(_span_ctx.get(), **kw)
+    ctx["span"] = f"{name}#{next(_span_ids)}"
+    token = _span_ctx.set(ctx)
+    t0 = time.perf_counter()
+    try:
+        yield ctx
+    except Exception as e:
+        log.warning(f"{name} failed: {e}", extra=fields(duration_ms=round((time.perf_counter() - t0) * 1000, 2)))
+        raise
+    else:
+        if log.isEnabledFor(logging.DEBUG):
+            log.debug(f"{name} done", extra=fields(duration_ms=round((time.perf_counter() - t0) * 1000, 2)))
+    finally:
+        _span_ctx.reset(token)
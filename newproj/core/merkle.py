@@ -0,0 +1,80 @@
+from __future__ import annotations
+
+from typing import List, Sequence, Tuple
+
+from core.utils import sha3_256_hex
+
+
+# Transaction merkle tree shared by block validation, block templates / mining jobs and
+# light-client proofs (core.lightproof).
+#
+# Leaves are 32-byte txids (hex, consensus ordering from get_txids_for_merkle). Each level
+# hashes sha3-256(left || right); an odd level duplicates its last node. A single txid is its
+# own root and an empty list hashes to sha3-256(b"").
+#
+# Duplicating the last node means [a, b, c] and [a, b, c, c] share a root (CVE-2012-2459).
+# merkle_root_mutated reports when any level pairs two identical nodes, which is how such a
+# padded list shows up; validation must reject those lists instead of trusting the root.
+
+
+def _pair(left: bytes, right: bytes) -> bytes:
+    return bytes.fromhex(sha3_256_hex(left + right))
+
+
+def _leaves(txids: Sequence[str]) -> List[bytes]:
+    return [bytes.fromhex(str(t).lower()) for t in txids]
+
+
+def merkle_root_mutated(txids: Sequence[str]) -> Tuple[str, bool]:
+    """Returns (root_hex, mutated); mutated is True if some level has two identical siblings."""
+    if not txids:
+        return sha3_256_hex(b""), False
+    layer = _leaves(txids)
+    mutated = False
+    while len(layer) > 1:
+        for i in range(0, len(layer) - 1, 2):
+            if layer[i] == layer[i + 1]:
+                mutated = True
+        if len(layer) % 2 == 1:
+            layer.append(layer[-1])
+        layer = [_pair(layer[i], layer[i + 1]) for i in range(0, len(layer), 2)]
+    return layer[0].hex(), mutated
+
+
+def merkle_root(txids: Sequence[str]) -> str:
+    return merkle_root_mutated(txids)[0]
+
+
+def merkle_branch(txids: Sequence[str], index: int) -> List[str]:
+    """Sibling hashes from leaf `index` up to (not including) the root."""
+    if not (0 <= index < len(txids)):
+        raise IndexError("merkle index out of range")
+    layer = _leaves(txids)
+    branch: List[str] = []
+    idx = index
+    while len(layer) > 1:
+        if len(layer) % 2 == 1:
+            layer.append(layer[-1])
+        branch.append(layer[idx ^ 1].hex())
+        layer = [_pair(layer[i], layer[i + 1]) for i in range(0, len(layer), 2)]
+        idx //= 2
+    return branch
+
+
+def root_from_branch(txid: str, branch: Sequence[str], index: int) -> str:
+    cur = bytes.fromhex(str(txid).lower())
+    idx = index
+    for sib_hex in branch:
+        sib = bytes.fromhex(sib_hex)
+        cur = _pair(sib, cur) if idx & 1 else _pair(cur, sib)
+        idx //= 2
+    return cur.hex()
+
+
+def verify_branch(txid: str, branch: Sequence[str], index: int, root_hex: str) -> bool:
+    if index < 0 or index >= (1 << len(branch)):
+        return False
+    try:
+        return root_from_branch(txid, branch, index) == (root_hex or "").lower()
+    except ValueError:
+        return False
@@ -0,0 +1,109 @@
+from __future__ import annotations
+
+import base64
+import binascii
+import io
+import json
+import zlib
+from typing import List, Optional, Tuple
+
+from core.config import get_config
+
+
+# Optional P2P message compression.
+#
+# Each side lists the codecs it accepts in VERSION ("compress": ["zstd", "zlib"], in its order of
+# preference, from network.compression minus codecs this process cannot load). Once a peer's
+# VERSION is in, messages to it of at least network.compression_min_bytes are sent as
+#   "~" <codec id> base64(compressed JSON) "\n"
+# using the first codec of our list the peer also accepts; everything else (and everything to
+# peers that listed nothing) stays a plain JSON line, so old peers keep working. Base64 keeps the
+# line framing; JSON block/tx relay still shrinks well below half on the wire. Decompression is
+# capped at network.max_message_bytes so a small frame cannot expand without bound.
+#
+# zstd needs the optional `zstandard` package; zlib is always available.
+
+PREFIX = b"~"
+_IDS = {"zlib": b"z", "zstd": b"s"}
+_BY_ID = {v: k for k, v in _IDS.items()}
+
+try:
+    import zstandard as _zstd  # type: ignore
+except ImportError:  # optional dependency
+    _zstd = None
+
+
+class CompressionError(ValueError):
+    pass
+
+
+def supported() -> List[str]:
+    """Codecs we offer in VERSION, in preference order."""
+    wanted = get_config().get("network.compression", ["zstd", "zlib"]) or []
+    return [c for c in wanted if c == "zlib" or (c == "zstd" and _zstd is not None)]
+
+
+def negotiate(offered: object) -> Optional[str]:
+    """First codec of ours the peer offered (VERSION "compress"), or None."""
+    if not isinstance(offered, list):
+        return None
+    theirs = {str(c) for c in offered}
+    return next((c for c in supported() if c in theirs), None)
+
+
+def min_bytes() -> int:
+    return int(get_config().get("network.compression_min_bytes", 512))
+
+
+def _max_bytes() -> int:
+    return int(get_config().get("network.max_message_bytes", 32 * 1024 * 1024))
+
+
+def encode(codec: str, payload: bytes) -> bytes:
+    """Framed line for payload (JSON without the newline)."""
+    if codec == "zstd":
+        body = _zstd.ZstdCompressor(level=3).compress(payload)
+    else:
+        body = zlib.compress(payload, 6)
+    return PREFIX + _IDS[codec] + base64.b64encode(body) + b"\n"
+
+
+def is_compressed(line: bytes) -> bool:
+    return line[:1] == PREFIX
+
+
+def decode(line: bytes) -> bytes:
+    """JSON payload of a framed line; raises CompressionError."""
+    codec = _BY_ID.get(line[1:2])
+    if codec is None or codec not in supported():
+        raise CompressionError("unknown or unnegotiated codec")
+    try:
+        body = base64.b64decode(line[2:].strip(), validate=True)
+    except (binascii.Error, ValueError) as e:
+        raise CompressionError(f"bad frame: {e}")
+    limit = _max_bytes()
+    try:
+        if codec == "zstd":
+            with _zstd.ZstdDecompressor().stream_reader(io.BytesIO(body)) as r:
+                out = r.read(limit + 1)
+        else:
+            out = zlib.decompressobj().decompress(body, limit + 1)
+    except Exception as e:
+        raise CompressionError(f"decompression failed: {e}")
+    if len(out) > limit:
+        raise CompressionError("message too large")
+    return out
+
+
+def decode_message(line: bytes) -> Tuple[dict, bytes]:
+    """(message object, JSON payload) of a wire line, framed or plain; raises ValueError."""
+    payload = decode(line) if is_compressed(line) else line
+    try:
+        msg = json.loads(payload.decode("utf-8").strip())
+    except UnicodeDecodeError as e:
+        raise ValueError(f"not utf-8: {e}")
+    except RecursionError:
+        raise ValueError("nested too deeply")
+    if not isinstance(msg, dict):
+        raise ValueError("message is not an object")
+    return msg, payload
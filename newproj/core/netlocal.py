@@ -0,0 +1,236 @@
+from __future__ import annotations
+
+import ipaddress
+import socket
+import threading
+from typing import Dict, List, Optional, Tuple
+
+from core.config import get_config
+
+
+# Listen addresses, address families and our own reachable addresses for the P2P layer.
+#
+# network.listen lists the addresses to bind ("0.0.0.0", "::", "[::1]:28444", "10.0.0.5:28444");
+# a bare host uses network.p2p_port. When it is empty the node binds network.p2p_host alone, as
+# before. Addresses are written host:port with IPv6 hosts in brackets ("[2001:db8::1]:28444") and
+# IPv4-mapped IPv6 folded to plain IPv4, so one peer has one spelling everywhere (addrman, banman,
+# peer maps).
+#
+# Local addresses (getnetworkinfo "localaddresses") are what we advertise to peers, in VERSION
+# ("addrs") and at the head of our ADDR answers. Each carries a score like Bitcoin Core's:
+#   network.externalip entries   score 4  (operator says so)
+#   explicitly bound addresses   score 3
+#   interface discovery          score 1  (network.discover; hostname lookup, routable only)
+#   peer reports                 +1 each  (outbound peers echo the address they saw in "addr_you")
+# Only routable addresses on a reachable network are advertised. network.onlynet limits which
+# networks we connect out to and accept gossip for (ipv4, ipv6); onion has no transport here and
+# is always reported unreachable.
+
+NETWORKS = ("ipv4", "ipv6", "onion")
+SCORE_EXTERNAL = 4
+SCORE_BIND = 3
+SCORE_DISCOVERED = 1
+MAX_ADVERTISED = 4
+
+_lock = threading.Lock()
+_local: Dict[Tuple[str, int], Dict[str, int]] = {}  # (host, port) -> {"score", "source"}
+_bound: List[str] = []
+
+
+def split_host_port(addr: str, default_port: Optional[int] = None) -> Tuple[str, int]:
+    """Parse host:port, [v6]:port, bare v6 or bare host; raises ValueError."""
+    s = str(addr or "").strip()
+    if s.startswith("["):
+        host, _, rest = s[1:].partition("]")
+        port = int(rest[1:]) if rest.startswith(":") else default_port
+    elif s.count(":") == 1:
+        host, p = s.split(":")
+        port = int(p)
+    else:
+        host, port = s, default_port  # bare IPv4/hostname, or bare IPv6 (several colons)
+    if not host or port is None or not (0 < int(port) < 65536):
+        raise ValueError(f"bad address: {addr!r}")
+    return canonical_host(host), int(port)
+
+
+def canonical_host(host: str) -> str:
+    host = str(host).strip().strip("[]").split("%", 1)[0]
+    try:
+        ip = ipaddress.ip_address(host)
+    except ValueError:
+        return host.lower()
+    if isinstance(ip, ipaddress.IPv6Address) and ip.ipv4_mapped is not None:
+        ip = ip.ipv4_mapped
+    return str(ip)
+
+
+def format_addr(host: str, port: int) -> str:
+    host = canonical_host(host)
+    return f"[{host}]:{int(port)}" if ":" in host else f"{host}:{int(port)}"
+
+
+def netgroup(host: str) -> str:
+    """
+    Network group for peer diversity (core.eviction): IPv4 /16, IPv6 /32, onion and hostnames
+    by name. Addresses in one group are likely one operator or one hosting provider. Loopback
+    and private addresses return "" (no group), so local test clusters are not limited.
+    """
+    host = canonical_host(host)
+    try:
+        ip = ipaddress.ip_address(host)
+    except ValueError:
+        return f"{'onion' if host.endswith('.onion') else 'name'}:{host}"
+    if ip.is_loopback or ip.is_private or ip.is_link_local:
+        return ""
+    if ip.version == 4:
+        a, b = host.split(".")[:2]
+        return f"ipv4:{a}.{b}"
+    return f"ipv6:{ip.exploded[:9]}"
+
+
+def network_of(host: str) -> str:
+    host = canonical_host(host)
+    if host.endswith(".onion"):
+        return "onion"
+    try:
+        return "ipv6" if ipaddress.ip_address(host).version == 6 else "ipv4"
+    except ValueError:
+        return "ipv4"  # hostnames resolve through the IPv4-first resolver path
+
+
+def is_routable(host: str) -> bool:
+    try:
+        ip = ipaddress.ip_address(canonical_host(host))
+    except ValueError:
+        return False
+    return not (ip.is_private or ip.is_loopback or ip.is_link_local or ip.is_multicast
+                or ip.is_unspecified or ip.is_reserved)
+
+
+def _onlynet() -> List[str]:
+    return [str(n).lower() for n in (get_config().get("network.onlynet", []) or [])]
+
+
+def is_reachable(net: str) -> bool:
+    if net == "onion":
+        return False
+    if net == "ipv6" and not socket.has_ipv6:
+        return False
+    only = _onlynet()
+    return not only or net in only
+
+
+def listen_addresses() -> List[Tuple[str, int]]:
+    """(host, port) pairs to bind, from network.listen or the single p2p_host."""
+    cfg = get_config()
+    port = int(cfg.get("network.p2p_port", 28447))
+    entries = list(cfg.get("network.listen", []) or [])
+    if not entries:
+        entries = [cfg.get("network.p2p_host") or cfg.get("network.rpc_host", "127.0.0.1")]
+    out: List[Tuple[str, int]] = []
+    for e in entries:
+        hp = split_host_port(str(e), port)
+        if hp not in out:
+            out.append(hp)
+    return out
+
+
+def bind_socket(host: str, port: int) -> socket.socket:
+    family = socket.AF_INET6 if ":" in host else socket.AF_INET
+    s = socket.socket(family, socket.SOCK_STREAM)
+    s.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+    if family == socket.AF_INET6 and hasattr(socket, "IPV6_V6ONLY"):
+        # "::" and "0.0.0.0" can then both be listed without an address-in-use clash
+        s.setsockopt(socket.IPPROTO_IPV6, socket.IPV6_V6ONLY, 1)
+    s.bind((host, port))
+    s.listen(50)
+    return s
+
+
+def add_local(host: str, port: int, score: int, source: str) -> bool:
+    host = canonical_host(host)
+    if not is_routable(host) or not is_reachable(network_of(host)):
+        return False
+    with _lock:
+        cur = _local.get((host, port))
+        if cur is None or cur["score"] < score:
+            _local[(host, port)] = {"score": score, "source": source}
+    return True
+
+
+def seen_by_peer(host: str, port: int) -> None:
+    """An outbound peer reported the address it sees us at; bump it (or start it at score 1)."""
+    host = canonical_host(host)
+    if not is_routable(host) or not is_reachable(network_of(host)):
+        return
+    with _lock:
+        cur = _local.setdefault((host, port), {"score": 0, "source": "peer"})
+        cur["score"] += 1
+
+
+def set_bound(addrs: List[str]) -> None:
+    with _lock:
+        _bound[:] = addrs
+
+
+def bound() -> List[str]:
+    with _lock:
+        return list(_bound)
+
+
+def discover(port: int) -> None:
+    """Register externalip, explicitly bound and (if network.discover) interface addresses."""
+    cfg = get_config()
+    for e in cfg.get("network.externalip", []) or []:
+        try:
+            h, p = split_host_port(str(e), port)
+        except ValueError:
+            continue
+        add_local(h, p, SCORE_EXTERNAL, "externalip")
+    for addr in bound():
+        h, p = split_host_port(addr)
+        add_local(h, p, SCORE_BIND, "bind")
+    if not bool(cfg.get("network.discover", True)):
+        return
+    try:
+        infos = socket.getaddrinfo(socket.gethostname(), None, socket.AF_UNSPEC, socket.SOCK_STREAM)
+    except OSError:
+        return
+    for info in infos:
+        add_local(str(info[4][0]), port, SCORE_DISCOVERED, "discovered")
+
+
+def local_addresses() -> List[Dict[str, object]]:
+    """getnetworkinfo "localaddresses", best first."""
+    with _lock:
+        items = sorted(_local.items(), key=lambda kv: (-kv[1]["score"], kv[0]))
+    return [{"address": h, "port": p, "score": v["score"]} for (h, p), v in items]
+
+
+def advertised() -> List[str]:
+    """Addresses we tell peers to reach us at (host:port), best first."""
+    return [format_addr(a["address"], a["port"]) for a in local_addresses()[:MAX_ADVERTISED]]
+
+
+def is_local(addr: str) -> bool:
+    try:
+        hp = split_host_port(addr)
+    except ValueError:
+        return False
+    with _lock:
+        return hp in _local or format_addr(*hp) in _bound
+
+
+def networks() -> List[Dict[str, object]]:
+    """getnetworkinfo "networks"."""
+    only = _onlynet()
+    return [
+        {
+            "name": net,
+            "limited": bool(only) and net not in only,
+            "reachable": is_reachable(net),
+            "proxy": "",
+            "proxy_randomize_credentials": False,
+        }
+        for net in NETWORKS
+    ]
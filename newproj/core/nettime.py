@@ -0,0 +1,95 @@
+from __future__ import annotations
+
+import threading
+from typing import Any, Dict, List, Optional
+
+from core.config import get_config
+from core.utils import get_mock_time, now_sec
+from core import logs
+
+
+# Network-adjusted time (timejacking protection).
+#
+# Every VERSION carries the sender's clock ("time", ms). Outbound peers (ones we chose to connect
+# to, so an attacker cannot simply open connections to us to stuff samples) contribute one
+# sample per host: their clock minus ours, in seconds. Up to MAX_SAMPLES hosts are kept, oldest
+# dropped first. Once there are at least network.time_min_samples samples and the count is odd
+# (as in Bitcoin Core, so the median is a real sample), the median becomes the offset, but only
+# if it is within network.max_time_adjustment_sec. A larger median means our clock or most of
+# our peers are badly off; the offset is then reset to 0 and, if no peer is within
+# WARN_CLOSE_SEC of us, a warning asks the operator to check the clock.
+#
+# adjusted_time() = node time + offset. Header validation uses it for the
+# consensus.max_future_block_time_sec bound and templates use it for curtime, so a node whose
+# clock drifts still agrees with the network about what "too far in the future" means. Under
+# setmocktime the offset is ignored so regtest stays deterministic.
+
+MAX_SAMPLES = 200
+WARN_CLOSE_SEC = 5 * 60
+
+_lock = threading.Lock()
+_samples: Dict[str, int] = {}  # host -> peer clock minus ours (s); dict order = arrival order
+_offset = 0
+_warned = False
+log = logs.get("net")
+
+
+def _max_adjustment() -> int:
+    return max(0, int(get_config().get("network.max_time_adjustment_sec", 4200)))
+
+
+def _min_samples() -> int:
+    return max(1, int(get_config().get("network.time_min_samples", 5)))
+
+
+def add_sample(host: str, peer_time_ms: Any):
+    """Record an outbound peer's VERSION time and recompute the offset."""
+    global _offset, _warned
+    try:
+        sample = int(peer_time_ms) // 1000 - now_sec()
+    except (TypeError, ValueError):
+        return
+    with _lock:
+        if host in _samples:
+            return
+        _samples[host] = sample
+        while len(_samples) > MAX_SAMPLES:
+            _samples.pop(next(iter(_samples)))
+        values = sorted(_samples.values())
+        if len(values) < _min_samples() or len(values) % 2 == 0:
+            return
+        median = values[len(values) // 2]
+        if abs(median) <= _max_adjustment():
+            if median != _offset:
+                log.info(f"network time offset {median:+d}s", extra=logs.fields(samples=len(values)))
+            _offset = median
+            return
+        _offset = 0
+        if not _warned and not any(abs(v) <= WARN_CLOSE_SEC for v in values):
+            _warned = True
+            log.warning(
+                f"peers' median clock differs from ours by {median:+d}s (more than "
+                f"network.max_time_adjustment_sec); check that this computer's date and time are correct",
+                extra=logs.fields(samples=len(values)),
+            )
+
+
+def offset() -> int:
+    if get_mock_time():
+        return 0
+    with _lock:
+        return _offset
+
+
+def adjusted_time() -> int:
+    return now_sec() + offset()
+
+
+def info() -> Dict[str, Any]:
+    """getnetworkinfo fields."""
+    with _lock:
+        values: List[int] = sorted(_samples.values())
+        out: Dict[str, Any] = {"timeoffset": _offset, "time_samples": len(values)}
+        median: Optional[int] = values[len(values) // 2] if values else None
+    out["time_sample_median"] = median
+    return out
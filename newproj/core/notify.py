@@ -0,0 +1,71 @@
+from __future__ import annotations
+
+import asyncio
+import threading
+from typing import Any, Dict, List, Optional, Set
+
+
+# In-process event fan-out for the RPC WebSocket (/ws, core.rpc).
+#
+# Producers run on any thread (P2P loop, RPC handlers) and call publish(topic, event); each
+# WebSocket session owns a Subscription whose asyncio queue is fed through its event loop, so
+# publishing never blocks on a slow client. A subscriber whose queue is full
+# (network.rpc_ws_queue_size) is dropped and its socket closed instead of buffering without bound.
+# Topics: "conflicts" (core.conflicts). An address filter narrows a subscription to events
+# whose "addresses" field intersects it.
+
+TOPICS = ("conflicts",)
+
+
+class Subscription:
+    def __init__(self, loop: asyncio.AbstractEventLoop, topics: Set[str], addresses: Set[str], maxsize: int):
+        self.loop = loop
+        self.topics = topics
+        self.addresses = addresses
+        self.queue: "asyncio.Queue[Optional[Dict[str, Any]]]" = asyncio.Queue(maxsize=maxsize)
+        self.overflowed = False
+
+    def wants(self, topic: str, event: Dict[str, Any]) -> bool:
+        if topic not in self.topics:
+            return False
+        return not self.addresses or bool(self.addresses & set(event.get("addresses") or []))
+
+    def _put(self, event: Optional[Dict[str, Any]]):
+        try:
+            self.queue.put_nowait(event)
+        except asyncio.QueueFull:
+            self.overflowed = True
+
+
+_lock = threading.Lock()
+_subs: List[Subscription] = []
+
+
+def subscribe(loop: asyncio.AbstractEventLoop, topics: Set[str], addresses: Set[str], maxsize: int = 256) -> Subscription:
+    sub = Subscription(loop, topics, addresses, maxsize)
+    with _lock:
+        _subs.append(sub)
+    return sub
+
+
+def unsubscribe(sub: Subscription):
+    with _lock:
+        if sub in _subs:
+            _subs.remove(sub)
+
+
+def publish(topic: str, event: Dict[str, Any]) -> int:
+    """Queue event for every interested subscriber; returns how many were reached."""
+    with _lock:
+        targets = [s for s in _subs if s.wants(topic, event)]
+    for sub in targets:
+        try:
+            sub.loop.call_soon_threadsafe(sub._put, dict(event, topic=topic))
+        except RuntimeError:
+            unsubscribe(sub)  # loop closed: the session is gone
+    return len(targets)
+
+
+def subscriber_count() -> int:
+    with _lock:
+        return len(_subs)
@@ -0,0 +1,29 @@
+from __future__ import annotations
+
+import threading
+from typing import Any, Callable, Dict, Optional
+
+
+# Process-wide P2P state shared by the node's P2P layer (apps.node.main) and the RPC server
+# (core.rpc).
+#
+# The node is often started as `python apps/node/main.py` (or -m), which makes its module
+# __main__; an `import apps.node.main` from the RPC side would then load a second, idle copy
+# with an empty peer table. Both sides import this module instead, which exists once.
+#
+#   peers / peers_lock   the live peer table (addr -> apps.node.main.PeerState)
+#   hooks                functions start_p2p() registers (announce_tip, sync_status, net_totals,
+#                        network_info, peer_info, connect_peer); none are registered when no P2P
+#                        layer runs in this process (standalone RPC), and callers fall back.
+
+peers: Dict[str, Any] = {}
+peers_lock = threading.Lock()
+_hooks: Dict[str, Callable[..., Any]] = {}
+
+
+def register(**fns: Callable[..., Any]):
+    _hooks.update(fns)
+
+
+def hook(name: str) -> Optional[Callable[..., Any]]:
+    return _hooks.get(name)
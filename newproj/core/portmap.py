@@ -0,0 +1,245 @@
+from __future__ import annotations
+
+import atexit
+import socket
+import struct
+import threading
+import time
+import urllib.parse
+import urllib.request
+import xml.etree.ElementTree as ET
+from typing import Any, Dict, Optional, Tuple
+
+from core import netlocal
+from core.config import get_config
+from core.utils import now_ms
+from core import logs
+
+
+# Opt-in port mapping for the P2P listen port (network.portmap), for nodes behind a home router.
+#
+# A background task asks the gateway to forward the port, trying the protocols in
+# network.portmap_protocols order:
+#   natpmp  NAT-PMP (RFC 6886) over UDP to the default gateway's port 5351; the gateway comes from
+#           network.natpmp_gateway or, on Linux, the default route in /proc/net/route
+#   upnp    UPnP IGD: SSDP discovery, then AddPortMapping / GetExternalIPAddress SOAP calls on the
+#           WANIPConnection (or WANPPPConnection) service
+# Mappings are leased for network.portmap_lease_sec and renewed at half the lease; if a renewal
+# fails the next protocol is tried on the following round. The external address the gateway
+# reports goes to core.netlocal as a mapped local address, so it is advertised to peers. The
+# mapping is deleted on shutdown (atexit) so it does not outlive the node.
+
+SCORE_MAPPED = 3
+_NATPMP_PORT = 5351
+_SSDP_ADDR = ("239.255.255.250", 1900)
+_IGD_SERVICES = ("urn:schemas-upnp-org:service:WANIPConnection:1",
+                 "urn:schemas-upnp-org:service:WANIPConnection:2",
+                 "urn:schemas-upnp-org:service:WANPPPConnection:1")
+
+_lock = threading.Lock()
+_stop = threading.Event()
+_state: Dict[str, Any] = {"protocol": None, "external": None, "lease_until_ms": 0, "error": None}
+_thread: Optional[threading.Thread] = None
+
+
+class PortMapError(Exception):
+    pass
+
+
+def _default_gateway() -> Optional[str]:
+    gw = str(get_config().get("network.natpmp_gateway", "") or "").strip()
+    if gw:
+        return gw
+    try:
+        with open("/proc/net/route") as f:
+            for line in f.readlines()[1:]:
+                fields = line.split()
+                if len(fields) > 2 and fields[1] == "00000000":
+                    return socket.inet_ntoa(struct.pack("<I", int(fields[2], 16)))
+    except (OSError, ValueError):
+        pass
+    return None
+
+
+def _natpmp_call(gateway: str, req: bytes, resp_len: int) -> bytes:
+    """Send with RFC 6886 retransmission (250 ms doubling, 4 tries) and check the result code."""
+    with socket.socket(socket.AF_INET, socket.SOCK_DGRAM) as s:
+        timeout = 0.25
+        for _ in range(4):
+            s.settimeout(timeout)
+            s.sendto(req, (gateway, _NATPMP_PORT))
+            try:
+                data, src = s.recvfrom(64)
+            except socket.timeout:
+                timeout *= 2
+                continue
+            if src[0] != gateway or len(data) < resp_len or data[1] != req[1] + 128:
+                continue
+            result = struct.unpack("!H", data[2:4])[0]
+            if result != 0:
+                raise PortMapError(f"NAT-PMP result code {result}")
+            return data
+    raise PortMapError("NAT-PMP gateway did not answer")
+
+
+def _natpmp_map(port: int, lease: int) -> Tuple[str, int]:
+    gateway = _default_gateway()
+    if not gateway:
+        raise PortMapError("no default gateway (set network.natpmp_gateway)")
+    data = _natpmp_call(gateway, struct.pack("!BB", 0, 0), 12)
+    ext_ip = socket.inet_ntoa(data[8:12])
+    data = _natpmp_call(gateway, struct.pack("!BBHHHI", 0, 2, 0, port, port, lease), 16)
+    return ext_ip, struct.unpack("!H", data[10:12])[0]
+
+
+def _natpmp_unmap(port: int) -> None:
+    gateway = _default_gateway()
+    if gateway:
+        _natpmp_call(gateway, struct.pack("!BBHHHI", 0, 2, 0, port, 0, 0), 16)
+
+
+def _ssdp_location(timeout: float = 2.0) -> str:
+    req = ("M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\n"
+           "ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\r\n").encode("ascii")
+    with socket.socket(socket.AF_INET, socket.SOCK_DGRAM) as s:
+        s.settimeout(timeout)
+        s.sendto(req, _SSDP_ADDR)
+        deadline = time.time() + timeout
+        while time.time() < deadline:
+            try:
+                data, _ = s.recvfrom(4096)
+            except socket.timeout:
+                break
+            for line in data.decode("latin-1").split("\r\n"):
+                key, _, val = line.partition(":")
+                if key.strip().lower() == "location" and val.strip():
+                    return val.strip()
+    raise PortMapError("no UPnP gateway found")
+
+
+def _igd_control(location: str) -> Tuple[str, str]:
+    """(control URL, service type) of the gateway's WAN connection service."""
+    with urllib.request.urlopen(location, timeout=5) as r:
+        root = ET.fromstring(r.read())
+    ns = {"d": "urn:schemas-upnp-org:device-1-0"}
+    base = root.findtext("d:URLBase", default="", namespaces=ns) or location
+    for svc in root.iter("{urn:schemas-upnp-org:device-1-0}service"):
+        stype = svc.findtext("d:serviceType", default="", namespaces=ns)
+        if stype in _IGD_SERVICES:
+            return urllib.parse.urljoin(base, svc.findtext("d:controlURL", default="", namespaces=ns)), stype
+    raise PortMapError("UPnP gateway has no WAN connection service")
+
+
+def _soap(control: str, stype: str, action: str, args: Dict[str, Any]) -> ET.Element:
+    body = "".join(f"<{k}>{v}</{k}>" for k, v in args.items())
+    envelope = ('<?xml version="1.0"?><s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" '
+                's:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body>'
+                f'<u:{action} xmlns:u="{stype}">{body}</u:{action}></s:Body></s:Envelope>').encode("utf-8")
+    req = urllib.request.Request(control, data=envelope, headers={
+        "Content-Type": 'text/xml; charset="utf-8"', "SOAPAction": f'"{stype}#{action}"'})
+    try:
+        with urllib.request.urlopen(req, timeout=5) as r:
+            return ET.fromstring(r.read())
+    except OSError as e:
+        raise PortMapError(f"UPnP {action} failed: {e}")
+
+
+def _local_ip_towards(url: str) -> str:
+    host = urllib.parse.urlparse(url).hostname or "8.8.8.8"
+    with socket.socket(socket.AF_INET, socket.SOCK_DGRAM) as s:
+        s.connect((host, 9))
+        return s.getsockname()[0]
+
+
+def _upnp_map(port: int, lease: int) -> Tuple[str, int]:
+    control, stype = _igd_control(_ssdp_location())
+    _soap(control, stype, "AddPortMapping", {
+        "NewRemoteHost": "", "NewExternalPort": port, "NewProtocol": "TCP", "NewInternalPort": port,
+        "NewInternalClient": _local_ip_towards(control), "NewEnabled": 1,
+        "NewPortMappingDescription": "smellycoin p2p", "NewLeaseDuration": lease,
+    })
+    resp = _soap(control, stype, "GetExternalIPAddress", {})
+    ext_ip = next((e.text for e in resp.iter() if e.tag.endswith("NewExternalIPAddress") and e.text), None)
+    if not ext_ip:
+        raise PortMapError("UPnP gateway reported no external address")
+    with _lock:
+        _state["upnp_control"] = (control, stype)
+    return ext_ip.strip(), port
+
+
+def _upnp_unmap(port: int) -> None:
+    with _lock:
+        ctl = _state.get("upnp_control")
+    if ctl:
+        _soap(ctl[0], ctl[1], "DeletePortMapping", {"NewRemoteHost": "", "NewExternalPort": port, "NewProtocol": "TCP"})
+
+
+_PROTOCOLS = {"natpmp": (_natpmp_map, _natpmp_unmap), "upnp": (_upnp_map, _upnp_unmap)}
+
+
+def _map_once(port: int, lease: int) -> bool:
+    errors = []
+    for name in get_config().get("network.portmap_protocols", ["natpmp", "upnp"]) or []:
+        funcs = _PROTOCOLS.get(str(name))
+        if funcs is None:
+            continue
+        try:
+            ext_ip, ext_port = funcs[0](port, lease)
+        except (PortMapError, OSError, ET.ParseError) as e:
+            errors.append(f"{name}: {e}")
+            continue
+        with _lock:
+            fresh = _state["external"] != (ext_ip, ext_port)
+            _state.update(protocol=name, external=(ext_ip, ext_port), lease_until_ms=now_ms() + lease * 1000, error=None)
+        if fresh:
+            logs.get("net").info(f"port mapping ({name}): external address {netlocal.format_addr(ext_ip, ext_port)}")
+        netlocal.add_local(ext_ip, ext_port, SCORE_MAPPED, name)
+        return True
+    with _lock:
+        _state.update(protocol=None, error="; ".join(errors) or "no port mapping protocol configured")
+    logs.get("net").warning(f"port mapping failed: {_state['error']}")
+    return False
+
+
+def _run(port: int):
+    lease = max(120, int(get_config().get("network.portmap_lease_sec", 3600)))
+    while not _stop.is_set():
+        ok = _map_once(port, lease)
+        _stop.wait(lease / 2 if ok else 300)
+
+
+def start(port: int) -> None:
+    """Start the mapping task if network.portmap is on (idempotent)."""
+    global _thread
+    if not bool(get_config().get("network.portmap", False)) or _thread is not None:
+        return
+    _stop.clear()
+    _thread = threading.Thread(target=_run, args=(port,), name="portmap", daemon=True)
+    _thread.start()
+    atexit.register(stop, port)
+
+
+def stop(port: int) -> None:
+    """Stop renewing and delete the mapping at the gateway."""
+    _stop.set()
+    with _lock:
+        proto = _state["protocol"]
+        _state.update(protocol=None, external=None, lease_until_ms=0)
+    if proto:
+        try:
+            _PROTOCOLS[proto][1](port)
+            logs.get("net").info(f"port mapping ({proto}) removed")
+        except (PortMapError, OSError) as e:
+            logs.get("net").warning(f"port mapping removal failed: {e}")
+
+
+def status() -> Dict[str, Any]:
+    with _lock:
+        ext = _state["external"]
+        return {
+            "enabled": bool(get_config().get("network.portmap", False)),
+            "protocol": _state["protocol"],
+            "external": netlocal.format_addr(*ext) if ext else None,
+            "lease_until_ms": _state["lease_until_ms"],
+            "error": _state["error"],
+        }
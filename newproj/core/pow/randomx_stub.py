@@ -4,6 +4,8 @@ import time
 import hashlib
 from typing import Tuple
 
+from core.target import difficulty_to_target, hash_meets_target  # noqa: F401 (re-exported)
+
 # NOTE: This is a placeholder for an ASIC-resistant PoW (e.g., RandomX).
 # For production, replace with native bindings to an audited RandomX implementation.
 # This stub mixes sha3_256 and memory-hardish loop to be CPU-friendly only for demo.
@@ -34,19 +36,7 @@ def pow_hash(header_bytes: bytes, nonce: int) -> bytes:
 
 
 def meets_target(hash_bytes: bytes, target_hex: str) -> bool:
-    # Compare as big integer with target
-    h = int.from_bytes(hash_bytes, "big")
-    t = int(target_hex, 16)
-    return h <= t
-
-
-def difficulty_to_target(difficulty: int) -> str:
-    # Simplified: target = max_hash // difficulty
-    if difficulty <= 0:
-        difficulty = 1
-    max_hash = (1 << 256) - 1
-    target = max_hash // difficulty
-    return f"{target:064x}"
+    return hash_meets_target(hash_bytes, target_hex)
 
 
 def mine(header_bytes: bytes, difficulty: int, start_nonce: int = 0, max_tries: int = 1_000_000) -> Tuple[int, bytes]:
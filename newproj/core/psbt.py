@@ -0,0 +1,209 @@
+from __future__ import annotations
+
+import base64
+import binascii
+import copy
+import json
+from typing import Any, Dict, Iterable, List, Optional, Tuple
+
+from core.crypto import SIGHASH_ALL, sign_transaction_input, tx_digest_hex, verify_input_signature
+from core.db import get_db
+from core.rawtx import build_unsigned, lookup_prev
+
+
+# Partially signed transactions (PSBT-like) for offline and hardware signing.
+#
+# A PSBT is base64 of compact JSON:
+#   {"magic": "spsbt", "version": 1,
+#    "tx": unsigned structured tx (see consensus.validate_mempool_tx; no pubkey/sig on inputs),
+#    "inputs": [{"prev": {"address", "amount"} | null, "sighash": 1, "partial_sigs": {pubkey: sig},
+#                "viewkey": hex view pubkey (key addresses; see core.crypto.key_address_matches)}],
+#    "outputs": [{}]}
+# one metadata entry per tx input/output. "prev" is the output being spent, which is all a signer
+# needs to compute the sighash (core.crypto.sighash commits to it), so a device holding only the
+# key can sign without access to the chain. Roles follow BIP174: createpsbt (creator, fills prev
+# from the UTXO set when known), walletprocesspsbt (updater/signer, apps.wallet.signers),
+# combinepsbt, finalizepsbt (moves one verified signature per input into the tx and extracts it
+# for /rpc/tx/submit). Signatures are checked when added, so a PSBT never carries a bad one.
+# Multisig inputs carry their redeem script in the unsigned tx and collect one partial signature
+# per cosigner; they are final once m of the redeem keys have signed.
+
+PSBT_MAGIC = "spsbt"
+PSBT_VERSION = 1
+
+
+def encode(p: Dict[str, Any]) -> str:
+    return base64.b64encode(json.dumps(p, sort_keys=True, separators=(",", ":")).encode("utf-8")).decode("ascii")
+
+
+def decode(data: str) -> Dict[str, Any]:
+    """Parse and sanity-check a base64 PSBT; raises ValueError."""
+    try:
+        p = json.loads(base64.b64decode((data or "").strip(), validate=True).decode("utf-8"))
+    except (binascii.Error, UnicodeDecodeError, ValueError) as e:
+        raise ValueError(f"TX decode failed: {e}")
+    if not isinstance(p, dict) or p.get("magic") != PSBT_MAGIC or p.get("version") != PSBT_VERSION:
+        raise ValueError("not a version-1 PSBT")
+    tx = p.get("tx")
+    if not isinstance(tx, dict) or not isinstance(tx.get("inputs"), list) or not isinstance(tx.get("outputs"), list):
+        raise ValueError("PSBT has no unsigned tx")
+    if any(isinstance(i, dict) and ("sig" in i or "sigs" in i or "pubkey" in i or "viewkey" in i) for i in tx["inputs"]):
+        raise ValueError("unsigned tx must not carry signatures")
+    if len(p.get("inputs") or []) != len(tx["inputs"]) or len(p.get("outputs") or []) != len(tx["outputs"]):
+        raise ValueError("input/output metadata does not match the unsigned tx")
+    for meta in p["inputs"]:
+        meta.setdefault("prev", None)
+        meta.setdefault("sighash", SIGHASH_ALL)
+        meta.setdefault("partial_sigs", {})
+    return p
+
+
+def create(inputs: List[Dict[str, Any]], outputs: List[Dict[str, Any]], fee: float,
+           replaceable: bool = False) -> Dict[str, Any]:
+    """Creator: PSBT around core.rawtx.build_unsigned()."""
+    tx, prevs = build_unsigned(inputs, outputs, fee, replaceable)
+    metas = [{"prev": prev, "sighash": SIGHASH_ALL, "partial_sigs": {}} for prev in prevs]
+    return {"magic": PSBT_MAGIC, "version": PSBT_VERSION, "tx": tx, "inputs": metas, "outputs": [{} for _ in tx["outputs"]]}
+
+
+def update(p: Dict[str, Any]) -> int:
+    """Updater: fill missing prev outputs from the UTXO set; returns how many were filled."""
+    filled = 0
+    db = get_db()
+    with db.session() as s:
+        for inp, meta in zip(p["tx"]["inputs"], p["inputs"]):
+            if meta.get("prev") is None:
+                meta["prev"] = lookup_prev(s, inp["txid"], int(inp["vout"]))
+                filled += meta["prev"] is not None
+    return filled
+
+
+def _redeem(p: Dict[str, Any], idx: int) -> Optional[Dict[str, Any]]:
+    return p["tx"]["inputs"][idx].get("redeem")
+
+
+def _sigs_needed(p: Dict[str, Any], idx: int) -> int:
+    redeem = _redeem(p, idx)
+    return int(redeem["m"]) if redeem else 1
+
+
+def add_signature(p: Dict[str, Any], idx: int, pubkey: str, sig: str) -> None:
+    """Record a signature for input idx after checking it against the input's prev output."""
+    if not (0 <= idx < len(p["inputs"])):
+        raise ValueError(f"input {idx} out of range")
+    meta = p["inputs"][idx]
+    if meta.get("prev") is None:
+        raise ValueError(f"input {idx} has no prev output to sign against")
+    redeem = _redeem(p, idx)
+    if redeem and pubkey.lower() not in redeem["pubkeys"]:
+        raise ValueError(f"key {pubkey[:16]} is not a cosigner of input {idx}")
+    if not verify_input_signature(p["tx"], idx, meta["prev"], pubkey, sig):
+        raise ValueError(f"invalid signature for input {idx}")
+    meta["partial_sigs"][pubkey.lower()] = sig.lower()
+
+
+def sign_with_key(p: Dict[str, Any], sk: bytes, addresses: Optional[Iterable[str]] = None,
+                  view_pubkey: Optional[bytes] = None) -> List[int]:
+    """
+    Signer with a local key: signs inputs that still need a signature, whose prev address is in
+    addresses (if given) and, for multisig inputs, whose redeem script lists this key.
+    view_pubkey is recorded for single-key inputs so the finalized input derives its address.
+    """
+    wanted = set(addresses) if addresses is not None else None
+    signed: List[int] = []
+    for idx, meta in enumerate(p["inputs"]):
+        prev = meta.get("prev")
+        if prev is None or len(meta["partial_sigs"]) >= _sigs_needed(p, idx):
+            continue
+        if wanted is not None and prev.get("address") not in wanted:
+            continue
+        res = sign_transaction_input(p["tx"], idx, prev, sk, int(meta.get("sighash", SIGHASH_ALL)))
+        redeem = _redeem(p, idx)
+        if res["pubkey"] in meta["partial_sigs"] or (redeem and res["pubkey"] not in redeem["pubkeys"]):
+            continue
+        add_signature(p, idx, res["pubkey"], res["sig"])
+        if view_pubkey is not None and not redeem:
+            meta["viewkey"] = view_pubkey.hex()
+        signed.append(idx)
+    return signed
+
+
+def merge_signatures(p: Dict[str, Any], other: Dict[str, Any]) -> None:
+    """Copy prev outputs and verified signatures from other (same unsigned tx) into p."""
+    if json.dumps(p["tx"], sort_keys=True) != json.dumps(other["tx"], sort_keys=True):
+        raise ValueError("PSBTs not compatible (different transactions)")
+    for idx, (meta, theirs) in enumerate(zip(p["inputs"], other["inputs"])):
+        if meta.get("prev") is None and theirs.get("prev") is not None:
+            meta["prev"] = theirs["prev"]
+        if not meta.get("viewkey") and theirs.get("viewkey"):
+            meta["viewkey"] = str(theirs["viewkey"]).lower()
+        for pubkey, sig in (theirs.get("partial_sigs") or {}).items():
+            if pubkey not in meta["partial_sigs"]:
+                add_signature(p, idx, pubkey, sig)
+
+
+def combine(psbts: List[Dict[str, Any]]) -> Dict[str, Any]:
+    if not psbts:
+        raise ValueError("at least one PSBT is required")
+    out = copy.deepcopy(psbts[0])
+    for other in psbts[1:]:
+        merge_signatures(out, other)
+    return out
+
+
+def finalize(p: Dict[str, Any]) -> Tuple[Optional[Dict[str, Any]], bool]:
+    """Finalizer/extractor: (signed tx, True) once every input has a signature, else (None, False)."""
+    tx = copy.deepcopy(p["tx"])
+    for idx, meta in enumerate(p["inputs"]):
+        if len(meta["partial_sigs"]) < _sigs_needed(p, idx):
+            return None, False
+        redeem = _redeem(p, idx)
+        if redeem:
+            # CHECKMULTISIG order: signatures follow the redeem script's key order
+            sigs = [meta["partial_sigs"][k] for k in redeem["pubkeys"] if k in meta["partial_sigs"]]
+            tx["inputs"][idx] = dict(tx["inputs"][idx], sigs=sigs[: int(redeem["m"])])
+            continue
+        pubkey, sig = sorted(meta["partial_sigs"].items())[0]
+        tx["inputs"][idx] = dict(tx["inputs"][idx], pubkey=pubkey, sig=sig)
+        if meta.get("viewkey"):
+            tx["inputs"][idx]["viewkey"] = meta["viewkey"]
+    return tx, True
+
+
+def analyze(p: Dict[str, Any]) -> Dict[str, Any]:
+    inputs = []
+    total_in = 0.0
+    known = True
+    for idx, meta in enumerate(p["inputs"]):
+        has_prev = meta.get("prev") is not None
+        is_final = len(meta["partial_sigs"]) >= _sigs_needed(p, idx)
+        known = known and has_prev
+        total_in += float(meta["prev"]["amount"]) if has_prev else 0.0
+        inputs.append({
+            "has_utxo": has_prev,
+            "is_final": is_final,
+            "next": "extractor" if is_final else ("signer" if has_prev else "updater"),
+        })
+    roles = [i["next"] for i in inputs]
+    out: Dict[str, Any] = {
+        "inputs": inputs,
+        "next": next((r for r in ("updater", "signer") if r in roles), "extractor"),
+        "fee": float(p["tx"].get("fee", 0.0)),
+    }
+    if known:
+        out["input_total"] = total_in
+        out["output_total"] = sum(float(o.get("amount", 0.0)) for o in p["tx"]["outputs"])
+        out["balanced"] = total_in + 1e-12 >= out["output_total"] + out["fee"]
+    return out
+
+
+def describe(p: Dict[str, Any]) -> Dict[str, Any]:
+    """decodepsbt view."""
+    return {
+        "tx": p["tx"],
+        "unsigned_txid": tx_digest_hex(p["tx"]),
+        "inputs": [{"prev": m.get("prev"), "sighash": m.get("sighash", SIGHASH_ALL),
+                    "partial_sigs": m["partial_sigs"], "viewkey": m.get("viewkey")} for m in p["inputs"]],
+        "outputs": p["outputs"],
+        "fee": float(p["tx"].get("fee", 0.0)),
+    }
@@ -0,0 +1,268 @@
+from __future__ import annotations
+
+import copy
+import json
+import math
+from typing import Any, Dict, List, Optional, Set, Tuple
+
+from core.config import get_config
+from core.crypto import (
+    SIGHASH_ALL,
+    SIGHASH_ANYONECANPAY,
+    data_output_bytes,
+    ed25519_keypair_from_seed,
+    encode_address,
+    multisig_redeem,
+    sign_transaction_input,
+    verify_transaction_input,
+)
+from core.db import get_db, MempoolTx, UTXO
+from core.utils import now_ms
+
+
+# Raw transaction tooling: createrawtransaction, signrawtransactionwithkey, fundrawtransaction.
+#
+# Parameter shapes follow Bitcoin Core where the tx model allows. A "raw" tx is the structured JSON
+# tx itself (consensus.validate_mempool_tx), accepted as an object or a JSON string. The fee is an
+# explicit field rather than inputs minus outputs; createrawtransaction leaves it 0 unless given
+# and fundrawtransaction sets it.
+#
+# Keys for signrawtransactionwithkey are hex:
+#   64 bytes  a wallet seed (BIP39 seed, as the wallet derives from its mnemonic); its spend key
+#             signs single-key inputs paying its primary address and multisig inputs listing it
+#   32 bytes  an ed25519 secret key; signs multisig inputs whose redeem script lists its pubkey,
+#             and single-key inputs when it is the only key given (a lone key cannot be matched
+#             to an address, which hashes the view key too)
+# Multisig inputs are signed only once m of the given keys are cosigners; partial multisig signing
+# goes through the PSBT RPCs (core.psbt).
+#
+# Funding: the chain debits a tx from its first input's address and returns the change to that
+# address when the block connects (consensus change outputs), so fundrawtransaction adds inputs
+# from one address only and no explicit change output (changepos is always -1).
+
+SEQUENCE_RBF = 0xFFFFFFFD
+SIGHASH_NAMES = {"ALL": SIGHASH_ALL, "ALL|ANYONECANPAY": SIGHASH_ALL | SIGHASH_ANYONECANPAY}
+_SIG_OVERHEAD = len(',"pubkey":"","sig":"","viewkey":""') + 64 + 130 + 64  # per signed single-key input
+
+
+def lookup_prev(s, txid: str, vout: int) -> Optional[Dict[str, Any]]:
+    u = s.query(UTXO).filter(UTXO.txid == txid, UTXO.vout == vout, UTXO.spent.is_(False)).first()
+    return {"address": u.address, "amount": float(u.amount)} if u else None
+
+
+def load_tx(raw: Any) -> Dict[str, Any]:
+    """A structured tx given as an object or a JSON string; raises ValueError."""
+    try:
+        tx = json.loads(raw) if isinstance(raw, str) else copy.deepcopy(raw)
+    except RecursionError:
+        raise ValueError("TX decode failed: nested too deeply")
+    if not isinstance(tx, dict) or not isinstance(tx.get("inputs"), list) or not isinstance(tx.get("outputs"), list):
+        raise ValueError("TX decode failed")
+    return tx
+
+
+def parse_output(o: Dict[str, Any]) -> Dict[str, Any]:
+    """{"address", "amount"}, Bitcoin Core style {address: amount}, or a data output {"data": hex}."""
+    if not isinstance(o, dict):
+        raise ValueError("each output must be an object")
+    if "data" in o:
+        out = {"data": str(o["data"]).lower(), "amount": 0.0}
+        data_output_bytes(out)
+        return out
+    if "address" not in o and len(o) == 1:
+        (address, amount), = o.items()
+        o = {"address": address, "amount": amount}
+    try:
+        amount = float(o.get("amount", 0.0))
+    except (TypeError, OverflowError):
+        raise ValueError("output amount must be a number")
+    if not o.get("address") or not math.isfinite(amount) or amount <= 0:
+        raise ValueError("each output needs an address and a positive amount")
+    return {"address": o["address"], "amount": amount}
+
+
+def build_unsigned(inputs: List[Dict[str, Any]], outputs: List[Dict[str, Any]], fee: float = 0.0,
+                   replaceable: bool = False) -> Tuple[Dict[str, Any], List[Optional[Dict[str, Any]]]]:
+    """Unsigned tx spending inputs [{txid, vout}] to outputs; returns (tx, prev output per input)."""
+    if not outputs:
+        raise ValueError("outputs are required")
+    tx_inputs: List[Dict[str, Any]] = []
+    prevs: List[Optional[Dict[str, Any]]] = []
+    db = get_db()
+    with db.session() as s:
+        for i in inputs:
+            txid = str(i.get("txid") or "").strip().lower()
+            vout = int(i.get("vout", -1))
+            if not txid or vout < 0:
+                raise ValueError("each input needs txid and vout")
+            prev = lookup_prev(s, txid, vout)
+            prevs.append(prev)
+            inp: Dict[str, Any] = {"txid": txid, "vout": vout}
+            if prev:
+                inp["address"] = prev["address"]
+            if i.get("redeem") is not None:
+                inp["redeem"] = multisig_redeem(i["redeem"].get("m", 0), i["redeem"].get("pubkeys"))
+            if replaceable or i.get("sequence") is not None:
+                inp["sequence"] = int(i["sequence"]) if i.get("sequence") is not None else SEQUENCE_RBF
+            tx_inputs.append(inp)
+    tx_outputs = [parse_output(o) for o in outputs]
+    if "data" in tx_outputs[0]:
+        raise ValueError("the first output must pay an address")
+    tx = {"version": 1, "inputs": tx_inputs, "outputs": tx_outputs, "fee": float(fee), "timestamp": now_ms() // 1000}
+    return tx, prevs
+
+
+# ----- signrawtransactionwithkey -----
+
+def _key_entries(privkeys: List[str]) -> List[Dict[str, Any]]:
+    keys = []
+    for k in privkeys:
+        try:
+            raw = bytes.fromhex(str(k).strip())
+        except ValueError:
+            raise ValueError("Invalid private key encoding (hex expected)")
+        if len(raw) == 64:
+            sk_spend, pk_spend = ed25519_keypair_from_seed(raw, ctx=b"smelly-spend")
+            _, pk_view = ed25519_keypair_from_seed(raw, ctx=b"smelly-view")
+            keys.append({"sk": sk_spend[:32], "pubkey": pk_spend.hex(), "view": pk_view,
+                         "address": encode_address(pk_view, pk_spend)})
+        elif len(raw) == 32:
+            from nacl.signing import SigningKey
+
+            keys.append({"sk": raw, "pubkey": SigningKey(raw).verify_key.encode().hex(), "view": None, "address": None})
+        else:
+            raise ValueError("Invalid private key length (32-byte key or 64-byte wallet seed)")
+    return keys
+
+
+def sign_with_keys(raw: Any, privkeys: List[str], prevtxs: Optional[List[Dict[str, Any]]] = None,
+                   sighashtype: str = "ALL") -> Dict[str, Any]:
+    """Core's signrawtransactionwithkey result: {"tx", "complete", "errors"?}."""
+    if sighashtype not in SIGHASH_NAMES:
+        raise ValueError(f"unsupported sighashtype {sighashtype!r}")
+    sighash_type = SIGHASH_NAMES[sighashtype]
+    tx = load_tx(raw)
+    keys = _key_entries(privkeys)
+    given = {(str(p.get("txid") or "").lower(), int(p.get("vout", -1))): {"address": p.get("address"), "amount": float(p.get("amount", 0.0))}
+             for p in prevtxs or [] if isinstance(p, dict)}
+    errors: List[Dict[str, Any]] = []
+    db = get_db()
+    with db.session() as s:
+        for idx, inp in enumerate(tx["inputs"]):
+            ref = (str(inp.get("txid") or "").lower(), int(inp.get("vout", -1)))
+            prev = given.get(ref) or lookup_prev(s, *ref)
+
+            def fail(msg: str):
+                errors.append({"txid": ref[0], "vout": ref[1], "error": msg})
+
+            if prev is None:
+                fail("Input not found or already spent")
+                continue
+            if verify_transaction_input(tx, idx, prev):
+                continue  # already signed
+            redeem = inp.get("redeem")
+            if redeem:
+                signers = [k for pk in redeem.get("pubkeys") or [] for k in keys if k["pubkey"] == pk]
+                if len(signers) < int(redeem.get("m", 0)):
+                    fail("Not enough cosigner keys; use the PSBT RPCs for partial multisig signing")
+                    continue
+                sigs = [sign_transaction_input(tx, idx, prev, k["sk"], sighash_type)["sig"] for k in signers[: int(redeem["m"])]]
+                tx["inputs"][idx] = dict(inp, sigs=sigs)
+            else:
+                match = [k for k in keys if k["address"] == prev["address"]]
+                if not match and len(keys) == 1 and keys[0]["address"] is None:
+                    match = keys
+                if not match:
+                    fail("Unable to sign input, no key for its address")
+                    continue
+                tx["inputs"][idx] = dict(inp, **sign_transaction_input(tx, idx, prev, match[0]["sk"], sighash_type,
+                                                                       match[0]["view"]))
+            if not verify_transaction_input(tx, idx, prev):
+                fail("Signature verification failed")
+    out: Dict[str, Any] = {"tx": tx, "complete": not errors}
+    if errors:
+        out["errors"] = errors
+    return out
+
+
+# ----- fundrawtransaction -----
+
+def estimate_size(tx: Dict[str, Any]) -> int:
+    """Serialized size once signed (single-key signature fields added to unsigned inputs)."""
+    base = len(json.dumps(tx, separators=(",", ":"), sort_keys=True).encode("utf-8"))
+    return base + sum(_SIG_OVERHEAD for i in tx["inputs"] if not i.get("sig") and not i.get("redeem"))
+
+
+def mempool_spent() -> Set[Tuple[str, int]]:
+    """Outpoints already spent by structured mempool txs."""
+    out: Set[Tuple[str, int]] = set()
+    db = get_db()
+    with db.session() as s:
+        for (raw,) in s.query(MempoolTx.raw).all():
+            try:
+                tx = json.loads(raw) if raw and raw.lstrip().startswith("{") else {}
+                out.update((str(i.get("txid") or "").lower(), int(i.get("vout", -1))) for i in tx.get("inputs") or [])
+            except (ValueError, AttributeError, TypeError):
+                continue
+    return out
+
+
+def fund(raw: Any, source: str, fee_rate_per_kb: Optional[float] = None,
+         subtract_fee_from: Optional[List[int]] = None, replaceable: bool = False) -> Dict[str, Any]:
+    """
+    Add inputs from `source` (largest first) until outputs + fee are covered and set the fee.
+    Returns {"tx", "fee", "changepos": -1}; raises ValueError on insufficient funds.
+    """
+    from core.consensus import get_chain_height, spendable_at
+
+    cfg = get_config()
+    tx = load_tx(raw)
+    min_fee = float(cfg.get("mempool.min_fee", 0.00001))
+    rate = float(fee_rate_per_kb) if fee_rate_per_kb is not None else min_fee
+    if tx["inputs"] and any(i.get("address") not in (None, source) for i in tx["inputs"]):
+        raise ValueError("preset inputs must all spend from the funding address")
+    for i in tx["inputs"]:
+        i.setdefault("address", source)
+    subtract = sorted(set(subtract_fee_from or []))
+    if any(not (0 <= n < len(tx["outputs"])) or "data" in tx["outputs"][n] for n in subtract):
+        raise ValueError("subtractFeeFromOutputs: invalid output index")
+    out_total = sum(float(o.get("amount", 0.0)) for o in tx["outputs"])
+    used = {(str(i.get("txid") or "").lower(), int(i.get("vout", -1))) for i in tx["inputs"]} | mempool_spent()
+
+    db = get_db()
+    with db.session() as s:
+        in_total = 0.0
+        for i in tx["inputs"]:
+            prev = lookup_prev(s, str(i.get("txid") or "").lower(), int(i.get("vout", -1)))
+            if prev is None:
+                raise ValueError(f"Input {i.get('txid')}:{i.get('vout')} not found or already spent")
+            in_total += prev["amount"]
+        candidates = (
+            s.query(UTXO)
+            .filter_by(address=source, spent=False)
+            .filter(spendable_at(get_chain_height() + 1))
+            .order_by(UTXO.amount.desc())
+            .all()
+        )
+        candidates = [u for u in candidates if (u.txid, int(u.vout)) not in used]
+        while True:
+            fee = max(min_fee, rate * estimate_size(tx) / 1000.0)
+            need = out_total + (0.0 if subtract else fee)
+            if tx["inputs"] and in_total + 1e-12 >= need:
+                break
+            if not candidates:
+                raise ValueError(f"Insufficient funds: {source} has {in_total:.8f} spendable, need {need:.8f}")
+            u = candidates.pop(0)
+            inp: Dict[str, Any] = {"txid": u.txid, "vout": int(u.vout), "address": source}
+            if replaceable:
+                inp["sequence"] = SEQUENCE_RBF
+            tx["inputs"].append(inp)
+            in_total += float(u.amount)
+    if subtract:
+        share = fee / len(subtract)
+        for n in subtract:
+            tx["outputs"][n]["amount"] = float(tx["outputs"][n]["amount"]) - share
+            if tx["outputs"][n]["amount"] <= 0:
+                raise ValueError("The transaction amount is too small to pay the fee")
+    tx["fee"] = round(fee, 8)
+    return {"tx": tx, "fee": tx["fee"], "changepos": -1}
@@ -0,0 +1,174 @@
+from __future__ import annotations
+
+import json
+import time
+from typing import Any, Callable, Dict, List, Optional, Tuple
+
+from core.db import (
+    get_db, AddressBalance, BlockFilter, BlockHeader, FairnessEpoch, KV, Reward, Transaction, TxIndex, UTXO,
+)
+from core.utils import now_ms
+from core import logs
+
+
+# Rebuild the chain state from the raw blocks in the flat files (node --reindex,
+# `python -m tools.run reindex`).
+#
+# start() picks the current tip as the target, checks that every block from it back to genesis
+# has a stored record (core.blockstore), then wipes everything derived from blocks: headers above
+# genesis, the UTXO set, rewards, tx index, block filters, address balances and the UTXO set hash
+# state. Fairness epochs are marked unsettled with it, since their payouts are rewards and UTXOs
+# too and are paid again when the replay crosses each epoch boundary (the accrued credits stay).
+# Confirmed transactions go back to the mempool first (their bodies live only in the
+# transactions table), so replaying a block finds the txs it lists. run() then reconnects the
+# target chain in height order through consensus.accept_external_header, i.e. with full
+# validation, and the per-block indexes are rebuilt as each block connects. accept_external_header
+# drops listed txs it cannot include, so a block that connects without all of them is
+# disconnected again and the reindex stops there.
+#
+# Progress is the chain itself plus the KV row reindex_state_json naming the target: a node
+# stopped mid-way resumes at its current height on the next run() (or --reindex), without
+# wiping again. Flat-file records are never rewritten (store_block is idempotent per hash).
+
+_STATE_KEY = "reindex_state_json"
+_DERIVED_KV = ("utxo_muhash_json", "utxo_muhash_checkpoints_json", "address_balances_tip", "txindex_status_json")
+ProgressFn = Callable[[int, int, str], None]
+log = logs.get("storage")
+
+
+def state() -> Optional[Dict[str, Any]]:
+    db = get_db()
+    with db.session() as s:
+        row = s.get(KV, _STATE_KEY)
+    try:
+        return json.loads(row.v) if row and row.v else None
+    except ValueError:
+        return None
+
+
+def _chain_path(target: str, genesis: str) -> Tuple[List[str], Optional[str]]:
+    """Block hashes from height 1 up to target, read back through the stored records."""
+    from core.blockstore import read_block
+
+    path: List[str] = []
+    h = target
+    while h != genesis:
+        rec = read_block(h)
+        if rec is None:
+            return [], f"no stored record for block {h} (height {len(path)} below the tip); resync instead"
+        path.append(h)
+        h = rec.prev_hash_hex
+    path.reverse()
+    return path, None
+
+
+def _genesis(s) -> Optional[BlockHeader]:
+    return s.query(BlockHeader).filter_by(height=0).first()
+
+
+def start() -> Tuple[Optional[Dict[str, Any]], Optional[str]]:
+    """Record the target and wipe derived state. A reindex already in progress is kept as is."""
+    from core.invalidation import requeue_tx
+
+    current = state()
+    if current is not None:
+        return current, None
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        genesis = _genesis(s)
+    if tip is None or genesis is None:
+        return None, "no chain to reindex"
+    path, err = _chain_path(tip.hash_hex, genesis.hash_hex)
+    if err:
+        return None, err
+    st = {"target": tip.hash_hex, "target_height": tip.height, "started_ms": now_ms()}
+    with db.session() as s:
+        requeued = 0
+        for t in s.query(Transaction).filter(Transaction.in_block_hash.isnot(None)).all():
+            t.in_block_hash = None
+            if t.raw:
+                requeue_tx(s, t)
+                requeued += 1
+        s.flush()
+        for model in (UTXO, Reward, TxIndex, BlockFilter, AddressBalance):
+            s.query(model).delete(synchronize_session=False)
+        s.query(FairnessEpoch).update({FairnessEpoch.settled: False}, synchronize_session=False)
+        s.query(BlockHeader).filter(BlockHeader.height > 0).delete(synchronize_session=False)
+        s.query(KV).filter(KV.k.in_(_DERIVED_KV)).delete(synchronize_session=False)
+        s.merge(KV(k=_STATE_KEY, v=json.dumps(st, separators=(",", ":"))))
+        s.commit()
+    log.info("reindex: derived state wiped", extra=logs.fields(target=tip.hash_hex, height=tip.height, requeued_txs=requeued))
+    return st, None
+
+
+def run(progress: Optional[ProgressFn] = None) -> Tuple[Dict[str, Any], Optional[str]]:
+    """
+    Reconnect the target chain from the flat files, resuming at the current height.
+    Returns ({"connected", "height", "target", "done"}, error); on error the state is kept.
+    """
+    from core.blockio import unconfirmed_txids
+    from core.blockstore import read_block
+    from core.consensus import accept_external_header
+    from core.invalidation import disconnect_tip
+
+    st = state()
+    if st is None:
+        return {"connected": 0, "done": True}, "no reindex in progress"
+    db = get_db()
+    with db.session() as s:
+        genesis = _genesis(s)
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+    path, err = _chain_path(st["target"], genesis.hash_hex if genesis else "")
+    if err:
+        return {"connected": 0, "done": False}, err
+    height = tip.height if tip else 0
+    if height > 0 and (height > len(path) or path[height - 1] != tip.hash_hex):
+        return {"connected": 0, "done": False}, f"tip {tip.hash_hex} is not on the reindex target chain"
+    connected = 0
+    last_log = time.time()
+    for h in path[height:]:
+        rec = read_block(h)
+        new_hash, err = accept_external_header(
+            prev_hash_hex=rec.prev_hash_hex,
+            merkle_root_hex=rec.merkle_root_hex,
+            version=rec.version,
+            timestamp=rec.timestamp,
+            target_hex=rec.target,
+            nonce=rec.nonce,
+            miner_address=rec.miner_address,
+            txids_snapshot=rec.txids,
+        )
+        if err or new_hash != h:
+            msg = f"block {h} at height {height + connected + 1} rejected: {err or f'connected as {new_hash}'}"
+            log.error(f"reindex: {msg}")
+            return {"connected": connected, "height": height + connected, "target": st["target"], "done": False}, msg
+        with db.session() as s:
+            missing = unconfirmed_txids(s, h, height + connected + 1, rec.txids)
+            if missing:
+                disconnect_tip(s)
+                s.commit()
+        if missing:
+            msg = (f"block {h} at height {height + connected + 1} connected without {len(missing)} of its txs "
+                   f"(first {missing[0]}); disconnected again")
+            log.error(f"reindex: {msg}")
+            return {"connected": connected, "height": height + connected, "target": st["target"], "done": False}, msg
+        connected += 1
+        if time.time() - last_log >= 5.0 or connected == len(path) - height:
+            log.info("reindex: progress", extra=logs.fields(height=height + connected, target_height=len(path)))
+            if progress:
+                progress(height + connected, len(path), h)
+            last_log = time.time()
+    with db.session() as s:
+        s.query(KV).filter(KV.k == _STATE_KEY).delete(synchronize_session=False)
+        s.commit()
+    log.info("reindex: done", extra=logs.fields(height=len(path), connected=connected))
+    return {"connected": connected, "height": len(path), "target": st["target"], "done": True}, None
+
+
+def reindex(progress: Optional[ProgressFn] = None) -> Tuple[Dict[str, Any], Optional[str]]:
+    """start() then run(): a fresh reindex, or the continuation of an interrupted one."""
+    st, err = start()
+    if err:
+        return {"connected": 0, "done": False}, err
+    return run(progress)
@@ -0,0 +1,173 @@
+from __future__ import annotations
+
+import json
+from typing import Any, Dict, List, Optional, Tuple
+
+from fastapi import APIRouter, Depends, HTTPException, Request
+from fastapi.responses import JSONResponse, Response
+
+from core.config import get_config
+from core.db import get_db, BlockHeader, MempoolTx, Transaction, Reward
+from core.target import target_to_difficulty
+
+
+# Read-only REST endpoints for block explorers (mounted on the RPC app under /rest).
+#
+#   GET /rest/block/<hash>[.json|.bin|.hex]      header fields + txids; bin/hex = block file record
+#   GET /rest/tx/<txid>[.json|.hex]              confirmed, coinbase or mempool transaction
+#   GET /rest/headers/<count>/<hash>[.json|.bin|.hex]   up to MAX_REST_HEADERS headers from <hash>
+#   GET /rest/chaininfo[.json]
+#
+# Without an extension the format follows the Accept header (application/octet-stream -> bin,
+# text/plain -> hex, otherwise json). Binary encodings are the core.blockio record payload for
+# blocks and the consensus header serialization for headers. Disabled with network.rest_enabled.
+
+MAX_REST_HEADERS = 2000
+
+_FORMATS = ("json", "bin", "hex")
+
+router = APIRouter(prefix="/rest")
+
+
+def _require_enabled():
+    if not bool(get_config().get("network.rest_enabled", True)):
+        raise HTTPException(status_code=404, detail={"error": "REST interface disabled"})
+
+
+def _split_format(name: str, request: Request, allowed: Tuple[str, ...] = _FORMATS) -> Tuple[str, str]:
+    """'abcd.hex' -> ('abcd', 'hex'); no extension negotiates from Accept."""
+    base, dot, ext = (name or "").rpartition(".")
+    if dot:
+        fmt = ext.lower()
+    else:
+        base = name or ""
+        accept = (request.headers.get("accept") or "").lower()
+        if "application/octet-stream" in accept:
+            fmt = "bin"
+        elif "text/plain" in accept:
+            fmt = "hex"
+        else:
+            fmt = "json"
+    if fmt not in allowed:
+        raise HTTPException(status_code=400, detail={"error": f"unsupported format {fmt!r}", "formats": list(allowed)})
+    return base.strip().lower(), fmt
+
+
+def _respond(fmt: str, obj: Any, raw: bytes) -> Response:
+    if fmt == "bin":
+        return Response(content=raw, media_type="application/octet-stream")
+    if fmt == "hex":
+        return Response(content=raw.hex() + "\n", media_type="text/plain")
+    return JSONResponse(obj)
+
+
+def _header_json(row: BlockHeader, tip_height: int) -> Dict[str, Any]:
+    return {
+        "hash": row.hash_hex,
+        "height": row.height,
+        "confirmations": tip_height - row.height + 1,
+        "version": row.version,
+        "previousblockhash": row.prev_hash_hex if row.height > 0 else None,
+        "merkleroot": row.merkle_root_hex,
+        "time": row.timestamp,
+        "target": row.target,
+        "difficulty": target_to_difficulty(row.target),
+        "nonce": int(row.nonce),
+        "miner": row.miner_address,
+        "nTx": row.tx_count,
+    }
+
+
+def _header_bytes(row: BlockHeader) -> bytes:
+    from core.consensus import Header
+
+    return Header(version=row.version, prev_hash_hex=row.prev_hash_hex, merkle_root_hex=row.merkle_root_hex,
+                  timestamp=row.timestamp, target=row.target, nonce=int(row.nonce),
+                  miner_address=row.miner_address, tx_count=row.tx_count).serialize()
+
+
+@router.get("/block/{name}", dependencies=[Depends(_require_enabled)])
+def rest_block(name: str, request: Request):
+    from core.blockio import encode_record, record_for_row
+    from core.blockstore import read_raw
+    from core import headeronly
+
+    hh, fmt = _split_format(name, request)
+    db = get_db()
+    with db.session() as s:
+        row = s.query(BlockHeader).filter_by(hash_hex=hh).first()
+        if row is None:
+            raise HTTPException(status_code=404, detail={"error": "block not found", "hash": hh})
+        if headeronly.is_pruned(s, row.height):
+            raise HTTPException(status_code=410, detail=headeronly.pruned_error(row.height, headeronly.body_floor(s)))
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        rec = record_for_row(s, row)
+        out = _header_json(row, tip.height)
+    stored = read_raw(hh)
+    raw = stored[8:] if stored is not None else encode_record(rec)[8:]
+    out["tx"] = rec.txids
+    return _respond(fmt, out, raw)
+
+
+@router.get("/tx/{name}", dependencies=[Depends(_require_enabled)])
+def rest_tx(name: str, request: Request):
+    txid, fmt = _split_format(name, request, ("json", "hex"))
+    db = get_db()
+    with db.session() as s:
+        t = s.query(Transaction).filter_by(txid=txid).first()
+        raw_json: Optional[str] = None
+        out: Dict[str, Any] = {"txid": txid}
+        if t is not None:
+            raw_json = t.raw
+            out.update(blockhash=t.in_block_hash, fee=t.fee)
+        else:
+            m = s.query(MempoolTx).filter_by(txid=txid).first()
+            if m is not None and m.raw:
+                raw_json = m.raw
+                out.update(blockhash=None, fee=m.fee, mempool=True)
+            else:
+                r = s.query(Reward).filter_by(txid=txid).first()
+                if r is None:
+                    raise HTTPException(status_code=404, detail={"error": "tx not found", "txid": txid})
+                blk = s.query(BlockHeader).filter_by(height=r.height).first()
+                out.update(coinbase=True, blockhash=blk.hash_hex if blk else None, height=r.height,
+                           outputs=[{"address": r.miner_address, "amount": r.amount}])
+                return _respond(fmt, out, json.dumps(out, separators=(",", ":"), sort_keys=True).encode("utf-8"))
+    try:
+        out["tx"] = json.loads(raw_json or "{}")
+    except ValueError:
+        out["tx"] = None
+    return _respond(fmt, out, (raw_json or "").encode("utf-8"))
+
+
+@router.get("/headers/{count}/{name}", dependencies=[Depends(_require_enabled)])
+def rest_headers(count: int, name: str, request: Request):
+    hh, fmt = _split_format(name, request)
+    if count <= 0 or count > MAX_REST_HEADERS:
+        raise HTTPException(status_code=400, detail={"error": f"count must be 1..{MAX_REST_HEADERS}"})
+    db = get_db()
+    with db.session() as s:
+        start = s.query(BlockHeader).filter_by(hash_hex=hh).first()
+        if start is None:
+            raise HTTPException(status_code=404, detail={"error": "block not found", "hash": hh})
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        rows: List[BlockHeader] = (
+            s.query(BlockHeader)
+            .filter(BlockHeader.height >= start.height)
+            .order_by(BlockHeader.height.asc())
+            .limit(count)
+            .all()
+        )
+        headers = [_header_json(r, tip.height) for r in rows]
+        encoded = [_header_bytes(r) for r in rows]
+    # Headers are variable length (miner address), so bin/hex entries are u32 LE length-prefixed
+    raw = b"".join(len(e).to_bytes(4, "little") + e for e in encoded)
+    return _respond(fmt, headers, raw)
+
+
+@router.get("/chaininfo", dependencies=[Depends(_require_enabled)])
+@router.get("/chaininfo.json", dependencies=[Depends(_require_enabled)])
+def rest_chaininfo():
+    from core.rpc import rpc_getblockchaininfo
+
+    return rpc_getblockchaininfo()
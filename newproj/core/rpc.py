@@ -1,7 +1,7 @@
 from __future__ import annotations
 
-from typing import Any, Dict, Optional, Tuple, List
-from fastapi import FastAPI, HTTPException
+from typing import Any, Dict, Optional, Set, Tuple, List
+from fastapi import FastAPI, HTTPException, WebSocket
 from pydantic import BaseModel
 import uvicorn
 import time
@@ -9,8 +9,8 @@ import uuid
 import json
 import hmac
 import hashlib
-import logging
-from logging import Logger
+import asyncio
+import contextvars
 
 from core.config import get_config
 from core.consensus import (
@@ -22,12 +22,31 @@ from core.consensus import (
     add_genesis_if_needed,
     accept_external_header,
     validate_mempool_tx,
+    expire_mempool,
+    next_block_target,
+    NONCE_RANGE,
 )
 from core.db import get_db, BlockHeader, MempoolTx, FairnessEpoch, FairnessCredit, KV
-from core.utils import ensure_dirs, now_ms
-from core.pow.randomx_stub import difficulty_to_target
+from core.merkle import merkle_root
+from core.utils import ensure_dirs, now_ms, now_sec, set_mock_time, get_mock_time
+from core import safemode
+from core import metrics
+from core import rest
+from core import txgraph
+from core import logs
+from core import rpcauth
+from core import notify
+from core import p2pstate
+from core import versionbits
+from core import blocksize
+from core import headeronly
+from core import nettime
+from core.blocktemplate import BlockTemplateProvider
+from core.target import to_int, U256_MAX
 from sqlalchemy import func
 import socket
+import os
+import threading
 
 # In-memory job cache for client-side mining (reset on restart)
 _WORK_JOBS: Dict[str, Dict[str, Any]] = {}
@@ -39,44 +58,216 @@ _NONCE_WINDOW_POW2 = 21  # 2^21
 _NEAR_TARGET_RATE_PER_MIN = 3
 
 app = FastAPI(title="SMELLY JSON-RPC", version="0.2")
-
-# Structured + colorized logger (very verbose for deep diagnostics)
-class _Color:
-    RESET = "\x1b[0m"
-    RED = "\x1b[31m"
-    GREEN = "\x1b[32m"
-    YEL = "\x1b[33m"
-    BLUE = "\x1b[34m"
-    MAG = "\x1b[35m"
-    CYA = "\x1b[36m"
-    DIM = "\x1b[2m"
-
-class ColorFormatter(logging.Formatter):
-    def format(self, record: logging.LogRecord) -> str:
-        level = record.levelno
-        color = _Color.RESET
-        if level >= logging.ERROR:
-            color = _Color.RED
-        elif level >= logging.WARNING:
-            color = _Color.YEL
-        elif level >= logging.INFO:
-            color = _Color.CYA
+app.include_router(rest.router)  # read-only /rest/* for explorers (network.rest_enabled)
+
+rpc_logger = logs.get("rpc")
+
+
+# Concurrency limit and per-method timeouts. Innermost middleware: the ACL below refuses callers
+# before they take a slot, and 408/503 answers still reach the request span and latency metrics.
+# At most network.rpc_max_concurrent_requests handlers run at once; up to rpc_max_queued_requests
+# more wait for a slot (rpc_queue_timeout_sec), anything beyond is refused with 503. A handler that
+# runs past its timeout (rpc_method_timeouts[method], else rpc_timeout_sec; 0 = none) answers 408;
+# sync handlers cannot be interrupted, so the slot stays taken until the handler really returns.
+# Long-poll handlers (get_block_template with longpollid) give their slot back with
+# release_rpc_slot() before they start waiting, so miners parked on a template cannot starve
+# other callers.
+_RPC_INFLIGHT = metrics.gauge("smelly_rpc_inflight_requests", "RPC requests being handled")
+_RPC_QUEUED = metrics.gauge("smelly_rpc_queued_requests", "RPC requests waiting for a handler slot")
+_RPC_QUEUE_WAIT = metrics.histogram("smelly_rpc_queue_wait_seconds", "Time RPC requests waited for a slot")
+_RPC_REJECTED = metrics.counter("smelly_rpc_rejected_total", "RPC requests refused by the limiter", ("reason",))
+_rpc_limiter: Dict[str, Any] = {"sem": None, "queued": 0}
+
+
+class _RpcSlot:
+    """A taken handler slot; released once, from the event loop or a handler's worker thread."""
+
+    def __init__(self, sem: asyncio.Semaphore, loop: asyncio.AbstractEventLoop):
+        self._sem = sem
+        self._loop = loop
+        self._lock = threading.Lock()
+        self._released = False
+
+    def release(self):
+        with self._lock:
+            if self._released:
+                return
+            self._released = True
+        _RPC_INFLIGHT.dec()
+        try:
+            running = asyncio.get_running_loop()
+        except RuntimeError:
+            running = None
+        if running is self._loop:
+            self._sem.release()
         else:
-            color = _Color.DIM
-        prefix = f"{color}[RPC]{_Color.RESET}"
-        ts = time.strftime("%Y-%m-%d %H:%M:%S", time.localtime())
-        return f"{prefix} {ts} {record.levelname} {record.getMessage()}"
+            self._loop.call_soon_threadsafe(self._sem.release)
+
+
+_rpc_slot: contextvars.ContextVar[Optional[_RpcSlot]] = contextvars.ContextVar("rpc_slot", default=None)
+
+
+def release_rpc_slot():
+    """Give the current request's handler slot back early (before a long-poll wait)."""
+    slot = _rpc_slot.get()
+    if slot is not None:
+        slot.release()
+
+
+def _rpc_timeout(method: str) -> float:
+    cfg = get_config()
+    per_method = cfg.get("network.rpc_method_timeouts", {}) or {}
+    return float(per_method.get(method, cfg.get("network.rpc_timeout_sec", 30)))
+
+
+@app.middleware("http")
+async def _rpc_limits(request, call_next):
+    method = rpcauth.method_of(request.url.path)
+    if request.method == "OPTIONS" or method == "metrics":
+        return await call_next(request)  # scrapes must keep working while the server is saturated
+    from fastapi.responses import JSONResponse
+
+    cfg = get_config()
+    if _rpc_limiter["sem"] is None:
+        _rpc_limiter["sem"] = asyncio.Semaphore(max(1, int(cfg.get("network.rpc_max_concurrent_requests", 16))))
+    sem: asyncio.Semaphore = _rpc_limiter["sem"]
+    if sem.locked() and _rpc_limiter["queued"] >= int(cfg.get("network.rpc_max_queued_requests", 64)):
+        _RPC_REJECTED.inc(labels=("busy",))
+        return JSONResponse(status_code=503, content={"detail": {"error": "rpc server busy"}}, headers={"Retry-After": "1"})
+    t0 = time.perf_counter()
+    _rpc_limiter["queued"] += 1
+    _RPC_QUEUED.set(_rpc_limiter["queued"])
+    try:
+        await asyncio.wait_for(sem.acquire(), timeout=float(cfg.get("network.rpc_queue_timeout_sec", 10)))
+    except asyncio.TimeoutError:
+        _RPC_REJECTED.inc(labels=("queue_timeout",))
+        return JSONResponse(status_code=503, content={"detail": {"error": "rpc server busy"}}, headers={"Retry-After": "1"})
+    finally:
+        _rpc_limiter["queued"] -= 1
+        _RPC_QUEUED.set(_rpc_limiter["queued"])
+    _RPC_QUEUE_WAIT.observe(time.perf_counter() - t0)
+    _RPC_INFLIGHT.inc()
+    slot = _RpcSlot(sem, asyncio.get_running_loop())
+    token = _rpc_slot.set(slot)
+    held_by_handler = False
+    try:
+        timeout = _rpc_timeout(method)
+        if timeout <= 0:
+            return await call_next(request)
+        handler = asyncio.ensure_future(call_next(request))
+        try:
+            return await asyncio.wait_for(asyncio.shield(handler), timeout=timeout)
+        except asyncio.TimeoutError:
+            # The handler keeps running; its slot is freed when it returns, not now
+            def _free_when_done(t: asyncio.Future):
+                if not t.cancelled():
+                    t.exception()  # retrieved, so a late failure is not reported as unhandled
+                slot.release()
+
+            held_by_handler = True
+            handler.add_done_callback(_free_when_done)
+            _RPC_REJECTED.inc(labels=("timeout",))
+            rpc_logger.warning("rpc request timed out", extra=logs.fields(rpc_method=method, timeout_sec=timeout))
+            return JSONResponse(status_code=408, content={"detail": {"error": f"rpc timeout after {timeout:g}s"}})
+    finally:
+        _rpc_slot.reset(token)
+        if not held_by_handler:
+            slot.release()
+
+
+# Method ACLs (core.rpcauth). Registered before the safe-mode and metrics middlewares so denials
+# still pass through the request span and the latency/status metrics below.
+@app.middleware("http")
+async def _rpc_acl(request, call_next):
+    if request.method == "OPTIONS" or not rpcauth.enabled():
+        return await call_next(request)
+    from fastapi.responses import JSONResponse
+
+    client = request.client.host if request.client else None
+    method = rpcauth.method_of(request.url.path)
+    caller, reason = rpcauth.authenticate(request.headers.get("authorization"))
+    if caller is None:
+        rpc_logger.warning(f"rpc auth failed: {reason}", extra=logs.fields(client=client, rpc_method=method))
+        return JSONResponse(status_code=401, content={"detail": {"error": reason}},
+                            headers={"WWW-Authenticate": 'Basic realm="smelly-rpc"'})
+    if not rpcauth.authorize(caller, request.method, request.url.path):
+        rpc_logger.warning("rpc method denied", extra=logs.fields(user=caller.name, role=caller.role, client=client, rpc_method=method))
+        return JSONResponse(status_code=403, content={"detail": {"error": f"method {method} not allowed for {caller.name}"}})
+    return await call_next(request)
+
+
+# Safe-mode warnings are attached to every response as a header (status cached briefly; it lives in KV)
+_safe_mode_cache: Dict[str, Any] = {"ts": 0, "warnings": []}
+
+
+@app.middleware("http")
+async def _safe_mode_warnings(request, call_next):
+    response = await call_next(request)
+    nowm = now_ms()
+    if nowm - _safe_mode_cache["ts"] > 2000:
+        try:
+            _safe_mode_cache["warnings"] = safemode.warnings()
+        except Exception:
+            _safe_mode_cache["warnings"] = []
+        _safe_mode_cache["ts"] = nowm
+    if _safe_mode_cache["warnings"]:
+        response.headers["X-Smelly-Warnings"] = " | ".join(_safe_mode_cache["warnings"])
+    return response
+
+
+# Prometheus: request latency per route template (not raw path, to keep label cardinality bounded)
+_RPC_LATENCY = metrics.histogram("smelly_rpc_request_seconds", "RPC request latency", ("method", "route"))
+_RPC_REQUESTS = metrics.counter("smelly_rpc_requests_total", "RPC requests by status", ("method", "route", "status"))
+_TIP_HEIGHT = metrics.gauge("smelly_chain_height", "Height of the active tip")
+_MEMPOOL_SIZE = metrics.gauge("smelly_mempool_txs", "Transactions in the mempool")
+_DB_BYTES = metrics.gauge("smelly_db_size_bytes", "On-disk size of node storage", ("store",))
+
+
+@app.middleware("http")
+async def _metrics_timing(request, call_next):
+    t0 = time.perf_counter()
+    with logs.span(rpc_logger, "request", method=request.method, path=request.url.path,
+                   client=request.client.host if request.client else None):
+        response = await call_next(request)
+    route = getattr(request.scope.get("route"), "path", "<unmatched>")
+    _RPC_LATENCY.observe(time.perf_counter() - t0, (request.method, route))
+    _RPC_REQUESTS.inc(labels=(request.method, route, str(response.status_code)))
+    return response
+
+
+def _dir_bytes(path: str) -> int:
+    total = 0
+    for root, _dirs, files in os.walk(path):
+        for f in files:
+            try:
+                total += os.path.getsize(os.path.join(root, f))
+            except OSError:
+                pass
+    return total
+
+
+def _collect_node_metrics():
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        _TIP_HEIGHT.set(-1 if tip is None else tip.height)
+        _MEMPOOL_SIZE.set(s.query(func.count(MempoolTx.id)).scalar() or 0)
+    sqlite_path = db.db_cfg.sqlite_path
+    if db.db_cfg.driver == "sqlite" and sqlite_path != ":memory:" and os.path.exists(sqlite_path):
+        _DB_BYTES.set(os.path.getsize(sqlite_path), ("sqlite",))
+    from core.blockstore import blocks_dir, enabled as blockstore_enabled
+    if blockstore_enabled():
+        _DB_BYTES.set(_dir_bytes(blocks_dir()), ("blocks",))
+
 
-def _mk_logger(name: str, level=logging.DEBUG) -> Logger:
-    lg = logging.getLogger(name)
-    lg.setLevel(level)
-    if not lg.handlers:
-        sh = logging.StreamHandler()
-        sh.setFormatter(ColorFormatter())
-        lg.addHandler(sh)
-    return lg
+metrics.register_collector(_collect_node_metrics)
 
-rpc_logger = _mk_logger("smelly.rpc", level=logging.DEBUG)
+
+@app.get("/metrics")
+def rpc_metrics():
+    """Prometheus text exposition of every metric registered in this process (core.metrics)."""
+    from fastapi.responses import Response
+    return Response(content=metrics.render(), media_type=metrics.CONTENT_TYPE)
 
 
 class MineRequest(BaseModel):
@@ -100,6 +291,11 @@ class SubmitWorkRequest(BaseModel):
     version: int
     merkle_root_hex: str
     prev_hash_hex: Optional[str] = None
+    txids: Optional[List[str]] = None  # pool's copy of the job's tx set; must match the issued snapshot
+
+
+class BlockHashRequest(BaseModel):
+    blockhash: str
 
 
 class TxSubmitRequest(BaseModel):
@@ -152,6 +348,59 @@ def on_startup():
     except Exception as e:
         rpc_logger.warning(f"startup: backend=unknown err={e}")
 
+    threading.Thread(target=_template_watcher, daemon=True).start()
+
+    # Flat block files: index any connected blocks written before the cold tier existed
+    try:
+        from core.blockstore import migrate_to_flat_files
+        n = migrate_to_flat_files()
+        if n:
+            rpc_logger.info(f"startup: blockstore migrated={n}")
+    except Exception as e:
+        rpc_logger.warning(f"startup: blockstore migration failed err={e}")
+
+    # Compact block filters: index blocks connected before the filter index existed
+    try:
+        from core.blockfilter import backfill
+        n = backfill()
+        if n:
+            rpc_logger.info(f"startup: blockfilter indexed={n}")
+    except Exception as e:
+        rpc_logger.warning(f"startup: blockfilter backfill failed err={e}")
+
+    # Transaction index (storage.txindex): index blocks connected while it was off or missing
+    try:
+        from core import txindex
+        n = txindex.backfill()
+        if n:
+            rpc_logger.info(f"startup: txindex indexed={n}")
+    except Exception as e:
+        rpc_logger.warning(f"startup: txindex backfill failed err={e}")
+
+    # UTXO set hash: recompute if the stored state does not describe the tip
+    try:
+        from core import utxohash
+        if utxohash.backfill():
+            rpc_logger.info("startup: utxo set hash recomputed")
+    except Exception as e:
+        rpc_logger.warning(f"startup: utxo set hash failed err={e}")
+
+    # Address balances (storage.address_index): rebuild if the table does not describe the tip
+    try:
+        from core import addrindex
+        if addrindex.backfill():
+            rpc_logger.info("startup: address balances rebuilt")
+    except Exception as e:
+        rpc_logger.warning(f"startup: address balances failed err={e}")
+
+    # Chain loaded from a snapshot: resume validating the history below its base
+    try:
+        from core import snapshot
+        if snapshot.start_background_validation():
+            rpc_logger.info("startup: resumed assumeutxo background validation")
+    except Exception as e:
+        rpc_logger.warning(f"startup: assumeutxo validation failed err={e}")
+
     # DB sanity
     try:
         with db.session() as s:
@@ -169,6 +418,148 @@ def rpc_get_height():
     return {"height": h}
 
 
+@app.get("/rpc/getchainwork")
+def rpc_getchainwork():
+    from core.consensus import chain_work
+
+    work = chain_work()
+    return {"height": get_chain_height(), "chainwork": f"{work:064x}"}
+
+
+def _tip_difficulty(tip: Optional[BlockHeader]) -> float:
+    from core.target import target_to_difficulty
+
+    return target_to_difficulty(tip.target) if tip is not None and tip.target else 0.0
+
+
+@app.get("/rpc/getdifficulty")
+def rpc_getdifficulty():
+    """Difficulty of the tip's target (U256_MAX / target; 1.0 = easiest)."""
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        return {"height": tip.height if tip else -1, "difficulty": _tip_difficulty(tip)}
+
+
+@app.get("/rpc/getnetworkhashps")
+def rpc_getnetworkhashps(nblocks: int = 120, height: int = -1):
+    """Hashes/sec estimated from the work and timestamps of the last nblocks blocks (height -1 = tip)."""
+    from core.consensus import network_hashps
+
+    if nblocks <= 0:
+        raise HTTPException(status_code=400, detail={"error": "nblocks must be positive"})
+    return {"networkhashps": network_hashps(nblocks, None if height < 0 else height)}
+
+
+@app.get("/rpc/getmininginfo")
+def rpc_getmininginfo():
+    from core.consensus import network_hashps
+    from core.target import target_to_bits
+
+    cfg = get_config()
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        pooled = s.query(func.count(MempoolTx.id)).scalar() or 0
+        out = {
+            "blocks": tip.height if tip else -1,
+            "difficulty": _tip_difficulty(tip),
+            "target": tip.target if tip else None,
+            "bits": f"{target_to_bits(tip.target):08x}" if tip and tip.target else None,
+            "currentblocktx": tip.tx_count if tip else 0,
+        }
+    out.update({
+        "networkhashps": network_hashps(int(cfg.get("mining.hashps_window_blocks", 120))),
+        "pooledtx": int(pooled),
+        "chain": str(cfg.get("network.name", "")),
+        "target_block_time_sec": int(cfg.get("consensus.target_block_time_sec", 60)),
+        "template": _TEMPLATES.info(),
+    })
+    return out
+
+
+@app.get("/rpc/getblockchaininfo")
+def rpc_getblockchaininfo():
+    from core.consensus import chain_work, network_hashps
+
+    cfg = get_config()
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        out = {
+            "chain": str(cfg.get("network.name", "")),
+            "blocks": tip.height if tip else -1,
+            "headers": tip.height if tip else -1,  # header-only chain: headers and blocks advance together
+            "bestblockhash": tip.hash_hex if tip else None,
+            "difficulty": _tip_difficulty(tip),
+            "time": tip.timestamp if tip else None,
+        }
+    out["chainwork"] = f"{chain_work():064x}"
+    out["networkhashps"] = network_hashps(int(cfg.get("mining.hashps_window_blocks", 120)))
+    out["safe_mode"] = safemode.is_active()
+    out["softforks"] = versionbits.softforks()
+    with db.session() as s:
+        out.update(headeronly.info(s))
+    return out
+
+
+@app.get("/rpc/getblockstats")
+def rpc_getblockstats(hash_or_height: str):
+    """Fee, feerate, size and value statistics for one block (hash or height)."""
+    from core.chainstats import block_stats
+
+    stats, err = block_stats(hash_or_height)
+    if err:
+        raise HTTPException(status_code=410 if err == headeronly.BODY_PRUNED else 404, detail={"error": err, "block": hash_or_height})
+    return stats
+
+
+@app.get("/rpc/getchaintxstats")
+def rpc_getchaintxstats(nblocks: Optional[int] = None, blockhash: Optional[str] = None):
+    """Transaction count and rate over the nblocks blocks ending at blockhash (default tip, ~1 day)."""
+    from core.chainstats import chain_tx_stats
+
+    cfg = get_config()
+    day_blocks = max(1, 86400 // max(1, int(cfg.get("consensus.target_block_time_sec", 60))))
+    stats, err = chain_tx_stats(nblocks, blockhash, default_window=day_blocks)
+    if err:
+        raise HTTPException(status_code=404 if err == "block not found" else 400, detail={"error": err})
+    return stats
+
+
+@app.post("/rpc/invalidateblock")
+def rpc_invalidateblock(req: BlockHashRequest):
+    """Mark a block invalid and disconnect it and its descendants (persisted across restarts)."""
+    from core.invalidation import invalidate_block
+
+    out, err = invalidate_block(req.blockhash)
+    if err:
+        raise HTTPException(status_code=404 if err == "block not found" else 400, detail={"error": err, "blockhash": req.blockhash})
+    rpc_logger.warning(f"invalidateblock: {req.blockhash[:16]}.. disconnected={len(out['disconnected'])} tip={(out['tip'] or '')[:16]}")
+    _TEMPLATES.refresh(force=True)
+    return out
+
+
+@app.post("/rpc/reconsiderblock")
+def rpc_reconsiderblock(req: BlockHashRequest):
+    """Clear a block's invalid flag and reconnect the blocks disconnected with it when possible."""
+    from core.invalidation import reconsider_block
+
+    out, err = reconsider_block(req.blockhash)
+    if err:
+        raise HTTPException(status_code=400, detail={"error": err, "blockhash": req.blockhash})
+    rpc_logger.warning(f"reconsiderblock: {req.blockhash[:16]}.. reconnected={len(out['reconnected'])}")
+    _TEMPLATES.refresh(force=True)
+    return out
+
+
+@app.get("/rpc/listinvalidblocks")
+def rpc_listinvalidblocks():
+    from core.invalidation import list_invalid
+
+    return {"invalid": list_invalid()}
+
+
 @app.get("/rpc/pow_backend")
 def rpc_pow_backend():
     try:
@@ -197,6 +588,105 @@ def rpc_get_header_by_height(height: int):
     }
 
 
+@app.get("/rpc/validateaddress")
+def rpc_validateaddress(address: str):
+    """Checks prefix, length, version and checksum of an address for the configured network."""
+    from core.crypto import ADDRESS_PREFIX, SCRIPT_ADDRESS_VERSION, check_address, decode_address, network_prefix
+
+    addr = (address or "").strip()
+    err = check_address(addr)
+    out: Dict[str, Any] = {"isvalid": err is None, "address": addr, "network_prefix": network_prefix()}
+    if err is not None:
+        out["error"] = err
+    elif addr.startswith(ADDRESS_PREFIX):
+        out["legacy"] = True
+    else:
+        prefix, version, h160 = decode_address(addr)
+        out.update({"legacy": False, "version": version, "hash160": h160.hex(), "isscript": version == SCRIPT_ADDRESS_VERSION})
+    return out
+
+
+@app.get("/rpc/getblockheader")
+def rpc_getblockheader(hash: str, verbose: bool = True):
+    """
+    Header-only lookup from block_headers. verbose=false returns the consensus serialization as hex;
+    otherwise header fields plus confirmations, neighbours, difficulty and chainwork.
+    """
+    from core.consensus import Header, chain_work
+    from core.target import target_to_difficulty
+
+    hh = (hash or "").strip().lower()
+    db = get_db()
+    with db.session() as s:
+        h = s.query(BlockHeader).filter_by(hash_hex=hh).first()
+        if not h:
+            raise HTTPException(status_code=404, detail={"error": "block not found", "hash": hh})
+        if not verbose:
+            hdr = Header(version=h.version, prev_hash_hex=h.prev_hash_hex, merkle_root_hex=h.merkle_root_hex,
+                         timestamp=h.timestamp, target=h.target, nonce=int(h.nonce),
+                         miner_address=h.miner_address, tx_count=h.tx_count)
+            return {"hex": hdr.serialize().hex()}
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        nxt = s.query(BlockHeader).filter_by(height=h.height + 1).first()
+        out = {
+            "hash": h.hash_hex,
+            "confirmations": tip.height - h.height + 1,
+            "height": h.height,
+            "version": h.version,
+            "merkleroot": h.merkle_root_hex,
+            "time": h.timestamp,
+            "nonce": int(h.nonce),
+            "target": h.target,
+            "difficulty": target_to_difficulty(h.target),
+            "miner": h.miner_address,
+            "nTx": h.tx_count,
+            "previousblockhash": h.prev_hash_hex if h.height > 0 else None,
+            "nextblockhash": nxt.hash_hex if nxt else None,
+        }
+    out["chainwork"] = f"{chain_work(out['height']):064x}"
+    return out
+
+
+@app.get("/rpc/getblockfilter")
+def rpc_getblockfilter(blockhash: str, filtertype: str = "basic"):
+    """BIP158 basic filter and filter header for a connected block."""
+    from core.blockfilter import get_filter
+
+    if filtertype != "basic":
+        raise HTTPException(status_code=400, detail={"error": "unknown filtertype", "filtertype": filtertype})
+    out = get_filter(blockhash)
+    if out is None:
+        raise HTTPException(status_code=404, detail={"error": "filter not found", "blockhash": blockhash})
+    return out
+
+
+@app.get("/rpc/getchaintips")
+def rpc_getchaintips():
+    """
+    Active tip plus side-chain tips seen from peers (relayed headers that built on a block below
+    our tip; from the fork history in core.confirmations). Side tips are headers-only: the node
+    never connects them.
+    """
+    from core import confirmations
+
+    tip = None
+    db = get_db()
+    with db.session() as s:
+        row = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        if row:
+            tip = {"height": row.height, "hash": row.hash_hex, "branchlen": 0, "status": "active"}
+    tips = [tip] if tip else []
+    seen = {tip["hash"]} if tip else set()
+    for e in reversed(confirmations.fork_history()):
+        hh = e.get("hash")
+        if not hh or hh in seen:
+            continue
+        seen.add(hh)
+        tips.append({"height": int(e.get("height", -1)), "hash": hh, "branchlen": 1,
+                     "status": "headers-only", "fork_depth": int(e.get("depth", 0)), "peer": e.get("peer", "")})
+    return tips
+
+
 @app.get("/rpc/get_header_by_hash/{hash_hex}")
 def rpc_get_header_by_hash(hash_hex: str):
     h = get_header_by_hash(hash_hex)
@@ -242,6 +732,7 @@ def rpc_mine_one(req: MineRequest):
         hh, err = append_block_header(req.miner_address)
         if err:
             return {"hash": None, "error": err}
+        _block_found()
         return {"hash": hh}
     except Exception as e:
         return {"hash": None, "error": str(e)}
@@ -251,7 +742,9 @@ def _build_work_snapshot(miner_address: Optional[str]) -> Dict[str, Any]:
     """
     Build a work package consistent with consensus merkle rules:
     - Height < 200: coinbase-only
-    - Height >= 200: mempool txids ordered by fee desc, added_ms asc, lowercase hex
+    - Height >= 200: mempool txids by ancestor-package feerate, parents first (core.txgraph), lowercase hex
+    - target/bits from the retarget (consensus.next_block_target), mintime = tip timestamp,
+      curtime/timestamp = network-adjusted time (core.nettime, never below mintime), noncerange = full 8-byte nonce
     Includes deep debug: selection, ordering, counts, and warnings at boundary.
     """
     db = get_db()
@@ -263,10 +756,9 @@ def _build_work_snapshot(miner_address: Optional[str]) -> Dict[str, Any]:
         height = 0 if tip is None else tip.height + 1
         prev_hash = "00" * 32 if tip is None else tip.hash_hex
 
-        if height < 200:
-            target_hex = difficulty_to_target(1)
-        else:
-            target_hex = tip.target if (tip and tip.target) else difficulty_to_target(1)
+        target_hex, bits = next_block_target(s, tip)
+        mintime = tip.timestamp if tip else 0  # validate_header: timestamps never decrease
+        curtime = max(nettime.adjusted_time(), mintime)
 
         try:
             from core.utils import sha3_256_hex as _sha
@@ -279,13 +771,10 @@ def _build_work_snapshot(miner_address: Optional[str]) -> Dict[str, Any]:
         mem = []
         mem_count = 0
         if height >= 200:
-            mem = (
-                s.query(MempoolTx)
-                .filter(MempoolTx.fee >= MIN_FEE)
-                .order_by(MempoolTx.fee.desc(), MempoolTx.added_ms.asc())
-                .limit(TXS_PER_BLOCK)
-                .all()
-            )
+            rows = s.query(MempoolTx).all()
+            by_txid = {m.txid: m for m in rows}
+            mem = [by_txid[t] for t in txgraph.select_packages(txgraph.build(rows), TXS_PER_BLOCK, MIN_FEE,
+                                                                 blocksize.template_max_bytes())]
             mem_count = len(mem)
             for m in mem:
                 txid_norm = ((m.txid or "").strip().lower())
@@ -308,8 +797,12 @@ def _build_work_snapshot(miner_address: Optional[str]) -> Dict[str, Any]:
             "height": height,
             "prev_hash": prev_hash,
             "target": (str(target_hex) or "").lower(),
-            "version": int(cfg.get("consensus.block_version", 1)),
-            "timestamp": int(time.time()),
+            "bits": f"{bits:08x}",
+            "version": versionbits.compute_block_version(tip),
+            "timestamp": curtime,
+            "curtime": curtime,
+            "mintime": mintime,
+            "noncerange": NONCE_RANGE,
             "miner_hint": miner_address or "",
             "txids": snapshot_txids,
         }
@@ -322,6 +815,22 @@ def _build_work_snapshot(miner_address: Optional[str]) -> Dict[str, Any]:
         return job
 
 
+def _block_found():
+    """A block connected through RPC (mined, submitted work or submitblock): new template, then INV to peers."""
+    try:
+        from core import txrelay
+        txrelay.remove_block_conflicts()
+    except Exception as e:
+        rpc_logger.warning(f"block conflict sweep failed: {e}")
+    _TEMPLATES.refresh(force=True)
+    announce = p2pstate.hook("announce_tip")
+    if announce is not None:
+        try:
+            announce()
+        except Exception:
+            pass
+
+
 def _store_job(job: Dict[str, Any]):
     _WORK_JOBS[job["job_id"]] = job
     nowm = now_ms()
@@ -330,16 +839,63 @@ def _store_job(job: Dict[str, Any]):
         _WORK_JOBS.pop(k, None)
 
 
+def _template_watcher():
+    # Detects new tips/mempool changes that arrive via P2P so long-pollers wake promptly.
+    # Also runs mempool expiry about once a minute.
+    last_expiry = 0.0
+    while True:
+        try:
+            if time.time() - last_expiry >= 60:
+                last_expiry = time.time()
+                n = expire_mempool()
+                if n:
+                    rpc_logger.info(f"mempool: expired {n} entries")
+            _TEMPLATES.refresh()
+        except Exception as e:
+            rpc_logger.debug(f"template watcher: {e}")
+        time.sleep(float(get_config().get("mining.template_watch_interval_sec", 1.0)))
+
+
+_TEMPLATES = BlockTemplateProvider(lambda: _build_work_snapshot(None), _store_job, _WORK_TTL_MS)
+
+
+class BlockTemplateRequest(BaseModel):
+    miner_address: Optional[str] = None
+    longpollid: Optional[str] = None
+    timeout_sec: Optional[float] = None
+
+
 @app.post("/rpc/get_work")
 def rpc_get_work(req: GetWorkRequest):
     try:
-        job = _build_work_snapshot(req.miner_address if req and req.miner_address else None)
-        _store_job(job)
+        job = dict(_TEMPLATES.current())
+        job["miner_hint"] = req.miner_address if req and req.miner_address else ""
         return job
     except Exception as e:
         raise HTTPException(status_code=500, detail=f"get_work failed: {e}")
 
 
+@app.post("/rpc/get_block_template")
+def rpc_get_block_template(req: BlockTemplateRequest):
+    """
+    Cached work template. With longpollid (from a previous response), blocks until the template
+    changes (new tip / significant mempool change) or timeout_sec elapses (default mining.longpoll_timeout_sec).
+    """
+    try:
+        if req.longpollid:
+            cap = float(get_config().get("mining.longpoll_timeout_sec", 60))
+            timeout = min(cap, float(req.timeout_sec)) if req.timeout_sec is not None else cap
+            release_rpc_slot()  # parked until the template changes; not a handler doing work
+            job = _TEMPLATES.wait_for_change(req.longpollid, timeout)
+        else:
+            job = _TEMPLATES.current()
+        job = dict(job)
+        job["miner_hint"] = req.miner_address or ""
+        return job
+    except Exception as e:
+        raise HTTPException(status_code=500, detail=f"get_block_template failed: {e}")
+
+
 @app.post("/rpc/submit_work")
 def rpc_submit_work(req: SubmitWorkRequest):
     """
@@ -397,6 +953,24 @@ def rpc_submit_work(req: SubmitWorkRequest):
     else:
         txids_snapshot = [str(t).strip().lower() for t in (job.get("txids") or []) if str(t).strip()]
 
+    if req.txids is not None:
+        submitted_txids = [str(t).strip().lower() for t in req.txids if str(t).strip()]
+        if submitted_txids != txids_snapshot:
+            rpc_logger.error(f"submit_work: txids_mismatch job_id={req.job_id} issued={len(txids_snapshot)} submitted={len(submitted_txids)}")
+            raise HTTPException(
+                status_code=400,
+                detail={"accepted": False, "error": "txids mismatch vs issued job", "job_id": req.job_id,
+                        "txids_len": len(txids_snapshot), "submitted_len": len(submitted_txids)},
+            )
+    expected_merkle = merkle_root(txids_snapshot)
+    if req.merkle_root_hex and req.merkle_root_hex.strip().lower() != expected_merkle:
+        rpc_logger.error(f"submit_work: merkle_mismatch job_id={req.job_id} submitted={req.merkle_root_hex[:16]}")
+        raise HTTPException(
+            status_code=400,
+            detail={"accepted": False, "error": "merkle mismatch vs issued job txids", "job_id": req.job_id,
+                    "expected_merkle": expected_merkle, "submitted_merkle": req.merkle_root_hex.strip().lower()},
+        )
+
     # Promote via consensus
     rpc_logger.debug(
         "submit_work: promoting via accept_external_header "
@@ -431,71 +1005,526 @@ def rpc_submit_work(req: SubmitWorkRequest):
         # Highlight common failure classes
         low = (err or "").lower()
         if "merkle" in low:
-            rpc_logger.error(_Color.RED + "HINT: Merkle mismatch; ensure coinbase-first ordering and lowercase txids." + _Color.RESET)
+            rpc_logger.error("HINT: Merkle mismatch; ensure coinbase-first ordering and lowercase txids.")
         if "prev" in low:
-            rpc_logger.error(_Color.RED + "HINT: Prev mismatch (stale). Miner should refresh work more frequently." + _Color.RESET)
+            rpc_logger.error("HINT: Prev mismatch (stale). Miner should refresh work more frequently.")
         if "pow" in low or "target" in low:
-            rpc_logger.error(_Color.RED + "HINT: PoW not meeting target; verify backend and nonce space." + _Color.RESET)
+            rpc_logger.error("HINT: PoW not meeting target; verify backend and nonce space.")
         raise HTTPException(status_code=400, detail=detail)
 
     _WORK_JOBS.pop(req.job_id, None)
-    rpc_logger.info(_Color.GREEN + f"submit_work: ACCEPTED h={height} hash={hh[:16]}.." + _Color.RESET)
+    _block_found()
+    rpc_logger.info(f"submit_work: ACCEPTED h={height} hash={hh[:16]}..")
     return {"accepted": True, "hash": hh, "height": height, "prev": prev_from_job, "job_id": req.job_id, "txids_len": len(txids_snapshot)}
 
 
-@app.post("/rpc/tx/submit")
-def rpc_tx_submit(req: TxSubmitRequest):
-    tx = req.tx
-    height = get_chain_height()
-    ok, reason, txid = validate_mempool_tx(tx, height=height if height >= 0 else 0)
-    if not ok:
-        raise HTTPException(status_code=400, detail={"accepted": False, "error": reason, "txid": txid})
+@app.get("/rpc/gettransaction")
+def rpc_gettransaction(txid: str):
+    """
+    Transaction status with a fork-aware safety score (core.confirmations) next to the raw
+    confirmation count; exchanges should gate deposits on safety_score / recommended_confirmations.
+    """
+    from core.db import Transaction, Reward, TxIndex
+    from core import confirmations
+    from core import conflicts
+
+    txid = (txid or "").strip().lower()
+    conflict = conflicts.get(txid)
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        tip_h = tip.height if tip else -1
+        out: Dict[str, Any] = {"txid": txid}
+        t = s.query(Transaction).filter_by(txid=txid).first()
+        r = None if t else s.query(Reward).filter_by(txid=txid).first()
+        # header-only nodes drop bodies below the window; the tx index still places the tx
+        ix = s.query(TxIndex).filter_by(txid=txid).first() if t is None and r is None and headeronly.enabled() else None
+        if t is not None and t.in_block_hash:
+            blk = s.query(BlockHeader).filter_by(hash_hex=t.in_block_hash).first()
+            out.update(status="confirmed", blockhash=t.in_block_hash, height=blk.height if blk else None, fee=t.fee)
+            conf = (tip_h - blk.height + 1) if blk else 0
+        elif r is not None:
+            blk = s.query(BlockHeader).filter_by(height=r.height).first()
+            out.update(status="confirmed", coinbase=True, blockhash=blk.hash_hex if blk else None, height=r.height,
+                       amount=r.amount, address=r.miner_address)
+            conf = tip_h - r.height + 1
+        elif ix is not None:
+            out.update(status="confirmed", blockhash=ix.block_hash, height=ix.height, pruned=True)
+            conf = tip_h - ix.height + 1
+        elif s.query(MempoolTx).filter_by(txid=txid).first() is not None or t is not None:
+            out.update(status="mempool")
+            conf = 0
+        elif conflict is not None:
+            # a wallet tx that lost a double spend (core.conflicts); it will not confirm
+            out.update(status="conflicted")
+            conf = 0
+        else:
+            raise HTTPException(status_code=404, detail={"error": "tx not found", "txid": txid})
+    out.update(confirmations.score(max(0, conf)))
+    if conflict is not None:
+        out["walletconflicts"] = list(conflict.get("conflicting") or [])
+        out["conflict_reason"] = conflict.get("reason")
+    return out
+
 
+@app.get("/rpc/getrawtransaction")
+def rpc_getrawtransaction(txid: str, blockhash: Optional[str] = None, verbose: bool = False):
+    """
+    Serialized transaction (hex of its canonical JSON) from the mempool, the block given by
+    blockhash, or the tx index (storage.txindex). verbose adds the decoded tx and block location.
+    """
+    from core.db import Transaction, Reward
+    from core import txindex
+
+    txid = (txid or "").strip().lower()
     db = get_db()
+    loc: Optional[Dict[str, Any]] = None
+    if blockhash:
+        loc = txindex.block_position(blockhash, txid)
+        if loc is None:
+            raise HTTPException(status_code=404, detail={"error": "block not found", "blockhash": blockhash})
+        if loc.get("position") is None:
+            raise HTTPException(status_code=404, detail={"error": "tx not found in block", "txid": txid, "blockhash": loc["blockhash"]})
     with db.session() as s:
-        raw_compact = json.dumps(tx, separators=(",", ":"), sort_keys=True)
-        from_addr = None
-        to_addr = None
-        amount = None
-        fee = float(tx.get("fee", 0.0))
+        raw: Optional[str] = None
+        if loc is None:
+            m = s.query(MempoolTx).filter_by(txid=txid).first()
+            if m is not None and m.raw:
+                raw = m.raw
+            else:
+                loc = txindex.lookup(txid)
+                if loc is None:
+                    if not txindex.enabled():
+                        raise HTTPException(status_code=404, detail={
+                            "error": "No such mempool transaction. The tx index is disabled (storage.txindex); "
+                                     "pass blockhash to look the tx up in a specific block",
+                            "txid": txid,
+                        })
+                    raise HTTPException(status_code=404, detail={"error": "tx not found", "txid": txid})
+        if raw is None:
+            t = s.query(Transaction).filter_by(txid=txid).first()
+            if t is not None and t.raw:
+                raw = t.raw
+            elif loc and loc.get("position") == 0:
+                r = s.query(Reward).filter_by(height=loc["height"]).first()
+                cb = {"coinbase": True, "height": loc["height"],
+                      "outputs": [{"address": r.miner_address, "amount": r.amount}] if r else []}
+                raw = json.dumps(cb, separators=(",", ":"), sort_keys=True)
+            elif loc and headeronly.is_pruned(s, loc["height"]):
+                raise HTTPException(status_code=410, detail=dict(
+                    headeronly.pruned_error(loc["height"], headeronly.body_floor(s)), txid=txid))
+            else:
+                raise HTTPException(status_code=404, detail={"error": "tx body not stored", "txid": txid})
+        tip_h = get_chain_height()
+    out: Dict[str, Any] = {"txid": txid, "hex": raw.encode("utf-8").hex()}
+    if verbose:
         try:
-            if tx.get("outputs"):
-                first_out = tx["outputs"][0]
-                to_addr = first_out.get("address")
-                amount = float(first_out.get("amount", 0.0))
-            if tx.get("inputs"):
-                first_in = tx["inputs"][0]
-                from_addr = first_in.get("address")
-        except Exception:
-            pass
+            out["tx"] = json.loads(raw)
+        except ValueError:
+            out["tx"] = raw
+        if loc:
+            out.update(blockhash=loc["blockhash"], height=loc["height"], confirmations=tip_h - loc["height"] + 1)
+        else:
+            out["confirmations"] = 0
+    return out
+
+
+@app.post("/rpc/reindex-txindex")
+def rpc_reindex_txindex():
+    """Drop and rebuild the transaction index from the chain in the background."""
+    from core import txindex
+
+    if not txindex.enabled():
+        raise HTTPException(status_code=400, detail={"error": "tx index disabled (storage.txindex)"})
+    if headeronly.enabled():
+        raise HTTPException(status_code=409, detail={"error": "header-only node: block bodies below the window are pruned"})
+    if not txindex.reindex():
+        raise HTTPException(status_code=409, detail={"error": "reindex already running"})
+    return {"started": True}
+
+
+@app.get("/rpc/gettxindexinfo")
+def rpc_gettxindexinfo():
+    from core import txindex
+
+    return txindex.status()
+
+
+class TxOutSetFileRequest(BaseModel):
+    path: str
+    trust: bool = False
+
+
+@app.post("/rpc/dumptxoutset")
+def rpc_dumptxoutset(req: TxOutSetFileRequest):
+    """
+    Write a chain-state snapshot (headers + UTXO set + muhash) at the tip to req.path on the node
+    host. Returns the snapshot meta and the file's sha256 (core.snapshot).
+    """
+    from core import snapshot
+
+    try:
+        return snapshot.dump(req.path)
+    except (OSError, ValueError) as e:
+        raise HTTPException(status_code=400, detail={"error": str(e), "path": req.path})
+
+
+@app.post("/rpc/loadtxoutset")
+def rpc_loadtxoutset(req: TxOutSetFileRequest):
+    """
+    Load a snapshot into a fresh node after checking the header chain and muhash. Snapshots not
+    listed in consensus.assumeutxo need trust=true. History is validated in the background.
+    """
+    from core import snapshot
+
+    if not os.path.isfile(req.path):
+        raise HTTPException(status_code=404, detail={"error": "snapshot file not found", "path": req.path})
+    meta, err = snapshot.load(req.path, trust=req.trust)
+    if err:
+        raise HTTPException(status_code=400, detail={"error": err, "path": req.path})
+    _TEMPLATES.refresh(force=True)
+    return {"loaded": meta, "assumeutxo": snapshot.status()}
+
+
+@app.get("/rpc/getaddressbalance")
+def rpc_getaddressbalance(address: str):
+    """
+    Confirmed balance of an address from the address index (core.addrindex): unspent value,
+    output count and the last block that changed it. Mempool spends are not counted.
+    """
+    from core import addrindex
+
+    if not addrindex.enabled():
+        raise HTTPException(status_code=503, detail={"error": "address index disabled (storage.address_index)"})
+    return addrindex.get_address_balance(address.strip())
+
+
+@app.get("/rpc/getrichlist")
+def rpc_getrichlist(count: int = 100):
+    """Addresses with the largest confirmed balances, highest first (at most storage.richlist_max)."""
+    from core import addrindex
+
+    if not addrindex.enabled():
+        raise HTTPException(status_code=503, detail={"error": "address index disabled (storage.address_index)"})
+    cap = int(get_config().get("storage.richlist_max", 1000))
+    return {**addrindex.info(), "top": addrindex.get_top_addresses(max(1, min(int(count), cap)))}
+
+
+@app.get("/rpc/verifychain")
+def rpc_verifychain(checklevel: Optional[int] = None, nblocks: Optional[int] = None):
+    """
+    Check the last nblocks (0 = all; default storage.verifychain_blocks) at checklevel 0-3
+    (default storage.verifychain_level); see core.verifychain. Reports the first broken block.
+    """
+    from core import verifychain
+
+    return verifychain.verify_chain(checklevel, nblocks)
+
+
+@app.get("/rpc/gettxoutsetinfo")
+def rpc_gettxoutsetinfo(verify: bool = False):
+    """
+    UTXO set summary at the tip: output count, total value and the MuHash set commitment
+    (core.utxohash). verify=true recomputes the hash from the UTXO table and compares.
+    """
+    from core import utxohash
+
+    from core import snapshot
+
+    out = utxohash.info(verify=verify)
+    if "error" in out:
+        raise HTTPException(status_code=503, detail=out)
+    au = snapshot.status()
+    if au:
+        out["assumeutxo"] = au
+    return out
+
+
+@app.get("/rpc/txproof")
+def rpc_txproof(txid: str, checkpoint: Optional[int] = None):
+    """
+    Light-wallet proof bundle: merkle branch for txid plus the header chain from `checkpoint`
+    (a height whose hash the client already trusts; defaults to the block itself) up to the tip.
+    Check it with core.lightproof.verify_bundle(bundle, checkpoint_hash).
+    """
+    from core import lightproof
+
+    bundle, err = lightproof.build_bundle(txid, checkpoint)
+    if err:
+        detail: Dict[str, Any] = {"error": err, "txid": txid}
+        if err == "tx not confirmed" and headeronly.enabled():
+            # the tx may be confirmed below the window, where its body is gone
+            with get_db().session() as s:
+                detail["pruneheight"] = headeronly.body_floor(s)
+        raise HTTPException(status_code=404 if err == "tx not confirmed" else 400, detail=detail)
+    return bundle
+
+
+class SubmitBlockRequest(BaseModel):
+    hexdata: str
+
+
+# accept_external_header reasons -> BIP22 submitblock result strings
+_SUBMITBLOCK_REASONS = (
+    ("pow target not met", "high-hash"),
+    ("bad-diffbits", "bad-diffbits"),
+    ("invalid version", "bad-version"),
+    ("timestamp decreased", "time-too-old"),
+    ("timestamp too far in future", "time-too-new"),
+    ("merkle-mismatch", "bad-txnmrklroot"),
+    ("missing coinbase", "bad-cb-missing"),
+    ("exceeds max supply", "bad-cb-amount"),
+)
+
+
+@app.post("/rpc/submitblock")
+def rpc_submitblock(req: SubmitBlockRequest):
+    """
+    Submit a serialized block (core.blockio record, hex; with or without the magic|len frame).
+    BIP22 semantics: result is null when the block became the new tip, otherwise a reason string
+    ("duplicate", "inconclusive", "bad-prevblk", "high-hash", ...). Undecodable data is a 400 error.
+    """
+    from core.blockio import network_magic, decode_payload
+    from core.consensus import Header
+    import struct
 
-        existing = s.query(MempoolTx).filter_by(txid=txid).first()
-        if not existing:
-            s.add(MempoolTx(
-                txid=txid,
-                raw=raw_compact,
-                added_ms=now_ms(),
-                fee=fee,
-                from_addr=from_addr,
-                to_addr=to_addr,
-                amount=amount,
-            ))
+    try:
+        raw = bytes.fromhex((req.hexdata or "").strip())
+        if raw[:4] == network_magic():
+            (plen,) = struct.unpack("<I", raw[4:8])
+            if len(raw) != 8 + plen:
+                raise ValueError("frame length mismatch")
+            raw = raw[8:]
+        rec = decode_payload(raw)
+    except (ValueError, struct.error) as e:
+        raise HTTPException(status_code=400, detail={"error": "Block decode failed", "reason": str(e)})
+
+    hh = Header(
+        version=rec.version,
+        prev_hash_hex=rec.prev_hash_hex,
+        merkle_root_hex=rec.merkle_root_hex,
+        timestamp=rec.timestamp,
+        target=rec.target,
+        nonce=rec.nonce,
+        miner_address=rec.miner_address,
+        tx_count=rec.tx_count,
+    ).hash_hex()
+
+    db = get_db()
+    with db.session() as s:
+        if s.query(BlockHeader).filter_by(hash_hex=hh).first() is not None:
+            return {"result": "duplicate", "hash": hh}
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        parent = s.query(BlockHeader).filter_by(hash_hex=rec.prev_hash_hex).first()
+        on_tip = tip is not None and rec.prev_hash_hex == tip.hash_hex
+    if parent is None:
+        return {"result": "bad-prevblk", "hash": hh}
+    if not on_tip:
+        # Valid-looking side-chain block: we keep no side branches, so it cannot become the tip
+        return {"result": "inconclusive", "hash": hh}
+
+    new_hash, err = accept_external_header(
+        prev_hash_hex=rec.prev_hash_hex,
+        merkle_root_hex=rec.merkle_root_hex,
+        version=rec.version,
+        timestamp=rec.timestamp,
+        target_hex=rec.target,
+        nonce=rec.nonce,
+        miner_address=rec.miner_address,
+        txids_snapshot=rec.txids,
+    )
+    if err:
+        if err == "stale-prev":
+            result = "inconclusive"
         else:
-            if not existing.raw:
-                existing.raw = raw_compact
-            if existing.fee is None:
-                existing.fee = fee
-            if not existing.from_addr and from_addr:
-                existing.from_addr = from_addr
-            if not existing.to_addr and to_addr:
-                existing.to_addr = to_addr
-            if existing.amount is None and amount is not None:
-                existing.amount = amount
-        s.commit()
+            result = next((code for needle, code in _SUBMITBLOCK_REASONS if needle in err), "rejected")
+        rpc_logger.warning(f"submitblock: {result} hash={hh[:16]}.. err={err}")
+        return {"result": result, "hash": hh, "reason": err}
+
+    _block_found()
+    rpc_logger.info(f"submitblock: ACCEPTED hash={new_hash[:16]}..")
+    return {"result": None, "hash": new_hash}
 
+
+@app.post("/rpc/tx/submit")
+def rpc_tx_submit(req: TxSubmitRequest):
+    from core.txrelay import accept_to_mempool
+
+    # Validated for inclusion in the next block (coinbase maturity counts from there), stored and
+    # announced to peers; spends of not-yet-confirmed outputs wait in the orphan pool
+    ok, reason, txid = accept_to_mempool(req.tx)
+    if reason == "orphan":
+        return {"accepted": False, "orphan": True, "txid": txid}
+    if not ok:
+        raise HTTPException(status_code=400, detail={"accepted": False, "error": reason, "txid": txid})
     return {"accepted": True, "txid": txid}
 
 
+class CreatePsbtRequest(BaseModel):
+    inputs: List[Dict[str, Any]]
+    outputs: List[Dict[str, Any]]
+    fee: float
+    replaceable: bool = False
+
+
+class PsbtRequest(BaseModel):
+    psbt: str
+    extract: bool = True
+
+
+class CombinePsbtRequest(BaseModel):
+    txs: List[str]
+
+
+class CreateRawTransactionRequest(BaseModel):
+    inputs: List[Dict[str, Any]] = []
+    outputs: List[Dict[str, Any]]  # {"address": .., "amount": ..} / {address: amount} / {"data": hex}
+    fee: float = 0.0  # explicit in this tx model; fundrawtransaction sets it
+    replaceable: bool = False
+
+
+class SignRawTransactionWithKeyRequest(BaseModel):
+    hexstring: Any  # the structured tx, as an object or JSON string
+    privkeys: List[str]
+    prevtxs: Optional[List[Dict[str, Any]]] = None  # [{txid, vout, address, amount}]
+    sighashtype: str = "ALL"
+
+
+@app.post("/rpc/createrawtransaction")
+def rpc_createrawtransaction(req: CreateRawTransactionRequest):
+    """Unsigned structured tx (sign the inputs, then /rpc/tx/submit); createpsbt wraps the same builder."""
+    from core import rawtx
+    from core.txrelay import check_standard
+
+    try:
+        tx, _ = rawtx.build_unsigned(req.inputs, req.outputs, req.fee, replaceable=req.replaceable)
+    except (ValueError, TypeError) as e:
+        raise HTTPException(status_code=400, detail={"error": str(e)})
+    out: Dict[str, Any] = {"tx": tx}
+    nonstandard = check_standard(tx)
+    if nonstandard:
+        out["warning"] = f"non-standard, will not relay: {nonstandard}"
+    return out
+
+
+@app.post("/rpc/signrawtransactionwithkey")
+def rpc_signrawtransactionwithkey(req: SignRawTransactionWithKeyRequest):
+    from core import rawtx
+
+    try:
+        return rawtx.sign_with_keys(req.hexstring, req.privkeys, req.prevtxs, req.sighashtype)
+    except (ValueError, TypeError) as e:
+        raise HTTPException(status_code=400, detail={"error": str(e)})
+
+
+class CreateMultisigRequest(BaseModel):
+    nrequired: int
+    keys: List[str]
+
+
+@app.post("/rpc/createmultisig")
+def rpc_createmultisig(req: CreateMultisigRequest):
+    """m-of-n script address for the given ed25519 pubkeys (spend with redeem + sigs, see core.crypto)."""
+    from core.crypto import multisig_redeem, redeem_script_bytes, script_address
+
+    try:
+        redeem = multisig_redeem(req.nrequired, req.keys)
+    except ValueError as e:
+        raise HTTPException(status_code=400, detail={"error": str(e)})
+    return {"address": script_address(redeem), "redeem": redeem, "redeemScript": redeem_script_bytes(redeem).hex()}
+
+
+def _decode_psbt(data: str) -> Dict[str, Any]:
+    from core import psbt
+
+    try:
+        return psbt.decode(data)
+    except ValueError as e:
+        raise HTTPException(status_code=400, detail={"error": str(e)})
+
+
+@app.post("/rpc/createpsbt")
+def rpc_createpsbt(req: CreatePsbtRequest):
+    from core import psbt
+
+    try:
+        return {"psbt": psbt.encode(psbt.create(req.inputs, req.outputs, req.fee, replaceable=req.replaceable))}
+    except (ValueError, TypeError) as e:
+        raise HTTPException(status_code=400, detail={"error": str(e)})
+
+
+@app.post("/rpc/decodepsbt")
+def rpc_decodepsbt(req: PsbtRequest):
+    from core import psbt
+
+    return psbt.describe(_decode_psbt(req.psbt))
+
+
+@app.post("/rpc/analyzepsbt")
+def rpc_analyzepsbt(req: PsbtRequest):
+    from core import psbt
+
+    return psbt.analyze(_decode_psbt(req.psbt))
+
+
+@app.post("/rpc/utxoupdatepsbt")
+def rpc_utxoupdatepsbt(req: PsbtRequest):
+    from core import psbt
+
+    p = _decode_psbt(req.psbt)
+    psbt.update(p)
+    return {"psbt": psbt.encode(p)}
+
+
+@app.post("/rpc/combinepsbt")
+def rpc_combinepsbt(req: CombinePsbtRequest):
+    from core import psbt
+
+    try:
+        return {"psbt": psbt.encode(psbt.combine([_decode_psbt(t) for t in req.txs]))}
+    except ValueError as e:
+        raise HTTPException(status_code=400, detail={"error": str(e)})
+
+
+@app.post("/rpc/finalizepsbt")
+def rpc_finalizepsbt(req: PsbtRequest):
+    from core import psbt
+    from core.crypto import tx_digest_hex
+
+    # The extracted tx goes to /rpc/tx/submit unchanged
+    p = _decode_psbt(req.psbt)
+    tx, complete = psbt.finalize(p)
+    if not complete or not req.extract:
+        return {"psbt": psbt.encode(p), "complete": complete}
+    return {"tx": tx, "txid": tx_digest_hex(tx), "complete": True}
+
+
+class TestMempoolAcceptRequest(BaseModel):
+    rawtxs: List[Any]  # structured txs, as objects or JSON strings
+    maxfeerate: float = 0.10  # per kB; 0 disables the check
+
+
+@app.post("/rpc/testmempoolaccept")
+def rpc_testmempoolaccept(req: TestMempoolAcceptRequest):
+    """Dry-run mempool acceptance (same checks as tx/submit, nothing stored or relayed)."""
+    from core import txrelay
+
+    if not req.rawtxs or len(req.rawtxs) > 25:
+        raise HTTPException(status_code=400, detail={"error": "rawtxs must hold 1 to 25 transactions"})
+    txs = []
+    for raw in req.rawtxs:
+        try:
+            txs.append(json.loads(raw) if isinstance(raw, str) else raw)
+        except ValueError:
+            txs.append(None)
+    results = txrelay.test_accept(txs)
+    if req.maxfeerate > 0:
+        for r in results:
+            if r.get("allowed") and r["fees"]["base"] * 1000.0 / max(1, r["vsize"]) > req.maxfeerate:
+                r.update(allowed=False, **{"reject-reason": "max-fee-exceeded"})
+    return results
+
+
 @app.get("/rpc/mempool")
 def rpc_mempool():
     db = get_db()
@@ -514,6 +1543,114 @@ def rpc_mempool():
         return out
 
 
+def _rbf_replaceable(by_txid: Dict[str, MempoolTx], graph: txgraph.Graph, scope: Set[str]) -> Set[str]:
+    """
+    Txs in scope (plus their descendants) that are replaceable: the tx or an unconfirmed ancestor
+    opts in (inherited signaling, as in BIP125). Each raw in scope is parsed once and the
+    inheritance is one walk down the graph, not an ancestor scan per entry.
+    """
+    from core import txrelay
+
+    if get_config().get("mempool.full_rbf", False):
+        return set(graph)
+    signalling = {t for t in scope if t in by_txid and txrelay.signals_rbf(txrelay.parse_raw(by_txid[t].raw))}
+    return signalling | txgraph.descendants_of(graph, signalling)
+
+
+def _mempool_entry(m: MempoolTx, graph: txgraph.Graph, replaceable: bool) -> Dict[str, Any]:
+    from core import txrelay
+
+    size = graph[m.txid].size
+    entry = {
+        "txid": m.txid,
+        "size": size,
+        "fee": float(m.fee or 0.0),
+        "feerate": float(m.fee or 0.0) / max(1, size),
+        "time": int((m.added_ms or 0) // 1000),
+        "added_ms": m.added_ms,
+        "from": m.from_addr,
+        "to": m.to_addr,
+        "amount": m.amount,
+        "depends": sorted(graph[m.txid].parents),
+        "spentby": sorted(graph[m.txid].children),
+    }
+    entry.update(txgraph.entry_stats(graph, m.txid))
+    entry.update({
+        "bip125-replaceable": replaceable,
+        "replaces": txrelay.replacement_info(m.txid)["replaces"],
+    })
+    return entry
+
+
+@app.get("/rpc/getrawmempool")
+def rpc_getrawmempool(verbose: bool = False):
+    """
+    Mempool contents: txid list (default, fee desc) or, with verbose=true, a txid -> entry map
+    (same fields as getmempoolentry).
+    """
+    db = get_db()
+    with db.session() as s:
+        rows = s.query(MempoolTx).order_by(MempoolTx.fee.desc(), MempoolTx.added_ms.asc()).all()
+        if not verbose:
+            return [m.txid for m in rows]
+        by_txid = {m.txid: m for m in rows}
+        graph = txgraph.build(rows)
+        replaceable = _rbf_replaceable(by_txid, graph, set(by_txid))
+        return {m.txid: _mempool_entry(m, graph, m.txid in replaceable) for m in rows}
+
+
+@app.get("/rpc/getmempoolentry")
+def rpc_getmempoolentry(txid: str):
+    """
+    Single mempool entry with fee, size, in-mempool ancestor/descendant links and RBF state
+    (bip125-replaceable, replaces). A tx evicted by a replacement reports replaced_by in the 404.
+    """
+    txid = (txid or "").strip().lower()
+    db = get_db()
+    with db.session() as s:
+        rows = s.query(MempoolTx).all()
+        by_txid = {m.txid: m for m in rows}
+        m = by_txid.get(txid)
+        if m is None:
+            from core.txrelay import replacement_info
+
+            detail = {"error": "Transaction not in mempool", "txid": txid}
+            replaced_by = replacement_info(txid)["replaced_by"]
+            if replaced_by:
+                detail["replaced_by"] = replaced_by
+            raise HTTPException(status_code=404, detail=detail)
+        graph = txgraph.build(rows)
+        scope = {txid} | txgraph.ancestors(graph, txid)
+        return _mempool_entry(m, graph, txid in _rbf_replaceable(by_txid, graph, scope))
+
+
+@app.get("/rpc/getmempoolinfo")
+def rpc_getmempoolinfo():
+    """
+    Mempool summary: entry count, raw bytes, total fees, relay min fee and expiry policy.
+    mempoolminfee is the per-kB floor for new txs: minrelaytxfee, or higher while the size limit
+    is evicting (core.txrelay).
+    """
+    from core import txrelay
+
+    cfg = get_config()
+    db = get_db()
+    with db.session() as s:
+        cnt, fees = s.query(func.count(MempoolTx.id), func.coalesce(func.sum(MempoolTx.fee), 0.0)).one()
+        size = s.query(func.coalesce(func.sum(func.length(MempoolTx.raw)), 0)).scalar()
+        oldest = s.query(func.min(MempoolTx.added_ms)).scalar()
+    return {
+        "size": int(cnt or 0),
+        "bytes": int(size or 0),
+        "total_fee": float(fees or 0.0),
+        "maxmempool": int(float(cfg.get("mempool.max_size_mb", 300)) * 1_000_000),
+        "mempoolminfee": txrelay.mempool_min_fee(),
+        "minrelaytxfee": float(cfg.get("mempool.min_fee", 0.00001)),
+        "expiry_sec": int(cfg.get("mempool.expiry_sec", 14 * 24 * 3600)),
+        "oldest_ms": oldest,
+    }
+
+
 @app.get("/rpc/mempool_count")
 def rpc_mempool_count():
     db = get_db()
@@ -522,23 +1659,425 @@ def rpc_mempool_count():
         return {"count": int(cnt)}
 
 
+@app.get("/rpc/peers")
+def rpc_peers():
+    """
+    Snapshot of connected P2P peers (core.p2pstate, filled by the P2P layer when it runs in this
+    process; standalone RPC reports an empty list).
+    """
+    from core import banman
+
+    nowm = now_ms()
+    with p2pstate.peers_lock:
+        return [
+            {
+                "addr": ps.addr,
+                "inbound": ps.inbound,
+                "last_seen_ms": ps.last_seen,
+                "idle_ms": max(0, nowm - ps.last_seen),
+                "bytes_sent": ps.net.bytes_sent,
+                "bytes_recv": ps.net.bytes_recv,
+                "banscore": banman.score_of(ps.addr),
+            }
+            for ps in p2pstate.peers.values()
+        ]
+
+
+@app.get("/rpc/getsyncstatus")
+def rpc_getsyncstatus():
+    """
+    Block download scheduler state: per-peer height, RTT and window timing, in-flight windows and
+    buffered blocks (core.blocksync). Standalone RPC (no P2P in process) reports only the tip.
+    """
+    sync_status = p2pstate.hook("sync_status")
+    if sync_status is None:
+        return {"tip_height": get_chain_height(), "peers": [], "inflight": [], "buffered": 0}
+    return sync_status()
+
+
+class MockTimeRequest(BaseModel):
+    timestamp: int
+
+
+def _is_regtest() -> bool:
+    cfg = get_config()
+    return bool(cfg.get("network.regtest", False)) or str(cfg.get("network.name", "")).endswith("regtest")
+
+
+@app.post("/rpc/setmocktime")
+def rpc_setmocktime(req: MockTimeRequest):
+    """
+    Regtest only: pin the node clock (unix seconds) used for block timestamps, future-timestamp
+    validation, difficulty retarget inputs and mempool expiry. timestamp=0 restores the real clock.
+    """
+    if not _is_regtest():
+        raise HTTPException(status_code=403, detail={"error": "setmocktime is only available on regtest"})
+    if req.timestamp < 0:
+        raise HTTPException(status_code=400, detail={"error": "timestamp must be >= 0"})
+    set_mock_time(req.timestamp)
+    _TEMPLATES.refresh(force=True)
+    rpc_logger.warning(f"setmocktime: mock_time={req.timestamp or 'off'}")
+    return {"mocktime": get_mock_time(), "now": now_sec()}
+
+
+class GenerateRequest(BaseModel):
+    nblocks: int
+    address: str
+
+
+def _is_testnet() -> bool:
+    return "testnet" in str(get_config().get("network.name", ""))
+
+
+@app.post("/rpc/generatetoaddress")
+def rpc_generatetoaddress(req: GenerateRequest):
+    """
+    Regtest/testnet only: mine nblocks blocks paying `address` on top of the current tip and
+    return their hashes. Each block is built, solved and connected by the same path as mine_one
+    (mempool txs included, network difficulty, which is minimal on regtest). On a mining failure
+    the hashes mined so far are returned with the error.
+    """
+    from core.crypto import check_address
+
+    if not (_is_regtest() or _is_testnet()):
+        raise HTTPException(status_code=403, detail={"error": "generatetoaddress is only available on regtest/testnet"})
+    max_blocks = int(get_config().get("mining.generate_max_blocks", 1000))
+    if not (0 < req.nblocks <= max_blocks):
+        raise HTTPException(status_code=400, detail={"error": f"nblocks must be 1..{max_blocks}"})
+    err = check_address(req.address)
+    if err:
+        raise HTTPException(status_code=400, detail={"error": f"invalid address: {err}"})
+    hashes: List[str] = []
+    error = None
+    for _ in range(req.nblocks):
+        try:
+            hh, error = append_block_header(req.address)
+        except Exception as e:
+            hh, error = None, str(e)
+        if error or not hh:
+            break
+        hashes.append(hh)
+    if hashes:
+        _block_found()
+        rpc_logger.info(f"generatetoaddress: {len(hashes)} block(s) to {req.address}, tip={hashes[-1]}")
+    if error:
+        return {"hashes": hashes, "error": error}
+    return hashes
+
+
+class LogLevelRequest(BaseModel):
+    target: str = "all"  # a core.logs target (net, sync, stratum, ...) or "all"
+    level: str
+
+
+@app.post("/rpc/setloglevel")
+def rpc_setloglevel(req: LogLevelRequest):
+    """
+    Change a log target's level at runtime (not persisted; logging.level / logging.targets apply
+    again on restart). Returns the effective level of every target.
+    """
+    try:
+        out = logs.set_level(req.target, req.level)
+    except ValueError as e:
+        raise HTTPException(status_code=400, detail={"error": str(e)})
+    rpc_logger.warning(f"setloglevel: {req.target}={req.level.upper()}")
+    return out
+
+
+@app.get("/rpc/getloglevels")
+def rpc_getloglevels():
+    return logs.levels()
+
+
+@app.websocket("/ws")
+async def ws_subscribe(ws: WebSocket):
+    """
+    Event subscriptions (core.notify). The client sends {"subscribe": ["conflicts"], "addresses": [...]}
+    (addresses optional) and then receives one JSON message per event. Credentials and ACLs are the
+    HTTP ones (method "ws"); the RPC middlewares do not see WebSocket sessions, so they are checked here.
+    """
+    from fastapi import WebSocketDisconnect
+
+    client = ws.client.host if ws.client else None
+    if rpcauth.enabled():
+        caller, reason = rpcauth.authenticate(ws.headers.get("authorization"))
+        if caller is None or not rpcauth.authorize(caller, "GET", "/ws"):
+            rpc_logger.warning("ws subscription refused", extra=logs.fields(
+                user=caller.name if caller else None, client=client, reason=reason or "denied"))
+            await ws.close(code=1008)
+            return
+    await ws.accept()
+    try:
+        msg = await asyncio.wait_for(ws.receive_json(), timeout=30)
+    except (asyncio.TimeoutError, ValueError, WebSocketDisconnect):
+        await ws.close(code=1008)
+        return
+    topics = {str(t) for t in (msg.get("subscribe") or [])} if isinstance(msg, dict) else set()
+    if not topics or not topics <= set(notify.TOPICS):
+        await ws.send_json({"error": f"subscribe to one or more of {', '.join(notify.TOPICS)}"})
+        await ws.close(code=1008)
+        return
+    addresses = {str(a) for a in (msg.get("addresses") or [])}
+    maxsize = max(1, int(get_config().get("network.rpc_ws_queue_size", 256)))
+    sub = notify.subscribe(asyncio.get_running_loop(), topics, addresses, maxsize=maxsize)
+
+    async def _watch_close():
+        # anything the client sends after subscribing is ignored; a disconnect ends the session
+        try:
+            while True:
+                await ws.receive_text()
+        except Exception:
+            sub._put(None)  # wakes the sender; a full queue marks it overflowed, which ends it too
+
+    watcher = asyncio.create_task(_watch_close())
+    try:
+        await ws.send_json({"subscribed": sorted(topics), "addresses": sorted(addresses)})
+        while True:
+            ev = await sub.queue.get()
+            if ev is None:
+                break
+            if sub.overflowed:
+                rpc_logger.warning("ws subscriber too slow, dropped", extra=logs.fields(client=client))
+                await ws.close(code=1013)
+                break
+            await ws.send_json(ev)
+    except (WebSocketDisconnect, RuntimeError):
+        pass
+    finally:
+        notify.unsubscribe(sub)
+        watcher.cancel()
+
+
+@app.get("/rpc/safe_mode")
+def rpc_safe_mode():
+    """
+    Safe-mode state (see core.safemode): active flag, trigger reason and recent trigger history.
+    """
+    return {**safemode.status(), "warnings": safemode.warnings()}
+
+
+@app.post("/rpc/safe_mode/clear")
+def rpc_safe_mode_clear(operator: Optional[str] = None):
+    """
+    Operator acknowledgement: leave safe mode after investigating the anomaly.
+    """
+    prev = safemode.clear(operator)
+    _safe_mode_cache["ts"] = 0
+    rpc_logger.warning(f"safe_mode cleared by={operator or 'operator'} was={prev.get('reason')}")
+    return {"cleared": True, "previous": prev}
+
+
+@app.get("/rpc/addrman")
+def rpc_addrman(limit: int = 50):
+    """
+    Address manager view: bucket sizes plus the freshest known addresses (tried first).
+    """
+    from core import addrman
+    return {**addrman.stats(), "addrs": addrman.get_addrs(limit)}
+
+
+@app.get("/rpc/getnodeaddresses")
+def rpc_getnodeaddresses(count: int = 1, network: Optional[str] = None):
+    """
+    Known peer addresses from the address manager, best quality score first (count 0 = all;
+    network: ipv4, ipv6 or onion), with services, last success, failure count and score.
+    """
+    from core import addrman
+    from core.netlocal import NETWORKS
+
+    if count < 0:
+        raise HTTPException(status_code=400, detail={"error": "count must be 0 or positive"})
+    if network is not None and network not in NETWORKS:
+        raise HTTPException(status_code=400, detail={"error": f"network must be one of {', '.join(NETWORKS)}"})
+    return addrman.node_addresses(count, network)
+
+
+class SetBanRequest(BaseModel):
+    subnet: str
+    command: str = "add"  # add | remove
+    bantime: int = 0  # seconds; 0 = network.ban_time_sec
+    absolute: bool = False  # bantime is a unix timestamp
+
+
+@app.post("/rpc/setban")
+def rpc_setban(req: SetBanRequest):
+    """
+    Add or remove a manual ban for a host or subnet (e.g. 1.2.3.4 or 10.0.0.0/8).
+    Connected peers in the range are dropped on their next message.
+    """
+    from core import banman
+
+    if req.command == "add":
+        entry = banman.ban(req.subnet, req.bantime, absolute=req.absolute)
+        rpc_logger.warning(f"setban add {entry['address']} until={entry['banned_until']}")
+        return {"banned": entry}
+    if req.command == "remove":
+        if not banman.unban(req.subnet):
+            raise HTTPException(status_code=404, detail={"error": "subnet not banned", "subnet": req.subnet})
+        return {"unbanned": req.subnet}
+    raise HTTPException(status_code=400, detail={"error": "command must be add or remove"})
+
+
+@app.get("/rpc/listbanned")
+def rpc_listbanned():
+    from core import banman
+    return banman.list_banned()
+
+
+@app.post("/rpc/clearbanned")
+def rpc_clearbanned():
+    from core import banman
+    n = banman.clear_banned()
+    rpc_logger.warning(f"clearbanned: removed={n}")
+    return {"cleared": n}
+
+
+@app.get("/rpc/getnettotals")
+def rpc_getnettotals():
+    """
+    P2P traffic counters: aggregate bytes in/out plus per-message-type counts and wire bytes, globally
+    and per peer (peers sorted by total bytes, heaviest first). Unknown message types are counted as
+    "*other*". Counters reset on node restart.
+    """
+    net_totals_snapshot = p2pstate.hook("net_totals")
+    if net_totals_snapshot is None:
+        return {"totalbytesrecv": 0, "totalbytessent": 0, "timemillis": now_ms(), "msgs_sent": {}, "msgs_recv": {},
+                "bytes_sent_per_msg": {}, "bytes_recv_per_msg": {}, "peers": []}
+    return net_totals_snapshot()
+
+
+@app.get("/rpc/getnetworkinfo")
+def rpc_getnetworkinfo():
+    """
+    Node network state: connection counts, bound P2P listeners, per-network reachability (ipv4,
+    ipv6, onion; network.onlynet) and the scored local addresses we advertise to peers.
+    """
+    network_info_snapshot = p2pstate.hook("network_info")
+    if network_info_snapshot is None:
+        from core import netlocal
+
+        return {"connections": 0, "connections_in": 0, "connections_out": 0, "networkactive": False,
+                "listening": False, "bound": [], "networks": netlocal.networks(), "localaddresses": []}
+    return network_info_snapshot()
+
+
+@app.get("/rpc/getpeerinfo")
+def rpc_getpeerinfo():
+    """
+    Connected peers with traffic per message type, negotiated compression and ping time
+    (Bitcoin Core field names). Standalone RPC (no P2P in process) reports an empty list.
+    """
+    peer_info_snapshot = p2pstate.hook("peer_info")
+    if peer_info_snapshot is None:
+        return []
+    return peer_info_snapshot()
+
+
+@app.get("/rpc/pool_stats")
+def rpc_pool_stats():
+    """
+    Latest pool snapshot persisted by the Stratum server (see StratumPool._snapshot_loop).
+    """
+    db = get_db()
+    with db.session() as s:
+        row = s.get(KV, "pool_snapshot_json")
+        if not row or not row.v:
+            return {"miners": [], "total_hashrate": 0.0, "ts": None}
+        try:
+            return json.loads(row.v)
+        except Exception:
+            return {"raw": row.v}
+
+
+@app.get("/rpc/getpoolstats")
+def rpc_getpoolstats(address: Optional[str] = None):
+    """
+    Pool-wide and per-worker hashrate (H/s over 1m/15m/1h, from accepted share difficulty) out of
+    the Stratum snapshot; address narrows the worker list. age_sec tells how fresh the snapshot is.
+    """
+    snap = rpc_pool_stats()
+    workers = [w for w in (snap.get("workers") or []) if not address or w.get("address") == address]
+    ts = snap.get("ts")
+    return {
+        "hashrate": snap.get("hashrate") or {"1m": 0.0, "15m": 0.0, "1h": 0.0},
+        "workers": workers,
+        "sessions": len(snap.get("miners") or []),
+        "share_diff": snap.get("share_diff"),
+        "accepted_5m": snap.get("accepted_5m", 0),
+        "rejected_5m": snap.get("rejected_5m", 0),
+        "ts": ts,
+        "age_sec": (now_ms() - int(ts)) / 1000.0 if ts else None,
+    }
+
+
+@app.get("/rpc/blockfiles")
+def rpc_blockfiles():
+    """
+    List flat block files (blkNNNNN.dat) available for bootstrap download.
+    """
+    from core.blockstore import iter_files
+    return [{"file_no": n, "name": os.path.basename(p), "size": size} for n, p, size in iter_files()]
+
+
+@app.get("/rpc/blockfiles/{file_no}")
+def rpc_blockfile(file_no: int):
+    """
+    Raw block file for fast bootstrap; feed it to `python -m tools.run import --file ...`.
+    """
+    from fastapi.responses import FileResponse
+    from core.blockstore import file_path
+    p = file_path(file_no)
+    if not os.path.exists(p):
+        raise HTTPException(status_code=404, detail={"error": "not_found", "file_no": file_no})
+    return FileResponse(p, media_type="application/octet-stream", filename=os.path.basename(p))
+
+
 @app.post("/rpc/p2p/connect")
 def rpc_p2p_connect(addr: str):
     """
     Attempt raw TCP connect as a quick connectivity probe. Returns {"connected":true} or 400.
     """
+    from core.netlocal import split_host_port
+
     try:
-        host, port_str = addr.split(":")
-        port = int(port_str)
-        s = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
-        s.settimeout(5.0)
-        s.connect((host, port))
+        host, port = split_host_port(addr)
+        s = socket.create_connection((host, port), timeout=5.0)
         s.close()
         return {"connected": True, "addr": addr}
     except Exception as e:
         raise HTTPException(status_code=400, detail=str(e))
 
 
+class AddNodeRequest(BaseModel):
+    addr: str
+    command: str = "onetry"  # onetry: connect now; add: also keep it in the address manager
+
+
+@app.post("/rpc/addnode")
+def rpc_addnode(req: AddNodeRequest):
+    """
+    Open an outbound P2P connection to addr (host:port or [v6]:port) through the node's own peer
+    loop, as Core's addnode. Needs the P2P layer in process (core.p2pstate).
+    """
+    from core import addrman
+    from core.netlocal import format_addr, split_host_port
+
+    if req.command not in ("onetry", "add"):
+        raise HTTPException(status_code=400, detail={"error": "command must be onetry or add"})
+    try:
+        addr = format_addr(*split_host_port(req.addr))
+    except ValueError as e:
+        raise HTTPException(status_code=400, detail={"error": str(e)})
+    connect_peer = p2pstate.hook("connect_peer")
+    if connect_peer is None:
+        raise HTTPException(status_code=503, detail={"error": "P2P is not running in this process"})
+    if req.command == "add":
+        addrman.add([addr], source="manual", allow_hostnames=True)
+    if not connect_peer(addr):
+        raise HTTPException(status_code=400, detail={"error": "connect failed", "addr": addr})
+    return {"connected": True, "addr": addr}
+
+
 # ========== Ticketed Solo Mining (diagnostics-first) ==========
 
 def _epoch_lengths() -> Tuple[int, int]:
@@ -603,17 +2142,18 @@ def _sign_ticket(payload: str) -> str:
 @app.get("/rpc/solo/get_ticket")
 def rpc_solo_get_ticket(addr: str):
     rpc_logger.info(f"solo_get_ticket: addr={addr}")
-    if not addr or not addr.startswith("SMELLY_"):
-        raise HTTPException(status_code=400, detail="invalid address")
+    from core.crypto import check_address
+
+    err = check_address(addr or "")
+    if err:
+        raise HTTPException(status_code=400, detail={"error": "invalid address", "reason": err})
     db = get_db()
     with db.session() as s:
         tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
         height = 0 if not tip else tip.height + 1
         prev_hash = "00" * 32 if not tip else tip.hash_hex
-        if height < 200:
-            target_hex = difficulty_to_target(1)
-        else:
-            target_hex = tip.target if tip and tip.target else difficulty_to_target(1)
+        target_hex = next_block_target(s, tip)[0]
+        version = versionbits.compute_block_version(tip)
     issued = now_ms()
     valid_to = issued + _TICKET_WINDOW_MS
     seed = uuid.uuid4().hex
@@ -624,7 +2164,7 @@ def rpc_solo_get_ticket(addr: str):
             "addr": addr,
             "prev": prev_hash,
             "target": target_hex,
-            "version": int(get_config().get("consensus.block_version", 1)),
+            "version": version,
             "issued": issued,
             "valid_to": valid_to,
             "nonce_start": start_nonce,
@@ -656,8 +2196,7 @@ def _validate_ticket(payload: str, sig: str) -> Dict[str, Any]:
 
 def _near_target_threshold(target_hex: str) -> int:
     # Easier than target to achieve ~N/min near proofs
-    t = int(target_hex, 16)
-    return min((1 << 256) - 1, t << 12)
+    return min(U256_MAX, to_int(target_hex) << 12)
 
 
 @app.post("/rpc/solo/submit_near_target")
@@ -809,13 +2348,10 @@ def rpc_solo_submit_block(req: SoloSubmitBlock):
 
         txids: List[str] = [coinbase_txid]
         if next_h >= 200:
-            mem = (
-                s.query(MempoolTx)
-                .filter(MempoolTx.fee >= MIN_FEE)
-                .order_by(MempoolTx.fee.desc(), MempoolTx.added_ms.asc())
-                .limit(TXS_PER_BLOCK)
-                .all()
-            )
+            rows = s.query(MempoolTx).all()
+            by_txid = {m.txid: m for m in rows}
+            mem = [by_txid[t] for t in txgraph.select_packages(txgraph.build(rows), TXS_PER_BLOCK, MIN_FEE,
+                                                                 blocksize.template_max_bytes())]
             for m in mem:
                 txid_norm = ((m.txid or "").strip().lower())
                 if txid_norm:
@@ -866,14 +2402,13 @@ def rpc_solo_submit_block(req: SoloSubmitBlock):
         except Exception:
             pass
         detail = {"accepted": False, "error": err, "diag": diag}
-        # Colorized hints
         low = (err or "").lower()
         if "prev" in low:
-            rpc_logger.error(_Color.RED + "solo_submit_block: REJECT prev mismatch (stale). Tip moved vs ticket prev." + _Color.RESET)
+            rpc_logger.error("solo_submit_block: REJECT prev mismatch (stale). Tip moved vs ticket prev.")
         elif "merkle" in low:
-            rpc_logger.error(_Color.RED + "solo_submit_block: REJECT merkle mismatch (ordering/normalization)." + _Color.RESET)
+            rpc_logger.error("solo_submit_block: REJECT merkle mismatch (ordering/normalization).")
         elif "pow" in low or "target" in low:
-            rpc_logger.error(_Color.RED + "solo_submit_block: REJECT target not met." + _Color.RESET)
+            rpc_logger.error("solo_submit_block: REJECT target not met.")
         rpc_logger.error("solo_submit_block reject %s", json.dumps(detail, separators=(",", ":"), sort_keys=True))
         raise HTTPException(status_code=400, detail=detail)
 
@@ -886,7 +2421,7 @@ def rpc_solo_submit_block(req: SoloSubmitBlock):
     except Exception:
         pass
 
-    rpc_logger.info(_Color.GREEN + f"solo_submit_block: ACCEPTED hash={hh[:16]}.. h_tip_next={diag.get('next_height')}" + _Color.RESET)
+    rpc_logger.info(f"solo_submit_block: ACCEPTED hash={hh[:16]}.. h_tip_next={diag.get('next_height')}")
     return {"accepted": True, "hash": hh, "diag": diag}
 
 
@@ -918,13 +2453,70 @@ def rpc_debug_solo_diag():
     return out
 
 
+def _configure_cors(cfg) -> None:
+    """network.rpc_cors_domains: origins allowed to call the RPC from a browser ("*" = any)."""
+    from fastapi.middleware.cors import CORSMiddleware
+
+    domains = cfg.get("network.rpc_cors_domains", []) or []
+    if isinstance(domains, str):
+        domains = [d.strip() for d in domains.split(",") if d.strip()]
+    if not domains:
+        return
+    app.add_middleware(
+        CORSMiddleware,
+        allow_origins=[str(d).rstrip("/") for d in domains],
+        allow_methods=["GET", "POST", "OPTIONS"],
+        allow_headers=["*"],
+        expose_headers=["X-Smelly-Warnings"],
+        max_age=600,
+    )
+    rpc_logger.info(f"rpc: CORS enabled for {', '.join(str(d) for d in domains)}")
+
+
+def _start_https_redirect(host: str, http_port: int, https_port: int):
+    """Plain-HTTP listener answering every request with a 301 to the HTTPS RPC port."""
+    from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+    class Redirect(BaseHTTPRequestHandler):
+        def _redirect(self):
+            hostname = (self.headers.get("Host") or host).rsplit(":", 1)[0] or host
+            self.send_response(301)
+            self.send_header("Location", f"https://{hostname}:{https_port}{self.path}")
+            self.send_header("Content-Length", "0")
+            self.end_headers()
+
+        do_GET = do_POST = do_HEAD = do_PUT = do_DELETE = do_OPTIONS = _redirect
+
+        def log_message(self, *args):
+            pass
+
+    srv = ThreadingHTTPServer((host, http_port), Redirect)
+    threading.Thread(target=srv.serve_forever, name="rpc-https-redirect", daemon=True).start()
+    rpc_logger.info(f"rpc: redirecting http://{host}:{http_port} -> https port {https_port}")
+    return srv
+
+
 def run_rpc_server():
-    import os
+    # SMELLY_RPC_HOST/PORT and node CLI flags are merged into the config as overrides
     cfg = get_config()
-    env_host = os.environ.get("SMELLY_RPC_HOST")
-    env_port = os.environ.get("SMELLY_RPC_PORT")
-    host = env_host or cfg.get("network.rpc_host", "127.0.0.1")
-    port = int(env_port) if env_port else int(cfg.get("network.rpc_port", 28445))
+    logs.setup()
+    host = cfg.get("network.rpc_host", "127.0.0.1")
+    port = int(cfg.get("network.rpc_port", 28445))
+    _configure_cors(cfg)
+    cert = str(cfg.get("network.rpc_tls_cert", "") or "")
+    key = str(cfg.get("network.rpc_tls_key", "") or "")
+    if bool(cert) != bool(key):
+        raise SystemExit("network.rpc_tls_cert and network.rpc_tls_key must be set together")
+    if cert:
+        for p in (cert, key):
+            if not os.path.isfile(p):
+                raise SystemExit(f"RPC TLS file not found: {p}")
+        redirect_port = int(cfg.get("network.rpc_http_redirect_port", 0))
+        if redirect_port:
+            _start_https_redirect(host, redirect_port, port)
+        rpc_logger.info(f"rpc: serving HTTPS on {host}:{port}")
+        uvicorn.run(app, host=host, port=port, log_level="info", ssl_certfile=cert, ssl_keyfile=key)
+        return
     uvicorn.run(app, host=host, port=port, log_level="info")
 
 
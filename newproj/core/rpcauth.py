@@ -0,0 +1,147 @@
+from __future__ import annotations
+
+import base64
+import hashlib
+import hmac
+from typing import Any, Dict, List, Optional, Tuple
+
+from core.config import get_config
+
+
+# RPC authentication and per-user method ACLs.
+#
+# network.rpc_users empty (the default): no authentication, every caller is admin, as before.
+# With users configured every request needs HTTP Basic credentials, unless
+# network.rpc_anonymous_role grants unauthenticated callers a role. Each user entry:
+#   user             login name
+#   password         plain text, or password_sha256: "<salt>$<hex sha256(salt + password)>"
+#   role             admin (every method) | readonly (GET routes + READONLY_POST) | none
+#   allow            methods granted on top of the role
+#   deny             methods refused whatever the role and allow list say
+# A method is the route below /rpc/ ("submitblock", "tx/submit", "safe_mode/clear"); other paths
+# use their first segment ("rest", "metrics"). An entry ending in "*" matches by prefix ("solo/*").
+# network.rpc_readonly caps every caller at readonly (deny lists still apply) for maintenance.
+#
+# In-tree RPC clients (pool, wallet backend) send network.rpc_client_user / rpc_client_password.
+
+ROLES = ("admin", "readonly", "none")
+
+# POST routes that compute an answer without touching chain, mempool, ban list or node settings
+READONLY_POST = frozenset({
+    "get_headers_range",
+    "createrawtransaction",
+    "createmultisig",
+    "createpsbt",
+    "decodepsbt",
+    "analyzepsbt",
+    "utxoupdatepsbt",
+    "combinepsbt",
+    "finalizepsbt",
+    "testmempoolaccept",
+})
+
+
+class Caller:
+    def __init__(self, user: Optional[str], role: str, allow: List[str], deny: List[str]):
+        self.user = user
+        self.role = role
+        self.allow = allow
+        self.deny = deny
+
+    @property
+    def name(self) -> str:
+        return self.user or "anonymous"
+
+
+def method_of(path: str) -> str:
+    p = path.strip("/")
+    if p.startswith("rpc/"):
+        return p[4:]
+    return p.split("/", 1)[0]
+
+
+def _matches(method: str, patterns: List[str]) -> bool:
+    for pat in patterns:
+        if pat == method or (pat.endswith("*") and method.startswith(pat[:-1])):
+            return True
+    return False
+
+
+def _check_password(entry: Dict[str, Any], password: str) -> bool:
+    hashed = str(entry.get("password_sha256", "") or "")
+    if hashed:
+        salt, _, digest = hashed.partition("$")
+        got = hashlib.sha256((salt + password).encode("utf-8")).hexdigest()
+        return hmac.compare_digest(got, digest.lower())
+    plain = str(entry.get("password", "") or "")
+    return bool(plain) and hmac.compare_digest(plain.encode("utf-8"), password.encode("utf-8"))
+
+
+def _parse_basic(header: Optional[str]) -> Optional[Tuple[str, str]]:
+    if not header or not header.lower().startswith("basic "):
+        return None
+    try:
+        raw = base64.b64decode(header[6:].strip(), validate=True).decode("utf-8")
+    except Exception:
+        return None
+    user, sep, password = raw.partition(":")
+    return (user, password) if sep else None
+
+
+def _list(val: Any) -> List[str]:
+    if not val:
+        return []
+    if isinstance(val, str):
+        return [v.strip() for v in val.split(",") if v.strip()]
+    return [str(v) for v in val]
+
+
+def enabled() -> bool:
+    return bool(get_config().get("network.rpc_users", []) or [])
+
+
+def authenticate(authorization: Optional[str]) -> Tuple[Optional[Caller], str]:
+    """Caller for an Authorization header, or (None, reason) when it must be refused with 401."""
+    cfg = get_config()
+    users = cfg.get("network.rpc_users", []) or []
+    if not users:
+        return Caller(None, "admin", [], []), ""
+    creds = _parse_basic(authorization)
+    if creds is None:
+        if authorization:
+            return None, "malformed credentials"
+        anon = str(cfg.get("network.rpc_anonymous_role", "") or "")
+        if anon in ROLES:
+            return Caller(None, anon, [], []), ""
+        return None, "authentication required"
+    user, password = creds
+    for entry in users:
+        if isinstance(entry, dict) and str(entry.get("user", "")) == user:
+            if not _check_password(entry, password):
+                break
+            role = str(entry.get("role", "readonly"))
+            return Caller(user, role if role in ROLES else "none", _list(entry.get("allow")), _list(entry.get("deny"))), ""
+    return None, "bad credentials"
+
+
+def authorize(caller: Caller, http_method: str, path: str) -> bool:
+    method = method_of(path)
+    if _matches(method, caller.deny):
+        return False
+    readonly_only = bool(get_config().get("network.rpc_readonly", False))
+    role_ok = (caller.role == "admin" and not readonly_only) or (
+        caller.role in ("admin", "readonly") and (http_method in ("GET", "HEAD") or method in READONLY_POST)
+    )
+    if role_ok:
+        return True
+    if _matches(method, caller.allow):
+        # in read-only mode an allow list can still widen "none" users to read-only methods only
+        return not readonly_only or http_method in ("GET", "HEAD") or method in READONLY_POST
+    return False
+
+
+def client_auth() -> Optional[Tuple[str, str]]:
+    """(user, password) for in-tree clients calling the node RPC, or None when not configured."""
+    cfg = get_config()
+    user = str(cfg.get("network.rpc_client_user", "") or "")
+    return (user, str(cfg.get("network.rpc_client_password", "") or "")) if user else None
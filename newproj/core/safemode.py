@@ -0,0 +1,158 @@
+from __future__ import annotations
+
+import json
+import threading
+from typing import Any, Dict, List, Optional
+
+from core.config import get_config
+from core.db import get_db, BlockHeader
+from core.target import block_work
+from core.utils import now_ms
+from core import banman
+from core import logs
+
+
+# Safe mode: a sticky, DB-persisted flag raised on consensus anomalies seen from the network.
+# While active, the wallet refuses to send, pool payouts are paused and every node RPC response
+# carries a warning. An operator clears it with /rpc/safe_mode/clear; it also clears itself once
+# our own chain has grown `clear_after_blocks` valid blocks past the last trigger (0 = operator only).
+#
+# Triggers (thresholds under `safe_mode.*` in config):
+#   invalid_work  - peers relayed headers that meet their PoW target but are otherwise invalid,
+#                   with work (each distinct header counted once) adding up to more than
+#                   `invalid_work_blocks` of our tip's blocks
+#   fork_depth    - a PoW-valid header forks off our chain deeper than `max_fork_depth`
+#
+# Headers that fail PoW cost nothing to make, so they never trip safe mode: a peer that sends
+# `pow_failures` of them within `pow_failure_window_sec` is banned instead (core.banman).
+#
+# State lives in KV so separate processes (wallet backend, pool) sharing the DB see it.
+
+_KV_KEY = "safe_mode_json"
+_lock = threading.Lock()
+_pow_failures: Dict[str, List[int]] = {}  # host -> failure timestamps (ms)
+_invalid_work: Dict[str, tuple] = {}  # header hash -> (ts_ms, work); dict order = arrival order
+
+
+def _enabled() -> bool:
+    return bool(get_config().get("safe_mode.enabled", True))
+
+
+def status() -> Dict[str, Any]:
+    raw = get_db().get_kv(_KV_KEY)
+    if not raw:
+        return {"active": False}
+    try:
+        st = json.loads(raw)
+    except Exception:
+        return {"active": False}
+    return st if isinstance(st, dict) else {"active": False}
+
+
+def is_active() -> bool:
+    return bool(status().get("active"))
+
+
+def warnings() -> List[str]:
+    st = status()
+    if not st.get("active"):
+        return []
+    return [f"SAFE MODE: {st.get('reason')} - {st.get('detail')}. Wallet sends and pool payouts are disabled until the chain moves past it or an operator clears it."]
+
+
+def _tip_height() -> int:
+    with get_db().session() as s:
+        tip = s.query(BlockHeader.height).order_by(BlockHeader.height.desc()).first()
+    return tip[0] if tip else -1
+
+
+def trip(reason: str, detail: str) -> bool:
+    """Enter safe mode (first trigger wins; later ones are appended to history). Returns True if newly tripped."""
+    if not _enabled():
+        return False
+    height = _tip_height()
+    with _lock:
+        st = status()
+        event = {"reason": reason, "detail": detail, "ts": now_ms(), "height": height}
+        if st.get("active"):
+            st.setdefault("history", []).append(event)
+            st["history"] = st["history"][-20:]
+            st["last_height"] = height
+            get_db().set_kv(_KV_KEY, json.dumps(st))
+            return False
+        st = {"active": True, "reason": reason, "detail": detail, "since_ms": event["ts"], "last_height": height,
+              "history": [event]}
+        get_db().set_kv(_KV_KEY, json.dumps(st))
+    logs.get("consensus").critical(f"SAFE MODE ENTERED: {reason}: {detail}")
+    return True
+
+
+def clear(operator: Optional[str] = None) -> Dict[str, Any]:
+    with _lock:
+        prev = status()
+        get_db().set_kv(_KV_KEY, json.dumps({"active": False, "cleared_ms": now_ms(), "cleared_by": operator or "operator", "last": prev}))
+        _pow_failures.clear()
+        _invalid_work.clear()
+    return prev
+
+
+def note_tip(height: int) -> bool:
+    """Clear safe mode once the chain is `clear_after_blocks` past the last trigger. Returns True if cleared."""
+    blocks = int(get_config().get("safe_mode.clear_after_blocks", 6))
+    if blocks <= 0:
+        return False
+    st = status()
+    if not st.get("active") or "last_height" not in st:
+        return False
+    if height < int(st["last_height"]) + blocks:
+        return False
+    clear("auto")
+    logs.get("consensus").warning(f"safe mode cleared: chain advanced to {height}, {blocks} valid blocks past the last trigger ({st.get('reason')})")
+    return True
+
+
+def note_pow_failure(peer: str, header_hash: str) -> bool:
+    """Count a PoW failure against the sending host; bans it at the limit. Returns True if banned."""
+    cfg = get_config()
+    limit = max(1, int(cfg.get("safe_mode.pow_failures", 3)))
+    window_ms = int(cfg.get("safe_mode.pow_failure_window_sec", 600)) * 1000
+    host = banman.host_of(peer)
+    nowm = now_ms()
+    with _lock:
+        for h in [h for h, ts in _pow_failures.items() if ts[-1] < nowm - window_ms]:
+            del _pow_failures[h]
+        failures = [t for t in _pow_failures.get(host, []) if t >= nowm - window_ms] + [nowm]
+        _pow_failures[host] = failures
+        n = len(failures)
+        if n >= limit:
+            del _pow_failures[host]
+    if n < limit:
+        return False
+    banman.ban(host, reason=f"{n} PoW verification failures in {window_ms // 1000}s (last hdr {header_hash[:16]}..)")
+    return True
+
+
+def note_invalid_header(peer: str, header_hash: str, target_hex: str, tip_target_hex: Optional[str]):
+    """Accumulate the work of relayed headers that met their PoW target but were invalid; trip when it outweighs N tip blocks."""
+    cfg = get_config()
+    blocks = int(cfg.get("safe_mode.invalid_work_blocks", 6))
+    window_ms = int(cfg.get("safe_mode.invalid_work_window_sec", 3600)) * 1000
+    work = block_work(target_hex)
+    tip_work = block_work(tip_target_hex or "f" * 64)
+    if work <= 0 or tip_work <= 0:
+        return
+    nowm = now_ms()
+    with _lock:
+        _invalid_work.setdefault(header_hash, (nowm, work))
+        while _invalid_work and next(iter(_invalid_work.values()))[0] < nowm - window_ms:
+            _invalid_work.pop(next(iter(_invalid_work)))
+        total = sum(w for _, w in _invalid_work.values())
+    if total > tip_work * blocks:
+        trip("invalid_work", f"invalid headers relayed with claimed work {total} > {blocks} tip blocks (last from {peer})")
+
+
+def note_fork(peer: str, fork_height: int, tip_height: int):
+    depth = tip_height - fork_height
+    max_depth = int(get_config().get("safe_mode.max_fork_depth", 6))
+    if depth > max_depth:
+        trip("fork_depth", f"PoW-valid header from {peer} forks at height {fork_height}, {depth} blocks below tip {tip_height}")
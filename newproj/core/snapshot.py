@@ -0,0 +1,289 @@
+from __future__ import annotations
+
+import hashlib
+import json
+import os
+import signal
+import threading
+from typing import Any, Callable, Dict, Iterator, List, Optional, Tuple
+
+from core.config import get_config
+from core.db import get_db, BlockHeader, KV, UTXO
+from core.utils import now_ms
+from core import utxohash
+from core import logs
+
+
+# Chain-state snapshots (assumeutxo-style): dumptxoutset / loadtxoutset.
+#
+# A snapshot is a JSON-lines file taken at the tip:
+#   {"t": "meta", "version", "network", "base_height", "base_hash", "txouts", "total_sats", "muhash", "headers"}
+#   {"t": "h", header fields + "txids"}   one per block, genesis first (the node only extends its tip,
+#                                         so the header chain up to the base comes along)
+#   {"t": "u", "txid", "vout", "address", "amount", "coinbase", "height"}   one per unspent output
+# The file's sha256 is returned by dump so operators can publish it next to the muhash.
+#
+# Loading requires a fresh node (nothing above genesis). The header chain is checked for linkage
+# and hashes, the outputs are hashed with core.utxohash and must reproduce the meta muhash, and
+# the (base_height, base_hash, muhash) triple must match consensus.assumeutxo unless the caller
+# passes trust. The loaded state replaces the local genesis; the node then syncs from the base.
+# History below the base is background-validated: a thread re-checks every header's PoW,
+# timestamps and checkpoints (block bodies below the base are not in the snapshot), with progress
+# in KV (assumeutxo_json) and resumed at startup.
+#
+# If that validation fails, everything built on the snapshot is suspect and there is no validated
+# chainstate to fall back to (the snapshot replaced it). The failure is recorded as
+# history="failed", safe mode is tripped so the wallet backend and pool stop acting on the chain,
+# and the process is interrupted; the node refuses to start again while the state says failed.
+
+SNAPSHOT_VERSION = 1
+_STATE_KEY = "assumeutxo_json"
+_validate_lock = threading.Lock()
+
+ProgressFn = Callable[[int, int], None]
+
+
+def _header_record(h: BlockHeader, txids: List[str]) -> Dict[str, Any]:
+    return {
+        "t": "h",
+        "height": h.height,
+        "hash": h.hash_hex,
+        "prev": h.prev_hash_hex,
+        "merkle": h.merkle_root_hex,
+        "ts": h.timestamp,
+        "ver": h.version,
+        "nonce": str(h.nonce),
+        "target": h.target,
+        "miner": h.miner_address,
+        "tx_count": h.tx_count,
+        "work": h.work,
+        "txids": txids,
+    }
+
+
+def _header_obj(rec: Dict[str, Any]):
+    from core.consensus import Header
+
+    return Header(version=int(rec["ver"]), prev_hash_hex=rec["prev"], merkle_root_hex=rec["merkle"],
+                  timestamp=int(rec["ts"]), target=rec["target"], nonce=int(rec["nonce"]),
+                  miner_address=rec["miner"], tx_count=int(rec["tx_count"]))
+
+
+def dump(path: str, progress: Optional[ProgressFn] = None) -> Dict[str, Any]:
+    """Write a snapshot at the current tip; returns its meta plus path and file sha256."""
+    from core.blockio import record_for_row
+    from core.blockstore import read_block
+
+    utxohash.backfill()
+    db = get_db()
+    tmp = path + ".tmp"
+    os.makedirs(os.path.dirname(os.path.abspath(path)), exist_ok=True)
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        if tip is None:
+            raise ValueError("no blocks")
+        res = utxohash.recompute(s)
+        meta = {
+            "t": "meta",
+            "version": SNAPSHOT_VERSION,
+            "network": str(get_config().get("network.name", "")),
+            "base_height": tip.height,
+            "base_hash": tip.hash_hex,
+            "txouts": res["txouts"],
+            "total_sats": res["total_sats"],
+            "muhash": res["muhash"],
+            "headers": tip.height + 1,
+        }
+        digest = hashlib.sha256()
+        with open(tmp, "w", encoding="utf-8") as f:
+            def emit(obj: Dict[str, Any]):
+                line = json.dumps(obj, separators=(",", ":"), sort_keys=True) + "\n"
+                digest.update(line.encode("utf-8"))
+                f.write(line)
+
+            emit(meta)
+            for h in s.query(BlockHeader).filter(BlockHeader.height <= tip.height).order_by(BlockHeader.height.asc()).yield_per(500):
+                stored = read_block(h.hash_hex)
+                emit(_header_record(h, stored.txids if stored else record_for_row(s, h).txids))
+                if progress and h.height % 1000 == 0:
+                    progress(h.height, tip.height)
+            for u in s.query(UTXO).filter(UTXO.spent.is_(False)).order_by(UTXO.txid.asc(), UTXO.vout.asc()).yield_per(1000):
+                emit({"t": "u", "txid": u.txid, "vout": int(u.vout), "address": u.address, "amount": float(u.amount),
+                      "coinbase": bool(u.coinbase), "height": u.height})
+            f.flush()
+            os.fsync(f.fileno())
+    os.replace(tmp, path)
+    return dict(meta, path=path, sha256=digest.hexdigest())
+
+
+def _read(path: str) -> Iterator[Dict[str, Any]]:
+    with open(path, "r", encoding="utf-8") as f:
+        for line in f:
+            if line.strip():
+                yield json.loads(line)
+
+
+def _trusted(meta: Dict[str, Any]) -> Optional[str]:
+    """None if consensus.assumeutxo lists this snapshot, else the reason it does not."""
+    entries = get_config().get("consensus.assumeutxo", {}) or {}
+    entry = entries.get(meta["base_height"]) or entries.get(str(meta["base_height"]))
+    if not entry:
+        return f"no consensus.assumeutxo entry for height {meta['base_height']}"
+    if str(entry.get("hash", "")).lower() != meta["base_hash"] or str(entry.get("muhash", "")).lower() != meta["muhash"]:
+        return "snapshot does not match consensus.assumeutxo"
+    return None
+
+
+def load(path: str, trust: bool = False) -> Tuple[Optional[Dict[str, Any]], Optional[str]]:
+    """Verify and load a snapshot into a fresh node. Returns (meta, error)."""
+    from core.blockstore import store_blocks
+
+    records = _read(path)
+    try:
+        meta = next(records)
+    except (StopIteration, ValueError) as e:
+        return None, f"unreadable snapshot: {e}"
+    if meta.get("t") != "meta" or meta.get("version") != SNAPSHOT_VERSION:
+        return None, "not a version-1 snapshot"
+    network = str(get_config().get("network.name", ""))
+    if meta.get("network") != network:
+        return None, f"snapshot is for network {meta.get('network')!r}, node runs {network!r}"
+    if not trust:
+        reason = _trusted(meta)
+        if reason:
+            return None, reason + " (pass trust to load anyway)"
+
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        if tip is not None and tip.height > 0:
+            return None, "node already has blocks above genesis; loadtxoutset needs a fresh datadir"
+
+    headers: List[Dict[str, Any]] = []
+    outputs: List[Dict[str, Any]] = []
+    mh = utxohash.MuHash()
+    total = 0
+    try:
+        for rec in records:
+            if rec.get("t") == "h":
+                expect_prev = headers[-1]["hash"] if headers else "00" * 32
+                if int(rec["height"]) != len(headers) or rec["prev"] != expect_prev:
+                    return None, f"header chain broken at height {rec.get('height')}"
+                if _header_obj(rec).hash_hex() != rec["hash"]:
+                    return None, f"header hash mismatch at height {rec['height']}"
+                headers.append(rec)
+            elif rec.get("t") == "u":
+                u = UTXO(txid=rec["txid"], vout=int(rec["vout"]), address=rec["address"], amount=float(rec["amount"]),
+                         spent=False, spent_txid=None, coinbase=bool(rec["coinbase"]), height=rec.get("height"))
+                mh.insert(utxohash.serialize_output(u))
+                total += utxohash.amount_sats(u.amount)
+                outputs.append(rec)
+    except (KeyError, TypeError, ValueError) as e:
+        return None, f"malformed snapshot record: {e}"
+    if not headers or headers[-1]["hash"] != meta["base_hash"] or len(headers) != int(meta["headers"]):
+        return None, "header chain does not end at the snapshot base"
+    if len(outputs) != int(meta["txouts"]) or total != int(meta["total_sats"]) or mh.digest_hex() != meta["muhash"]:
+        return None, "UTXO set does not match the snapshot muhash"
+
+    with db.session() as s:
+        s.query(BlockHeader).delete(synchronize_session=False)
+        s.query(UTXO).delete(synchronize_session=False)
+        for rec in headers:
+            s.add(BlockHeader(height=int(rec["height"]), hash_hex=rec["hash"], prev_hash_hex=rec["prev"],
+                              merkle_root_hex=rec["merkle"], timestamp=int(rec["ts"]), version=int(rec["ver"]),
+                              nonce=str(rec["nonce"]), target=rec["target"], miner_address=rec["miner"],
+                              tx_count=int(rec["tx_count"]), work=rec["work"]))
+        for rec in outputs:
+            s.add(UTXO(txid=rec["txid"], vout=int(rec["vout"]), address=rec["address"], amount=float(rec["amount"]),
+                       spent=False, spent_txid=None, coinbase=bool(rec["coinbase"]), height=rec.get("height")))
+        state = {"base_height": int(meta["base_height"]), "base_hash": meta["base_hash"], "muhash": meta["muhash"],
+                 "loaded_ms": now_ms(), "trusted": not trust or _trusted(meta) is None,
+                 "history": "validating", "validated_height": 0}
+        _save_state(s, state)
+        s.commit()
+    store_blocks([(rec["hash"], rec["txids"]) for rec in headers])
+    utxohash.rebuild()
+    start_background_validation()
+    return {k: v for k, v in meta.items() if k != "t"}, None
+
+
+def _save_state(s, state: Dict[str, Any]):
+    row = s.get(KV, _STATE_KEY) or KV(k=_STATE_KEY, v="")
+    row.v = json.dumps(state, separators=(",", ":"), sort_keys=True)
+    s.merge(row)
+
+
+def status() -> Optional[Dict[str, Any]]:
+    db = get_db()
+    with db.session() as s:
+        row = s.get(KV, _STATE_KEY)
+    return json.loads(row.v) if row and row.v else None
+
+
+def _validate_history():
+    from core.consensus import validate_header
+
+    db = get_db()
+    with db.session() as s:
+        row = s.get(KV, _STATE_KEY)
+        state = json.loads(row.v) if row and row.v else None
+        if not state or state.get("history") != "validating":
+            return
+        base = int(state["base_height"])
+        h = int(state.get("validated_height", 0))
+        prev = s.query(BlockHeader).filter_by(height=h).first()
+        while h < base:
+            row_h = s.query(BlockHeader).filter_by(height=h + 1).first()
+            if row_h is None or prev is None:
+                state.update(history="failed", error=f"missing header at height {h + 1}")
+                break
+            ok, reason = validate_header(_header_obj(_header_record(row_h, [])), prev)
+            # Timestamps are judged against the node clock; history cannot be "too far in future"
+            if not ok and reason != "timestamp too far in future":
+                state.update(history="failed", error=f"height {h + 1}: {reason}")
+                break
+            prev, h = row_h, h + 1
+            if h % 100 == 0 or h == base:
+                state["validated_height"] = h
+                _save_state(s, state)
+                s.commit()
+        else:
+            state.update(history="validated", validated_height=base, validated_ms=now_ms())
+        _save_state(s, state)
+        s.commit()
+    if state["history"] == "failed":
+        _halt(state.get("error") or "")
+
+
+def failed() -> Optional[str]:
+    """The background validation error if the loaded snapshot's history turned out invalid."""
+    st = status()
+    if st and st.get("history") == "failed":
+        return st.get("error") or "unknown error"
+    return None
+
+
+def _halt(error: str):
+    from core import safemode
+
+    logs.get("storage").critical(f"assumeutxo: background validation failed: {error}; the snapshot chainstate is invalid, shutting down")
+    safemode.trip("assumeutxo-invalid", f"snapshot history failed validation: {error}")
+    os.kill(os.getpid(), signal.SIGINT)
+
+
+def start_background_validation() -> bool:
+    """Start (or resume) background header validation below the snapshot base."""
+    st = status()
+    if not st or st.get("history") != "validating" or not _validate_lock.acquire(blocking=False):
+        return False
+
+    def _run():
+        try:
+            _validate_history()
+        except Exception as e:
+            logs.get("storage").exception(f"assumeutxo: background validation error: {e}")
+        finally:
+            _validate_lock.release()
+
+    threading.Thread(target=_run, name="assumeutxo-validate", daemon=True).start()
+    return True
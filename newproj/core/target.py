@@ -0,0 +1,96 @@
+from __future__ import annotations
+
+from typing import Iterable, Union
+
+
+# Work-target arithmetic.
+#
+# Targets travel as 64-char hex strings (BlockHeader.target, job target_hex); hashes as 32 raw bytes.
+# Everything here works on plain ints clamped to 256 bits so every caller (validation, miners,
+# stratum share checks, chainwork) compares the same way:  int(hash, big-endian) <= target.
+#
+# Compact "bits" use the Bitcoin encoding: 1 byte exponent + 3 byte mantissa,
+# target = mantissa * 256^(exponent-3), sign bit (0x00800000) never set.
+
+U256_MAX = (1 << 256) - 1
+
+TargetLike = Union[int, str, bytes]
+
+
+def to_int(value: TargetLike) -> int:
+    """Parse a target/hash given as int, hex string or big-endian bytes; clamp into [0, U256_MAX]."""
+    if isinstance(value, int):
+        n = value
+    elif isinstance(value, (bytes, bytearray)):
+        n = int.from_bytes(bytes(value), "big")
+    else:
+        n = int(str(value).strip() or "0", 16)
+    return max(0, min(U256_MAX, n))
+
+
+def to_hex(value: int) -> str:
+    return f"{max(0, min(U256_MAX, int(value))):064x}"
+
+
+def hash_meets_target(hash_value: TargetLike, target: TargetLike) -> bool:
+    """True when hash <= target. A malformed target never validates."""
+    try:
+        return to_int(hash_value) <= to_int(target)
+    except (TypeError, ValueError):
+        return False
+
+
+def difficulty_to_target(difficulty: Union[int, float]) -> str:
+    """target = U256_MAX // difficulty (difficulty 1 = easiest)."""
+    if difficulty <= 0:
+        difficulty = 1
+    if isinstance(difficulty, float):
+        # Keep precision for fractional share difficulties
+        return to_hex(int(U256_MAX / difficulty))
+    return to_hex(U256_MAX // int(difficulty))
+
+
+def target_to_difficulty(target: TargetLike) -> float:
+    t = to_int(target)
+    return U256_MAX / t if t > 0 else float(U256_MAX)
+
+
+def bits_to_target(bits: int) -> str:
+    exponent = (bits >> 24) & 0xFF
+    mantissa = bits & 0x007FFFFF
+    if bits & 0x00800000:
+        raise ValueError("negative compact target")
+    if exponent <= 3:
+        n = mantissa >> (8 * (3 - exponent))
+    else:
+        n = mantissa << (8 * (exponent - 3))
+    if n > U256_MAX:
+        raise ValueError("compact target overflows 256 bits")
+    return to_hex(n)
+
+
+def target_to_bits(target: TargetLike) -> int:
+    n = to_int(target)
+    size = (n.bit_length() + 7) // 8
+    if size <= 3:
+        mantissa = n << (8 * (3 - size))
+    else:
+        mantissa = n >> (8 * (size - 3))
+    # Keep the sign bit clear by moving one byte into the exponent
+    if mantissa & 0x00800000:
+        mantissa >>= 8
+        size += 1
+    return (size << 24) | (mantissa & 0x007FFFFF)
+
+
+def block_work(target: TargetLike) -> int:
+    """Expected hashes to meet target: 2^256 / (target + 1)."""
+    try:
+        t = to_int(target)
+    except (TypeError, ValueError):
+        return 0
+    return (1 << 256) // (t + 1)
+
+
+def chainwork(targets: Iterable[TargetLike]) -> int:
+    return sum(block_work(t) for t in targets)
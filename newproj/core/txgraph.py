@@ -0,0 +1,181 @@
+from __future__ import annotations
+
+import json
+from dataclasses import dataclass, field
+from typing import Any, Dict, List, Optional, Set, Tuple
+
+from core.config import get_config
+from core.db import MempoolTx
+
+
+# Mempool dependency graph: ancestor/descendant tracking and ancestor-package selection.
+#
+# A structured tx may spend output 0 of a tx still in the mempool (the only output consensus
+# materializes for a tx is (txid, 0) = outputs[0]). Such in-mempool parents make a DAG; the
+# graph is rebuilt from MempoolTx rows when needed (mempool sizes here make that cheap) rather
+# than maintained incrementally. Size is the stored raw length, as everywhere else.
+#
+# Acceptance enforces mempool.max_ancestors / max_descendants (counts, the tx itself included)
+# and max_ancestor_size_kb / max_descendant_size_kb. Templates are built by ancestor feerate
+# (child-pays-for-parent): repeatedly take the tx whose not-yet-selected ancestor package has the
+# best fee per byte and append that package parents-first, so a block connects every parent
+# before the child spending it.
+
+
+@dataclass
+class Entry:
+    txid: str
+    fee: float
+    size: int
+    added_ms: int
+    parents: Set[str] = field(default_factory=set)
+    children: Set[str] = field(default_factory=set)
+
+
+Graph = Dict[str, Entry]
+
+
+def input_txids(raw: Optional[str]) -> List[str]:
+    """Referenced txids of a structured (JSON) raw; legacy "from=..;to=.." raws have none."""
+    try:
+        tx = json.loads(raw) if raw and raw.lstrip().startswith("{") else {}
+    except ValueError:
+        return []
+    if not isinstance(tx, dict):
+        return []
+    return [str(i.get("txid") or "").strip().lower() for i in (tx.get("inputs") or []) if isinstance(i, dict) and i.get("txid")]
+
+
+def build(rows: List[Any]) -> Graph:
+    """rows: MempoolTx objects or query rows carrying txid, raw, fee and added_ms."""
+    g: Graph = {}
+    for m in rows:
+        g[m.txid] = Entry(txid=m.txid, fee=float(m.fee or 0.0), size=max(1, len((m.raw or "").encode("utf-8"))),
+                          added_ms=int(m.added_ms or 0))
+    for m in rows:
+        for p in input_txids(m.raw):
+            if p in g and p != m.txid:
+                g[m.txid].parents.add(p)
+                g[p].children.add(m.txid)
+    return g
+
+
+def load(s) -> Graph:
+    return build(s.query(MempoolTx).all())
+
+
+def _walk(g: Graph, start: Set[str], attr: str) -> Set[str]:
+    seen: Set[str] = set()
+    stack = list(start)
+    while stack:
+        t = stack.pop()
+        if t in seen or t not in g:
+            continue
+        seen.add(t)
+        stack.extend(getattr(g[t], attr))
+    return seen
+
+
+def ancestors(g: Graph, txid: str) -> Set[str]:
+    """In-mempool ancestors of txid (excluding itself)."""
+    return _walk(g, set(g[txid].parents), "parents") - {txid} if txid in g else set()
+
+
+def descendants(g: Graph, txid: str) -> Set[str]:
+    """In-mempool descendants of txid (excluding itself)."""
+    return _walk(g, set(g[txid].children), "children") - {txid} if txid in g else set()
+
+
+def descendants_of(g: Graph, txids: Set[str]) -> Set[str]:
+    """In-mempool descendants of any of txids, in one walk (excluding txids none of the others spend)."""
+    return _walk(g, {c for t in txids if t in g for c in g[t].children}, "children")
+
+
+def entry_stats(g: Graph, txid: str) -> Dict[str, Any]:
+    e = g[txid]
+    anc = ancestors(g, txid)
+    desc = descendants(g, txid)
+    return {
+        "ancestorcount": len(anc) + 1,
+        "ancestorsize": e.size + sum(g[t].size for t in anc),
+        "ancestorfees": e.fee + sum(g[t].fee for t in anc),
+        "descendantcount": len(desc) + 1,
+        "descendantsize": e.size + sum(g[t].size for t in desc),
+        "descendantfees": e.fee + sum(g[t].fee for t in desc),
+    }
+
+
+def check_limits(g: Graph, parents: Set[str], size: int) -> Optional[str]:
+    """Reason a new tx with these in-mempool parents would exceed the chain limits, else None."""
+    cfg = get_config()
+    max_anc = int(cfg.get("mempool.max_ancestors", 25))
+    max_desc = int(cfg.get("mempool.max_descendants", 25))
+    max_anc_bytes = int(float(cfg.get("mempool.max_ancestor_size_kb", 101)) * 1000)
+    max_desc_bytes = int(float(cfg.get("mempool.max_descendant_size_kb", 101)) * 1000)
+    parents = {p for p in parents if p in g}
+    anc = _walk(g, parents, "parents")
+    if len(anc) + 1 > max_anc:
+        return "too-long-mempool-chain: too many unconfirmed ancestors"
+    if size + sum(g[t].size for t in anc) > max_anc_bytes:
+        return "too-long-mempool-chain: ancestor size limit"
+    for a in anc:
+        desc = descendants(g, a)
+        if len(desc) + 2 > max_desc:
+            return f"too-long-mempool-chain: too many descendants for {a}"
+        if g[a].size + sum(g[t].size for t in desc) + size > max_desc_bytes:
+            return f"too-long-mempool-chain: descendant size limit for {a}"
+    return None
+
+
+def select_packages(g: Graph, max_txs: int, min_fee: float = 0.0, max_bytes: Optional[int] = None) -> List[str]:
+    """
+    Up to max_txs txids (and max_bytes of raw, if given) ordered for a block: best ancestor-package
+    feerate first, each package parents-first. Entries below min_fee are only included as
+    ancestors of a paying child. A package that does not fit is skipped, not truncated.
+    """
+    selected: List[str] = []
+    used_bytes = 0
+    done: Set[str] = set()
+    anc_cache: Dict[str, Set[str]] = {t: ancestors(g, t) for t in g}
+
+    def package(t: str) -> Set[str]:
+        return (anc_cache[t] - done) | {t}
+
+    candidates = {t for t, e in g.items() if e.fee >= min_fee}
+    while candidates and len(selected) < max_txs:
+        best: Optional[Tuple[float, int, str]] = None
+        for t in candidates:
+            pkg = package(t)
+            rate = sum(g[x].fee for x in pkg) / sum(g[x].size for x in pkg)
+            # Highest feerate; ties go to the older tx, then txid for determinism
+            key = (rate, -g[t].added_ms, t)
+            if best is None or key > best:
+                best = key
+        t = best[2]
+        pkg = package(t)
+        candidates.discard(t)
+        if len(selected) + len(pkg) > max_txs:
+            continue
+        pkg_bytes = sum(g[x].size for x in pkg)
+        if max_bytes is not None and used_bytes + pkg_bytes > max_bytes:
+            continue
+        used_bytes += pkg_bytes
+        # parents-first: fewer in-mempool ancestors sorts earlier, then arrival order
+        for x in sorted(pkg, key=lambda x: (len(anc_cache[x]), g[x].added_ms, x)):
+            selected.append(x)
+            done.add(x)
+            candidates.discard(x)
+    return selected
+
+
+def unconfirmed_outputs(rows: List[Any]) -> Dict[Tuple[str, int], Dict[str, Any]]:
+    """(txid, 0) -> {"address", "amount"} for structured mempool txs, spendable by children."""
+    out: Dict[Tuple[str, int], Dict[str, Any]] = {}
+    for m in rows:
+        try:
+            tx = json.loads(m.raw) if m.raw and m.raw.lstrip().startswith("{") else {}
+            o = (tx.get("outputs") or [])[0]
+            out[(m.txid, 0)] = {"address": o.get("address"), "amount": float(o.get("amount", 0.0))}
+        except (ValueError, IndexError, AttributeError, TypeError):
+            continue
+    return out
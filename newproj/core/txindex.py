@@ -0,0 +1,156 @@
+from __future__ import annotations
+
+import json
+import threading
+from typing import Any, Dict, List, Optional
+
+from core.config import get_config
+from core.db import get_db, BlockHeader, KV, TxIndex
+from core import logs
+
+
+# Optional transaction index: txid -> (block hash, height, position), coinbases included.
+#
+# Confirmed transaction bodies live in the transactions table either way; the index is what
+# lets getrawtransaction find a confirmed tx without being told its block. Controlled by
+# storage.txindex: when off, blocks are not indexed and lookups fail so callers can ask for a
+# block hash instead. Blocks are indexed as they connect (consensus) and backfilled at RPC
+# startup; reindex() drops the table and rebuilds it from the chain in a background thread.
+
+_reindex_lock = threading.Lock()
+_STATUS_KEY = "txindex_status_json"
+
+
+def enabled() -> bool:
+    return bool(get_config().get("storage.txindex", True))
+
+
+def _block_txids(s, block: BlockHeader) -> List[str]:
+    from core.blockstore import read_block
+    from core.blockio import _block_txids as rebuild_txids
+
+    rec = read_block(block.hash_hex)
+    return rec.txids if rec else rebuild_txids(s, block)
+
+
+def index_block(hash_hex: str, txids: Optional[List[str]] = None) -> bool:
+    db = get_db()
+    with db.session() as s:
+        block = s.query(BlockHeader).filter_by(hash_hex=hash_hex).first()
+        if block is None:
+            return False
+        if txids is None:
+            txids = _block_txids(s, block)
+        for pos, txid in enumerate(txids):
+            row = s.query(TxIndex).filter_by(txid=txid).first()
+            if row is None:
+                s.add(TxIndex(txid=txid, block_hash=hash_hex, height=block.height, position=pos))
+            else:
+                row.block_hash, row.height, row.position = hash_hex, block.height, pos
+        s.commit()
+    return True
+
+
+def index_block_best_effort(hash_hex: Optional[str], txids: Optional[List[str]] = None):
+    if not hash_hex or not enabled():
+        return
+    try:
+        index_block(hash_hex, txids)
+    except Exception as e:
+        logs.get("storage").error(f"txindex: index failed: {e}", extra=logs.fields(hash=hash_hex))
+
+
+def remove_block(s, hash_hex: str):
+    """Drop entries of a disconnected block (caller's session, see core.invalidation)."""
+    s.query(TxIndex).filter_by(block_hash=hash_hex).delete(synchronize_session=False)
+
+
+def _indexed_height(s) -> int:
+    last = s.query(TxIndex).order_by(TxIndex.height.desc()).first()
+    return -1 if last is None else last.height
+
+
+def backfill() -> int:
+    """Index connected blocks above the highest indexed height."""
+    if not enabled():
+        return 0
+    db = get_db()
+    with db.session() as s:
+        start = _indexed_height(s) + 1
+        hashes = [h for (h,) in s.query(BlockHeader.hash_hex).filter(BlockHeader.height >= start).order_by(BlockHeader.height.asc()).all()]
+    done = 0
+    for hh in hashes:
+        if not index_block(hh):
+            break
+        done += 1
+        if done % 1000 == 0:
+            _set_status({"state": "indexing", "indexed_blocks": done, "remaining": len(hashes) - done})
+    return done
+
+
+def _set_status(status: Dict[str, Any]):
+    db = get_db()
+    with db.session() as s:
+        row = s.get(KV, _STATUS_KEY) or KV(k=_STATUS_KEY, v="")
+        row.v = json.dumps(status, separators=(",", ":"), sort_keys=True)
+        s.merge(row)
+        s.commit()
+
+
+def status() -> Dict[str, Any]:
+    db = get_db()
+    with db.session() as s:
+        row = s.get(KV, _STATUS_KEY)
+        out: Dict[str, Any] = json.loads(row.v) if row and row.v else {"state": "idle"}
+        out["enabled"] = enabled()
+        out["indexed_height"] = _indexed_height(s)
+        out["entries"] = s.query(TxIndex.id).count()
+    return out
+
+
+def reindex() -> bool:
+    """Drop and rebuild the index in the background; False if a reindex is already running."""
+    if not _reindex_lock.acquire(blocking=False):
+        return False
+
+    def _run():
+        try:
+            _set_status({"state": "indexing", "indexed_blocks": 0})
+            db = get_db()
+            with db.session() as s:
+                s.query(TxIndex).delete(synchronize_session=False)
+                s.commit()
+            n = backfill() if enabled() else 0
+            _set_status({"state": "done", "indexed_blocks": n})
+        except Exception as e:
+            _set_status({"state": "failed", "error": str(e)})
+        finally:
+            _reindex_lock.release()
+
+    threading.Thread(target=_run, name="reindex-txindex", daemon=True).start()
+    return True
+
+
+def lookup(txid: str) -> Optional[Dict[str, Any]]:
+    if not enabled():
+        return None
+    db = get_db()
+    with db.session() as s:
+        row = s.query(TxIndex).filter_by(txid=(txid or "").strip().lower()).first()
+        if row is None:
+            return None
+        return {"txid": row.txid, "blockhash": row.block_hash, "height": row.height, "position": row.position}
+
+
+def block_position(blockhash: str, txid: str) -> Optional[Dict[str, Any]]:
+    """Locate txid inside a given block without the index (scans that block's txids)."""
+    db = get_db()
+    with db.session() as s:
+        block = s.query(BlockHeader).filter_by(hash_hex=(blockhash or "").strip().lower()).first()
+        if block is None:
+            return None
+        txids = _block_txids(s, block)
+        txid = (txid or "").strip().lower()
+        if txid not in txids:
+            return {"blockhash": block.hash_hex, "height": block.height, "position": None}
+        return {"txid": txid, "blockhash": block.hash_hex, "height": block.height, "position": txids.index(txid)}
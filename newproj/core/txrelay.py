@@ -0,0 +1,684 @@
+from __future__ import annotations
+
+import heapq
+import json
+import threading
+from collections import OrderedDict
+from typing import Any, Callable, Dict, List, NamedTuple, Optional, Set, Tuple
+
+from core import txgraph
+from core.config import get_config
+from core.consensus import get_chain_height, validate_mempool_tx
+from core.crypto import data_output_bytes, tx_digest_hex
+from core.db import get_db, MempoolTx, UTXO
+from core.utils import now_ms
+from core import logs
+from core import conflicts
+
+
+# Transaction admission and relay bookkeeping.
+#
+# Every transaction entering the mempool (RPC tx/submit or a relayed P2P TX) goes through
+# accept_to_mempool, which validates it for the next block and inserts the MempoolTx row.
+# The checks themselves live in _evaluate, which has no side effects; testmempoolaccept runs the
+# same function against the current mempool rows (plus earlier txs of the same request).
+# Accepted txids are handed to the announcer registered by the P2P layer (INV to peers that do
+# not already know the txid; peers fetch the body with GETDATA). Admissions are serialized
+# (_admission_lock) from validation to the size trim; announcing and orphan retries run after.
+#
+# A transaction spending outputs we have never seen (parent not confirmed yet) is kept in the
+# orphan pool instead of being rejected. Orphans are retried whenever new blocks connect and
+# dropped after mempool.orphan_expiry_sec or when the pool exceeds mempool.max_orphans
+# (oldest first). Orphans waiting on a parent that enters the mempool are retried right away,
+# since a tx may spend an unconfirmed parent's output (core.txgraph).
+#
+# Conflicts (BIP125-style replace-by-fee): a tx spending an outpoint already spent by a mempool
+# entry is rejected ("txn-mempool-conflict") unless every conflicting entry signals
+# replaceability (an input with sequence < 0xfffffffe, or mempool.full_rbf) and the newcomer
+#   - pays a higher feerate than each conflict,
+#   - pays at least the fees of all evicted entries (conflicts + their in-mempool descendants),
+#   - and adds at least mempool.incremental_fee_per_kb for its own size on top of that,
+# evicting at most mempool.max_replacements entries. Replacement links are remembered in memory
+# (bounded) for getmempoolentry.
+#
+# Size limit: mempool.max_size_mb caps the pool, counted in stored raw bytes (getmempoolinfo.bytes).
+# When an admission pushes it over, txs are evicted in reverse mining order: the leaf (no
+# in-mempool children) whose ancestor package has the lowest feerate goes first, until the pool
+# fits. The eviction is planned before anything changes: a newcomer that would itself be evicted
+# is refused as "mempool-full" with its replacement conflicts still in place. Sizes, fees and
+# in-mempool parents come from a running index (_SizeIndex) rather than a reload and re-parse of
+# every row per admission. Every eviction raises a
+# rolling minimum feerate to the evicted package's feerate plus mempool.incremental_fee_per_kb;
+# new txs paying less per kB are refused ("mempool-min-fee-not-met"). The floor halves every
+# mempool.min_fee_halflife_sec and is dropped once it decays below half of mempool.min_fee.
+#
+# Standardness (relay policy, not consensus): at most one data (OP_RETURN-style) output, of at
+# most mempool.max_datacarrier_bytes, and none at all when mempool.datacarrier is off. Blocks may
+# still contain non-standard txs; we just do not accept or relay them.
+
+_announcer: Optional[Callable[[str, Optional[str]], None]] = None
+
+# Held from _evaluate through trim_to_size, so two admissions cannot both pass the conflict/RBF
+# check against the same rows and store spends of one outpoint
+_admission_lock = threading.Lock()
+
+_orphans_lock = threading.Lock()
+_orphans: "OrderedDict[str, Dict[str, Any]]" = OrderedDict()  # txid -> {"tx", "peer", "added_ms", "missing"}
+
+MAX_BIP125_RBF_SEQUENCE = 0xFFFFFFFD
+_REPLACEMENT_HISTORY = 10000
+
+_replacements_lock = threading.Lock()
+_replaces: "OrderedDict[str, List[str]]" = OrderedDict()  # new txid -> txids it evicted
+_replaced_by: "OrderedDict[str, str]" = OrderedDict()  # evicted txid -> replacing txid
+
+_min_fee_lock = threading.Lock()
+_rolling_min_fee = {"per_kb": 0.0, "ms": 0}
+
+
+class RecentSet:
+    """Bounded insertion-ordered set (per-peer known inventory, recently rejected txids)."""
+
+    def __init__(self, limit: int):
+        self.limit = max(1, int(limit))
+        self._items: "OrderedDict[str, None]" = OrderedDict()
+
+    def add(self, key: str):
+        self._items[key] = None
+        self._items.move_to_end(key)
+        while len(self._items) > self.limit:
+            self._items.popitem(last=False)
+
+    def __contains__(self, key: str) -> bool:
+        return key in self._items
+
+    def __len__(self) -> int:
+        return len(self._items)
+
+
+def set_announcer(fn: Callable[[str, Optional[str]], None]):
+    """fn(txid, source_peer) announces a newly accepted tx; registered by the P2P node."""
+    global _announcer
+    _announcer = fn
+
+
+def announce(txid: str, source_peer: Optional[str] = None):
+    if _announcer is None:
+        return
+    try:
+        _announcer(txid, source_peer)
+    except Exception as e:
+        logs.get("mempool").warning(f"announce failed: {e}", extra=logs.fields(txid=txid))
+
+
+def in_mempool(txid: str) -> bool:
+    db = get_db()
+    with db.session() as s:
+        return s.query(MempoolTx.id).filter_by(txid=txid).first() is not None
+
+
+def _missing_parents(tx: Dict[str, Any]) -> List[str]:
+    """Parent txids of inputs whose outputs are unknown (not merely spent)."""
+    missing: List[str] = []
+    db = get_db()
+    with db.session() as s:
+        for i in tx.get("inputs") or []:
+            if not isinstance(i, dict):
+                continue
+            ref = str(i.get("txid") or "").strip().lower()
+            try:
+                vout = int(i.get("vout", -1))
+            except (TypeError, ValueError):
+                continue
+            if ref and s.query(UTXO.id).filter(UTXO.txid == ref, UTXO.vout == vout).first() is None:
+                if ref not in missing:
+                    missing.append(ref)
+    return missing
+
+
+# ----- conflicts / replace-by-fee -----
+
+def parse_raw(raw: Optional[str]) -> Dict[str, Any]:
+    try:
+        tx = json.loads(raw) if raw and raw.lstrip().startswith("{") else {}
+    except ValueError:
+        return {}
+    return tx if isinstance(tx, dict) else {}
+
+
+def _outpoints(tx: Dict[str, Any]) -> Set[Tuple[str, int]]:
+    out: Set[Tuple[str, int]] = set()
+    for i in tx.get("inputs") or []:
+        if not isinstance(i, dict):
+            continue
+        try:
+            out.add((str(i.get("txid") or "").strip().lower(), int(i.get("vout", -1))))
+        except (TypeError, ValueError):
+            continue
+    return out
+
+
+def signals_rbf(tx: Dict[str, Any]) -> bool:
+    """BIP125 opt-in: any input with an explicit sequence below 0xfffffffe (absent = final)."""
+    for i in tx.get("inputs") or []:
+        if not isinstance(i, dict) or i.get("sequence") is None:
+            continue
+        try:
+            if int(i["sequence"]) <= MAX_BIP125_RBF_SEQUENCE:
+                return True
+        except (TypeError, ValueError):
+            continue
+    return False
+
+
+def _check_replacement(tx: Dict[str, Any], txid: str, rows: List[Any], graph: txgraph.Graph) -> Tuple[Optional[List[str]], str]:
+    """
+    Returns (txids to evict, "ok") when tx may enter the mempool (empty list: no conflicts),
+    or (None, reason) when it conflicts and does not qualify as a replacement.
+    """
+    cfg = get_config()
+    spends = _outpoints(tx)
+    by_txid: Dict[str, Dict[str, Any]] = {}
+    fees: Dict[str, float] = {}
+    sizes: Dict[str, int] = {}
+    conflicts: List[str] = []
+    for m in rows:
+        t = m.txid
+        if t == txid:
+            continue
+        by_txid[t] = parse_raw(m.raw)
+        fees[t] = float(m.fee or 0.0)
+        sizes[t] = max(1, len((m.raw or "").encode("utf-8")))
+        if spends & _outpoints(by_txid[t]):
+            conflicts.append(t)
+    if not conflicts:
+        return [], "ok"
+
+    full_rbf = bool(cfg.get("mempool.full_rbf", False))
+    if not full_rbf and not all(signals_rbf(by_txid[c]) for c in conflicts):
+        return None, "txn-mempool-conflict"
+    evict = list(dict.fromkeys(conflicts + [d for c in conflicts for d in sorted(txgraph.descendants(graph, c))]))
+    if any(ref in evict for ref, _ in spends):
+        return None, "replacement-spends-conflicting-tx"
+    if len(evict) > int(cfg.get("mempool.max_replacements", 100)):
+        return None, "too-many-replacements"
+
+    new_fee = float(tx.get("fee", 0.0))
+    new_size = max(1, len(json.dumps(tx, separators=(",", ":"), sort_keys=True).encode("utf-8")))
+    for c in conflicts:
+        if new_fee / new_size <= fees[c] / sizes[c]:
+            return None, "insufficient-fee"
+    evicted_fees = sum(fees[t] for t in evict)
+    incremental = float(cfg.get("mempool.incremental_fee_per_kb", 0.00001)) * new_size / 1000.0
+    if new_fee + 1e-12 < evicted_fees + incremental:
+        return None, "insufficient-fee"
+    return evict, "ok"
+
+
+def _evict(txids: List[str], replacement: str):
+    db = get_db()
+    with db.session() as s:
+        raws = dict(s.query(MempoolTx.txid, MempoolTx.raw).filter(MempoolTx.txid.in_(txids)).all())
+        s.query(MempoolTx).filter(MempoolTx.txid.in_(txids)).delete(synchronize_session=False)
+        s.commit()
+    for t in txids:
+        conflicts.record(t, raws.get(t), replacement, "replaced")
+    with _replacements_lock:
+        _replaces[replacement] = list(txids)
+        for t in txids:
+            _replaced_by[t] = replacement
+        while len(_replaces) > _REPLACEMENT_HISTORY:
+            _replaces.popitem(last=False)
+        while len(_replaced_by) > _REPLACEMENT_HISTORY:
+            _replaced_by.popitem(last=False)
+
+
+def replacement_info(txid: str) -> Dict[str, Any]:
+    """{"replaces": [...], "replaced_by": txid or None} as recorded when replacements happened."""
+    with _replacements_lock:
+        return {"replaces": list(_replaces.get(txid, [])), "replaced_by": _replaced_by.get(txid)}
+
+
+def spent_address(s, tx: Dict[str, Any]) -> Optional[str]:
+    """Address of the output the first input spends (UTXO set, else its in-mempool parent)."""
+    try:
+        i = (tx.get("inputs") or [])[0]
+        ref, vout = str(i.get("txid") or "").strip().lower(), int(i.get("vout", -1))
+    except (IndexError, AttributeError, TypeError, ValueError):
+        return None
+    u = s.query(UTXO.address).filter(UTXO.txid == ref, UTXO.vout == vout).first()
+    if u is not None:
+        return u[0]
+    parent = s.query(MempoolTx.txid, MempoolTx.raw).filter_by(txid=ref).first()
+    return txgraph.unconfirmed_outputs([parent]).get((ref, vout), {}).get("address") if parent else None
+
+
+def _store(tx: Dict[str, Any], txid: str):
+    raw_compact = json.dumps(tx, separators=(",", ":"), sort_keys=True)
+    to_addr = None
+    amount = None
+    try:
+        if tx.get("outputs"):
+            to_addr = tx["outputs"][0].get("address")
+            amount = float(tx["outputs"][0].get("amount", 0.0))
+    except (AttributeError, TypeError, ValueError):
+        pass
+    db = get_db()
+    with db.session() as s:
+        # Blocks spend from from_addr, so it comes from the spent output, never the tx body
+        from_addr = spent_address(s, tx)
+        existing = s.query(MempoolTx).filter_by(txid=txid).first()
+        if existing is None:
+            s.add(MempoolTx(
+                txid=txid,
+                raw=raw_compact,
+                added_ms=now_ms(),
+                fee=float(tx.get("fee", 0.0)),
+                from_addr=from_addr,
+                to_addr=to_addr,
+                amount=amount,
+            ))
+        else:
+            if not existing.raw:
+                existing.raw = raw_compact
+            if existing.fee is None:
+                existing.fee = float(tx.get("fee", 0.0))
+            if from_addr:
+                existing.from_addr = from_addr
+            if not existing.to_addr and to_addr:
+                existing.to_addr = to_addr
+            if existing.amount is None and amount is not None:
+                existing.amount = amount
+        s.commit()
+
+
+def remove_block_conflicts() -> List[str]:
+    """
+    After blocks connect: drop mempool txs spending an outpoint a block has spent for another tx,
+    with their in-mempool descendants, and record the conflicts (core.conflicts). Returns txids removed.
+    """
+    rows = _mempool_rows()
+    losers: Dict[str, str] = {}  # mempool txid -> hash of the block that spent its input (UTXO.spent_txid)
+    db = get_db()
+    with db.session() as s:
+        for m in rows:
+            for ref, vout in _outpoints(parse_raw(m.raw)):
+                u = s.query(UTXO.spent, UTXO.spent_txid).filter(UTXO.txid == ref, UTXO.vout == vout).first()
+                if u is not None and u.spent:
+                    losers[m.txid] = u.spent_txid or ""
+                    break
+    if not losers:
+        return []
+    g = txgraph.build(rows)
+    drop: Dict[str, str] = dict(losers)
+    for t, winner in losers.items():
+        for d in txgraph.descendants(g, t):
+            drop.setdefault(d, winner)
+    raws = {m.txid: m.raw for m in rows}
+    with db.session() as s:
+        s.query(MempoolTx).filter(MempoolTx.txid.in_(list(drop))).delete(synchronize_session=False)
+        s.commit()
+    for t, winner in drop.items():
+        conflicts.record(t, raws.get(t), winner, "block")
+    logs.get("mempool").info("removed txs conflicting with a block", extra=logs.fields(count=len(drop)))
+    return list(drop)
+
+
+def check_standard(tx: Dict[str, Any]) -> Optional[str]:
+    """Relay policy reason tx is non-standard, else None (tx already passed validation)."""
+    cfg = get_config()
+    payloads = [p for p in (data_output_bytes(o) for o in tx.get("outputs") or []) if p is not None]
+    if not payloads:
+        return None
+    if not bool(cfg.get("mempool.datacarrier", True)):
+        return "datacarrier"
+    if len(payloads) > 1:
+        return "multi-op-return"
+    if len(payloads[0]) > int(cfg.get("mempool.max_datacarrier_bytes", 80)):
+        return "datacarrier-size"
+    return None
+
+
+def _mempool_rows() -> List[Any]:
+    db = get_db()
+    with db.session() as s:
+        return s.query(MempoolTx.txid, MempoolTx.raw, MempoolTx.fee, MempoolTx.added_ms).all()
+
+
+def _evaluate(tx: Dict[str, Any], rows: List[Any], height: int) -> Tuple[bool, str, str, List[str]]:
+    """
+    Every acceptance check, without side effects: consensus validation, standardness,
+    replacement rules and chain limits against the given mempool rows.
+    Returns (ok, reason, txid, txids a successful tx would evict).
+    """
+    graph = txgraph.build(rows)
+    ok, reason, txid = validate_mempool_tx(tx, height=height, unconfirmed=txgraph.unconfirmed_outputs(rows))
+    if not ok:
+        return False, reason, txid, []
+    floor = rolling_min_fee_per_kb()
+    if floor > 0.0 and float(tx.get("fee", 0.0)) * 1000.0 / max(1, _tx_size(tx)) < floor:
+        return False, "mempool-min-fee-not-met", txid, []
+    reason = check_standard(tx)
+    if reason:
+        return False, reason, txid, []
+    evict, reason = _check_replacement(tx, txid, rows, graph)
+    if evict is None:
+        return False, reason, txid, []
+    if evict:
+        graph = txgraph.build([m for m in rows if m.txid not in evict])
+    limit_err = txgraph.check_limits(graph, {ref for ref, _ in _outpoints(tx)}, _tx_size(tx))
+    if limit_err:
+        return False, limit_err, txid, []
+    return True, "ok", txid, evict
+
+
+def rolling_min_fee_per_kb() -> float:
+    """Current eviction-driven feerate floor (decayed), 0.0 when none is in force."""
+    cfg = get_config()
+    halflife_ms = max(1.0, float(cfg.get("mempool.min_fee_halflife_sec", 43200)) * 1000.0)
+    with _min_fee_lock:
+        rate = _rolling_min_fee["per_kb"]
+        if rate <= 0.0:
+            return 0.0
+        nowm = now_ms()
+        rate *= 0.5 ** (max(0, nowm - _rolling_min_fee["ms"]) / halflife_ms)
+        _rolling_min_fee["per_kb"], _rolling_min_fee["ms"] = rate, nowm
+        if rate < float(cfg.get("mempool.min_fee", 0.00001)) / 2.0:
+            _rolling_min_fee["per_kb"] = 0.0
+            return 0.0
+        return rate
+
+
+def mempool_min_fee() -> float:
+    """getmempoolinfo.mempoolminfee: per-kB fee a new tx must pay, at least mempool.min_fee."""
+    return max(float(get_config().get("mempool.min_fee", 0.00001)), rolling_min_fee_per_kb())
+
+
+def _bump_min_fee(evicted_per_kb: float):
+    incremental = float(get_config().get("mempool.incremental_fee_per_kb", 0.00001))
+    with _min_fee_lock:
+        _rolling_min_fee["per_kb"] = max(_rolling_min_fee["per_kb"], evicted_per_kb + incremental)
+        _rolling_min_fee["ms"] = now_ms()
+
+
+class _SizeIndex:
+    """
+    Running (size, fee, added_ms, input txids) per mempool entry plus the byte total. Block
+    connection, expiry and the custodial wallet write the mempool table directly, so sync()
+    reconciles against its txid column; only rows not seen before are loaded and parsed.
+    """
+
+    def __init__(self):
+        self.lock = threading.Lock()
+        self.entries: Dict[str, Tuple[int, float, int, List[str]]] = {}
+        self.total = 0
+
+    def _add(self, txid: str, raw: Optional[str], fee: Optional[float], added_ms: Optional[int]):
+        # caller holds self.lock
+        if txid in self.entries:
+            return
+        size = max(1, len((raw or "").encode("utf-8")))
+        self.entries[txid] = (size, float(fee or 0.0), int(added_ms or 0), txgraph.input_txids(raw))
+        self.total += size
+
+    def _remove(self, txid: str):
+        # caller holds self.lock
+        e = self.entries.pop(txid, None)
+        if e is not None:
+            self.total -= e[0]
+
+    def sync(self):
+        db = get_db()
+        with db.session() as s:
+            current = {t for (t,) in s.query(MempoolTx.txid).all()}
+            with self.lock:
+                for t in [t for t in self.entries if t not in current]:
+                    self._remove(t)
+                new = [t for t in current if t not in self.entries]
+            for i in range(0, len(new), 500):
+                rows = (s.query(MempoolTx.txid, MempoolTx.raw, MempoolTx.fee, MempoolTx.added_ms)
+                        .filter(MempoolTx.txid.in_(new[i:i + 500])).all())
+                with self.lock:
+                    for m in rows:
+                        self._add(m.txid, m.raw, m.fee, m.added_ms)
+
+    def graph(self, drop: Set[str] = frozenset(), extra: Optional[Tuple[str, str, float]] = None) -> Tuple[txgraph.Graph, int]:
+        """(graph, total bytes) of the indexed mempool without drop, plus extra = (txid, raw, fee)."""
+        with self.lock:
+            items = {t: e for t, e in self.entries.items() if t not in drop}
+            total = self.total - sum(self.entries[t][0] for t in drop if t in self.entries)
+        if extra is not None and extra[0] not in items:
+            size = max(1, len(extra[1].encode("utf-8")))
+            items[extra[0]] = (size, extra[2], now_ms(), txgraph.input_txids(extra[1]))
+            total += size
+        g: txgraph.Graph = {t: txgraph.Entry(txid=t, fee=fee, size=size, added_ms=added)
+                            for t, (size, fee, added, _) in items.items()}
+        for t, (_, _, _, inputs) in items.items():
+            for p in inputs:
+                if p in g and p != t:
+                    g[t].parents.add(p)
+                    g[p].children.add(t)
+        return g, total
+
+    def discard(self, txids: List[str]):
+        with self.lock:
+            for t in txids:
+                self._remove(t)
+
+
+_size_index = _SizeIndex()
+
+
+def _max_mempool_bytes() -> int:
+    return int(float(get_config().get("mempool.max_size_mb", 300)) * 1_000_000)
+
+
+def _plan_trim(g: txgraph.Graph, total: int, max_bytes: int) -> Tuple[List[str], float]:
+    """(txids to evict, worst evicted package feerate) to bring total under max_bytes; g is consumed."""
+    if total <= max_bytes:
+        return [], 0.0
+    rate: Dict[str, float] = {}
+    for t, e in g.items():
+        anc = txgraph.ancestors(g, t)
+        rate[t] = (e.fee + sum(g[a].fee for a in anc)) / (e.size + sum(g[a].size for a in anc))
+    # removing a leaf never changes anyone else's ancestors, so the rates stay valid
+    heap = [(rate[t], -e.added_ms, t) for t, e in g.items() if not e.children]
+    heapq.heapify(heap)
+    evicted: List[str] = []
+    worst = 0.0
+    while heap and total > max_bytes:
+        r, _, t = heapq.heappop(heap)
+        e = g.pop(t)
+        total -= e.size
+        evicted.append(t)
+        worst = max(worst, r)
+        for p in e.parents:
+            if p in g:
+                g[p].children.discard(t)
+                if not g[p].children:
+                    heapq.heappush(heap, (rate[p], -g[p].added_ms, p))
+    return evicted, worst
+
+
+def _would_fit(tx: Dict[str, Any], txid: str, evict: List[str]) -> bool:
+    """Whether tx survives the size limit once evict is gone, decided before anything is removed."""
+    _size_index.sync()
+    raw = json.dumps(tx, separators=(",", ":"), sort_keys=True)
+    g, total = _size_index.graph(set(evict), (txid, raw, float(tx.get("fee", 0.0))))
+    evicted, _ = _plan_trim(g, total, _max_mempool_bytes())
+    return txid not in evicted
+
+
+def trim_to_size() -> List[str]:
+    """Evict lowest ancestor-feerate leaves until the mempool fits mempool.max_size_mb."""
+    max_bytes = _max_mempool_bytes()
+    _size_index.sync()
+    if _size_index.total <= max_bytes:
+        return []
+    g, total = _size_index.graph()
+    evicted, worst = _plan_trim(g, total, max_bytes)
+    if not evicted:
+        return []
+    db = get_db()
+    with db.session() as s:
+        s.query(MempoolTx).filter(MempoolTx.txid.in_(evicted)).delete(synchronize_session=False)
+        s.commit()
+    _size_index.discard(evicted)
+    _bump_min_fee(worst * 1000.0)
+    logs.get("mempool").info("mempool full, evicted txs", extra=logs.fields(
+        count=len(evicted), bytes=_size_index.total, min_fee_per_kb=round(rolling_min_fee_per_kb(), 8)))
+    return evicted
+
+
+def _tx_size(tx: Dict[str, Any]) -> int:
+    return len(json.dumps(tx, separators=(",", ":"), sort_keys=True).encode("utf-8"))
+
+
+class _PackageRow(NamedTuple):
+    txid: str
+    raw: str
+    fee: float
+    added_ms: int
+
+
+def test_accept(txs: List[Any]) -> List[Dict[str, Any]]:
+    """
+    testmempoolaccept: run _evaluate for each tx without touching the mempool. Later txs may
+    spend earlier ones in the list (a package); a tx after a rejected one is not evaluated.
+    """
+    rows = list(_mempool_rows())
+    height = get_chain_height() + 1
+    results: List[Dict[str, Any]] = []
+    failed = False
+    for tx in txs:
+        if not isinstance(tx, dict):
+            results.append({"txid": "", "allowed": False, "reject-reason": "bad-format"})
+            failed = True
+            continue
+        txid = tx_digest_hex(tx)
+        res: Dict[str, Any] = {"txid": txid}
+        if failed:
+            res["package-error"] = "package-not-validated"
+            results.append(res)
+            continue
+        if any(m.txid == txid for m in rows):
+            ok, reason, evict = False, "txn-already-in-mempool", []
+        else:
+            ok, reason, txid, evict = _evaluate(tx, rows, height)
+            res["txid"] = txid
+        if reason == "utxo-missing-or-spent" and _missing_parents(tx):
+            reason = "missing-inputs"
+        res["allowed"] = ok
+        if not ok:
+            res["reject-reason"] = reason
+            failed = True
+        else:
+            res.update({"vsize": _tx_size(tx), "fees": {"base": float(tx.get("fee", 0.0))}})
+            if evict:
+                res["replaces"] = evict
+            rows = [m for m in rows if m.txid not in evict]
+            rows.append(_PackageRow(txid, json.dumps(tx, separators=(",", ":"), sort_keys=True), float(tx.get("fee", 0.0)), now_ms()))
+        results.append(res)
+    return results
+
+
+def accept_to_mempool(tx: Dict[str, Any], source_peer: Optional[str] = None, relay: bool = True) -> Tuple[bool, str, str]:
+    """
+    Validate tx for the next block and add it to the mempool.
+    Returns (ok, reason, txid); reason "orphan" means it was parked in the orphan pool.
+    """
+    if not isinstance(tx, dict):
+        return False, "bad-format", ""
+    with _admission_lock:
+        ok, reason, txid, evict = _evaluate(tx, _mempool_rows(), get_chain_height() + 1)
+        if ok:
+            if not _would_fit(tx, txid, evict):
+                return False, "mempool-full", txid
+            if evict:
+                _evict(evict, txid)
+            _store(tx, txid)
+            if txid in trim_to_size():
+                return False, "mempool-full", txid
+    if not ok:
+        if reason == "utxo-missing-or-spent":
+            missing = _missing_parents(tx)
+            if missing:
+                _add_orphan(txid or tx_digest_hex(tx), tx, source_peer, missing)
+                return False, "orphan", txid
+        return False, reason, txid
+    if relay:
+        announce(txid, source_peer)
+    _accept_orphan_children(txid)
+    return True, "ok", txid
+
+
+# ----- orphan pool -----
+
+def _add_orphan(txid: str, tx: Dict[str, Any], peer: Optional[str], missing: List[str]):
+    cfg = get_config()
+    limit = int(cfg.get("mempool.max_orphans", 100))
+    with _orphans_lock:
+        prev = _orphans.get(txid)
+        added = prev["added_ms"] if prev else now_ms()  # retries keep the original expiry clock
+        _orphans[txid] = {"tx": tx, "peer": peer, "added_ms": added, "missing": missing}
+        _orphans.move_to_end(txid)
+        while len(_orphans) > limit:
+            _orphans.popitem(last=False)
+
+
+def is_orphan(txid: str) -> bool:
+    with _orphans_lock:
+        return txid in _orphans
+
+
+def orphan_count() -> int:
+    with _orphans_lock:
+        return len(_orphans)
+
+
+def orphan_parents(txid: str) -> List[str]:
+    with _orphans_lock:
+        o = _orphans.get(txid)
+        return list(o["missing"]) if o else []
+
+
+def orphans_snapshot() -> List[Dict[str, Any]]:
+    with _orphans_lock:
+        return [
+            {"txid": t, "peer": o["peer"], "added_ms": o["added_ms"], "missing_parents": list(o["missing"])}
+            for t, o in _orphans.items()
+        ]
+
+
+def _accept_orphan_children(parent: str):
+    """Children may spend an unconfirmed parent, so orphans waiting on parent are retried on its arrival."""
+    with _orphans_lock:
+        waiting = [(t, o) for t, o in _orphans.items() if parent in o["missing"]]
+    for txid, o in waiting:
+        _, reason, _ = accept_to_mempool(o["tx"], o["peer"])
+        if reason != "orphan":
+            with _orphans_lock:
+                _orphans.pop(txid, None)
+
+
+def process_orphans() -> List[str]:
+    """Retry orphans (call after blocks connect); returns txids that made it into the mempool."""
+    expiry_ms = int(get_config().get("mempool.orphan_expiry_sec", 1200)) * 1000
+    nowm = now_ms()
+    with _orphans_lock:
+        for t in [t for t, o in _orphans.items() if nowm - o["added_ms"] > expiry_ms]:
+            del _orphans[t]
+        pending = list(_orphans.items())
+    accepted: List[str] = []
+    resolved: Set[str] = set()
+    for txid, o in pending:
+        ok, reason, _ = accept_to_mempool(o["tx"], o["peer"])
+        if ok:
+            accepted.append(txid)
+        if reason != "orphan":
+            resolved.add(txid)  # accepted or now definitively invalid
+    if resolved:
+        with _orphans_lock:
+            for t in resolved:
+                _orphans.pop(t, None)
+    return accepted
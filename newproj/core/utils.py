@@ -7,7 +7,26 @@ import secrets
 from typing import Any, Dict
 
 
+# Regtest-only clock override (setmocktime). 0 = real clock.
+_mock_time_sec = 0
+
+
+def set_mock_time(ts_sec: int):
+    global _mock_time_sec
+    _mock_time_sec = max(0, int(ts_sec))
+
+
+def get_mock_time() -> int:
+    return _mock_time_sec
+
+
+def now_sec() -> int:
+    return _mock_time_sec if _mock_time_sec else int(time.time())
+
+
 def now_ms() -> int:
+    if _mock_time_sec:
+        return _mock_time_sec * 1000
     return int(time.time() * 1000)
 
 
@@ -0,0 +1,241 @@
+from __future__ import annotations
+
+import hashlib
+import json
+from typing import Any, Dict, Iterable, List, Optional
+
+from core.config import get_config
+from core.db import get_db, BlockHeader, KV, Reward, Transaction, UTXO
+from core import logs
+
+
+# UTXO set commitment (MuHash3072-style rolling hash).
+#
+# Each unspent output is serialized as "txid:vout:address:amount_sats:coinbase" and mapped to a
+# 3072-bit number (SHAKE-256, 384 bytes, little endian) modulo p = 2^3072 - 1103717. The set
+# hash is the product of its elements mod p, kept as numerator/denominator so adding an output
+# multiplies the numerator and removing one multiplies the denominator; the published digest is
+# sha256(num * den^-1 mod p). Order-independent and incremental: two nodes with the same UTXO set
+# get the same muhash however they got there. Output height is left out (null on legacy rows).
+#
+# The state lives in KV (utxo_muhash_json) together with the output count and total value and
+# the block it describes. apply_block() folds in one connected block's delta:
+#   added   = recipient outputs of its txs, change outputs (txid = block hash), reward outputs
+#             (coinbase and fairness payouts recorded at its height)
+#   removed = outputs spent by it (spent_txid = block hash)
+# An output created and spent within the same block appears in both and cancels out.
+# core.invalidation calls undo_block() with what it actually deletes/restores. If the state does
+# not follow the block being applied, it is recomputed from the UTXO table (also at startup).
+#
+# Every storage.utxo_hash_checkpoint_interval blocks the digest is recorded
+# (utxo_muhash_checkpoints_json) so it can later be compared against a trusted value
+# (consensus.utxo_hash_checkpoints: {height: muhash}) for assumeutxo-style snapshots.
+
+MUHASH_PRIME = 2 ** 3072 - 1103717
+_STATE_KEY = "utxo_muhash_json"
+_CHECKPOINTS_KEY = "utxo_muhash_checkpoints_json"
+SATS_PER_COIN = 100_000_000
+
+
+def amount_sats(amount: Optional[float]) -> int:
+    return int(round(float(amount or 0.0) * SATS_PER_COIN))
+
+
+def serialize_output(u: UTXO) -> bytes:
+    return f"{u.txid}:{int(u.vout)}:{u.address}:{amount_sats(u.amount)}:{1 if u.coinbase else 0}".encode("utf-8")
+
+
+def element(data: bytes) -> int:
+    return int.from_bytes(hashlib.shake_256(data).digest(384), "little") % MUHASH_PRIME
+
+
+class MuHash:
+    def __init__(self, numerator: int = 1, denominator: int = 1):
+        self.numerator = numerator
+        self.denominator = denominator
+
+    def insert(self, data: bytes):
+        self.numerator = (self.numerator * element(data)) % MUHASH_PRIME
+
+    def remove(self, data: bytes):
+        self.denominator = (self.denominator * element(data)) % MUHASH_PRIME
+
+    def digest_hex(self) -> str:
+        value = (self.numerator * pow(self.denominator, -1, MUHASH_PRIME)) % MUHASH_PRIME
+        return hashlib.sha256(value.to_bytes(384, "little")).hexdigest()
+
+
+def _load(s) -> Optional[Dict[str, Any]]:
+    row = s.get(KV, _STATE_KEY)
+    if row is None or not row.v:
+        return None
+    try:
+        return json.loads(row.v)
+    except ValueError:
+        return None
+
+
+def _save(s, mh: MuHash, count: int, total_sats: int, block: Optional[BlockHeader]):
+    state = {
+        "num": format(mh.numerator, "x"),
+        "den": format(mh.denominator, "x"),
+        "txouts": count,
+        "total_sats": total_sats,
+        "height": block.height if block else -1,
+        "block_hash": block.hash_hex if block else None,
+    }
+    row = s.get(KV, _STATE_KEY) or KV(k=_STATE_KEY, v="")
+    row.v = json.dumps(state, separators=(",", ":"), sort_keys=True)
+    s.merge(row)
+
+
+def _unpack(state: Dict[str, Any]):
+    return MuHash(int(state["num"], 16), int(state["den"], 16)), int(state["txouts"]), int(state["total_sats"])
+
+
+def _record_checkpoint(s, height: int, digest: str):
+    interval = int(get_config().get("storage.utxo_hash_checkpoint_interval", 1000))
+    if interval <= 0 or height % interval:
+        return
+    row = s.get(KV, _CHECKPOINTS_KEY) or KV(k=_CHECKPOINTS_KEY, v="{}")
+    try:
+        cps = json.loads(row.v or "{}")
+    except ValueError:
+        cps = {}
+    cps[str(height)] = digest
+    row.v = json.dumps(cps, separators=(",", ":"), sort_keys=True)
+    s.merge(row)
+
+
+def recompute(s) -> Dict[str, Any]:
+    """Full pass over unspent outputs; returns {"muhash", "txouts", "total_sats"} without saving."""
+    mh = MuHash()
+    count = total = 0
+    for u in s.query(UTXO).filter(UTXO.spent.is_(False)).yield_per(1000):
+        mh.insert(serialize_output(u))
+        count += 1
+        total += amount_sats(u.amount)
+    return {"muhash": mh.digest_hex(), "txouts": count, "total_sats": total, "_mh": mh}
+
+
+def rebuild() -> Dict[str, Any]:
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        res = recompute(s)
+        _save(s, res["_mh"], res["txouts"], res["total_sats"], tip)
+        s.commit()
+    return {k: v for k, v in res.items() if k != "_mh"}
+
+
+def block_created(s, block: BlockHeader) -> List[UTXO]:
+    """Outputs a connected block added to the UTXO set (shared with core.addrindex)."""
+    txids = [t for (t,) in s.query(Transaction.txid).filter(Transaction.in_block_hash == block.hash_hex).all()]
+    reward_txids = [t for (t,) in s.query(Reward.txid).filter(Reward.height == block.height).all()]
+    out: List[UTXO] = []
+    if txids or reward_txids:
+        out.extend(s.query(UTXO).filter(UTXO.txid.in_(txids + reward_txids), UTXO.vout == 0).all())
+    out.extend(s.query(UTXO).filter(UTXO.txid == block.hash_hex, UTXO.vout >= 10_000_000).all())
+    return out
+
+
+def apply_block(hash_hex: str):
+    db = get_db()
+    with db.session() as s:
+        block = s.query(BlockHeader).filter_by(hash_hex=hash_hex).first()
+        if block is None:
+            return
+        state = _load(s)
+        if state is None or state.get("block_hash") != block.prev_hash_hex:
+            res = recompute(s)
+            mh, count, total = res["_mh"], res["txouts"], res["total_sats"]
+        else:
+            mh, count, total = _unpack(state)
+            for u in block_created(s, block):
+                mh.insert(serialize_output(u))
+                count += 1
+                total += amount_sats(u.amount)
+            for u in s.query(UTXO).filter(UTXO.spent_txid == hash_hex).all():
+                mh.remove(serialize_output(u))
+                count -= 1
+                total -= amount_sats(u.amount)
+        _save(s, mh, count, total, block)
+        _record_checkpoint(s, block.height, mh.digest_hex())
+        s.commit()
+
+
+def apply_block_best_effort(hash_hex: Optional[str]):
+    if not hash_hex:
+        return
+    try:
+        apply_block(hash_hex)
+    except Exception as e:
+        logs.get("storage").error(f"utxohash: update failed: {e}", extra=logs.fields(hash=hash_hex))
+
+
+def undo_block(s, block: BlockHeader, deleted: Iterable[UTXO], restored: Iterable[UTXO]):
+    """
+    Reverse a disconnected block in the caller's session (core.invalidation): deleted outputs
+    leave the set, restored (unspent again) outputs return. Skipped if the state is not at block.
+    """
+    state = _load(s)
+    if state is None or state.get("block_hash") != block.hash_hex:
+        return
+    mh, count, total = _unpack(state)
+    for u in deleted:
+        if not u.spent:
+            mh.remove(serialize_output(u))
+            count -= 1
+            total -= amount_sats(u.amount)
+    for u in restored:
+        mh.insert(serialize_output(u))
+        count += 1
+        total += amount_sats(u.amount)
+    prev = s.query(BlockHeader).filter_by(hash_hex=block.prev_hash_hex).first()
+    _save(s, mh, count, total, prev)
+
+
+def backfill() -> bool:
+    """Recompute at startup if the stored state does not describe the current tip."""
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        state = _load(s)
+    if state is not None and state.get("block_hash") == (tip.hash_hex if tip else None):
+        return False
+    rebuild()
+    return True
+
+
+def checkpoints() -> Dict[str, str]:
+    db = get_db()
+    with db.session() as s:
+        row = s.get(KV, _CHECKPOINTS_KEY)
+    try:
+        return json.loads(row.v) if row and row.v else {}
+    except ValueError:
+        return {}
+
+
+def info(verify: bool = False) -> Dict[str, Any]:
+    db = get_db()
+    with db.session() as s:
+        state = _load(s)
+        if state is None:
+            return {"error": "utxo set hash not computed yet"}
+        mh, count, total = _unpack(state)
+        out: Dict[str, Any] = {
+            "height": state.get("height"),
+            "bestblock": state.get("block_hash"),
+            "txouts": count,
+            "total_amount": total / SATS_PER_COIN,
+            "muhash": mh.digest_hex(),
+        }
+        if verify:
+            res = recompute(s)
+            out["verified"] = res["muhash"] == out["muhash"] and res["txouts"] == count
+    trusted = (get_config().get("consensus.utxo_hash_checkpoints", {}) or {})
+    local = checkpoints()
+    mismatched = [h for h, d in trusted.items() if str(h) in local and local[str(h)] != str(d).lower()]
+    out["checkpoints"] = {"recorded": len(local), "trusted": len(trusted), "mismatched": sorted(mismatched, key=int)}
+    return out
@@ -0,0 +1,224 @@
+from __future__ import annotations
+
+import threading
+from typing import Any, Dict, List, Optional, Tuple
+
+from core.config import get_config
+from core.db import get_db, BlockHeader
+from core import logs
+
+
+# BIP9-style versionbits: coordinated activation of future consensus rule changes.
+#
+# Deployments are configured under consensus.deployments as
+#   name: {bit, start_time, timeout, min_activation_height}
+# (times are unix seconds compared against the median time past of the last block of a period;
+# start_time -1 means always active). The chain is split into periods of
+# consensus.versionbits_period blocks. A block signals for a deployment when its version has the
+# top bits 001 (TOP_BITS under TOP_MASK) and the deployment's bit set. State changes only at
+# period boundaries and is the same for every block of a period:
+#   DEFINED    -> STARTED    once the period's MTP reaches start_time (FAILED if past timeout)
+#   STARTED    -> LOCKED_IN  when at least consensus.versionbits_threshold blocks of the period
+#                            signalled, otherwise FAILED once MTP reaches timeout
+#   LOCKED_IN  -> ACTIVE     at the next boundary at or above min_activation_height
+#   ACTIVE, FAILED are final.
+# States are cached per (deployment, hash of the period's last block), so a reorg that replaces
+# that block recomputes from the new ancestors. Rule code asks is_active(name, prev); templates
+# use compute_block_version(prev), which sets the bits of STARTED and LOCKED_IN deployments.
+# With no deployments configured blocks keep consensus.block_version.
+
+TOP_BITS = 0x20000000
+TOP_MASK = 0xE0000000
+MAX_BITS = 29
+ALWAYS_ACTIVE = -1
+
+DEFINED, STARTED, LOCKED_IN, ACTIVE, FAILED = "defined", "started", "locked_in", "active", "failed"
+
+_lock = threading.Lock()
+_cache: Dict[Tuple[str, str], Tuple[str, int]] = {}
+log = logs.get("consensus")
+
+
+class Deployment:
+    def __init__(self, name: str, bit: int, start_time: int, timeout: int, min_activation_height: int):
+        self.name = name
+        self.bit = bit
+        self.start_time = start_time
+        self.timeout = timeout
+        self.min_activation_height = min_activation_height
+
+    def mask(self) -> int:
+        return 1 << self.bit
+
+
+def period() -> int:
+    return max(1, int(get_config().get("consensus.versionbits_period", 2016)))
+
+
+def threshold() -> int:
+    return max(1, min(period(), int(get_config().get("consensus.versionbits_threshold", 1815))))
+
+
+def deployments() -> List[Deployment]:
+    out: List[Deployment] = []
+    for name, d in (get_config().get("consensus.deployments", {}) or {}).items():
+        d = d or {}
+        bit = int(d.get("bit", -1))
+        if not 0 <= bit < MAX_BITS:
+            log.warning(f"versionbits: deployment {name} has invalid bit {bit}; ignored")
+            continue
+        out.append(Deployment(
+            str(name), bit,
+            int(d.get("start_time", 0)),
+            int(d.get("timeout", 0)),
+            int(d.get("min_activation_height", 0)),
+        ))
+    return out
+
+
+def deployment(name: str) -> Optional[Deployment]:
+    return next((d for d in deployments() if d.name == name), None)
+
+
+def signals(version: int, bit: int) -> bool:
+    return (int(version) & TOP_MASK) == TOP_BITS and bool((int(version) >> bit) & 1)
+
+
+def valid_version(version: int) -> bool:
+    """Headers carry either the legacy consensus.block_version or a versionbits version."""
+    return int(version) == int(get_config().get("consensus.block_version", 1)) or (int(version) & TOP_MASK) == TOP_BITS
+
+
+def _at(s, height: int) -> Optional[BlockHeader]:
+    return s.query(BlockHeader).filter_by(height=height).first()
+
+
+def _median_time_past(s, height: int) -> int:
+    rows = (
+        s.query(BlockHeader.timestamp)
+        .filter(BlockHeader.height <= height, BlockHeader.height > height - 11)
+        .all()
+    )
+    times = sorted(t for (t,) in rows)
+    return times[len(times) // 2] if times else 0
+
+
+def _count_signals(s, last: int, bit: int, blocks: Optional[int] = None) -> int:
+    rows = (
+        s.query(BlockHeader.version)
+        .filter(BlockHeader.height <= last, BlockHeader.height > last - (period() if blocks is None else blocks))
+        .all()
+    )
+    return sum(1 for (v,) in rows if signals(v, bit))
+
+
+def _next_state(s, dep: Deployment, state: str, last: int) -> str:
+    """State of the period after the one ending at height last."""
+    if state == DEFINED:
+        mtp = _median_time_past(s, last)
+        if mtp >= dep.timeout:
+            return FAILED
+        if mtp >= dep.start_time:
+            return STARTED
+    elif state == STARTED:
+        if _count_signals(s, last, dep.bit) >= threshold():
+            return LOCKED_IN
+        if _median_time_past(s, last) >= dep.timeout:
+            return FAILED
+    elif state == LOCKED_IN:
+        if last + 1 >= dep.min_activation_height:
+            return ACTIVE
+    return state
+
+
+def _state_since(s, dep: Deployment, prev_height: int) -> Tuple[str, int]:
+    """(state, height the state began) for the block after prev_height on the active chain."""
+    if dep.start_time == ALWAYS_ACTIVE:
+        return ACTIVE, 0
+    p = period()
+    last = ((prev_height + 1) // p) * p - 1
+    walk: List[Tuple[int, str]] = []
+    state, since = DEFINED, 0
+    while last >= 0:
+        row = _at(s, last)
+        if row is None:
+            break
+        with _lock:
+            hit = _cache.get((dep.name, row.hash_hex))
+        if hit is not None:
+            state, since = hit
+            break
+        walk.append((last, row.hash_hex))
+        last -= p
+    for last, hash_hex in reversed(walk):
+        nxt = _next_state(s, dep, state, last)
+        if nxt != state:
+            state, since = nxt, last + 1
+        with _lock:
+            _cache[(dep.name, hash_hex)] = (state, since)
+    return state, since
+
+
+def state_for(name: str, prev: Optional[BlockHeader]) -> str:
+    dep = deployment(name)
+    if dep is None:
+        return FAILED
+    db = get_db()
+    with db.session() as s:
+        return _state_since(s, dep, prev.height if prev else -1)[0]
+
+
+def is_active(name: str, prev: Optional[BlockHeader]) -> bool:
+    """Whether the deployment's rules apply to the block built on prev."""
+    return state_for(name, prev) == ACTIVE
+
+
+def compute_block_version(prev: Optional[BlockHeader]) -> int:
+    deps = deployments()
+    if not deps:
+        return int(get_config().get("consensus.block_version", 1))
+    version = TOP_BITS
+    db = get_db()
+    with db.session() as s:
+        prev_height = prev.height if prev else -1
+        for dep in deps:
+            if _state_since(s, dep, prev_height)[0] in (STARTED, LOCKED_IN):
+                version |= dep.mask()
+    return version
+
+
+def softforks() -> Dict[str, Any]:
+    """getblockchaininfo "softforks": state of each deployment for the block after the tip."""
+    out: Dict[str, Any] = {}
+    p, t = period(), threshold()
+    db = get_db()
+    with db.session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        tip_height = tip.height if tip else -1
+        for dep in deployments():
+            state, since = _state_since(s, dep, tip_height)
+            bip9: Dict[str, Any] = {
+                "status": state,
+                "bit": dep.bit,
+                "start_time": dep.start_time,
+                "timeout": dep.timeout,
+                "min_activation_height": dep.min_activation_height,
+                "since": since,
+            }
+            if state == STARTED:
+                elapsed = (tip_height + 1) % p
+                count = _count_signals(s, tip_height, dep.bit, elapsed)
+                bip9["statistics"] = {
+                    "period": p,
+                    "threshold": t,
+                    "elapsed": elapsed,
+                    "count": count,
+                    "possible": count + (p - elapsed) >= t,
+                }
+            entry: Dict[str, Any] = {"type": "bip9", "active": state == ACTIVE, "bip9": bip9}
+            if state == ACTIVE:
+                entry["height"] = since
+            elif state == LOCKED_IN:
+                entry["height"] = max(since + p, -(-dep.min_activation_height // p) * p)
+            out[dep.name] = entry
+    return out
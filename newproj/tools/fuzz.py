@@ -0,0 +1,360 @@
+"""
+Property checks and mutation fuzzing for the consensus/wire serializers.
+
+Usage:
+  python -m tools.fuzz                          # every target, 2000 iterations each
+  python -m tools.fuzz stratum p2p -n 100000 --seed 7
+  python -m tools.fuzz --list
+  python -m tools.fuzz blockio --replay tools/fuzz_corpus/blockio/crash-1a2b3c4d
+
+Each target has two halves:
+  property  generated valid values must round-trip (decode(encode(x)) == x) and encodings must
+            be deterministic
+  fuzz      the checked-in seeds in tools/fuzz_corpus/<target>/ plus fresh valid encodings are
+            mutated (bit flips, byte insert/delete, truncation, splices, boundary values) and fed
+            to the parser, which may only fail with its declared error (ValueError for all of
+            them; anything else is a crash, e.g. a TypeError that would drop a peer or session)
+Failing inputs are saved as tools/fuzz_corpus/<target>/crash-<sha3 prefix> and should be checked
+in as seeds once fixed. Runs are reproducible from --seed.
+
+Targets: blockio (blk*.dat records), header (PoW preimage), tx (structured tx JSON, outputs,
+canonical JSON), script (multisig redeem scripts, data outputs, addresses), stratum (requests and
+mining.submit params), p2p (line framing, optional compression).
+"""
+
+import argparse
+import json
+import os
+import random
+import string
+import sys
+from typing import Any, Callable, Dict, List, Tuple
+
+ROOT = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+CORPUS = os.path.join(ROOT, "tools", "fuzz_corpus")
+INTERESTING = [b"\x00", b"\xff", b"\x7f", b"\x80", b"\xff\xff\xff\xff", b"\x00\x00\x00\x80", b"[", b"{", b'"', b"-1",
+               b"1e400", b"NaN", b"null", b"true", b"\xc3", b"\xed\xa0\x80", b"~z", b"~s", b"=" * 4, b"[" * 2000]
+
+
+class PropertyFailure(AssertionError):
+    pass
+
+
+def check(cond: bool, what: str):
+    if not cond:
+        raise PropertyFailure(what)
+
+
+# ----- generators -----
+
+def rand_hex(rng: random.Random, nbytes: int) -> str:
+    return rng.getrandbits(8 * nbytes).to_bytes(nbytes, "big").hex()
+
+
+def rand_text(rng: random.Random, max_len: int = 40) -> str:
+    alphabet = string.ascii_letters + string.digits + "._-" + "é中\U0001f4a9"
+    return "".join(rng.choice(alphabet) for _ in range(rng.randint(0, max_len)))
+
+
+def rand_u(rng: random.Random, bits: int) -> int:
+    return rng.choice([0, 1, (1 << bits) - 1, rng.getrandbits(bits)])
+
+
+def mutate(rng: random.Random, data: bytes, pool: List[bytes]) -> bytes:
+    b = bytearray(data)
+    for _ in range(rng.randint(1, 4)):
+        op = rng.randrange(7)
+        pos = rng.randint(0, len(b))
+        if op == 0 and b:
+            i = rng.randrange(len(b))
+            b[i] ^= 1 << rng.randrange(8)
+        elif op == 1:
+            b[pos:pos] = bytes([rng.randrange(256)])
+        elif op == 2 and b:
+            del b[pos:pos + rng.randint(1, 8)]
+        elif op == 3:
+            del b[pos:]
+        elif op == 4 and pool:
+            other = rng.choice(pool)
+            j = rng.randint(0, len(other))
+            b[pos:] = other[j:]
+        elif op == 5:
+            b[pos:pos] = rng.choice(INTERESTING)
+        elif op == 6 and len(b) >= 4:
+            i = rng.randrange(len(b) - 3)
+            b[i:i + 4] = rng.choice([b"\x00\x00\x00\x00", b"\xff\xff\xff\xff", b"\x00\x00\x01\x00"])
+    return bytes(b)
+
+
+# ----- targets -----
+#
+# gen(rng) -> valid encoding (bytes), after checking the round-trip property on the value behind it
+# parse(data) -> parse attempt; may raise only the errors in the target's allowed tuple
+
+def _blockio_gen(rng: random.Random) -> bytes:
+    from core.blockio import BlockRecord, decode_payload, encode_record
+
+    rec = BlockRecord(
+        version=rand_u(rng, 32),
+        prev_hash_hex=rand_hex(rng, 32),
+        merkle_root_hex=rand_hex(rng, 32),
+        timestamp=rand_u(rng, 64),
+        target=rand_hex(rng, 32),
+        nonce=rand_u(rng, 64),
+        miner_address=rand_text(rng),
+        tx_count=rand_u(rng, 32),
+        txids=[rand_hex(rng, 32) for _ in range(rng.randint(0, 5))],
+        bodies=[(rand_hex(rng, 32), rand_text(rng, 200)) for _ in range(rng.choice([0, 0, 1, 3]))],
+    )
+    raw = encode_record(rec)
+    check(raw == encode_record(rec), "blockio: encoding is not deterministic")
+    check(decode_payload(raw[8:]) == rec, f"blockio: round-trip changed {rec}")
+    return raw[8:]
+
+
+def _blockio_parse(data: bytes):
+    from core.blockio import decode_payload, encode_record
+
+    rec = decode_payload(data)
+    # Anything that decodes must re-encode to the same bytes (no two encodings of one record)
+    check(encode_record(rec)[8:] == data, "blockio: decoded record re-encodes differently")
+
+
+def _header_gen(rng: random.Random) -> bytes:
+    from core.consensus import Header
+
+    fields = dict(version=rand_u(rng, 32), prev_hash_hex=rand_hex(rng, 32), merkle_root_hex=rand_hex(rng, 32),
+                  timestamp=rand_u(rng, 64), target=rand_hex(rng, 32), nonce=rand_u(rng, 64),
+                  miner_address=rand_text(rng), tx_count=rand_u(rng, 32))
+    h = Header(**fields)
+    raw = h.serialize()
+    check(raw == Header(**fields).serialize(), "header: serialization is not deterministic")
+    check([tuple(kv) for kv in json.loads(raw)] == list(fields.items()), "header: preimage does not carry the fields in order")
+    bumped = Header(**dict(fields, nonce=(fields["nonce"] + 1) % (1 << 64)))
+    check(bumped.serialize() != raw and bumped.hash_hex() != h.hash_hex(), "header: nonce not committed")
+    return raw
+
+
+def _header_parse(data: bytes):
+    from core.consensus import Header
+
+    # Headers travel as JSON fields; whatever field list decodes must serialize back identically
+    fields = json.loads(data.decode("utf-8"))
+    if not isinstance(fields, list) or [f[0] if isinstance(f, list) and f else None for f in fields] != \
+            ["version", "prev_hash_hex", "merkle_root_hex", "timestamp", "target", "nonce", "miner_address", "tx_count"]:
+        raise ValueError("not a header field list")
+    raw = Header(**{k: v for k, v in fields}).serialize()
+    check(Header(**dict(json.loads(raw))).serialize() == raw, "header: serialization is not a fixed point")
+
+
+def _tx_gen(rng: random.Random) -> bytes:
+    from core.crypto import tx_canonical_json, tx_digest_hex
+
+    tx = {
+        "version": 1,
+        "inputs": [{"txid": rand_hex(rng, 32), "vout": rng.randint(0, 9), "pubkey": rand_hex(rng, 32), "sig": rand_hex(rng, 65)}
+                   for _ in range(rng.randint(1, 3))],
+        "outputs": [{"address": "rsmc" + rand_text(rng, 30), "amount": round(rng.uniform(0.00000001, 1000), 8)}
+                    for _ in range(rng.randint(1, 3))],
+        "fee": round(rng.uniform(0, 0.01), 8),
+        "timestamp": rng.randint(0, 1 << 40),
+    }
+    canon = tx_canonical_json(tx)
+    shuffled = json.loads(json.dumps(dict(reversed(list(tx.items())))))
+    check(tx_canonical_json(shuffled) == canon, "tx: canonical JSON depends on key order")
+    unsigned = dict(tx, inputs=[{k: v for k, v in i.items() if k != "sig"} for i in tx["inputs"]])
+    check(tx_canonical_json(unsigned) == canon, "tx: txid depends on signatures")
+    paid_more = json.loads(json.dumps(tx))
+    paid_more["outputs"][0]["amount"] += 1
+    check(tx_digest_hex(paid_more) != tx_digest_hex(tx), "tx: output amount not committed")
+    return json.dumps(tx).encode("utf-8")
+
+
+def _tx_parse(data: bytes):
+    from core.crypto import tx_canonical_json
+    from core.rawtx import load_tx, parse_output
+
+    tx = load_tx(data.decode("utf-8"))
+    canon = tx_canonical_json(tx)
+    check(tx_canonical_json(json.loads(canon)) == canon, "tx: canonical JSON is not a fixed point")
+    for o in tx["outputs"]:
+        parse_output(o)
+
+
+def _script_gen(rng: random.Random) -> bytes:
+    from core.crypto import SCRIPT_ADDRESS_VERSION, decode_address, ed25519_keypair_from_seed, multisig_redeem, \
+        redeem_script_bytes, script_address
+
+    n = rng.randint(1, 5)
+    keys = [ed25519_keypair_from_seed(rng.getrandbits(256).to_bytes(32, "big"))[1].hex() for _ in range(n)]
+    m = rng.randint(1, n)
+    redeem = multisig_redeem(m, keys)
+    raw = redeem_script_bytes(redeem)
+    check(json.loads(raw) == redeem, "script: redeem script does not round-trip")
+    check(multisig_redeem(redeem["m"], redeem["pubkeys"]) == redeem, "script: redeem script is not canonical")
+    addr = script_address(redeem, "rsmc")
+    check(decode_address(addr, "rsmc")[1] == SCRIPT_ADDRESS_VERSION, "script: address version lost")
+    return json.dumps({"m": m, "pubkeys": keys, "data": rand_hex(rng, rng.randint(0, 40)), "address": addr}).encode("utf-8")
+
+
+def _script_parse(data: bytes):
+    from core.crypto import data_output_bytes, decode_address, multisig_redeem
+
+    obj = json.loads(data.decode("utf-8"))
+    if not isinstance(obj, dict):
+        raise ValueError("not an object")
+    multisig_redeem(obj.get("m"), obj.get("pubkeys"))
+    data_output_bytes({"data": obj.get("data"), "amount": obj.get("amount")})
+    decode_address(str(obj.get("address")), "rsmc")
+
+
+def _stratum_gen(rng: random.Random) -> bytes:
+    from apps.pool.protocol import Submit, parse_request, parse_submit
+
+    sub = Submit(address="rsmc" + rand_text(rng, 30).replace(".", ""), worker=rand_text(rng, 8).replace(".", ""),
+                 job_id=rand_text(rng, 16), nonce=rand_u(rng, 64), timestamp=rand_u(rng, 32),
+                 merkle_root_hex=rand_hex(rng, 32), version=rand_u(rng, 32), prev_hash_hex=rand_hex(rng, 32))
+    login = sub.address + ("." + sub.worker if sub.worker else "")
+    params = [login, sub.job_id, sub.nonce, sub.timestamp, sub.merkle_root_hex, sub.version, sub.prev_hash_hex.upper()]
+    line = (json.dumps({"id": rng.randint(0, 1000), "method": "mining.submit", "params": params}) + "\n").encode("utf-8")
+    msg = parse_request(line)
+    check(parse_submit(msg["params"]) == sub, "stratum: submit params do not round-trip")
+    check(parse_submit(params[:6]).prev_hash_hex is None, "stratum: 6-field submit grew a prev_hash")
+    return line
+
+
+def _stratum_parse(data: bytes):
+    from apps.pool.protocol import parse_request, parse_submit
+
+    msg = parse_request(data)
+    parse_submit(msg.get("params"))
+
+
+def _p2p_gen(rng: random.Random) -> bytes:
+    from core import netcompress
+
+    msg = {"type": rng.choice(["INV", "ADDR", "BLOCK", "TX", "PING", "VERSION"]), "time": rng.getrandbits(40),
+           "items": [rand_hex(rng, 32) for _ in range(rng.randint(0, 40))], "note": rand_text(rng)}
+    payload = json.dumps(msg).encode("utf-8")
+    plain = payload + b"\n"
+    check(netcompress.decode_message(plain) == (msg, plain), "p2p: plain line does not round-trip")
+    out = plain
+    for codec in netcompress.supported():
+        framed = netcompress.encode(codec, payload)
+        check(netcompress.is_compressed(framed) and framed.count(b"\n") == 1, f"p2p: {codec} frame breaks line framing")
+        check(netcompress.decode_message(framed) == (msg, payload), f"p2p: {codec} frame does not round-trip")
+        out = rng.choice([out, framed])
+    return out
+
+
+def _p2p_parse(data: bytes):
+    from core import netcompress
+
+    netcompress.decode_message(data)
+
+
+TARGETS: Dict[str, Tuple[Callable[[random.Random], bytes], Callable[[bytes], Any], Tuple[type, ...]]] = {
+    "blockio": (_blockio_gen, _blockio_parse, (ValueError,)),
+    "header": (_header_gen, _header_parse, (ValueError,)),
+    "tx": (_tx_gen, _tx_parse, (ValueError,)),
+    "script": (_script_gen, _script_parse, (ValueError,)),
+    "stratum": (_stratum_gen, _stratum_parse, (ValueError,)),
+    "p2p": (_p2p_gen, _p2p_parse, (ValueError,)),
+}
+
+
+# ----- runner -----
+
+def _load_corpus(target: str) -> List[bytes]:
+    d = os.path.join(CORPUS, target)
+    if not os.path.isdir(d):
+        return []
+    out = []
+    for name in sorted(os.listdir(d)):
+        with open(os.path.join(d, name), "rb") as f:
+            out.append(f.read())
+    return out
+
+
+def _save_crash(target: str, data: bytes) -> str:
+    from core.utils import sha3_256_hex
+
+    d = os.path.join(CORPUS, target)
+    os.makedirs(d, exist_ok=True)
+    path = os.path.join(d, f"crash-{sha3_256_hex(data)[:8]}")
+    with open(path, "wb") as f:
+        f.write(data)
+    return path
+
+
+def run_target(target: str, iterations: int, rng: random.Random, max_failures: int = 5) -> int:
+    gen, parse, allowed = TARGETS[target]
+    pool = _load_corpus(target)
+    failures = 0
+    for data in list(pool):  # seeds themselves must not crash the parser
+        failures += _try(target, parse, allowed, data)
+    for i in range(iterations):
+        if failures >= max_failures:
+            break
+        if i % 10 == 0:
+            try:
+                pool.append(gen(rng))
+            except PropertyFailure as e:
+                print(f"[FUZZ] {target}: property failed: {e}")
+                failures += 1
+                continue
+            if len(pool) > 256:
+                pool.pop(rng.randrange(len(pool)))
+        data = mutate(rng, rng.choice(pool), pool) if pool else bytes(rng.getrandbits(8) for _ in range(rng.randint(0, 64)))
+        failures += _try(target, parse, allowed, data)
+    print(f"[FUZZ] {target}: {iterations} iterations, {len(pool)} inputs in pool, {failures} failure(s)")
+    return failures
+
+
+def _try(target: str, parse: Callable[[bytes], Any], allowed: Tuple[type, ...], data: bytes) -> int:
+    try:
+        parse(data)
+    except allowed:
+        return 0
+    except Exception as e:
+        path = _save_crash(target, data)
+        print(f"[FUZZ] {target}: {type(e).__name__}: {e} (input saved to {os.path.relpath(path, ROOT)})")
+        return 1
+    return 0
+
+
+def main():
+    ap = argparse.ArgumentParser(description="Property checks and mutation fuzzing for serializers")
+    ap.add_argument("targets", nargs="*", help=f"subset of: {', '.join(TARGETS)}")
+    ap.add_argument("-n", "--iterations", type=int, default=2000)
+    ap.add_argument("--seed", type=int, default=None)
+    ap.add_argument("--list", action="store_true")
+    ap.add_argument("--replay", metavar="FILE", help="run one saved input through the target's parser")
+    args = ap.parse_args()
+
+    os.chdir(ROOT)  # configs/defaults.yaml (network magic, codecs) is read relative to the project root
+    if args.list:
+        print("\n".join(TARGETS))
+        return
+    targets = args.targets or list(TARGETS)
+    unknown = [t for t in targets if t not in TARGETS]
+    if unknown:
+        ap.error(f"unknown target(s): {', '.join(unknown)}")
+    if args.replay:
+        if len(targets) != 1:
+            ap.error("--replay needs exactly one target")
+        with open(args.replay, "rb") as f:
+            data = f.read()
+        _, parse, _ = TARGETS[targets[0]]
+        parse(data)  # let the traceback through
+        print("[FUZZ] no crash")
+        return
+    seed = args.seed if args.seed is not None else random.SystemRandom().randrange(1 << 32)
+    print(f"[FUZZ] seed {seed}")
+    rng = random.Random(seed)
+    failures = sum(run_target(t, args.iterations, rng) for t in targets)
+    sys.exit(1 if failures else 0)
+
+
+if __name__ == "__main__":
+    main()
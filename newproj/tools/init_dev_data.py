@@ -5,12 +5,14 @@ import os
 from core.utils import ensure_dirs
 from core.db import get_db
 from core.consensus import add_genesis_if_needed
+from core.blockstore import migrate_to_flat_files
 
 
 def main():
     ensure_dirs()
     db = get_db()
     add_genesis_if_needed()
+    migrate_to_flat_files()
     print("Initialized dev data and ensured genesis exists.")
 
 
@@ -0,0 +1,250 @@
+"""
+Replay consensus validation of a single block, rule by rule, against a node database.
+
+Usage:
+  python -m tools.replay_block --height 250
+  python -m tools.replay_block --hash <block_hash> --db data/smellyA.db --dump replay.json
+  python -m tools.replay_block --header '{"prev":"..","merkle":"..","ver":1,"ts":..,"target":"..","nonce":..,"miner":"SMELLY_..","txids":[..]}'
+
+Stored blocks are re-checked against their parent as recorded in the DB. A raw header
+(same JSON shape as the P2P BLOCKHDR item) is checked against the current tip, the parent
+accept_external_header would use, without writing anything. The trace covers the header and
+retarget rules accept_external_header applies; its mutated-merkle, block size and
+invalidateblock checks are not replayed.
+
+Rules traced: version, prev link, timestamp, target/difficulty (against the retarget from the
+parent), PoW digest, consensus.validate_header as a whole, merkle root, coinbase/tx count,
+reward amount, and the UTXO operations attributed to the block.
+"""
+
+from __future__ import annotations
+
+import argparse
+import json
+import os
+import sys
+from typing import Any, Dict, List, Optional
+
+
+class _C:
+    RESET = "\x1b[0m"
+    RED = "\x1b[31m"
+    GREEN = "\x1b[32m"
+    YEL = "\x1b[33m"
+    DIM = "\x1b[2m"
+
+
+class Trace:
+    def __init__(self, verbose: bool):
+        self.verbose = verbose
+        self.steps: List[Dict[str, Any]] = []
+        self.first_failure: Optional[str] = None
+
+    def step(self, rule: str, ok: Optional[bool], detail: str, **state):
+        self.steps.append({"rule": rule, "ok": ok, "detail": detail, "state": state})
+        if ok is False and self.first_failure is None:
+            self.first_failure = rule
+        if ok is True:
+            tag = f"{_C.GREEN}[PASS]{_C.RESET}"
+        elif ok is False:
+            tag = f"{_C.RED}[FAIL]{_C.RESET}"
+        else:
+            tag = f"{_C.YEL}[INFO]{_C.RESET}"
+        print(f"{tag} {rule:<12} {detail}")
+        if self.verbose and state:
+            for k, v in state.items():
+                print(f"       {_C.DIM}{k} = {v}{_C.RESET}")
+
+
+def _coinbase_txid(height: int) -> str:
+    from core.utils import sha3_256_hex
+    return sha3_256_hex(f"COINBASE:{height}".encode("utf-8")).lower()
+
+
+def replay(header, height: int, parent, block_hash: Optional[str], txids_hint: Optional[List[str]], trace: Trace, stored_row=None):
+    from core.config import get_config
+    from core.consensus import (calc_merkle_root, get_txids_for_merkle, compute_block_reward, within_max_supply,
+                                next_block_difficulty, target_within_retarget, validate_header)
+    from core.pow.pow_backend import pow_hash, backend_name
+    from core.pow.randomx_stub import difficulty_to_target
+    from core.db import get_db, Transaction, UTXO, Reward
+
+    cfg = get_config()
+    db = get_db()
+
+    # 1. version
+    from core.versionbits import valid_version
+
+    want_ver = int(cfg.get("consensus.block_version", 1))
+    trace.step("version", valid_version(header.version), f"header={header.version:#x} consensus={want_ver} or versionbits")
+
+    # 2. prev link
+    if parent is None:
+        trace.step("prev-link", header.prev_hash_hex == "00" * 32, "genesis (no parent)", prev=header.prev_hash_hex)
+    else:
+        trace.step(
+            "prev-link",
+            header.prev_hash_hex == parent.hash_hex,
+            f"prev={header.prev_hash_hex[:16]}.. parent@{parent.height}={parent.hash_hex[:16]}..",
+        )
+
+    # 3. timestamp
+    if parent is not None:
+        delta = header.timestamp - parent.timestamp
+        trace.step("timestamp", delta >= 0, f"ts={header.timestamp} parent_ts={parent.timestamp} delta={delta}s")
+    else:
+        trace.step("timestamp", None, f"ts={header.timestamp} (genesis, unchecked)")
+
+    # 4. target / difficulty: no easier than the retarget from the parent (difficulty 1 through the
+    # bootstrap); templates round it down through compact bits, so <= rather than equality
+    with db.session() as s:
+        allowed = difficulty_to_target(next_block_difficulty(s, parent))
+        ok = target_within_retarget(s, parent, header.target)
+    base = "genesis (no parent)" if parent is None else f"parent@{parent.height}"
+    trace.step("target", ok, f"target {'<=' if ok else '>'} retarget from {base}",
+               header_target=header.target, expected=allowed)
+    target_int = int(header.target, 16)
+    implied_diff = ((1 << 256) - 1) // max(1, target_int)
+    trace.step("difficulty", None, f"implied difficulty={implied_diff}")
+
+    # 5. PoW
+    if height == 0:
+        trace.step("pow", None, "genesis PoW is not checked")
+    else:
+        prev_hex = parent.hash_hex if parent is not None else "00" * 32
+        digest = pow_hash(header.serialize(), header.nonce, prev_hex)
+        ok = int(digest.hex(), 16) <= target_int
+        trace.step("pow", ok, f"backend={backend_name()} digest={digest.hex()[:16]}.. {'<=' if ok else '>'} target={header.target[:16]}..",
+                   digest=digest.hex(), header_bytes=header.serialize().decode("utf-8"))
+
+    # 5b. the header rules exactly as block acceptance applies them
+    if height == 0:
+        trace.step("header", None, "genesis is not run through validate_header")
+    else:
+        ok, reason = validate_header(header, parent)
+        trace.step("header", ok, f"consensus.validate_header: {reason}")
+
+    # 6. merkle
+    if txids_hint is not None:
+        txids = get_txids_for_merkle(height, txids_hint)
+        source = "supplied txids"
+    else:
+        with db.session() as s:
+            confirmed = [t.txid for t in s.query(Transaction).filter(Transaction.in_block_hash == block_hash).order_by(Transaction.id.asc()).all()] if block_hash else []
+        txids = get_txids_for_merkle(height, [_coinbase_txid(height)] + confirmed)
+        source = "coinbase + confirmed txs (DB insertion order)"
+    rebuilt = calc_merkle_root(txids).lower()
+    merkle_ok = rebuilt == header.merkle_root_hex.lower()
+    trace.step("merkle", merkle_ok if height > 0 else None,
+               f"rebuilt={rebuilt[:16]}.. header={header.merkle_root_hex[:16]}.. from {source}",
+               txids=txids)
+    if not merkle_ok and height >= 200 and txids_hint is None:
+        trace.step("merkle", None, "hint: confirmed-tx order is not stored; pass --txids to replay with the miner snapshot order")
+
+    # 7. coinbase / tx count
+    trace.step("tx-count", header.tx_count >= 1 or height == 0, f"tx_count={header.tx_count} merkle_leaves={len(txids)}")
+
+    # 8. supply + reward
+    trace.step("supply", within_max_supply(height), f"within_max_supply({height})")
+    if block_hash:
+        with db.session() as s:
+            rewards = s.query(Reward).filter(Reward.height == height).all()
+            for r in rewards:
+                trace.step("reward", None, f"{r.amount:.8f} -> {r.miner_address} txid={r.txid[:16]}..",
+                           base_reward=compute_block_reward(height))
+            # 9. UTXO ops attributed to this block
+            created = s.query(UTXO).filter(UTXO.txid.in_([block_hash] + txids)).all()
+            spent = s.query(UTXO).filter(UTXO.spent_txid == block_hash).all()
+            for u in created:
+                trace.step("utxo-add", None, f"{u.txid[:16]}..:{u.vout} {u.amount:.8f} -> {u.address} coinbase={u.coinbase} spent={u.spent}")
+            for u in spent:
+                trace.step("utxo-spend", None, f"{u.txid[:16]}..:{u.vout} {u.amount:.8f} from {u.address}")
+
+    # 10. stored hash
+    if stored_row is not None:
+        hh = header.hash_hex()
+        trace.step("hash", hh == stored_row.hash_hex, f"recomputed={hh[:16]}.. stored={stored_row.hash_hex[:16]}..")
+
+
+def main(argv: Optional[List[str]] = None):
+    parser = argparse.ArgumentParser(description="Replay validation of a block with per-rule tracing")
+    g = parser.add_mutually_exclusive_group(required=True)
+    g.add_argument("--height", type=int, help="Stored block height")
+    g.add_argument("--hash", type=str, help="Stored block hash")
+    g.add_argument("--header", type=str, help="Raw header JSON (BLOCKHDR item shape) checked against current tip")
+    parser.add_argument("--txids", type=str, default="", help="Comma-separated txid snapshot (coinbase first) for merkle replay")
+    parser.add_argument("--config", type=str, default=None, help="Config YAML (defaults to SMELLY_CONFIG/configs/defaults.yaml)")
+    parser.add_argument("--db", type=str, default=None, help="SQLite database path (datadir override)")
+    parser.add_argument("--dump", type=str, default=None, help="Write the full trace with intermediate state as JSON")
+    parser.add_argument("-v", "--verbose", action="store_true", help="Print intermediate state for every rule")
+    args = parser.parse_args(argv)
+
+    if args.config:
+        os.environ["SMELLY_CONFIG"] = args.config
+    from core.config import get_config
+    if args.db:
+        cfg = get_config()
+        cfg.data.setdefault("database", {})["sqlite_path"] = args.db
+
+    from core.db import get_db, BlockHeader
+    from core.consensus import Header
+
+    trace = Trace(args.verbose)
+    txids_hint = [t.strip().lower() for t in args.txids.split(",") if t.strip()] or None
+    db = get_db()
+
+    with db.session() as s:
+        if args.header:
+            h = json.loads(args.header)
+            parent = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+            height = 0 if parent is None else parent.height + 1
+            if txids_hint is None and h.get("txids"):
+                txids_hint = [str(t).lower() for t in h.get("txids")]
+            header = Header(
+                version=int(h.get("ver", 1)),
+                prev_hash_hex=str(h.get("prev") or "").lower(),
+                merkle_root_hex=str(h.get("merkle") or "").lower(),
+                timestamp=int(h.get("ts", 0)),
+                target=str(h.get("target") or "").lower(),
+                nonce=int(h.get("nonce", 0)),
+                miner_address=h.get("miner") or "",
+                tx_count=max(1, len(txids_hint or [])),
+            )
+            print(f"Replaying raw header as candidate for height {height} on tip {parent.hash_hex[:16] if parent else '-'}..")
+            replay(header, height, parent, None, txids_hint, trace)
+        else:
+            row = (
+                s.query(BlockHeader).filter_by(height=args.height).first()
+                if args.height is not None
+                else s.query(BlockHeader).filter_by(hash_hex=args.hash.lower()).first()
+            )
+            if row is None:
+                print("Block not found in database.")
+                sys.exit(2)
+            parent = s.query(BlockHeader).filter_by(hash_hex=row.prev_hash_hex).first()
+            header = Header(
+                version=row.version,
+                prev_hash_hex=row.prev_hash_hex,
+                merkle_root_hex=row.merkle_root_hex,
+                timestamp=row.timestamp,
+                target=row.target,
+                nonce=int(row.nonce),
+                miner_address=row.miner_address,
+                tx_count=row.tx_count,
+            )
+            print(f"Replaying stored block h={row.height} hash={row.hash_hex[:16]}.. work={int(row.work, 16)}")
+            replay(header, row.height, parent, row.hash_hex, txids_hint, trace, stored_row=row)
+
+    if args.dump:
+        with open(args.dump, "w", encoding="utf-8") as f:
+            json.dump({"first_failure": trace.first_failure, "steps": trace.steps}, f, indent=2, default=str)
+        print(f"Trace written to {args.dump}")
+
+    if trace.first_failure:
+        print(f"{_C.RED}RESULT: invalid (first failing rule: {trace.first_failure}){_C.RESET}")
+        sys.exit(1)
+    print(f"{_C.GREEN}RESULT: all checked rules pass{_C.RESET}")
+
+
+if __name__ == "__main__":
+    main()
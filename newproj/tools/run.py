@@ -11,6 +11,10 @@ Usage examples (from project root with venv activated):
   python -m tools.run solo-miner
   python -m tools.run pool-miner
   python -m tools.run explorer
+  python -m tools.run top
+  python -m tools.run export --out blocks.dat --start 0
+  python -m tools.run import --file blocks.dat
+  python -m tools.run reindex
 """
 
 import os
@@ -129,6 +133,29 @@ def build_parser() -> argparse.ArgumentParser:
     sub.add_parser("pool", help="Start Stratum-like pool")
     sub.add_parser("explorer", help="Start block explorer")
 
+    sp_top = sub.add_parser("top", help="Live operator dashboard over node RPC")
+    sp_top.add_argument("--rpc", type=str, default=None, help="Node RPC base URL")
+    sp_top.add_argument("--interval", type=float, default=3.0, help="Refresh interval in seconds")
+    sp_top.add_argument("--blocks", type=int, default=10, help="Number of recent blocks to show")
+
+    sp_exp = sub.add_parser("export", help="Export blocks to a blk*.dat-style file")
+    sp_exp.add_argument("--out", type=str, required=True, help="Output file path")
+    sp_exp.add_argument("--start", type=int, default=0, help="First height to export")
+    sp_exp.add_argument("--end", type=int, default=None, help="Last height to export (default: tip)")
+
+    sp_imp = sub.add_parser("import", help="Import blocks from a blk*.dat-style file with full validation")
+    sp_imp.add_argument("--file", type=str, required=True, help="Input file path")
+    sp_imp.add_argument("--no-resume", action="store_true", help="Ignore saved progress and start at the beginning")
+
+    sub.add_parser("reindex", help="Rebuild chain state and indexes from stored block files (resumable)")
+
+    sp_dump = sub.add_parser("dumptxoutset", help="Write a chain-state (UTXO) snapshot at the tip")
+    sp_dump.add_argument("--out", type=str, required=True, help="Output file path")
+
+    sp_load = sub.add_parser("loadtxoutset", help="Bootstrap a fresh node from a chain-state snapshot")
+    sp_load.add_argument("--file", type=str, required=True, help="Snapshot file path")
+    sp_load.add_argument("--trust", action="store_true", help="Load even if not listed in consensus.assumeutxo")
+
     sp_solo = sub.add_parser("solo-miner", help="Run solo miner (ensures node RPC)")
     sp_solo.add_argument("--miner-address", type=str, default="SMELLY_SOLO", help="Miner payout address")
     sp_solo.add_argument("--loop", action="store_true", help="Continuously mine")
@@ -220,6 +247,67 @@ def main():
         from apps.explorer.server import run_explorer
         run_explorer()
 
+    elif cmd == "export":
+        from core.blockio import export_blocks
+        try:
+            n = export_blocks(
+                args.out, args.start, args.end,
+                progress=lambda done, total, hh: print(f"exported {done}/{total} (last {hh[:16]}..)"),
+            )
+        except ValueError as e:
+            print(f"Export stopped: {e}")
+            sys.exit(1)
+        print(f"Exported {n} blocks to {args.out}")
+
+    elif cmd == "import":
+        from core.consensus import add_genesis_if_needed
+        from core.blockio import import_blocks
+        add_genesis_if_needed()
+        accepted, skipped, err = import_blocks(
+            args.file, resume=not args.no_resume,
+            progress=lambda done, total, hh: print(f"import {100.0 * done / max(1, total):.1f}% (last {hh[:16]}..)"),
+        )
+        print(f"Imported {accepted} blocks, skipped {skipped} already known")
+        if err:
+            print(f"Import stopped: {err}")
+            sys.exit(1)
+
+    elif cmd == "reindex":
+        from core.consensus import add_genesis_if_needed
+        from core import reindex
+        add_genesis_if_needed()
+        if reindex.state() is not None:
+            print("Resuming interrupted reindex")
+        res, err = reindex.reindex(progress=lambda done, total, hh: print(f"reindex {done}/{total} (last {hh[:16]}..)"))
+        if err:
+            print(f"Reindex stopped: {err}")
+            sys.exit(1)
+        print(f"Reindexed {res['height']} blocks ({res['connected']} connected this run)")
+
+    elif cmd == "dumptxoutset":
+        from core import snapshot
+        meta = snapshot.dump(args.out, progress=lambda done, total: print(f"headers {done}/{total}"))
+        print(f"Snapshot at height {meta['base_height']} ({meta['base_hash']}) written to {args.out}")
+        print(f"  txouts={meta['txouts']} muhash={meta['muhash']} sha256={meta['sha256']}")
+
+    elif cmd == "loadtxoutset":
+        from core import snapshot
+        from core.consensus import add_genesis_if_needed
+        add_genesis_if_needed()
+        meta, err = snapshot.load(args.file, trust=args.trust)
+        if err:
+            print(f"Snapshot not loaded: {err}")
+            sys.exit(1)
+        print(f"Loaded snapshot at height {meta['base_height']} ({meta['base_hash']}), {meta['txouts']} txouts")
+        print("History below the base is validated in the background when the node runs")
+
+    elif cmd == "top":
+        from tools.top import main as top_main
+        argv = ["--interval", str(args.interval), "--blocks", str(args.blocks)]
+        if args.rpc:
+            argv += ["--rpc", args.rpc]
+        top_main(argv)
+
     else:
         parser.error(f"Unknown command: {cmd}")
 
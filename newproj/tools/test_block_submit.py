@@ -0,0 +1,102 @@
+"""
+Regression check for the block submission RPCs: a valid block sent through each endpoint is
+connected and answered with the endpoint's success response (not a 500 from the logging after
+the commit):
+  - /rpc/submit_work        {"accepted": true, "hash": <new tip>, "height": ...}
+  - /rpc/submitblock        BIP22 {"result": null, "hash": <new tip>}
+  - /rpc/solo/submit_block  {"accepted": true, "hash": <new tip>}
+
+Usage:
+  python -m tools.test_block_submit [--keep]
+
+Runs a one-node regtest cluster (tools.testkit); blocks are solved here at bootstrap difficulty.
+"""
+
+import argparse
+import json
+import os
+import sys
+import time
+
+ROOT = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+if ROOT not in sys.path:
+    sys.path.insert(0, ROOT)
+
+from tools.testkit import Cluster, assert_equal, solve_header  # noqa: E402
+
+
+def _submit_work(node, address: str):
+    height = node.get_height()
+    job, hdr = node.block_template(address)
+    solve_header(hdr, range(1_000_000))
+    res = node.post("/rpc/submit_work", {
+        "job_id": job["job_id"], "miner_address": address, "nonce": hdr.nonce, "timestamp": hdr.timestamp,
+        "version": hdr.version, "merkle_root_hex": hdr.merkle_root_hex, "prev_hash_hex": hdr.prev_hash_hex,
+        "txids": job["txids"],
+    })
+    assert_equal(res.get("accepted"), True, f"submit_work response {res}")
+    assert_equal(res.get("height"), height + 1, "submit_work height")
+    assert_equal(node.tip(), res.get("hash"), "tip after submit_work")
+
+
+def _submitblock(node, address: str):
+    from core.blockio import BlockRecord, encode_record
+
+    job, hdr = node.block_template(address)
+    solve_header(hdr, range(1_000_000))
+    rec = BlockRecord(version=hdr.version, prev_hash_hex=hdr.prev_hash_hex, merkle_root_hex=hdr.merkle_root_hex,
+                      timestamp=hdr.timestamp, target=hdr.target, nonce=hdr.nonce, miner_address=address,
+                      tx_count=hdr.tx_count, txids=job["txids"])
+    # unframed payload: the frame magic depends on this process's network config, not the node's
+    res = node.post("/rpc/submitblock", {"hexdata": encode_record(rec)[8:].hex()})
+    assert_equal(res.get("result"), None, f"submitblock response {res}")
+    assert_equal(node.tip(), res.get("hash"), "tip after submitblock")
+
+
+def _solo_submit_block(node, address: str):
+    from core.consensus import Header, get_txids_for_merkle
+    from core.merkle import merkle_root
+
+    height = node.get_height() + 1
+    ticket = node.get("/rpc/solo/get_ticket", addr=address)
+    tk = json.loads(ticket["payload"])
+    txids = get_txids_for_merkle(height, [])  # bootstrap: coinbase only, as the node rebuilds it
+    hdr = Header(version=int(tk["version"]), prev_hash_hex=tk["prev"], merkle_root_hex=merkle_root(txids),
+                 timestamp=int(time.time()), target=tk["target"], nonce=0, miner_address=address,
+                 tx_count=len(txids))
+    start = int(tk["nonce_start"])
+    solve_header(hdr, range(start, start + int(tk["nonce_window"])))
+    res = node.post("/rpc/solo/submit_block", {
+        "ticket_id": ticket["ticket_id"], "addr": address, "nonce": hdr.nonce, "version": hdr.version,
+        "timestamp": hdr.timestamp, "merkle_root_hex": hdr.merkle_root_hex,
+        "payload": ticket["payload"], "sig": ticket["sig"],
+    })
+    assert_equal(res.get("accepted"), True, f"solo submit_block response {res}")
+    assert_equal(node.tip(), res.get("hash"), "tip after solo submit_block")
+
+
+def test_each_endpoint_accepts_a_valid_block(keep: bool = False):
+    with Cluster(1, keep=keep) as c:
+        node = c.nodes[0]
+        address = c.new_key().address
+        start = node.get_height()
+        for submit in (_submit_work, _submitblock, _solo_submit_block):
+            submit(node, address)
+        assert_equal(node.get_height(), start + 3, "height after one block per endpoint")
+
+
+def main() -> int:
+    ap = argparse.ArgumentParser(description="Regtest block submission regression check")
+    ap.add_argument("--keep", action="store_true", help="keep node data dirs for inspection")
+    args = ap.parse_args()
+    try:
+        test_each_endpoint_accepts_a_valid_block(args.keep)
+    except Exception as e:
+        print(f"FAIL test_each_endpoint_accepts_a_valid_block: {e!r}")
+        return 1
+    print("PASS test_each_endpoint_accepts_a_valid_block")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
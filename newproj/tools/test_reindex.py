@@ -0,0 +1,83 @@
+"""
+Regression check for --reindex and fairness epochs: payouts of epochs settled before the reindex
+must be paid again when the replay crosses their boundary (the reindex wipes rewards and UTXOs,
+so an epoch left marked settled would lose them).
+
+Usage:
+  python -m tools.test_reindex [--keep]
+
+Runs a one-node regtest cluster (tools.testkit). Blocks are mined through the external-miner path
+(TestNode.mine_external), since that is the path that settles epochs and the one a reindex
+replays. The fairness credit is written straight into the stopped node's database; earning one
+through the ticket RPCs is not what is under test.
+"""
+
+import argparse
+import hashlib
+import os
+import sqlite3
+import sys
+import time
+
+ROOT = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+if ROOT not in sys.path:
+    sys.path.insert(0, ROOT)
+
+from tools.testkit import Cluster, TestKitError, assert_equal  # noqa: E402
+
+EPOCH = 5
+
+
+def _fairness_outputs(db_path: str, start: int, end: int, address: str):
+    txid = hashlib.sha3_256(f"FAIRNESS:{start}-{end}:{address}".encode("utf-8")).hexdigest()
+    with sqlite3.connect(db_path) as conn:
+        return conn.execute("SELECT txid, address, amount FROM utxos WHERE txid = ?", (txid,)).fetchall()
+
+
+def _credit(db_path: str, start: int, end: int, address: str, units: float):
+    with sqlite3.connect(db_path) as conn:
+        row = conn.execute("SELECT id FROM fairness_epoch WHERE start_height = ? AND end_height = ?", (start, end)).fetchone()
+        if row is None:
+            raise TestKitError(f"fairness epoch {start}-{end} was not created")
+        conn.execute("INSERT INTO fairness_credit (epoch_id, miner_addr, credit_units, last_ms) VALUES (?, ?, ?, ?)",
+                     (row[0], address, units, int(time.time() * 1000)))
+
+
+def test_reindex_repays_fairness(keep: bool = False):
+    with Cluster(1, extra_args=[[f"fairness.epoch_length_dev={EPOCH}"]], keep=keep) as c:
+        node = c.nodes[0]
+        miner, credited = c.new_key(), c.new_key()
+        for _ in range(2):
+            node.mine_external(miner.address)  # creates epoch 0..EPOCH-1
+        node.stop()
+        _credit(node.db_path, 0, EPOCH - 1, credited.address, 3.0)
+        node.start()
+        while node.get_height() < EPOCH + 1:
+            node.mine_external(miner.address)  # block EPOCH settles epoch 0
+        before = _fairness_outputs(node.db_path, 0, EPOCH - 1, credited.address)
+        if not before:
+            raise TestKitError("epoch 0 was not paid out before the reindex")
+        tip, height = node.tip(), node.get_height()
+
+        node.stop()
+        node.start(timeout=120.0, flags=["--reindex"])
+        assert_equal(node.tip(), tip, "tip after reindex")
+        assert_equal(node.get_height(), height, "height after reindex")
+        assert_equal(_fairness_outputs(node.db_path, 0, EPOCH - 1, credited.address), before, "fairness payout after reindex")
+
+
+def main() -> int:
+    ap = argparse.ArgumentParser(description="Regtest reindex regression check")
+    ap.add_argument("--keep", action="store_true", help="keep node data dirs for inspection")
+    args = ap.parse_args()
+    try:
+        test_reindex_repays_fairness(args.keep)
+    except Exception as e:
+        print(f"FAIL test_reindex_repays_fairness: {e!r}")
+        return 1
+    print("PASS test_reindex_repays_fairness")
+    return 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -0,0 +1,105 @@
+"""
+Regression check for the retarget on externally mined blocks: a header whose PoW meets the target
+it claims, but whose target is easier than the retarget allows, is refused by
+consensus.accept_external_header (the path of submit_work, submitblock, solo submit and P2P relay)
+with bad-diffbits, which submitblock reports as BIP22 "bad-diffbits".
+
+Usage:
+  python -m tools.test_retarget
+
+Runs in-process against a throwaway SQLite file (SMELLY_DB_PATH) holding a synthetic chain past
+the 200-block bootstrap at difficulty DIFF (a regtest chain mined quickly stays at difficulty 1,
+where nothing is easier). Also collected by pytest (test_* functions), but like the other tools/
+harnesses it needs no external test framework.
+"""
+
+import os
+import sys
+import tempfile
+import time
+
+ROOT = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+if ROOT not in sys.path:
+    sys.path.insert(0, ROOT)
+
+_TMP = tempfile.mkdtemp(prefix="smelly-retarget-")
+os.environ["SMELLY_DB_PATH"] = os.path.join(_TMP, "retarget.db")
+
+from core import versionbits  # noqa: E402
+from core.config import get_config  # noqa: E402
+from core.consensus import accept_external_header, next_block_target, target_within_retarget  # noqa: E402
+from core.db import get_db, BlockHeader  # noqa: E402
+from core.target import difficulty_to_target, to_int  # noqa: E402
+
+DIFF = 100
+HEIGHT = 210
+MINER = "SMELLY_test_retarget"
+
+
+def _chain() -> BlockHeader:
+    """Headers 0..HEIGHT spaced at the target block time, each adding DIFF work; returns the tip."""
+    with get_db().session() as s:
+        tip = s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+        if tip is not None:
+            return tip
+        spacing = int(get_config().get("consensus.target_block_time_sec", 60))
+        start = int(time.time()) - spacing * (HEIGHT + 1)
+        prev = "00" * 32
+        for h in range(HEIGHT + 1):
+            row = BlockHeader(height=h, hash_hex=os.urandom(32).hex(), prev_hash_hex=prev, merkle_root_hex="00" * 32,
+                              timestamp=start + h * spacing, version=1, nonce="0", target=difficulty_to_target(DIFF),
+                              miner_address=MINER, tx_count=1, work=f"{(h + 1) * DIFF:064x}")
+            s.add(row)
+            prev = row.hash_hex
+        s.commit()
+        return s.query(BlockHeader).order_by(BlockHeader.height.desc()).first()
+
+
+def _submit(tip: BlockHeader, target_hex: str):
+    return accept_external_header(prev_hash_hex=tip.hash_hex, merkle_root_hex="", version=versionbits.compute_block_version(tip),
+                                  timestamp=int(time.time()), target_hex=target_hex, nonce=0, miner_address=MINER,
+                                  txids_snapshot=[])
+
+
+def test_retarget_bounds_the_claimed_target():
+    tip = _chain()
+    with get_db().session() as s:
+        expected = next_block_target(s, tip)[0]
+        assert to_int(expected) < to_int(difficulty_to_target(1)), expected
+        assert target_within_retarget(s, tip, expected)
+        assert not target_within_retarget(s, tip, difficulty_to_target(1))
+
+
+def test_easier_target_is_bad_diffbits():
+    tip = _chain()
+    # every hash meets the all-ones target, so PoW passes and only the retarget can refuse it
+    hh, err = _submit(tip, "f" * 64)
+    assert hh is None, hh
+    assert (err or "").startswith("bad-diffbits"), err
+    with get_db().session() as s:
+        assert s.query(BlockHeader).order_by(BlockHeader.height.desc()).first().hash_hex == tip.hash_hex
+
+
+def test_submitblock_reports_bad_diffbits():
+    from core.rpc import _SUBMITBLOCK_REASONS
+
+    _, err = _submit(_chain(), "f" * 64)
+    assert next((code for needle, code in _SUBMITBLOCK_REASONS if needle in err), "rejected") == "bad-diffbits", err
+
+
+def main() -> int:
+    tests = [v for k, v in sorted(globals().items()) if k.startswith("test_") and callable(v)]
+    failed = 0
+    for t in tests:
+        try:
+            t()
+            print(f"PASS {t.__name__}")
+        except Exception as e:
+            failed += 1
+            print(f"FAIL {t.__name__}: {e!r}")
+    print(f"{len(tests) - failed}/{len(tests)} passed")
+    return 1 if failed else 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -0,0 +1,180 @@
+"""
+Regression checks for transaction admission:
+  - an input's keys must derive the address of the output it spends: a valid signature by some
+    other key, or an input claiming another address, is rejected, and a stored entry spends from
+    the spent output's address rather than the one the tx declares
+  - tx bodies that arrive with a block (BLOCKTXN / BLOCKS) go through txrelay like a relayed TX:
+    a body whose txid does not match is refused and an invalid one never reaches the mempool
+  - admission is serialized: two conflicting spends submitted at once leave exactly one of them
+    in the mempool
+
+Usage:
+  python -m tools.test_tx_admission
+
+Runs in-process against a throwaway SQLite file (SMELLY_DB_PATH) with UTXOs inserted directly;
+no node or network needed. Also collected by pytest (test_* functions), but like the other
+tools/ harnesses it needs no external test framework.
+"""
+
+import json
+import os
+import sys
+import tempfile
+import threading
+
+ROOT = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+if ROOT not in sys.path:
+    sys.path.insert(0, ROOT)
+
+_TMP = tempfile.mkdtemp(prefix="smelly-txadmission-")
+os.environ["SMELLY_DB_PATH"] = os.path.join(_TMP, "txadmission.db")
+
+from core.consensus import validate_mempool_tx  # noqa: E402
+from core.crypto import (  # noqa: E402
+    ed25519_keypair_from_seed, encode_address, key_address_matches, sign_transaction_input,
+    tx_digest_hex, verify_transaction_input,
+)
+from core.db import get_db, MempoolTx, UTXO  # noqa: E402
+from core import rawtx, txrelay  # noqa: E402
+
+PEER = "127.0.0.1:1"
+HEIGHT = 1000
+
+
+class _Key:
+    def __init__(self):
+        seed = os.urandom(64)
+        sk, pk_spend = ed25519_keypair_from_seed(seed, ctx=b"smelly-spend")
+        _, self.pk_view = ed25519_keypair_from_seed(seed, ctx=b"smelly-view")
+        self.sk = sk[:32]
+        self.address = encode_address(self.pk_view, pk_spend)
+
+
+def _fund(address: str, amount: float = 5.0) -> dict:
+    """A fresh confirmed output paying address; returns it as a prevtx {txid, vout, address, amount}."""
+    txid = os.urandom(32).hex()
+    with get_db().session() as s:
+        s.add(UTXO(txid=txid, vout=0, address=address, amount=amount, spent=False, coinbase=False, height=1))
+        s.commit()
+    return {"txid": txid, "vout": 0, "address": address, "amount": amount}
+
+
+def _spend(prev: dict, signer: _Key, claim: str = None) -> dict:
+    """Tx spending prev to a fresh address, signed by signer; claim overrides the input's address."""
+    tx, _ = rawtx.build_unsigned([{"txid": prev["txid"], "vout": prev["vout"]}],
+                                 [{"address": _Key().address, "amount": 1.0}], fee=0.001)
+    if claim is not None:
+        tx["inputs"][0]["address"] = claim
+    spent = {"address": prev["address"], "amount": prev["amount"]}
+    tx["inputs"][0].update(sign_transaction_input(tx, 0, spent, signer.sk, view_pubkey=signer.pk_view))
+    return tx
+
+
+def _body(tx: dict) -> dict:
+    """A BLOCKTXN / BLOCKS tx item as a peer sends it."""
+    return {"txid": tx_digest_hex(tx), "raw": json.dumps(tx, separators=(",", ":"), sort_keys=True)}
+
+
+def test_key_must_derive_spent_address():
+    owner, other = _Key(), _Key()
+    spent = {"address": owner.address, "amount": 5.0}
+    tx = {"version": 1, "inputs": [{"txid": "ab" * 32, "vout": 0}], "outputs": [{"address": other.address, "amount": 1.0}],
+          "fee": 0.001, "timestamp": 1_700_000_000}
+    good = dict(tx, inputs=[dict(tx["inputs"][0], **sign_transaction_input(tx, 0, spent, owner.sk, view_pubkey=owner.pk_view))])
+    assert verify_transaction_input(good, 0, spent)
+    # other's signature over the same sighash is valid for other's key, not for owner's output
+    forged = dict(tx, inputs=[dict(tx["inputs"][0], **sign_transaction_input(tx, 0, spent, other.sk, view_pubkey=other.pk_view))])
+    assert not verify_transaction_input(forged, 0, spent)
+    # borrowing the owner's public view key does not help: the spend key is part of the address
+    borrowed = dict(forged, inputs=[dict(forged["inputs"][0], viewkey=owner.pk_view.hex())])
+    assert not verify_transaction_input(borrowed, 0, spent)
+    assert key_address_matches(owner.address, good["inputs"][0]["pubkey"], owner.pk_view.hex())
+    assert not key_address_matches(owner.address, forged["inputs"][0]["pubkey"], other.pk_view.hex())
+
+
+def test_mempool_rejects_foreign_key_spend():
+    owner, thief = _Key(), _Key()
+    prev = _fund(owner.address)
+    ok, reason, _ = validate_mempool_tx(_spend(prev, thief), height=HEIGHT)
+    assert (ok, reason) == (False, "bad-signature"), reason
+    ok, reason, _ = validate_mempool_tx(_spend(prev, thief, claim=thief.address), height=HEIGHT)
+    assert (ok, reason) == (False, "bad-input-address"), reason
+    ok, reason, _ = validate_mempool_tx(_spend(prev, owner), height=HEIGHT)
+    assert ok, reason
+
+
+def test_stored_entry_spends_from_spent_output():
+    owner = _Key()
+    prev = _fund(owner.address)
+    tx = _spend(prev, owner)
+    del tx["inputs"][0]["address"]  # optional on the wire; from_addr must not depend on it
+    ok, reason, txid = txrelay.accept_to_mempool(tx, relay=False)
+    assert ok, reason
+    with get_db().session() as s:
+        assert s.query(MempoolTx).filter_by(txid=txid).first().from_addr == owner.address
+
+
+def test_block_tx_with_wrong_txid_is_refused():
+    from apps.node.main import _admit_block_tx
+
+    owner = _Key()
+    tx = _spend(_fund(owner.address), owner)
+    claimed = "00" * 32
+    assert _admit_block_tx(PEER, dict(_body(tx), txid=claimed)) is None
+    assert not txrelay.in_mempool(claimed)
+    assert not txrelay.in_mempool(tx_digest_hex(tx))
+
+
+def test_block_tx_is_validated_before_admission():
+    from apps.node.main import _admit_block_tx
+
+    owner, thief = _Key(), _Key()
+    forged = _spend(_fund(owner.address), thief)
+    txid = tx_digest_hex(forged)
+    # the slot is filled (the body matches its txid) but the tx does not enter the mempool
+    assert _admit_block_tx(PEER, _body(forged)) == txid
+    assert not txrelay.in_mempool(txid)
+
+    valid = _spend(_fund(owner.address), owner)
+    txid = tx_digest_hex(valid)
+    assert _admit_block_tx(PEER, _body(valid)) == txid
+    assert txrelay.in_mempool(txid)
+
+
+def test_concurrent_conflicting_spends_admit_one():
+    owner = _Key()
+    for _ in range(5):
+        prev = _fund(owner.address)
+        spends = [_spend(prev, owner), _spend(prev, owner)]  # same input, different outputs
+        start = threading.Barrier(len(spends))
+        results = [None] * len(spends)
+
+        def submit(i):
+            start.wait()
+            results[i] = txrelay.accept_to_mempool(spends[i], relay=False)
+
+        threads = [threading.Thread(target=submit, args=(i,)) for i in range(len(spends))]
+        for t in threads:
+            t.start()
+        for t in threads:
+            t.join()
+        assert sum(1 for ok, _, _ in results if ok) == 1, results
+        assert sum(1 for tx in spends if txrelay.in_mempool(tx_digest_hex(tx))) == 1, results
+
+
+def main() -> int:
+    tests = [v for k, v in sorted(globals().items()) if k.startswith("test_") and callable(v)]
+    failed = 0
+    for t in tests:
+        try:
+            t()
+            print(f"PASS {t.__name__}")
+        except Exception as e:
+            failed += 1
+            print(f"FAIL {t.__name__}: {e!r}")
+    print(f"{len(tests) - failed}/{len(tests)} passed")
+    return 1 if failed else 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
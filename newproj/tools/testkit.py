@@ -0,0 +1,375 @@
+"""
+Functional test harness: throwaway multi-node regtest clusters on one machine.
+
+Each TestNode is a real node process (apps.node.main) on regtest with its own ephemeral data dir
+(the process runs with that dir as cwd, so data/, logs/, blocks and wallets all land there) and
+free RPC/P2P/pool ports picked at start. Nodes only find each other through connect()/addnode:
+seed peers, DNS seeds, interface discovery and port mapping are off.
+
+    from tools.testkit import Cluster, assert_equal
+
+    with Cluster(2) as c:
+        a, b = c.nodes
+        c.connect_all()
+        key = c.new_key()
+        blocks = a.generate(12, key.address)
+        c.sync_blocks()
+        cb = a.coinbase_output(blocks[0])
+        txid = a.send(key, [cb], {c.new_key().address: 1.0})
+        c.sync_mempools()
+        assert_equal(b.getrawmempool(), [txid])
+
+Usage as a smoke test:
+  python -m tools.testkit [--nodes 3] [--blocks 15] [--keep]
+
+Nodes run as separate processes because config, DB and the P2P peer table (core.p2pstate) are
+process-wide in this codebase.
+"""
+
+import argparse
+import os
+import shutil
+import socket
+import subprocess
+import sys
+import tempfile
+import time
+from typing import Any, Callable, Dict, Iterable, List, Optional
+
+import requests
+
+ROOT = os.path.dirname(os.path.dirname(os.path.abspath(__file__)))
+PY = sys.executable
+
+# Applied to every node; per-node extra_args come after and win
+REGTEST_SETTINGS = [
+    "network.name=smelly-regtest",
+    "network.regtest=true",
+    "network.seed_peers=[]",
+    "network.dns_seeds=[]",
+    "network.discover=false",
+    "network.portmap=false",
+    "network.outbound_check_sec=1",
+    "network.ping_interval_sec=5",
+]
+
+
+class TestKitError(AssertionError):
+    pass
+
+
+def free_port() -> int:
+    with socket.socket(socket.AF_INET, socket.SOCK_STREAM) as s:
+        s.bind(("127.0.0.1", 0))
+        return s.getsockname()[1]
+
+
+def wait_until(pred: Callable[[], Any], timeout: float = 30.0, interval: float = 0.25, what: str = "condition") -> Any:
+    """Poll pred() until it returns something truthy; raise TestKitError on timeout."""
+    deadline = time.time() + timeout
+    last_err: Optional[Exception] = None
+    while time.time() < deadline:
+        try:
+            val = pred()
+            if val:
+                return val
+        except Exception as e:  # node still starting, transient RPC error
+            last_err = e
+        time.sleep(interval)
+    raise TestKitError(f"timed out after {timeout}s waiting for {what}" + (f" (last error: {last_err})" if last_err else ""))
+
+
+def assert_equal(a: Any, b: Any, msg: str = ""):
+    if a != b:
+        raise TestKitError(f"{a!r} != {b!r}" + (f": {msg}" if msg else ""))
+
+
+def solve_header(hdr: Any, nonces: Iterable[int]) -> int:
+    """First nonce in nonces whose PoW hash meets hdr.target; sets hdr.nonce to it."""
+    from core.pow.pow_backend import pow_hash
+    from core.target import hash_meets_target
+
+    for nonce in nonces:
+        hdr.nonce = nonce
+        if hash_meets_target(pow_hash(hdr.serialize(), nonce, hdr.prev_hash_hex), hdr.target):
+            return nonce
+    raise TestKitError(f"no nonce in range meets {hdr.target}")
+
+
+class Key:
+    """Regtest spending key: a 64-byte wallet seed (signrawtransactionwithkey format) and its address."""
+
+    def __init__(self, seed_hex: Optional[str] = None):
+        from core.crypto import NETWORK_PREFIXES, ed25519_keypair_from_seed, encode_address
+
+        self.seed_hex = seed_hex or os.urandom(64).hex()
+        seed = bytes.fromhex(self.seed_hex)
+        _, pk_spend = ed25519_keypair_from_seed(seed, ctx=b"smelly-spend")
+        _, pk_view = ed25519_keypair_from_seed(seed, ctx=b"smelly-view")
+        self.address = encode_address(pk_view, pk_spend, NETWORK_PREFIXES["regtest"])
+
+
+class TestNode:
+    def __init__(self, index: int, basedir: str, extra_args: Optional[List[str]] = None):
+        self.index = index
+        self.datadir = os.path.join(basedir, f"node{index}")
+        self.extra_args = list(extra_args or [])
+        self.rpc_port = 0
+        self.p2p_port = 0
+        self.proc: Optional[subprocess.Popen] = None
+        self.log_path = os.path.join(self.datadir, "stdout.log")
+
+    @property
+    def url(self) -> str:
+        return f"http://127.0.0.1:{self.rpc_port}"
+
+    @property
+    def p2p_addr(self) -> str:
+        return f"127.0.0.1:{self.p2p_port}"
+
+    # ----- lifecycle -----
+
+    @property
+    def db_path(self) -> str:
+        return os.path.join(self.datadir, "smelly.db")
+
+    def start(self, timeout: float = 60.0, flags: Optional[List[str]] = None):
+        """Start the node process; flags are one-off CLI switches for this run (e.g. ["--reindex"])."""
+        os.makedirs(self.datadir, exist_ok=True)
+        self.rpc_port, self.p2p_port, pool_port = free_port(), free_port(), free_port()
+        args = [PY, "-m", "apps.node.main",
+                "--config", os.path.join(ROOT, "configs", "defaults.yaml"),
+                "--rpc-host", "127.0.0.1", "--rpc-port", str(self.rpc_port),
+                "--p2p-host", "127.0.0.1", "--p2p-port", str(self.p2p_port),
+                "--db", self.db_path] + list(flags or [])
+        for kv in REGTEST_SETTINGS + [f"network.pool_port={pool_port}"] + self.extra_args:
+            args += ["--set", kv]
+        env = os.environ.copy()
+        env["PYTHONPATH"] = ROOT + os.pathsep + env.get("PYTHONPATH", "")
+        for var in ("SMELLY_CONFIG", "SMELLY_RPC_HOST", "SMELLY_RPC_PORT", "SMELLY_P2P_HOST", "SMELLY_P2P_PORT", "SMELLY_DB_PATH"):
+            env.pop(var, None)
+        log = open(self.log_path, "ab")
+        self.proc = subprocess.Popen(args, cwd=self.datadir, env=env, stdout=log, stderr=subprocess.STDOUT)
+        log.close()
+
+        def ready():
+            if self.proc.poll() is not None:
+                raise TestKitError(f"node{self.index} exited with {self.proc.returncode}; see {self.log_path}")
+            return self.get_height() >= 0
+
+        try:
+            wait_until(ready, timeout, 0.3, f"node{self.index} RPC")
+        except TestKitError as e:
+            raise TestKitError(f"{e}\n--- node{self.index} log tail ---\n{self.log_tail()}")
+
+    def stop(self):
+        if self.proc is None or self.proc.poll() is not None:
+            return
+        self.proc.terminate()
+        try:
+            self.proc.wait(timeout=10)
+        except subprocess.TimeoutExpired:
+            self.proc.kill()
+            self.proc.wait(timeout=5)
+
+    def restart(self, timeout: float = 60.0):
+        """Stop and start on the same data dir (new ports; reconnect peers afterwards)."""
+        self.stop()
+        self.start(timeout)
+
+    def log_tail(self, lines: int = 40) -> str:
+        try:
+            with open(self.log_path, "r", encoding="utf-8", errors="replace") as f:
+                return "".join(f.readlines()[-lines:])
+        except OSError:
+            return ""
+
+    # ----- RPC -----
+
+    def rpc(self, method: str, path: str, timeout: float = 30.0, **kwargs) -> Any:
+        r = requests.request(method, f"{self.url}{path}", timeout=timeout, **kwargs)
+        if r.status_code != 200:
+            raise TestKitError(f"node{self.index} {method} {path} -> {r.status_code}: {r.text[:500]}")
+        return r.json()
+
+    def get(self, path: str, **params) -> Any:
+        return self.rpc("GET", path, params=params or None)
+
+    def post(self, path: str, body: Optional[Dict[str, Any]] = None, **params) -> Any:
+        return self.rpc("POST", path, json=body, params=params or None, timeout=300.0)
+
+    def get_height(self) -> int:
+        return int(self.get("/rpc/get_height")["height"])
+
+    def tip(self) -> Optional[str]:
+        return self.get("/rpc/getblockchaininfo")["bestblockhash"]
+
+    def getrawmempool(self) -> List[str]:
+        return sorted(self.get("/rpc/getrawmempool"))
+
+    def getpeerinfo(self) -> List[Dict[str, Any]]:
+        return self.get("/rpc/getpeerinfo")
+
+    def generate(self, nblocks: int, address: str) -> List[str]:
+        out = self.post("/rpc/generatetoaddress", {"nblocks": nblocks, "address": address})
+        if isinstance(out, dict):
+            raise TestKitError(f"node{self.index} generatetoaddress stopped after {len(out['hashes'])}: {out['error']}")
+        return out
+
+    def mine_external(self, address: str, max_nonces: int = 1_000_000) -> str:
+        """
+        Mine one block the way external miners and the pool do (get_block_template, solve here,
+        submit_work), so it connects through consensus.accept_external_header rather than the
+        node's own append path used by generate().
+        """
+        job, hdr = self.block_template(address)
+        solve_header(hdr, range(max_nonces))
+        res = self.post("/rpc/submit_work", {
+            "job_id": job["job_id"], "miner_address": address, "nonce": hdr.nonce, "timestamp": hdr.timestamp,
+            "version": hdr.version, "merkle_root_hex": hdr.merkle_root_hex, "prev_hash_hex": hdr.prev_hash_hex,
+            "txids": job["txids"],
+        })
+        if not res.get("accepted"):
+            raise TestKitError(f"node{self.index} submit_work rejected: {res}")
+        return res["hash"]
+
+    def block_template(self, address: str):
+        """(job, unsolved Header) from get_block_template on the current tip."""
+        from core.consensus import Header
+        from core.merkle import merkle_root
+
+        tip = self.tip()
+
+        def template():
+            job = self.post("/rpc/get_block_template", {"miner_address": address})
+            return job if job.get("prev_hash") == tip else None  # the cached template lags a new tip briefly
+
+        job = wait_until(template, 15, what=f"node{self.index} template on {tip}")
+        hdr = Header(version=int(job["version"]), prev_hash_hex=job["prev_hash"], merkle_root_hex=merkle_root(job["txids"]),
+                     timestamp=int(job["timestamp"]), target=job["target"], nonce=0,
+                     miner_address=address, tx_count=max(1, len(job["txids"])))
+        return job, hdr
+
+    def connect(self, other: "TestNode"):
+        """Outbound P2P connection self -> other, waiting for the handshake on both sides."""
+        self.post("/rpc/addnode", {"addr": other.p2p_addr, "command": "onetry"})
+        wait_until(lambda: any(p.get("addr") == other.p2p_addr for p in self.getpeerinfo()), 15, what=f"node{self.index} -> node{other.index}")
+        wait_until(lambda: len(other.getpeerinfo()) > 0, 15, what=f"node{other.index} sees node{self.index}")
+
+    def coinbase_output(self, block_hash: str) -> Dict[str, Any]:
+        """The block's coinbase output as a prevtx entry {txid, vout, address, amount}."""
+        txid = self.get(f"/rest/block/{block_hash}.json")["tx"][0]
+        out = self.get(f"/rest/tx/{txid}.json")["outputs"][0]
+        return {"txid": txid, "vout": 0, "address": out["address"], "amount": out["amount"]}
+
+    def send(self, key: Key, prevouts: List[Dict[str, Any]], outputs: Dict[str, float], fee: float = 0.0001) -> str:
+        """Spend prevouts (owned by key) to outputs; change goes back to the spending address."""
+        tx = self.post("/rpc/createrawtransaction", {
+            "inputs": [{"txid": p["txid"], "vout": p["vout"]} for p in prevouts],
+            "outputs": [{"address": a, "amount": v} for a, v in outputs.items()],
+            "fee": fee,
+        })["tx"]
+        signed = self.post("/rpc/signrawtransactionwithkey", {"hexstring": tx, "privkeys": [key.seed_hex], "prevtxs": prevouts})
+        if not signed.get("complete"):
+            raise TestKitError(f"signing incomplete: {signed.get('errors')}")
+        return self.post("/rpc/tx/submit", {"tx": signed["tx"]})["txid"]
+
+
+class Cluster:
+    """N regtest nodes in one temp dir; a context manager that tears everything down (keep=True leaves the dirs)."""
+
+    def __init__(self, num_nodes: int = 2, extra_args: Optional[List[List[str]]] = None, keep: bool = False):
+        self.basedir = tempfile.mkdtemp(prefix="smelly-testkit-")
+        self.keep = keep
+        extra_args = extra_args or [[] for _ in range(num_nodes)]
+        self.nodes = [TestNode(i, self.basedir, extra_args[i]) for i in range(num_nodes)]
+
+    def __enter__(self) -> "Cluster":
+        try:
+            for n in self.nodes:
+                n.start()
+        except Exception:
+            self.stop()
+            raise
+        return self
+
+    def __exit__(self, exc_type, exc, tb):
+        if exc_type is not None:
+            for n in self.nodes:
+                print(f"--- node{n.index} log tail ({n.datadir}) ---\n{n.log_tail()}", file=sys.stderr)
+        self.stop()
+
+    def stop(self):
+        for n in self.nodes:
+            n.stop()
+        if not self.keep:
+            shutil.rmtree(self.basedir, ignore_errors=True)
+
+    @staticmethod
+    def new_key() -> Key:
+        return Key()
+
+    def connect_all(self):
+        """Line topology: node i -> node i+1 (blocks and txs must relay across hops)."""
+        for a, b in zip(self.nodes, self.nodes[1:]):
+            a.connect(b)
+
+    def sync_blocks(self, nodes: Optional[List[TestNode]] = None, timeout: float = 60.0) -> str:
+        """Wait until all nodes report the same tip; returns it."""
+        nodes = nodes or self.nodes
+        wait_until(lambda: len({n.tip() for n in nodes}) == 1, timeout, 0.5, "block sync")
+        return nodes[0].tip()
+
+    def sync_mempools(self, nodes: Optional[List[TestNode]] = None, timeout: float = 60.0) -> List[str]:
+        nodes = nodes or self.nodes
+        wait_until(lambda: all(n.getrawmempool() == nodes[0].getrawmempool() for n in nodes[1:]), timeout, 0.5, "mempool sync")
+        return nodes[0].getrawmempool()
+
+    def disconnect_all(self):
+        """Split the cluster (restart every node; data is kept, peers are not)."""
+        for n in self.nodes:
+            n.restart()
+
+    def assert_tips_equal(self, nodes: Optional[List[TestNode]] = None):
+        tips = {f"node{n.index}": n.tip() for n in nodes or self.nodes}
+        if len(set(tips.values())) != 1:
+            raise TestKitError(f"tips differ: {tips}")
+
+
+def main():
+    ap = argparse.ArgumentParser(description="Regtest cluster smoke test: mine, relay a spend, reorg")
+    ap.add_argument("--nodes", type=int, default=3)
+    ap.add_argument("--blocks", type=int, default=15, help="blocks mined before the spend (>= coinbase maturity)")
+    ap.add_argument("--keep", action="store_true", help="keep node data dirs for inspection")
+    args = ap.parse_args()
+
+    with Cluster(max(2, args.nodes), keep=args.keep) as c:
+        print(f"[TESTKIT] {len(c.nodes)} nodes in {c.basedir}")
+        c.connect_all()
+        first, last = c.nodes[0], c.nodes[-1]
+        key = c.new_key()
+        blocks = first.generate(args.blocks, key.address)
+        c.sync_blocks()
+        assert_equal(last.get_height(), first.get_height(), "heights after sync")
+        print(f"[TESTKIT] synced {args.blocks} blocks, height {first.get_height()}")
+
+        txid = first.send(key, [first.coinbase_output(blocks[0])], {c.new_key().address: 1.0})
+        assert_equal(c.sync_mempools(), [txid], "relayed mempool")
+        first.generate(1, key.address)
+        c.sync_blocks()
+        assert_equal(last.getrawmempool(), [], "mempool after confirmation")
+        print(f"[TESTKIT] spend {txid} relayed and confirmed")
+
+        # Reorg: split, let the last node build the longer branch, rejoin
+        c.disconnect_all()
+        first.generate(2, key.address)
+        last.generate(4, c.new_key().address)
+        c.connect_all()
+        tip = c.sync_blocks()
+        assert_equal(tip, last.tip(), "longest branch wins")
+        print(f"[TESTKIT] reorg to {tip} at height {first.get_height()}")
+    print("[TESTKIT] OK")
+
+
+if __name__ == "__main__":
+    main()
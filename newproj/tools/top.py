@@ -0,0 +1,203 @@
+"""
+Operator dashboard for a running SMELLY node (read-only, RPC only).
+
+Usage:
+  python -m tools.run top
+  python -m tools.run top --rpc http://127.0.0.1:28445 --interval 3
+
+Panels (refreshed every --interval seconds):
+- Sync: tip height/hash, tip age, PoW backend
+- Peers: connected P2P peers with idle time and traffic
+- Mempool: count, total fees, highest-fee entries
+- Mining/Pool: pool share stats and hashrate from the Stratum snapshot
+- Recent blocks: last N headers with miner, tx count and interval
+"""
+
+from __future__ import annotations
+
+import argparse
+import os
+import sys
+import time
+from datetime import datetime
+from typing import Any, Dict, List, Optional
+
+import requests
+
+
+class _C:
+    RESET = "\x1b[0m"
+    BOLD = "\x1b[1m"
+    DIM = "\x1b[2m"
+    RED = "\x1b[31m"
+    GREEN = "\x1b[32m"
+    YEL = "\x1b[33m"
+    CYA = "\x1b[36m"
+
+
+def _default_rpc() -> str:
+    from core.config import get_config
+    cfg = get_config()
+    host = os.environ.get("SMELLY_RPC_HOST") or cfg.get("network.rpc_host", "127.0.0.1")
+    port = os.environ.get("SMELLY_RPC_PORT") or cfg.get("network.rpc_port", 28445)
+    return f"http://{host}:{port}"
+
+
+def _get(base: str, path: str, timeout: float = 3.0) -> Optional[Any]:
+    try:
+        r = requests.get(f"{base}{path}", timeout=timeout)
+        if r.status_code == 200:
+            return r.json()
+    except Exception:
+        pass
+    return None
+
+
+def _post(base: str, path: str, body: Dict[str, Any], timeout: float = 3.0) -> Optional[Any]:
+    try:
+        r = requests.post(f"{base}{path}", json=body, timeout=timeout)
+        if r.status_code == 200:
+            return r.json()
+    except Exception:
+        pass
+    return None
+
+
+def _age(ts_sec: Optional[int]) -> str:
+    if not ts_sec:
+        return "-"
+    d = max(0, int(time.time()) - int(ts_sec))
+    if d < 120:
+        return f"{d}s"
+    if d < 7200:
+        return f"{d // 60}m"
+    return f"{d // 3600}h"
+
+
+def _hashrate(v: float) -> str:
+    for unit in ("H/s", "kH/s", "MH/s", "GH/s"):
+        if v < 1000.0:
+            return f"{v:.2f} {unit}"
+        v /= 1000.0
+    return f"{v:.2f} TH/s"
+
+
+def _bytes(n: int) -> str:
+    v = float(n or 0)
+    for unit in ("B", "KB", "MB", "GB"):
+        if v < 1024.0:
+            return f"{v:.0f}{unit}" if unit == "B" else f"{v:.1f}{unit}"
+        v /= 1024.0
+    return f"{v:.1f}TB"
+
+
+def _title(text: str) -> str:
+    return f"{_C.BOLD}{_C.YEL}== {text} {'=' * max(0, 60 - len(text))}{_C.RESET}"
+
+
+def collect(base: str, blocks: int) -> Dict[str, Any]:
+    snap: Dict[str, Any] = {"ok": False}
+    h = _get(base, "/rpc/get_height")
+    if not h:
+        return snap
+    snap["ok"] = True
+    height = int(h.get("height", -1))
+    snap["height"] = height
+    snap["backend"] = (_get(base, "/rpc/pow_backend") or {}).get("backend", "unknown")
+    snap["peers"] = _get(base, "/rpc/peers") or []
+    snap["net"] = _get(base, "/rpc/getnettotals") or {}
+    snap["safe_mode"] = _get(base, "/rpc/safe_mode") or {}
+    snap["mempool"] = _get(base, "/rpc/mempool") or []
+    snap["pool"] = _get(base, "/rpc/pool_stats") or {}
+    start = max(0, height - blocks + 1)
+    headers = _post(base, "/rpc/get_headers_range", {"start_height": start, "count": blocks}) or []
+    snap["headers"] = list(reversed(headers))
+    return snap
+
+
+def render(base: str, snap: Dict[str, Any]) -> str:
+    out: List[str] = []
+    now = datetime.now().strftime("%Y-%m-%d %H:%M:%S")
+    out.append(f"{_C.BOLD}SMELLY top{_C.RESET}  {_C.DIM}{base}  {now}{_C.RESET}")
+    if not snap.get("ok"):
+        out.append(f"{_C.RED}Node RPC unreachable; retrying...{_C.RESET}")
+        return "\n".join(out)
+
+    headers = snap.get("headers") or []
+    tip = headers[0] if headers else {}
+    tip_age = _age(tip.get("timestamp"))
+    out.append(_title("Sync"))
+    age_color = _C.GREEN if tip and int(time.time()) - int(tip.get("timestamp", 0)) < 120 else _C.YEL
+    out.append(f"  height {snap['height']}  tip {str(tip.get('hash', '-'))[:16]}..  "
+               f"age {age_color}{tip_age}{_C.RESET}  pow {snap.get('backend')}")
+
+    sm = snap.get("safe_mode") or {}
+    if sm.get("active"):
+        out.append(f"  {_C.RED}{_C.BOLD}SAFE MODE ({sm.get('reason')}): {sm.get('detail')}{_C.RESET}")
+
+    peers = snap.get("peers") or []
+    out.append(_title(f"Peers ({len(peers)})"))
+    if not peers:
+        out.append(f"  {_C.DIM}no peers connected{_C.RESET}")
+    net = snap.get("net") or {}
+    if net:
+        out.append(f"  {_C.DIM}total in {_bytes(net.get('totalbytesrecv', 0))}  out {_bytes(net.get('totalbytessent', 0))}{_C.RESET}")
+    for p in peers[:8]:
+        out.append(f"  {p.get('addr', '?'):<24} {'in ' if p.get('inbound') else 'out'} idle {int(p.get('idle_ms', 0)) // 1000}s  "
+                   f"rx {_bytes(p.get('bytes_recv', 0))} tx {_bytes(p.get('bytes_sent', 0))}")
+
+    mem = snap.get("mempool") or []
+    total_fees = sum(float(m.get("fee") or 0.0) for m in mem)
+    out.append(_title(f"Mempool ({len(mem)} tx, fees {total_fees:.6f})"))
+    for m in sorted(mem, key=lambda x: float(x.get("fee") or 0.0), reverse=True)[:5]:
+        out.append(f"  {str(m.get('txid', ''))[:16]}..  fee {float(m.get('fee') or 0.0):.6f}  amt {m.get('amount')}")
+
+    pool = snap.get("pool") or {}
+    miners = pool.get("miners") or []
+    out.append(_title("Mining / Pool"))
+    if pool.get("ts"):
+        out.append(f"  hashrate {_hashrate(float(pool.get('total_hashrate') or 0.0))}  miners {len(miners)}  "
+                   f"share_diff {pool.get('share_diff')}  accepted_5m {pool.get('accepted_5m')}  "
+                   f"rejected_5m {pool.get('rejected_5m')}  updated {_age(int(pool['ts']) // 1000)} ago")
+        for m in miners[:5]:
+            out.append(f"  {str(m.get('addr', ''))[:24]:<24} acc {m.get('accepted')} rej {m.get('rejected')} hr {m.get('hashrate')}  "
+                       f"immature {float(m.get('immature_balance') or 0.0):.4f} spendable {float(m.get('spendable_balance') or 0.0):.4f}")
+    else:
+        out.append(f"  {_C.DIM}no pool snapshot{_C.RESET}")
+
+    out.append(_title("Recent blocks"))
+    for i, b in enumerate(headers):
+        nxt = headers[i + 1] if i + 1 < len(headers) else None
+        interval = f"{int(b['timestamp']) - int(nxt['timestamp'])}s" if nxt else "-"
+        out.append(f"  {b.get('height'):>7}  {str(b.get('hash', ''))[:16]}..  txs {b.get('tx_count'):>3}  "
+                   f"dt {interval:>5}  {str(b.get('miner', ''))[:24]}")
+    return "\n".join(out)
+
+
+def main(argv: Optional[List[str]] = None):
+    parser = argparse.ArgumentParser(description="SMELLY node operator dashboard")
+    parser.add_argument("--rpc", type=str, default=None, help="Node RPC base URL")
+    parser.add_argument("--interval", type=float, default=3.0, help="Refresh interval in seconds")
+    parser.add_argument("--blocks", type=int, default=10, help="Number of recent blocks to show")
+    parser.add_argument("--once", action="store_true", help="Render a single frame and exit")
+    args = parser.parse_args(argv)
+
+    base = (args.rpc or _default_rpc()).rstrip("/")
+    if os.name == "nt":
+        # Enable ANSI escape handling on Windows consoles
+        os.system("")
+    try:
+        while True:
+            frame = render(base, collect(base, max(1, args.blocks)))
+            if args.once:
+                print(frame)
+                return
+            sys.stdout.write("\x1b[2J\x1b[H" + frame + "\n")
+            sys.stdout.flush()
+            time.sleep(max(0.5, args.interval))
+    except KeyboardInterrupt:
+        pass
+
+
+if __name__ == "__main__":
+    main()